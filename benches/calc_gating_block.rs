@@ -25,12 +25,14 @@ fn calc_gating_block(
     audio_data: &[f64],
     audio_data_index: usize,
     channel_map: &[ebur128::Channel],
+    channel_gains: &[f64],
 ) -> f64 {
     ebur128::filter::Filter::calc_gating_block(
         frames_per_block,
         audio_data,
         audio_data_index,
         channel_map,
+        channel_gains,
     )
 }
 
@@ -48,6 +50,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         }
 
         let channel_map = [ebur128::Channel::Left; 2];
+        let channel_gains = [1.0; 2];
 
         let frames_per_block = 144_000;
 
@@ -76,6 +79,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                     black_box(&data),
                     black_box(0),
                     black_box(&channel_map),
+                    black_box(&channel_gains),
                 )
             })
         });
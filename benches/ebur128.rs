@@ -1,6 +1,6 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
 
-use ebur128::{EbuR128, Mode};
+use ebur128::{EbuR128, EbuR128Builder, Mode, NonFiniteHandling, Precision};
 use ebur128_c::Mode as ModeC;
 
 #[cfg(feature = "c-tests")]
@@ -139,7 +139,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
             data.chunks_exact_mut(2),
             Iterator::zip(fst.iter_mut(), snd.iter_mut()),
         ) {
-            let val = f32::sin(accumulator) * std::i16::MAX as f32;
+            let val = f32::sin(accumulator) * i16::MAX as f32;
             out[0] = val as i16;
             out[1] = val as i16;
             *fst = val as i16;
@@ -191,7 +191,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
             data.chunks_exact_mut(2),
             Iterator::zip(fst.iter_mut(), snd.iter_mut()),
         ) {
-            let val = f32::sin(accumulator) * std::i32::MAX as f32;
+            let val = f32::sin(accumulator) * i32::MAX as f32;
             out[0] = val as i32;
             out[1] = val as i32;
             *fst = val as i32;
@@ -284,6 +284,27 @@ pub fn criterion_benchmark(c: &mut Criterion) {
             })
         });
 
+        group.bench_function("Rust/Interleaved/F32", |b| {
+            b.iter(|| {
+                let mut ebu =
+                    EbuR128::new(black_box(2), black_box(48_000), black_box(mode)).unwrap();
+                ebu.set_precision(Precision::F32).unwrap();
+                ebu.add_frames_f32(&data).unwrap();
+
+                get_results(&ebu, black_box(mode));
+            })
+        });
+        group.bench_function("Rust/Interleaved/Fixed", |b| {
+            b.iter(|| {
+                let mut ebu =
+                    EbuR128::new(black_box(2), black_box(48_000), black_box(mode)).unwrap();
+                ebu.set_precision(Precision::Fixed).unwrap();
+                ebu.add_frames_f32(&data).unwrap();
+
+                get_results(&ebu, black_box(mode));
+            })
+        });
+
         group.finish();
 
         let mut data = vec![0.0f64; 48_000 * 5 * 2];
@@ -340,5 +361,180 @@ pub fn criterion_benchmark(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches, criterion_benchmark);
+pub fn silence_tail_benchmark(c: &mut Criterion) {
+    // 10s of loud content followed by 60s of silence. While the filter states decay they must not
+    // become denormal, otherwise processing the silence is a lot slower than processing the sine.
+    let mut data = vec![0.0f32; 48_000 * 10 * 2];
+    let mut accumulator = 0.0;
+    let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+    for out in data.chunks_exact_mut(2) {
+        let val = f32::sin(accumulator);
+        out[0] = val;
+        out[1] = val;
+        accumulator += step;
+    }
+    let sine = data
+        .iter()
+        .cycle()
+        .take(48_000 * 60 * 2)
+        .copied()
+        .collect::<Vec<_>>();
+    let silence = vec![0.0f32; 48_000 * 60 * 2];
+
+    for (name, mode) in &[("I", Mode::I), ("all", Mode::all() & !Mode::HISTOGRAM)] {
+        let mut group = c.benchmark_group(format!("ebur128 process tail: 48kHz f32 2ch {}", name));
+        group.sample_size(10);
+
+        for (tail_name, tail) in &[("sine", &sine), ("silence", &silence)] {
+            group.bench_function(*tail_name, |b| {
+                b.iter_batched(
+                    || {
+                        let mut ebu = EbuR128::new(2, 48_000, *mode).unwrap();
+                        ebu.add_frames_f32(&data).unwrap();
+                        ebu
+                    },
+                    |mut ebu| {
+                        for chunk in tail.chunks(4_800 * 2) {
+                            ebu.add_frames_f32(black_box(chunk)).unwrap();
+                        }
+                        ebu
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+
+        group.finish();
+    }
+}
+
+pub fn many_channels_benchmark(c: &mut Criterion) {
+    // 10s of 24 channel audio, e.g. 22.2. With the `rayon` feature the channels are filtered in
+    // parallel.
+    const CHANNELS: usize = 24;
+    let mut data = vec![0.0f32; 48_000 * 10 * CHANNELS];
+    let mut accumulator = 0.0;
+    let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+    for out in data.chunks_exact_mut(CHANNELS) {
+        let val = f32::sin(accumulator);
+        for (i, out) in out.iter_mut().enumerate() {
+            *out = val / (i + 1) as f32;
+        }
+        accumulator += step;
+    }
+
+    for (name, mode) in &[("I", Mode::I), ("S", Mode::S)] {
+        let mut group = c.benchmark_group(format!(
+            "ebur128 process: 48kHz f32 {}ch {}",
+            CHANNELS, name
+        ));
+        group.sample_size(10);
+
+        group.bench_function("Rust", |b| {
+            b.iter(|| {
+                let mut ebu = EbuR128::new(CHANNELS as u32, 48_000, *mode).unwrap();
+                ebu.add_frames_f32(black_box(&data)).unwrap();
+                black_box(ebu.loudness_window(1).unwrap());
+            })
+        });
+        group.bench_function("Rust/F32", |b| {
+            b.iter(|| {
+                let mut ebu = EbuR128::new(CHANNELS as u32, 48_000, *mode).unwrap();
+                ebu.set_precision(Precision::F32).unwrap();
+                ebu.add_frames_f32(black_box(&data)).unwrap();
+                black_box(ebu.loudness_window(1).unwrap());
+            })
+        });
+
+        group.finish();
+    }
+}
+
+pub fn large_batch_benchmark(c: &mut Criterion) {
+    // 10 minutes of stereo audio passed as a single slice, processed in chunks of the default
+    // size or all at once.
+    let mut data = vec![0.0f32; 48_000 * 600 * 2];
+    let mut accumulator = 0.0;
+    let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+    for out in data.chunks_exact_mut(2) {
+        let val = f32::sin(accumulator);
+        out[0] = val;
+        out[1] = val;
+        accumulator += step;
+    }
+    // One NaN per second that is replaced with silence, which requires a copy of the input
+    let mut data_nan = data.clone();
+    for frame in data_nan.chunks_exact_mut(48_000 * 2) {
+        frame[0] = f32::NAN;
+    }
+
+    for (name, handling, data) in &[
+        ("propagate", NonFiniteHandling::Propagate, &data),
+        ("skip", NonFiniteHandling::Skip, &data_nan),
+    ] {
+        let mut group = c.benchmark_group(format!(
+            "ebur128 process 10min: 48kHz f32 2ch I non-finite {}",
+            name
+        ));
+        group.sample_size(10);
+
+        for (chunk_name, chunk_size) in &[("chunked", None), ("whole", Some(usize::MAX))] {
+            group.bench_function(*chunk_name, |b| {
+                b.iter(|| {
+                    let mut builder = EbuR128Builder::new()
+                        .channels(2)
+                        .rate(48_000)
+                        .mode(Mode::I)
+                        .non_finite_handling(*handling);
+                    if let Some(chunk_size) = chunk_size {
+                        builder = builder.chunk_size(*chunk_size);
+                    }
+                    let mut ebu = builder.build().unwrap();
+                    ebu.add_frames_f32(black_box(data)).unwrap();
+                    black_box(ebu.loudness_global().unwrap());
+                })
+            });
+        }
+
+        group.finish();
+    }
+}
+
+pub fn mono_long_benchmark(c: &mut Criterion) {
+    // 5 minutes of mono audio. Most of the time is spent summing up the energy of the gating
+    // blocks, especially of the 3s short term blocks.
+    let mut data = vec![0.0f32; 48_000 * 300];
+    let mut accumulator = 0.0;
+    let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+    for out in data.iter_mut() {
+        *out = f32::sin(accumulator);
+        accumulator += step;
+    }
+
+    for (name, mode) in &[("I", Mode::I), ("S", Mode::S), ("LRA", Mode::LRA)] {
+        let mut group = c.benchmark_group(format!("ebur128 process 5min: 48kHz f32 1ch {}", name));
+        group.sample_size(10);
+
+        group.bench_function("Rust", |b| {
+            b.iter(|| {
+                let mut ebu = EbuR128::new(1, 48_000, *mode).unwrap();
+                for chunk in data.chunks(4_800) {
+                    ebu.add_frames_f32(black_box(chunk)).unwrap();
+                }
+                black_box(ebu.loudness_window(1).unwrap());
+            })
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(
+    benches,
+    criterion_benchmark,
+    silence_tail_benchmark,
+    many_channels_benchmark,
+    large_batch_benchmark,
+    mono_long_benchmark
+);
 criterion_main!(benches);
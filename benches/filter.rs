@@ -28,6 +28,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
             let f = filter::Filter::new(
                 black_box(48_000),
                 black_box(2),
+                black_box(ebur128::Weighting::K),
                 black_box(false),
                 black_box(false),
             );
@@ -51,7 +52,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
             data.chunks_exact_mut(2),
             Iterator::zip(fst.iter_mut(), snd.iter_mut()),
         ) {
-            let val = f32::sin(accumulator) * std::i16::MAX as f32;
+            let val = f32::sin(accumulator) * i16::MAX as f32;
             out[0] = val as i16;
             out[1] = val as i16;
             *fst = val as i16;
@@ -79,19 +80,23 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         }
 
         {
-            let mut f = filter::Filter::new(48_000, 2, *sample_peak, false);
+            let mut f =
+                filter::Filter::new(48_000, 2, ebur128::Weighting::K, *sample_peak, false).unwrap();
             group.bench_function("Rust/Interleaved", |b| {
                 b.iter(|| {
                     f.process(
-                        black_box(ebur128::Interleaved::new(&*data, 2).unwrap()),
+                        black_box(ebur128::Interleaved::new(&data, 2).unwrap()),
                         black_box(&mut data_out),
                         black_box(0),
                         black_box(&channel_map),
-                    );
+                        None,
+                    )
+                    .unwrap();
                 })
             });
 
-            let mut f = filter::Filter::new(48_000, 2, *sample_peak, false);
+            let mut f =
+                filter::Filter::new(48_000, 2, ebur128::Weighting::K, *sample_peak, false).unwrap();
             group.bench_function("Rust/Planar", |b| {
                 b.iter(|| {
                     f.process(
@@ -99,7 +104,9 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                         black_box(&mut data_out),
                         black_box(0),
                         black_box(&channel_map),
-                    );
+                        None,
+                    )
+                    .unwrap();
                 })
             });
         }
@@ -115,7 +122,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
             data.chunks_exact_mut(2),
             Iterator::zip(fst.iter_mut(), snd.iter_mut()),
         ) {
-            let val = f32::sin(accumulator) * std::i32::MAX as f32;
+            let val = f32::sin(accumulator) * i32::MAX as f32;
             out[0] = val as i32;
             out[1] = val as i32;
             *fst = val as i32;
@@ -143,19 +150,23 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         }
 
         {
-            let mut f = filter::Filter::new(48_000, 2, *sample_peak, false);
+            let mut f =
+                filter::Filter::new(48_000, 2, ebur128::Weighting::K, *sample_peak, false).unwrap();
             group.bench_function("Rust/Interleaved", |b| {
                 b.iter(|| {
                     f.process(
-                        black_box(ebur128::Interleaved::new(&*data, 2).unwrap()),
+                        black_box(ebur128::Interleaved::new(&data, 2).unwrap()),
                         black_box(&mut data_out),
                         black_box(0),
                         black_box(&channel_map),
-                    );
+                        None,
+                    )
+                    .unwrap();
                 })
             });
 
-            let mut f = filter::Filter::new(48_000, 2, *sample_peak, false);
+            let mut f =
+                filter::Filter::new(48_000, 2, ebur128::Weighting::K, *sample_peak, false).unwrap();
             group.bench_function("Rust/Planar", |b| {
                 b.iter(|| {
                     f.process(
@@ -163,7 +174,9 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                         black_box(&mut data_out),
                         black_box(0),
                         black_box(&channel_map),
-                    );
+                        None,
+                    )
+                    .unwrap();
                 })
             });
         }
@@ -207,19 +220,23 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         }
 
         {
-            let mut f = filter::Filter::new(48_000, 2, *sample_peak, false);
+            let mut f =
+                filter::Filter::new(48_000, 2, ebur128::Weighting::K, *sample_peak, false).unwrap();
             group.bench_function("Rust/Interleaved", |b| {
                 b.iter(|| {
                     f.process(
-                        black_box(ebur128::Interleaved::new(&*data, 2).unwrap()),
+                        black_box(ebur128::Interleaved::new(&data, 2).unwrap()),
                         black_box(&mut data_out),
                         black_box(0),
                         black_box(&channel_map),
-                    );
+                        None,
+                    )
+                    .unwrap();
                 })
             });
 
-            let mut f = filter::Filter::new(48_000, 2, *sample_peak, false);
+            let mut f =
+                filter::Filter::new(48_000, 2, ebur128::Weighting::K, *sample_peak, false).unwrap();
             group.bench_function("Rust/Planar", |b| {
                 b.iter(|| {
                     f.process(
@@ -227,7 +244,9 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                         black_box(&mut data_out),
                         black_box(0),
                         black_box(&channel_map),
-                    );
+                        None,
+                    )
+                    .unwrap();
                 })
             });
         }
@@ -271,19 +290,23 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         }
 
         {
-            let mut f = filter::Filter::new(48_000, 2, *sample_peak, false);
+            let mut f =
+                filter::Filter::new(48_000, 2, ebur128::Weighting::K, *sample_peak, false).unwrap();
             group.bench_function("Rust/Interleaved", |b| {
                 b.iter(|| {
                     f.process(
-                        black_box(ebur128::Interleaved::new(&*data, 2).unwrap()),
+                        black_box(ebur128::Interleaved::new(&data, 2).unwrap()),
                         black_box(&mut data_out),
                         black_box(0),
                         black_box(&channel_map),
-                    );
+                        None,
+                    )
+                    .unwrap();
                 })
             });
 
-            let mut f = filter::Filter::new(48_000, 2, *sample_peak, false);
+            let mut f =
+                filter::Filter::new(48_000, 2, ebur128::Weighting::K, *sample_peak, false).unwrap();
             group.bench_function("Rust/Planar", |b| {
                 b.iter(|| {
                     f.process(
@@ -291,7 +314,9 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                         black_box(&mut data_out),
                         black_box(0),
                         black_box(&channel_map),
-                    );
+                        None,
+                    )
+                    .unwrap();
                 })
             });
         }
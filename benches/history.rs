@@ -67,7 +67,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
 
             group.bench_function("Rust", |b| {
                 b.iter(|| {
-                    black_box(hist.gated_loudness());
+                    black_box(hist.gated_loudness(&history::Gating::default()));
                 })
             });
         }
@@ -99,7 +99,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
 
             group.bench_function("Rust", |b| {
                 b.iter(|| {
-                    black_box(hist.relative_threshold());
+                    black_box(hist.relative_threshold(&history::Gating::default()));
                 })
             });
         }
@@ -137,6 +137,28 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         }
         group.finish();
     }
+
+    // Querying the loudness range of a growing short term history, e.g. once per second of a live
+    // stream, with and without caching. The cached one doesn't depend on the number of energies.
+    let mut group = c.benchmark_group("history loudness range: growing Queue");
+    for &len in &[1_000, 10_000, 100_000] {
+        for &(cached, name) in &[(false, "Rust"), (true, "Rust cached")] {
+            let mut hist = history::History::new(false, usize::MAX);
+            if cached {
+                hist.cache_loudness_range();
+            }
+            for e in black_box(&energies[..len]) {
+                hist.add(*e);
+            }
+
+            group.bench_function(format!("{} {}", name, len), |b| {
+                b.iter(|| {
+                    black_box(hist.loudness_range());
+                })
+            });
+        }
+    }
+    group.finish();
 }
 
 criterion_group!(benches, criterion_benchmark);
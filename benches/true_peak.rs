@@ -12,7 +12,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         data.chunks_exact_mut(2),
         Iterator::zip(fst.iter_mut(), snd.iter_mut()),
     ) {
-        let val = f32::sin(accumulator) * std::i16::MAX as f32;
+        let val = f32::sin(accumulator) * i16::MAX as f32;
         out[0] = val as i16;
         out[1] = val as i16;
         *fst = val as i16;
@@ -49,7 +49,8 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                 tp.check_true_peak(
                     black_box(ebur128::Interleaved::new(&data, 2).unwrap()),
                     black_box(&mut peaks),
-                );
+                )
+                .unwrap();
             })
         });
 
@@ -59,7 +60,8 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                 tp.check_true_peak(
                     black_box(ebur128::Planar::new(&[fst, snd]).unwrap()),
                     black_box(&mut peaks),
-                );
+                )
+                .unwrap();
             })
         });
     }
@@ -75,7 +77,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         data.chunks_exact_mut(2),
         Iterator::zip(fst.iter_mut(), snd.iter_mut()),
     ) {
-        let val = f32::sin(accumulator) * std::i32::MAX as f32;
+        let val = f32::sin(accumulator) * i32::MAX as f32;
         out[0] = val as i32;
         out[1] = val as i32;
         *fst = val as i32;
@@ -110,7 +112,8 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                 tp.check_true_peak(
                     black_box(ebur128::Interleaved::new(&data, 2).unwrap()),
                     black_box(&mut peaks),
-                );
+                )
+                .unwrap();
             })
         });
 
@@ -120,7 +123,8 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                 tp.check_true_peak(
                     black_box(ebur128::Planar::new(&[fst, snd]).unwrap()),
                     black_box(&mut peaks),
-                );
+                )
+                .unwrap();
             })
         });
     }
@@ -171,7 +175,8 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                 tp.check_true_peak(
                     black_box(ebur128::Interleaved::new(&data, 2).unwrap()),
                     black_box(&mut peaks),
-                );
+                )
+                .unwrap();
             })
         });
 
@@ -181,7 +186,8 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                 tp.check_true_peak(
                     black_box(ebur128::Planar::new(&[fst, snd]).unwrap()),
                     black_box(&mut peaks),
-                );
+                )
+                .unwrap();
             })
         });
     }
@@ -232,7 +238,8 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                 tp.check_true_peak(
                     black_box(ebur128::Interleaved::new(&data, 2).unwrap()),
                     black_box(&mut peaks),
-                );
+                )
+                .unwrap();
             })
         });
 
@@ -242,7 +249,36 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                 tp.check_true_peak(
                     black_box(ebur128::Planar::new(&[fst, snd]).unwrap()),
                     black_box(&mut peaks),
-                );
+                )
+                .unwrap();
+            })
+        });
+    }
+
+    group.finish();
+
+    let mut data = vec![0.0f32; 48_000 * 10 * 2];
+    let mut accumulator = 0.0;
+    let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+    for out in data.chunks_exact_mut(2) {
+        let val = f32::sin(accumulator);
+        out[0] = val;
+        out[1] = val;
+        accumulator += step;
+    }
+
+    let mut group = c.benchmark_group("true_peak: 48kHz 2ch f32 10s");
+    group.sample_size(20);
+
+    {
+        let mut tp = true_peak::TruePeak::new(black_box(48_000), black_box(2)).unwrap();
+        group.bench_function("Rust/Interleaved", |b| {
+            b.iter(|| {
+                tp.check_true_peak(
+                    black_box(ebur128::Interleaved::new(&data, 2).unwrap()),
+                    black_box(&mut peaks),
+                )
+                .unwrap();
             })
         });
     }
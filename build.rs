@@ -1,6 +1,12 @@
 fn main() {
     #[cfg(feature = "c-tests")]
     {
+        // The C implementation is only needed for comparing against it and can't be built for
+        // WebAssembly
+        if std::env::var("CARGO_CFG_TARGET_ARCH").as_deref() == Ok("wasm32") {
+            return;
+        }
+
         let mut b = cc::Build::new();
 
         b.compiler("clang");
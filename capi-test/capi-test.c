@@ -79,6 +79,28 @@ int main(int argc, char **argv) {
   }
 
   free(data);
+  ebur128_destroy(&s);
+  assert_int_eq(s == NULL, 1);
+
+  /* Error codes and edge cases */
+  s = ebur128_init(1, sample_rate, EBUR128_MODE_I);
+
+  assert_int_eq(ebur128_loudness_global(s, &val), EBUR128_SUCCESS);
+  assert_int_eq(val == -HUGE_VAL, 1);
+  assert_int_eq(ebur128_loudness_range(s, &val), EBUR128_ERROR_INVALID_MODE);
+
+  assert_int_eq(ebur128_set_channel(s, 0, EBUR128_DUAL_MONO), EBUR128_SUCCESS);
+  assert_int_eq(ebur128_set_channel(s, 1, EBUR128_LEFT), EBUR128_ERROR_INVALID_CHANNEL_INDEX);
+  assert_int_eq(ebur128_set_channel(s, 0, EBUR128_Bm045 + 1), EBUR128_ERROR_INVALID_CHANNEL_INDEX);
+  assert_int_eq(ebur128_set_channel(s, 0, -1), EBUR128_ERROR_INVALID_CHANNEL_INDEX);
+
+  assert_int_eq(ebur128_change_parameters(s, 1, sample_rate), EBUR128_ERROR_NO_CHANGE);
+  assert_int_eq(ebur128_change_parameters(s, 2, 44100), EBUR128_SUCCESS);
+  assert_int_eq(ebur128_set_channel(s, 0, EBUR128_DUAL_MONO), EBUR128_ERROR_INVALID_CHANNEL_INDEX);
+  assert_int_eq(s->channels, 2);
+  assert_int_eq((int) s->samplerate, 44100);
+  assert_int_eq(s->mode, EBUR128_MODE_I);
+
   ebur128_destroy(&s);
 
   return 0;
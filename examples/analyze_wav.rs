@@ -0,0 +1,34 @@
+// Analyzes a WAV file and prints its integrated loudness, loudness range and true peak.
+//
+// Run with `cargo run --example analyze_wav --features hound -- <file.wav>`.
+
+use ebur128::{wav, Mode};
+
+fn main() {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: analyze_wav <file.wav>");
+            std::process::exit(1);
+        }
+    };
+
+    let ebu =
+        wav::analyze_file(&path, Mode::I | Mode::LRA | Mode::TRUE_PEAK).unwrap_or_else(|err| {
+            eprintln!("Failed to analyze {}: {}", path, err);
+            std::process::exit(1);
+        });
+
+    match ebu.loudness_global() {
+        Ok(loudness) => println!("integrated: {:.1} LUFS", loudness),
+        Err(err) => println!("integrated: {}", err),
+    }
+    match ebu.loudness_range() {
+        Ok(range) => println!("range: {:.1} LU", range),
+        Err(err) => println!("range: {}", err),
+    }
+    println!(
+        "true peak: {:.1} dBTP",
+        ebu.true_peak_dbtp_max().expect("True peak is measured")
+    );
+}
@@ -0,0 +1,45 @@
+// Analyzes an in-memory stream of sine chunks and prints a measurement every second.
+//
+// Run with `cargo run --example stream --features futures`.
+
+use ebur128::stream::MeasurementStream;
+use ebur128::{EbuR128, Mode};
+
+use futures::executor::block_on;
+use futures::stream::{self, StreamExt};
+
+fn main() {
+    let rate = 48_000;
+    let channels = 2;
+
+    // 10 seconds of a 1kHz sine in chunks of 1000 samples, which don't always contain complete
+    // frames
+    let step = 2.0 * std::f32::consts::PI * 1_000.0 / rate as f32;
+    let samples = (0..10 * rate * channels)
+        .map(|i| 0.5 * f32::sin((i / channels) as f32 * step))
+        .collect::<Vec<_>>();
+    let chunks = stream::iter(
+        samples
+            .chunks(1_001)
+            .map(|c| c.to_vec())
+            .collect::<Vec<_>>(),
+    );
+
+    let ebu = EbuR128::new(
+        channels as u32,
+        rate as u32,
+        Mode::I | Mode::S | Mode::TRUE_PEAK,
+    )
+    .expect("Failed to create EbuR128");
+    let mut measurements = MeasurementStream::new(chunks, ebu, 10).expect("Invalid interval");
+
+    block_on(async {
+        while let Some(measurement) = measurements.next().await {
+            let measurement = measurement.expect("Failed to analyze samples");
+            println!(
+                "integrated: {:?} LUFS, short term: {:?} LUFS, true peak: {:?}",
+                measurement.integrated, measurement.shortterm, measurement.true_peak
+            );
+        }
+    });
+}
@@ -65,6 +65,17 @@ impl From<ebur128::Error> for i32 {
             ebur128::Error::NoMem => 1,
             ebur128::Error::InvalidMode => 2,
             ebur128::Error::InvalidChannelIndex => 3,
+            // Not part of the C API, closest match is an invalid mode for this rate
+            ebur128::Error::UnsupportedRate => 2,
+            // Handled by the functions returning it, -HUGE_VAL is returned instead
+            ebur128::Error::NotEnoughData => 2,
+            // Not returned as the C API never checks the input for non-finite samples
+            ebur128::Error::NonFiniteSample => 1,
+            // Not returned as the C API has no functions taking a duration
+            ebur128::Error::DurationMismatch => 2,
+            // libebur128 reports invalid parameters as out of memory
+            ebur128::Error::InvalidChannels => 1,
+            ebur128::Error::InvalidRate => 1,
         }
     }
 }
@@ -79,7 +90,16 @@ pub unsafe extern "C" fn ebur128_set_channel(
     let s = &mut *state;
     let e = &mut *s.internal;
 
-    match e.set_channel(channel_number, mem::transmute(value)) {
+    // The values are contiguous from EBUR128_UNUSED to EBUR128_Bm045, anything else would be an
+    // invalid enum value
+    if value < ebur128::Channel::Unused as i32 || value > ebur128::Channel::Bm045 as i32 {
+        return 3; // EBUR128_ERROR_INVALID_CHANNEL_INDEX
+    }
+
+    match e.set_channel(
+        channel_number,
+        mem::transmute::<u32, ebur128::Channel>(value as u32),
+    ) {
         Err(err) => err.into(),
         Ok(_) => 0,
     }
@@ -247,6 +267,10 @@ pub unsafe extern "C" fn ebur128_loudness_global(state: *mut State, out: *mut f6
     let e = &*s.internal;
 
     match e.loudness_global() {
+        Err(ebur128::Error::NotEnoughData) => {
+            *out = -f64::INFINITY;
+            0
+        }
         Err(err) => err.into(),
         Ok(val) => {
             *out = val;
@@ -267,6 +291,10 @@ pub unsafe extern "C" fn ebur128_loudness_global_multiple(
     let iter = s.iter().copied().map(|s: *mut State| &*(*s).internal);
 
     match ebur128::EbuR128::loudness_global_multiple(iter) {
+        Err(ebur128::Error::NotEnoughData) => {
+            *out = -f64::INFINITY;
+            0
+        }
         Err(err) => err.into(),
         Ok(val) => {
             *out = val;
@@ -331,6 +359,10 @@ pub unsafe extern "C" fn ebur128_loudness_range(state: *mut State, out: *mut f64
     let e = &*s.internal;
 
     match e.loudness_range() {
+        Err(ebur128::Error::NotEnoughData) => {
+            *out = 0.0;
+            0
+        }
         Err(err) => err.into(),
         Ok(val) => {
             *out = val;
@@ -351,6 +383,10 @@ pub unsafe extern "C" fn ebur128_loudness_range_multiple(
     let iter = s.iter().copied().map(|s: *mut State| &*(*s).internal);
 
     match ebur128::EbuR128::loudness_range_multiple(iter) {
+        Err(ebur128::Error::NotEnoughData) => {
+            *out = 0.0;
+            0
+        }
         Err(err) => err.into(),
         Ok(val) => {
             *out = val;
@@ -0,0 +1,201 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Measuring the loudness of a mono downmix of multichannel input.
+
+use crate::ebur128::{EbuR128, Error, Mode};
+use crate::utils::{Sample, Samples};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// [`EbuR128`] instance measuring a mono downmix of the input.
+///
+/// Each input frame is downmixed to a single sample before the frequency weighting by
+/// multiplying every channel with its coefficient of the downmix matrix and summing up the
+/// results, e.g. `[0.5, 0.5]` for the average of a stereo programme. All measurements of the
+/// wrapped instance then reflect the mono fold-down, including the peaks.
+///
+/// This is not how EBU R 128 measures multichannel programmes, which sums up the energy of the
+/// individual channels instead, so correlated and uncorrelated channels give different results.
+/// It is useful for checking the mono compatibility of a programme.
+#[derive(Debug, Clone)]
+pub struct MonoDownmix {
+    ebur128: EbuR128,
+    matrix: Box<[f64]>,
+    buffer: Vec<f64>,
+}
+
+impl MonoDownmix {
+    /// Create a new instance downmixing input with one channel per coefficient of `matrix`.
+    ///
+    /// Returns `Error::InvalidChannelIndex` if the matrix is empty and `Error::InvalidMode` if any
+    /// of its coefficients is not finite. Otherwise the errors are the same as for
+    /// [`EbuR128::new`](struct.EbuR128.html#method.new) with a single channel.
+    pub fn new(matrix: &[f64], rate: u32, mode: Mode) -> Result<Self, Error> {
+        if matrix.is_empty() {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        if matrix.iter().any(|c| !c.is_finite()) {
+            return Err(Error::InvalidMode);
+        }
+
+        Ok(MonoDownmix {
+            ebur128: EbuR128::new(1, rate, mode)?,
+            matrix: matrix.into(),
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Get the downmix coefficient of each input channel.
+    pub fn matrix(&self) -> &[f64] {
+        &self.matrix
+    }
+
+    /// Get the number of input channels.
+    pub fn channels(&self) -> u32 {
+        self.matrix.len() as u32
+    }
+
+    /// Add interleaved frames to be downmixed and processed.
+    ///
+    /// Returns `Error::NoMem` if the number of samples is not a multiple of the number of input
+    /// channels, like [`EbuR128::add_frames`](struct.EbuR128.html#method.add_frames).
+    pub fn add_frames<T: Sample>(&mut self, frames: &[T]) -> Result<(), Error> {
+        self.add_samples(crate::Interleaved::new(frames, self.matrix.len())?)
+    }
+
+    /// Add planar frames to be downmixed and processed.
+    ///
+    /// Returns `Error::InvalidChannelIndex` if the number of channels doesn't match the number of
+    /// input channels.
+    pub fn add_frames_planar<T: Sample>(&mut self, frames: &[&[T]]) -> Result<(), Error> {
+        self.add_samples(crate::Planar::new(frames)?)
+    }
+
+    fn add_samples<'a, T: Sample + 'a, S: Samples<'a, T>>(&mut self, src: S) -> Result<(), Error> {
+        if src.channels() != self.matrix.len() {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        // The buffer only ever grows so that it doesn't have to be reallocated for following
+        // calls with the same number of frames
+        let frames = src.frames();
+        if self.buffer.len() < frames {
+            self.buffer.resize(frames, 0.0);
+        }
+
+        let buffer = &mut self.buffer[..frames];
+        buffer.fill(0.0);
+        for (c, coeff) in self.matrix.iter().enumerate() {
+            src.foreach_sample_zipped(c, buffer.iter_mut(), |s, out| {
+                *out += s.to_sample::<f64>() * coeff;
+            });
+        }
+
+        self.ebur128.add_frames_f64(buffer)
+    }
+
+    /// Get the wrapped [`EbuR128`] instance for getting the measurements.
+    pub fn ebur128(&self) -> &EbuR128 {
+        &self.ebur128
+    }
+
+    /// Get the wrapped [`EbuR128`] instance mutably, e.g. for resetting it or changing its
+    /// configuration. Frames added to it directly are not downmixed and have to be mono.
+    pub fn ebur128_mut(&mut self) -> &mut EbuR128 {
+        &mut self.ebur128
+    }
+
+    /// Get the wrapped [`EbuR128`] instance back.
+    pub fn into_inner(self) -> EbuR128 {
+        self.ebur128
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downmix_stereo() {
+        // Two sines with different frequencies
+        let data = (0..48_000 * 3 * 2)
+            .map(|i| {
+                let frame = (i / 2) as f32;
+                if i % 2 == 0 {
+                    f32::sin(frame * 0.05) * 0.5
+                } else {
+                    f32::sin(frame * 0.13) * 0.25
+                }
+            })
+            .collect::<Vec<_>>();
+        let matrix = [0.6, 0.4];
+        let mode = Mode::I | Mode::LRA | Mode::TRUE_PEAK;
+
+        let mono = data
+            .chunks_exact(2)
+            .map(|f| 0.0 + f64::from(f[0]) * matrix[0] + f64::from(f[1]) * matrix[1])
+            .collect::<Vec<_>>();
+        let mut expected = EbuR128::new(1, 48_000, mode).unwrap();
+        expected.add_frames_f64(&mono).unwrap();
+
+        let mut downmix = MonoDownmix::new(&matrix, 48_000, mode).unwrap();
+        assert_eq!(downmix.channels(), 2);
+        downmix.add_frames(&data[..48_000 * 2]).unwrap();
+        downmix.add_frames(&data[48_000 * 2..]).unwrap();
+        assert_eq!(
+            downmix.ebur128().loudness_global(),
+            expected.loudness_global()
+        );
+        assert_eq!(
+            downmix.ebur128().loudness_range(),
+            expected.loudness_range()
+        );
+        assert_eq!(downmix.ebur128().true_peak(0), expected.true_peak(0));
+
+        let (left, right): (Vec<_>, Vec<_>) = data.chunks_exact(2).map(|f| (f[0], f[1])).unzip();
+        let mut downmix_planar = MonoDownmix::new(&matrix, 48_000, mode).unwrap();
+        downmix_planar
+            .add_frames_planar(&[&left[..], &right[..]])
+            .unwrap();
+        assert_eq!(
+            downmix_planar.ebur128().loudness_global(),
+            expected.loudness_global()
+        );
+
+        // Errors
+        assert_eq!(
+            MonoDownmix::new(&[], 48_000, mode).err(),
+            Some(Error::InvalidChannelIndex)
+        );
+        assert_eq!(
+            MonoDownmix::new(&[0.5, f64::NAN], 48_000, mode).err(),
+            Some(Error::InvalidMode)
+        );
+        assert_eq!(downmix.add_frames(&data[..3]), Err(Error::NoMem));
+        assert_eq!(
+            downmix.add_frames_planar(&[&left[..]]),
+            Err(Error::InvalidChannelIndex)
+        );
+    }
+}
@@ -20,12 +20,20 @@
 // THE SOFTWARE.
 
 use crate::energy_to_loudness;
+use crate::math;
 use crate::utils::Sample;
 
 use bitflags::bitflags;
 
-use std::error;
-use std::fmt;
+#[cfg(feature = "serde")]
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+#[cfg(feature = "serde")]
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
 
 /// Error values for [`EbuR128`](struct.EbuR128.html) functions.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -36,9 +44,22 @@ pub enum Error {
     InvalidMode,
     /// Invalid channel index passed
     InvalidChannelIndex,
+    /// Sample rate not supported by the selected mode
+    UnsupportedRate,
+    /// Not enough data measured yet to calculate the requested value
+    NotEnoughData,
+    /// Input contained a NaN or infinite sample
+    NonFiniteSample,
+    /// Number of frames doesn't match the declared duration
+    DurationMismatch,
+    /// Number of channels is zero or higher than supported
+    InvalidChannels,
+    /// Sample rate is lower or higher than supported
+    InvalidRate,
 }
 
-impl error::Error for Error {}
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -46,6 +67,12 @@ impl fmt::Display for Error {
             Error::NoMem => write!(f, "NoMem"),
             Error::InvalidMode => write!(f, "Invalid Mode"),
             Error::InvalidChannelIndex => write!(f, "Invalid Channel Index"),
+            Error::UnsupportedRate => write!(f, "Unsupported Rate"),
+            Error::NotEnoughData => write!(f, "Not Enough Data"),
+            Error::NonFiniteSample => write!(f, "Non-Finite Sample"),
+            Error::DurationMismatch => write!(f, "Duration Mismatch"),
+            Error::InvalidChannels => write!(f, "Invalid Channels"),
+            Error::InvalidRate => write!(f, "Invalid Rate"),
         }
     }
 }
@@ -79,7 +106,14 @@ bitflags! {
 /// Use these values when setting the channel map with
 /// [`EbuR128::set_channel`](struct.EbuR128.html#method.set_channel).
 /// See definitions in ITU R-REC-BS 1770-4.
+///
+/// The positions cover all loudspeaker layouts of ITU-R BS.2051, including 7.1.4 and 22.2, where
+/// `Left`, `Right`, `LeftSurround` and `RightSurround` are M+030, M-030, M+110 and M-110. Only
+/// the surround positions `LeftSurround`, `RightSurround`, `Mp060`, `Mm060`, `Mp090` and `Mm090`
+/// are weighted by +1.5dB. All other positions, including the height (`U`), top (`T`) and
+/// bottom (`B`) channels, are weighted by 0dB, and all `Lfe` channels are excluded.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u32)]
 pub enum Channel {
     /// unused channel (for example LFE channel)
@@ -122,7 +156,7 @@ pub enum Channel {
     Um030,
     /// itu U+045
     Up045,
-    /// itu U-030
+    /// itu U-045
     Um045,
     /// itu U+090
     Up090,
@@ -146,9 +180,516 @@ pub enum Channel {
     Bp045,
     /// itu B-045
     Bm045,
+    /// low frequency effects channel, not included in the measurement like `Unused`
+    Lfe,
+}
+
+impl Channel {
+    /// Whether the channel is not included in the measurement.
+    #[inline]
+    pub(crate) fn is_excluded(self) -> bool {
+        matches!(self, Channel::Unused | Channel::Lfe)
+    }
+}
+
+/// Frequency weighting applied before measuring the loudness.
+///
+/// Use these values in [`EbuR128::set_weighting`](struct.EbuR128.html#method.set_weighting).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Weighting {
+    /// K-weighting as specified in ITU-R BS.1770. This is the default and the only weighting
+    /// that gives loudness values according to EBU R128.
+    #[default]
+    K,
+    /// A-weighting as specified in IEC 61672-1.
+    A,
+    /// C-weighting as specified in IEC 61672-1.
+    C,
+}
+
+/// Numeric precision of the frequency weighting and the filtered audio data.
+///
+/// Use these values in [`EbuR128::set_precision`](struct.EbuR128.html#method.set_precision).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Precision {
+    /// Double precision. This is the default and gives the same results as libebur128.
+    #[default]
+    F64,
+    /// Single precision, which is faster and needs half the memory for the audio data at the
+    /// cost of slightly less accurate loudness values.
+    F32,
+    /// Fixed point with 32 bit samples and 64 bit accumulators for targets without a floating
+    /// point unit. This is slightly less accurate than double precision.
+    Fixed,
+}
+
+/// Quality of the oversampling for the true peak measurement.
+///
+/// Use these values in
+/// [`EbuR128::set_true_peak_quality`](struct.EbuR128.html#method.set_true_peak_quality). The
+/// interpolation filter has 12 taps per phase, or 24 taps per phase for 2x oversampling. The
+/// true peak is under-read most for the highest frequencies, at most by `20 * log10(cos(pi * f /
+/// (factor * rate)))` dB for a sine of frequency `f` plus the ripple of the filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TruePeakQuality {
+    /// 4x oversampling below 96kHz and 2x below 192kHz as recommended by ITU-R BS.1770. This is
+    /// the default and gives the same results as libebur128.
+    #[default]
+    Standard,
+    /// Half the oversampling of `Standard`, i.e. 2x below 96kHz and no oversampling above, which
+    /// is about twice as fast. For 48kHz the true peak of a sine is under-read by up to 0.5dB at
+    /// 12kHz and 1.1dB at 16kHz, compared to 0.1dB with `Standard`.
+    Fast,
+    /// Twice the oversampling of `Standard`, i.e. 8x below 96kHz, 4x below 192kHz and 2x up to
+    /// 384kHz. This is about twice as slow and under-reads the true peak of a sine by about
+    /// 0.1dB at most up to two thirds of the Nyquist frequency.
+    Accurate,
+}
+
+/// Filtered audio data in the configured precision (used as ring buffer).
+///
+/// The channels are stored one after another.
+#[derive(Debug, Clone, PartialEq)]
+enum AudioData {
+    F64(Box<[f64]>),
+    F32(Box<[f32]>),
+    /// Fixed point with the fractional bits of the fixed point filter.
+    I32(Box<[i32]>),
+}
+
+/// Allocate a zero initialized buffer, returning `Error::NoMem` instead of panicking or aborting
+/// if the size in bytes overflows or there is not enough memory.
+fn allocate_zeroed<F: Clone + Default>(len: usize) -> Result<Box<[F]>, Error> {
+    let mut data = Vec::new();
+    data.try_reserve_exact(len).map_err(|_| Error::NoMem)?;
+    data.resize(len, F::default());
+
+    Ok(data.into_boxed_slice())
+}
+
+/// Convert all samples of `old` to the numeric type of `new`.
+fn convert_samples<A: crate::filter::Float, B: crate::filter::Float>(old: &[A], new: &mut [B]) {
+    for (new, old) in Iterator::zip(new.iter_mut(), old.iter()) {
+        *new = B::from_f64(old.to_f64());
+    }
+}
+
+/// Copy the `frames` most recent frames before `old_index` of each channel in `old` to the
+/// frames before `new_index` in `new`, wrapping around at the beginning of the buffers.
+fn copy_recent<F: Copy>(
+    old: &[F],
+    old_index: usize,
+    new: &mut [F],
+    new_index: usize,
+    channels: usize,
+    frames: usize,
+) {
+    let old_frames = old.len() / channels;
+    let new_frames = new.len() / channels;
+    for (old, new) in Iterator::zip(
+        old.chunks_exact(old_frames),
+        new.chunks_exact_mut(new_frames),
+    ) {
+        for k in 1..=frames {
+            new[(new_index + new_frames - k) % new_frames] =
+                old[(old_index + old_frames - k) % old_frames];
+        }
+    }
+}
+
+/// Copy `frames` frames starting at `audio_data_index` of each measured channel to `filtered`,
+/// starting at `index`. Excluded channels are filled with silence.
+fn copy_channels<F: crate::filter::Float>(
+    audio_data: &[F],
+    audio_data_index: usize,
+    channel_map: &[Channel],
+    filtered: &mut [f64],
+    index: usize,
+    frames: usize,
+) {
+    let audio_data_stride = audio_data.len() / channel_map.len();
+    let filtered_stride = filtered.len() / channel_map.len();
+
+    for ((channel, audio_data), filtered) in Iterator::zip(
+        channel_map.iter(),
+        audio_data.chunks_exact(audio_data_stride),
+    )
+    .zip(filtered.chunks_exact_mut(filtered_stride))
+    {
+        let filtered = &mut filtered[index..(index + frames)];
+
+        if channel.is_excluded() {
+            filtered.fill(0.0);
+        } else {
+            for (filtered, v) in Iterator::zip(
+                filtered.iter_mut(),
+                &audio_data[audio_data_index..(audio_data_index + frames)],
+            ) {
+                *filtered = v.to_f64();
+            }
+        }
+    }
+}
+
+impl AudioData {
+    fn len(&self) -> usize {
+        match self {
+            AudioData::F64(data) => data.len(),
+            AudioData::F32(data) => data.len(),
+            AudioData::I32(data) => data.len(),
+        }
+    }
+
+    fn precision(&self) -> Precision {
+        match self {
+            AudioData::F64(_) => Precision::F64,
+            AudioData::F32(_) => Precision::F32,
+            AudioData::I32(_) => Precision::Fixed,
+        }
+    }
+
+    /// Allocate a buffer of the same size with the given precision and convert the data to it.
+    fn convert(&self, precision: Precision) -> Result<AudioData, Error> {
+        let mut audio_data = match precision {
+            Precision::F64 => AudioData::F64(allocate_zeroed(self.len())?),
+            Precision::F32 => AudioData::F32(allocate_zeroed(self.len())?),
+            Precision::Fixed => AudioData::I32(allocate_zeroed(self.len())?),
+        };
+
+        match (self, &mut audio_data) {
+            (AudioData::F64(old), AudioData::F32(new)) => {
+                for (new, old) in Iterator::zip(new.iter_mut(), old.iter()) {
+                    *new = *old as f32;
+                }
+            }
+            (AudioData::F32(old), AudioData::F64(new)) => {
+                for (new, old) in Iterator::zip(new.iter_mut(), old.iter()) {
+                    *new = *old as f64;
+                }
+            }
+            (AudioData::F64(old), AudioData::F64(new)) => new.copy_from_slice(old),
+            (AudioData::F32(old), AudioData::F32(new)) => new.copy_from_slice(old),
+            (AudioData::I32(old), AudioData::I32(new)) => new.copy_from_slice(old),
+            (AudioData::F64(old), AudioData::I32(new)) => convert_samples(old, new),
+            (AudioData::F32(old), AudioData::I32(new)) => convert_samples(old, new),
+            (AudioData::I32(old), AudioData::F64(new)) => convert_samples(old, new),
+            (AudioData::I32(old), AudioData::F32(new)) => convert_samples(old, new),
+        }
+
+        Ok(audio_data)
+    }
+
+    fn reset(&mut self) {
+        match self {
+            AudioData::F64(data) => data.fill(0.0),
+            AudioData::F32(data) => data.fill(0.0),
+            AudioData::I32(data) => data.fill(0),
+        }
+    }
+
+    /// Copy the `frames` most recent frames before `index` to the frames before `new_index` in
+    /// `new`, which must have the same precision.
+    fn copy_recent(
+        &self,
+        index: usize,
+        new: &mut AudioData,
+        new_index: usize,
+        channels: usize,
+        frames: usize,
+    ) {
+        match (self, new) {
+            (AudioData::F64(old), AudioData::F64(new)) => {
+                copy_recent(old, index, new, new_index, channels, frames)
+            }
+            (AudioData::F32(old), AudioData::F32(new)) => {
+                copy_recent(old, index, new, new_index, channels, frames)
+            }
+            (AudioData::I32(old), AudioData::I32(new)) => {
+                copy_recent(old, index, new, new_index, channels, frames)
+            }
+            _ => unreachable!("different precision"),
+        }
+    }
+
+    /// Filter `src` with `filter` and store the result starting at `index`, and write the
+    /// oversampled signal of the true peak measurement to `oversampled` if given.
+    fn process<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        filter: &mut crate::filter::Filter,
+        src: S,
+        index: usize,
+        channel_map: &[Channel],
+        oversampled: Option<(&mut [f32], usize)>,
+    ) -> Result<(), Error> {
+        match self {
+            AudioData::F64(data) => filter.process(src, data, index, channel_map, oversampled),
+            AudioData::F32(data) => filter.process(src, data, index, channel_map, oversampled),
+            AudioData::I32(data) => filter.process(src, data, index, channel_map, oversampled),
+        }
+    }
+
+    fn calc_gating_block(
+        &self,
+        frames: usize,
+        index: usize,
+        channel_map: &[Channel],
+        channel_gains: &[f64],
+    ) -> f64 {
+        match self {
+            AudioData::F64(data) => crate::filter::Filter::calc_gating_block(
+                frames,
+                data,
+                index,
+                channel_map,
+                channel_gains,
+            ),
+            AudioData::F32(data) => crate::filter::Filter::calc_gating_block(
+                frames,
+                data,
+                index,
+                channel_map,
+                channel_gains,
+            ),
+            AudioData::I32(data) => crate::filter::Filter::calc_gating_block(
+                frames,
+                data,
+                index,
+                channel_map,
+                channel_gains,
+            ),
+        }
+    }
+
+    fn calc_gating_block_channels(
+        &self,
+        frames: usize,
+        index: usize,
+        channel_map: &[Channel],
+        channel_gains: &[f64],
+        energies: &mut [f64],
+    ) {
+        match self {
+            AudioData::F64(data) => crate::filter::Filter::calc_gating_block_channels(
+                frames,
+                data,
+                index,
+                channel_map,
+                channel_gains,
+                energies,
+            ),
+            AudioData::F32(data) => crate::filter::Filter::calc_gating_block_channels(
+                frames,
+                data,
+                index,
+                channel_map,
+                channel_gains,
+                energies,
+            ),
+            AudioData::I32(data) => crate::filter::Filter::calc_gating_block_channels(
+                frames,
+                data,
+                index,
+                channel_map,
+                channel_gains,
+                energies,
+            ),
+        }
+    }
+
+    fn copy_channels(
+        &self,
+        audio_data_index: usize,
+        channel_map: &[Channel],
+        filtered: &mut [f64],
+        index: usize,
+        frames: usize,
+    ) {
+        match self {
+            AudioData::F64(data) => {
+                copy_channels(data, audio_data_index, channel_map, filtered, index, frames)
+            }
+            AudioData::F32(data) => {
+                copy_channels(data, audio_data_index, channel_map, filtered, index, frames)
+            }
+            AudioData::I32(data) => {
+                copy_channels(data, audio_data_index, channel_map, filtered, index, frames)
+            }
+        }
+    }
+
+    /// Get the data in double precision for serialization.
+    #[cfg(feature = "serde")]
+    fn to_f64(&self) -> Cow<'_, [f64]> {
+        match self {
+            AudioData::F64(data) => Cow::Borrowed(data),
+            AudioData::F32(data) => Cow::Owned(data.iter().map(|v| *v as f64).collect()),
+            AudioData::I32(data) => {
+                use crate::filter::Float;
+
+                Cow::Owned(data.iter().map(|v| v.to_f64()).collect())
+            }
+        }
+    }
+
+    /// Restore the data from a deserialized state of the same size.
+    #[cfg(feature = "serde")]
+    fn copy_from_f64(&mut self, state: &[f64]) {
+        match self {
+            AudioData::F64(data) => data.copy_from_slice(state),
+            AudioData::F32(data) => {
+                for (v, state) in Iterator::zip(data.iter_mut(), state.iter()) {
+                    *v = *state as f32;
+                }
+            }
+            AudioData::I32(data) => convert_samples(state, data),
+        }
+    }
+}
+
+/// Handling of NaN and infinite input samples.
+///
+/// Use these values in
+/// [`EbuR128::set_non_finite_handling`](struct.EbuR128.html#method.set_non_finite_handling).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NonFiniteHandling {
+    /// Don't check the input. This is the default and has no overhead, but a single non-finite
+    /// sample makes all following loudness values NaN.
+    #[default]
+    Propagate,
+    /// Return `Error::NonFiniteSample` without processing any of the frames if the input
+    /// contains a non-finite sample.
+    Reject,
+    /// Replace non-finite samples with silence and count them.
+    Skip,
+}
+
+/// Format of raw interleaved samples.
+///
+/// Use these values in
+/// [`EbuR128::add_frames_raw`](struct.EbuR128.html#method.add_frames_raw). `Le` formats are
+/// little endian and `Be` formats big endian. Integer samples are scaled like the corresponding
+/// typed `add_frames` variants and floating point samples have a full scale of 1.0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SampleFormat {
+    /// Unsigned 8 bit, silence is 128.
+    U8,
+    /// Signed 8 bit.
+    S8,
+    /// Signed 16 bit, little endian.
+    S16Le,
+    /// Signed 16 bit, big endian.
+    S16Be,
+    /// Signed 24 bit packed into 3 bytes, little endian.
+    S24Le,
+    /// Signed 24 bit packed into 3 bytes, big endian.
+    S24Be,
+    /// Signed 32 bit, little endian.
+    S32Le,
+    /// Signed 32 bit, big endian.
+    S32Be,
+    /// 32 bit floating point, little endian.
+    F32Le,
+    /// 32 bit floating point, big endian.
+    F32Be,
+    /// 64 bit floating point, little endian.
+    F64Le,
+    /// 64 bit floating point, big endian.
+    F64Be,
+}
+
+impl SampleFormat {
+    /// Size of a single sample in bytes.
+    pub fn sample_size(self) -> usize {
+        match self {
+            SampleFormat::U8 | SampleFormat::S8 => 1,
+            SampleFormat::S16Le | SampleFormat::S16Be => 2,
+            SampleFormat::S24Le | SampleFormat::S24Be => 3,
+            SampleFormat::S32Le | SampleFormat::S32Be => 4,
+            SampleFormat::F32Le | SampleFormat::F32Be => 4,
+            SampleFormat::F64Le | SampleFormat::F64Be => 8,
+        }
+    }
+}
+
+/// All measurement results at one point in time.
+///
+/// Returned by [`EbuR128::measurement`](struct.EbuR128.html#method.measurement). Each value is
+/// `None` if the mode it requires is not enabled, or if there is no value because not enough
+/// data was measured yet or all of it was below the gates. The latter are reported as
+/// `-f64::INFINITY` or `Error::NotEnoughData` by the individual getters.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Measurement {
+    /// Integrated loudness in LUFS, requires `Mode::I`.
+    pub integrated: Option<f64>,
+    /// Loudness range in LU, requires `Mode::LRA`.
+    pub range: Option<f64>,
+    /// Momentary loudness (last 400ms) in LUFS, requires `Mode::M`.
+    pub momentary: Option<f64>,
+    /// Short term loudness (last 3s) in LUFS, requires `Mode::S`.
+    pub shortterm: Option<f64>,
+    /// Maximum momentary loudness in LUFS, requires `Mode::M`.
+    pub momentary_max: Option<f64>,
+    /// Maximum short term loudness in LUFS, requires `Mode::S` and
+    /// [`EbuR128::enable_shortterm_max`](struct.EbuR128.html#method.enable_shortterm_max).
+    pub shortterm_max: Option<f64>,
+    /// Maximum sample peak per channel, requires `Mode::SAMPLE_PEAK`.
+    pub sample_peak: Option<Vec<f64>>,
+    /// Maximum true peak per channel, requires `Mode::TRUE_PEAK`.
+    pub true_peak: Option<Vec<f64>>,
+}
+
+/// Snapshot of the measurement state of an [`EbuR128`](struct.EbuR128.html).
+///
+/// Created by [`EbuR128::snapshot`](struct.EbuR128.html#method.snapshot) and restored by
+/// [`EbuR128::restore`](struct.EbuR128.html#method.restore). It only contains the state that
+/// changes while measuring, not the configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EbuR128Snapshot {
+    /// Configuration the state belongs to, checked when restoring.
+    mode: Mode,
+    rate: u32,
+    channels: u32,
+    window: usize,
+    history: usize,
+    block_hop: u32,
+    weighting: Weighting,
+
+    audio_data: AudioData,
+    audio_data_index: usize,
+    needed_frames: usize,
+    filter: crate::filter::FilterState,
+    block_energy_history: crate::history::History,
+    short_term_block_energy_history: crate::history::History,
+    short_term_frame_counter: usize,
+    momentary_max_energy: f64,
+    shortterm_max_energy: f64,
+    momentary_timeseries: Vec<f64>,
+    shortterm_timeseries: Vec<f64>,
+    shortterm_timeseries_timed: Vec<(core::time::Duration, f64)>,
+    block_energies: Vec<f64>,
+    block_timestamps: Vec<u64>,
+    sample_peak: Box<[f64]>,
+    true_peak: Box<[f64]>,
+    true_peak_overs: Box<[u64]>,
+    non_finite_samples: u64,
+    gating_blocks: u64,
+    short_term_blocks: u64,
+    integrated_blocks: u64,
+    last_block_energy: f64,
+    buffered_frames: usize,
+    frames_processed: u64,
+    channel_block_energies: VecDeque<f64>,
+    pending_samples: Vec<f32>,
 }
 
 /// EBU R128 loudness analyzer.
+#[derive(Clone)]
 pub struct EbuR128 {
     /// The current mode.
     mode: Mode,
@@ -158,7 +699,7 @@ pub struct EbuR128 {
     channels: u32,
 
     /// Filtered audio data (used as ring buffer).
-    audio_data: Box<[f64]>,
+    audio_data: AudioData,
     /// Current index for audio_data.
     audio_data_index: usize,
 
@@ -169,9 +710,15 @@ pub struct EbuR128 {
 
     /// The channel map. Has as many elements as there are channels.
     channel_map: Box<[Channel]>,
+    /// Linear gain of each channel. Has as many elements as there are channels.
+    channel_gains: Box<[f64]>,
 
     /// How many samples fit in 100ms (rounded).
     samples_in_100ms: usize,
+    /// Hop between the start of two gating blocks in ms.
+    block_hop: u32,
+    /// How many samples fit in the hop (rounded).
+    samples_per_hop: usize,
 
     /// Filter.
     filter: crate::filter::Filter,
@@ -183,15 +730,85 @@ pub struct EbuR128 {
     short_term_block_energy_history: crate::history::History,
     short_term_frame_counter: usize,
 
+    /// Maximum energy of all momentary blocks.
+    momentary_max_energy: f64,
+    /// Maximum energy of all short term blocks.
+    shortterm_max_energy: f64,
+    /// Whether the maximum short term loudness is tracked.
+    shortterm_max_enabled: bool,
+
+    /// Whether the momentary and short term loudness of every block is recorded.
+    timeseries: bool,
+    /// Recorded momentary loudness, one value per block.
+    momentary_timeseries: Vec<f64>,
+    /// Recorded short term loudness, one value per block.
+    shortterm_timeseries: Vec<f64>,
+    /// Whether the short term loudness of the blocks of the loudness range is recorded.
+    shortterm_timeseries_timed_enabled: bool,
+    /// Recorded short term loudness of the blocks of the loudness range with the time of their
+    /// end.
+    shortterm_timeseries_timed: Vec<(core::time::Duration, f64)>,
+    /// Whether the energy of every gating block is recorded.
+    block_energies_enabled: bool,
+    /// Recorded energy of the gating blocks.
+    block_energies: Vec<f64>,
+    /// Number of processed frames at the end of each recorded gating block.
+    block_timestamps: Vec<u64>,
+
     /// Maximum sample peak, one per channel.
     sample_peak: Box<[f64]>,
 
     /// Maximum true peak, one per channel.
     true_peak: Box<[f64]>,
+    /// Whether the interpolated samples above the threshold are counted.
+    true_peak_overs_enabled: bool,
+    /// Linear threshold for counting the overs of the true peak measurement.
+    true_peak_overs_threshold: f64,
+    /// Number of interpolated samples above the threshold, one per channel.
+    true_peak_overs: Box<[u64]>,
 
     /// The maximum window duration in ms.
     window: usize,
     history: usize,
+    /// Resolution of the histogram in bins per LU.
+    histogram_resolution: u32,
+
+    /// Whether the energy is halved for dual mono input.
+    dual_mono_compensation: bool,
+    /// Handling of non-finite input samples.
+    non_finite_handling: NonFiniteHandling,
+    /// Number of non-finite samples replaced with silence.
+    non_finite_samples: u64,
+    /// Number of completed gating blocks.
+    gating_blocks: u64,
+    /// Number of completed short term blocks for the loudness range.
+    short_term_blocks: u64,
+    /// Number of gating blocks measured for the integrated loudness.
+    integrated_blocks: u64,
+    /// Energy of the most recently completed gating block.
+    last_block_energy: f64,
+    /// Number of frames of measured audio in the audio buffer, at most its length.
+    buffered_frames: usize,
+    /// Number of frames added for the measurement.
+    frames_processed: u64,
+    /// Whether the energy of each channel is stored for every gating block.
+    channel_history: bool,
+    /// Energy of each channel for the gating blocks in the history, `channels` values per block.
+    channel_block_energies: VecDeque<f64>,
+    /// Maximum number of frames processed at once.
+    chunk_size: usize,
+    /// Oversampling quality of the true peak measurement.
+    true_peak_quality: TruePeakQuality,
+    /// Reference loudness in LUFS that corresponds to 0 LU for the relative loudness.
+    reference_loudness: f64,
+    /// Gates used for the integrated loudness.
+    gating: crate::history::Gating,
+    /// Samples of an incomplete frame from the end of the previous
+    /// [`EbuR128::add_frames_iter`](struct.EbuR128.html#method.add_frames_iter) call.
+    pending_samples: Vec<f32>,
+
+    /// Scratch buffer for interleaved input samples, reused between calls.
+    scratch: Vec<f64>,
 }
 
 impl fmt::Debug for EbuR128 {
@@ -204,7 +821,10 @@ impl fmt::Debug for EbuR128 {
             .field("audio_data_index", &self.audio_data_index)
             .field("needed_frames", &self.needed_frames)
             .field("channel_map", &self.channel_map)
+            .field("channel_gains", &self.channel_gains)
             .field("samples_in_100ms", &self.samples_in_100ms)
+            .field("block_hop", &self.block_hop)
+            .field("samples_per_hop", &self.samples_per_hop)
             .field("filter", &self.filter)
             .field("block_energy_history", &self.block_energy_history)
             .field(
@@ -212,14 +832,337 @@ impl fmt::Debug for EbuR128 {
                 &self.short_term_block_energy_history,
             )
             .field("short_term_frame_counter", &self.short_term_frame_counter)
+            .field("momentary_max_energy", &self.momentary_max_energy)
+            .field("shortterm_max_energy", &self.shortterm_max_energy)
+            .field("shortterm_max_enabled", &self.shortterm_max_enabled)
+            .field("timeseries", &self.timeseries)
+            .field("momentary_timeseries", &self.momentary_timeseries)
+            .field("shortterm_timeseries", &self.shortterm_timeseries)
+            .field(
+                "shortterm_timeseries_timed_enabled",
+                &self.shortterm_timeseries_timed_enabled,
+            )
+            .field(
+                "shortterm_timeseries_timed",
+                &self.shortterm_timeseries_timed,
+            )
+            .field("block_energies_enabled", &self.block_energies_enabled)
+            .field("block_energies", &self.block_energies)
+            .field("block_timestamps", &self.block_timestamps)
             .field("sample_peak", &self.sample_peak)
             .field("true_peak", &self.true_peak)
+            .field("true_peak_overs_enabled", &self.true_peak_overs_enabled)
+            .field("true_peak_overs_threshold", &self.true_peak_overs_threshold)
+            .field("true_peak_overs", &self.true_peak_overs)
             .field("window", &self.window)
             .field("history", &self.history)
+            .field("histogram_resolution", &self.histogram_resolution)
+            .field("dual_mono_compensation", &self.dual_mono_compensation)
+            .field("non_finite_handling", &self.non_finite_handling)
+            .field("non_finite_samples", &self.non_finite_samples)
+            .field("gating_blocks", &self.gating_blocks)
+            .field("short_term_blocks", &self.short_term_blocks)
+            .field("integrated_blocks", &self.integrated_blocks)
+            .field("last_block_energy", &self.last_block_energy)
+            .field("buffered_frames", &self.buffered_frames)
+            .field("frames_processed", &self.frames_processed)
+            .field("channel_history", &self.channel_history)
+            .field("channel_block_energies", &self.channel_block_energies)
+            .field("chunk_size", &self.chunk_size)
+            .field("true_peak_quality", &self.true_peak_quality)
+            .field("reference_loudness", &self.reference_loudness)
+            .field("gating", &self.gating)
+            .field("pending_samples", &self.pending_samples)
             .finish()
     }
 }
 
+/// Compares the configuration and the complete measurement state: the buffered audio data, the
+/// filter and interpolator states, the energy histories, the peaks and all counters. Only the
+/// scratch buffer is ignored.
+///
+/// Floating point values are compared exactly, so two instances are only equal if they processed
+/// the same input with the same configuration, or one is a clone or a deserialized copy of the
+/// other. Non-finite samples that were processed with [`NonFiniteHandling::Propagate`] can make an
+/// instance unequal to itself.
+impl PartialEq for EbuR128 {
+    fn eq(&self, other: &Self) -> bool {
+        self.mode == other.mode
+            && self.rate == other.rate
+            && self.channels == other.channels
+            && self.audio_data == other.audio_data
+            && self.audio_data_index == other.audio_data_index
+            && self.needed_frames == other.needed_frames
+            && self.channel_map == other.channel_map
+            && self.channel_gains == other.channel_gains
+            && self.block_hop == other.block_hop
+            && self.samples_per_hop == other.samples_per_hop
+            && self.filter == other.filter
+            && self.block_energy_history == other.block_energy_history
+            && self.short_term_block_energy_history == other.short_term_block_energy_history
+            && self.short_term_frame_counter == other.short_term_frame_counter
+            && self.momentary_max_energy == other.momentary_max_energy
+            && self.shortterm_max_energy == other.shortterm_max_energy
+            && self.shortterm_max_enabled == other.shortterm_max_enabled
+            && self.timeseries == other.timeseries
+            && self.momentary_timeseries == other.momentary_timeseries
+            && self.shortterm_timeseries == other.shortterm_timeseries
+            && self.shortterm_timeseries_timed_enabled == other.shortterm_timeseries_timed_enabled
+            && self.shortterm_timeseries_timed == other.shortterm_timeseries_timed
+            && self.block_energies_enabled == other.block_energies_enabled
+            && self.block_energies == other.block_energies
+            && self.block_timestamps == other.block_timestamps
+            && self.sample_peak == other.sample_peak
+            && self.true_peak == other.true_peak
+            && self.true_peak_overs_enabled == other.true_peak_overs_enabled
+            && self.true_peak_overs_threshold == other.true_peak_overs_threshold
+            && self.true_peak_overs == other.true_peak_overs
+            && self.window == other.window
+            && self.history == other.history
+            && self.histogram_resolution == other.histogram_resolution
+            && self.dual_mono_compensation == other.dual_mono_compensation
+            && self.non_finite_handling == other.non_finite_handling
+            && self.non_finite_samples == other.non_finite_samples
+            && self.gating_blocks == other.gating_blocks
+            && self.short_term_blocks == other.short_term_blocks
+            && self.integrated_blocks == other.integrated_blocks
+            && self.last_block_energy == other.last_block_energy
+            && self.buffered_frames == other.buffered_frames
+            && self.frames_processed == other.frames_processed
+            && self.channel_history == other.channel_history
+            && self.channel_block_energies == other.channel_block_energies
+            && self.chunk_size == other.chunk_size
+            && self.true_peak_quality == other.true_peak_quality
+            && self.reference_loudness == other.reference_loudness
+            && self.gating == other.gating
+            && self.pending_samples == other.pending_samples
+    }
+}
+
+/// Serialized state of an [`EbuR128`](struct.EbuR128.html).
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct StateRef<'a> {
+    mode: u8,
+    rate: u32,
+    channels: u32,
+    audio_data: Cow<'a, [f64]>,
+    audio_data_index: usize,
+    needed_frames: usize,
+    channel_map: &'a [Channel],
+    channel_gains: &'a [f64],
+    weighting: Weighting,
+    precision: Precision,
+    filter: crate::filter::FilterState,
+    block_energy_history: &'a crate::history::History,
+    short_term_block_energy_history: &'a crate::history::History,
+    short_term_frame_counter: usize,
+    momentary_max_energy: f64,
+    shortterm_max_energy: f64,
+    shortterm_max_enabled: bool,
+    timeseries: bool,
+    momentary_timeseries: &'a [f64],
+    shortterm_timeseries: &'a [f64],
+    shortterm_timeseries_timed_enabled: bool,
+    shortterm_timeseries_timed: &'a [(core::time::Duration, f64)],
+    block_energies_enabled: bool,
+    block_energies: &'a [f64],
+    block_timestamps: &'a [u64],
+    sample_peak: &'a [f64],
+    true_peak: &'a [f64],
+    true_peak_overs_enabled: bool,
+    true_peak_overs_threshold: f64,
+    true_peak_overs: &'a [u64],
+    window: usize,
+    history: usize,
+    histogram_resolution: u32,
+    dual_mono_compensation: bool,
+    non_finite_handling: NonFiniteHandling,
+    non_finite_samples: u64,
+    gating_blocks: u64,
+    short_term_blocks: u64,
+    integrated_blocks: u64,
+    last_block_energy: f64,
+    buffered_frames: usize,
+    frames_processed: u64,
+    channel_history: bool,
+    channel_block_energies: &'a VecDeque<f64>,
+    chunk_size: usize,
+    true_peak_quality: TruePeakQuality,
+    reference_loudness: f64,
+    block_hop: u32,
+    absolute_gate: f64,
+    relative_gate: f64,
+    pending_samples: &'a [f32],
+}
+
+/// Deserialized state of an [`EbuR128`](struct.EbuR128.html). Must have the same fields as
+/// `StateRef`.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct State {
+    mode: u8,
+    rate: u32,
+    channels: u32,
+    audio_data: Vec<f64>,
+    audio_data_index: usize,
+    needed_frames: usize,
+    channel_map: Vec<Channel>,
+    channel_gains: Vec<f64>,
+    weighting: Weighting,
+    precision: Precision,
+    filter: crate::filter::FilterState,
+    block_energy_history: crate::history::History,
+    short_term_block_energy_history: crate::history::History,
+    short_term_frame_counter: usize,
+    momentary_max_energy: f64,
+    shortterm_max_energy: f64,
+    shortterm_max_enabled: bool,
+    timeseries: bool,
+    momentary_timeseries: Vec<f64>,
+    shortterm_timeseries: Vec<f64>,
+    shortterm_timeseries_timed_enabled: bool,
+    shortterm_timeseries_timed: Vec<(core::time::Duration, f64)>,
+    block_energies_enabled: bool,
+    block_energies: Vec<f64>,
+    block_timestamps: Vec<u64>,
+    sample_peak: Vec<f64>,
+    true_peak: Vec<f64>,
+    true_peak_overs_enabled: bool,
+    true_peak_overs_threshold: f64,
+    true_peak_overs: Vec<u64>,
+    window: usize,
+    history: usize,
+    histogram_resolution: u32,
+    dual_mono_compensation: bool,
+    non_finite_handling: NonFiniteHandling,
+    non_finite_samples: u64,
+    gating_blocks: u64,
+    short_term_blocks: u64,
+    integrated_blocks: u64,
+    last_block_energy: f64,
+    buffered_frames: usize,
+    frames_processed: u64,
+    channel_history: bool,
+    channel_block_energies: VecDeque<f64>,
+    chunk_size: usize,
+    true_peak_quality: TruePeakQuality,
+    reference_loudness: f64,
+    block_hop: u32,
+    absolute_gate: f64,
+    relative_gate: f64,
+    pending_samples: Vec<f32>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for EbuR128 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        StateRef {
+            mode: self.mode.bits(),
+            rate: self.rate,
+            channels: self.channels,
+            audio_data: self.audio_data.to_f64(),
+            audio_data_index: self.audio_data_index,
+            needed_frames: self.needed_frames,
+            channel_map: &self.channel_map,
+            channel_gains: &self.channel_gains,
+            weighting: self.filter.weighting(),
+            precision: self.audio_data.precision(),
+            filter: self.filter.state(),
+            block_energy_history: &self.block_energy_history,
+            short_term_block_energy_history: &self.short_term_block_energy_history,
+            short_term_frame_counter: self.short_term_frame_counter,
+            momentary_max_energy: self.momentary_max_energy,
+            shortterm_max_energy: self.shortterm_max_energy,
+            shortterm_max_enabled: self.shortterm_max_enabled,
+            timeseries: self.timeseries,
+            momentary_timeseries: &self.momentary_timeseries,
+            shortterm_timeseries: &self.shortterm_timeseries,
+            shortterm_timeseries_timed_enabled: self.shortterm_timeseries_timed_enabled,
+            shortterm_timeseries_timed: &self.shortterm_timeseries_timed,
+            block_energies_enabled: self.block_energies_enabled,
+            block_energies: &self.block_energies,
+            block_timestamps: &self.block_timestamps,
+            sample_peak: &self.sample_peak,
+            true_peak: &self.true_peak,
+            true_peak_overs_enabled: self.true_peak_overs_enabled,
+            true_peak_overs_threshold: self.true_peak_overs_threshold,
+            true_peak_overs: &self.true_peak_overs,
+            window: self.window,
+            history: self.history,
+            histogram_resolution: self.histogram_resolution,
+            dual_mono_compensation: self.dual_mono_compensation,
+            non_finite_handling: self.non_finite_handling,
+            non_finite_samples: self.non_finite_samples,
+            gating_blocks: self.gating_blocks,
+            short_term_blocks: self.short_term_blocks,
+            integrated_blocks: self.integrated_blocks,
+            last_block_energy: self.last_block_energy,
+            buffered_frames: self.buffered_frames,
+            frames_processed: self.frames_processed,
+            channel_history: self.channel_history,
+            channel_block_energies: &self.channel_block_energies,
+            block_hop: self.block_hop,
+            chunk_size: self.chunk_size,
+            true_peak_quality: self.true_peak_quality,
+            reference_loudness: self.reference_loudness,
+            absolute_gate: self.gating.absolute(),
+            relative_gate: self.gating.relative(),
+            pending_samples: &self.pending_samples,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for EbuR128 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+
+        let state = State::deserialize(deserializer)?;
+        EbuR128::from_state(state).map_err(D::Error::custom)
+    }
+}
+
+/// Loudness of a single block, -inf if it is silent.
+fn block_loudness(energy: f64) -> f64 {
+    if energy <= 0.0 {
+        -f64::INFINITY
+    } else {
+        energy_to_loudness(energy)
+    }
+}
+
+/// Reference loudness of ReplayGain 2.0 in LUFS.
+const REPLAYGAIN_REFERENCE_LOUDNESS: f64 = -18.0;
+
+/// Default reference loudness in LUFS for the relative loudness, the target level of EBU R128.
+const DEFAULT_REFERENCE_LOUDNESS: f64 = -23.0;
+
+/// Default threshold for counting the overs of the true peak measurement, 0 dBTP.
+const DEFAULT_TRUE_PEAK_OVERS_THRESHOLD: f64 = 1.0;
+
+/// Default hop between the start of two gating blocks in ms.
+const DEFAULT_BLOCK_HOP: u32 = 100;
+
+/// How many samples fit in the hop between two gating blocks (rounded), at least one.
+fn samples_per_hop(rate: u32, hop: u32) -> usize {
+    core::cmp::max(1, (rate as usize * hop as usize + 500) / 1000)
+}
+
+/// Default maximum number of frames processed at once.
+const DEFAULT_CHUNK_SIZE: usize = 8192;
+
+/// Gains and ratios relative to the integrated loudness are not defined if all gating blocks
+/// were below the gates, i.e. the loudness is -inf.
+fn finite_loudness(loudness: f64) -> Result<f64, Error> {
+    if loudness.is_infinite() {
+        Err(Error::NotEnoughData)
+    } else {
+        Ok(loudness)
+    }
+}
+
 pub(crate) fn default_channel_map(channels: u32) -> Vec<Channel> {
     match channels {
         4 => vec![
@@ -238,7 +1181,7 @@ pub(crate) fn default_channel_map(channels: u32) -> Vec<Channel> {
         _ => {
             let mut v = vec![Channel::Unused; channels as usize];
 
-            let set_channels = std::cmp::min(channels as usize, 6);
+            let set_channels = core::cmp::min(channels as usize, 6);
             v[0..set_channels].copy_from_slice(
                 &[
                     Channel::Left,
@@ -255,13 +1198,22 @@ pub(crate) fn default_channel_map(channels: u32) -> Vec<Channel> {
     }
 }
 
+const MIN_RATE: u32 = 16;
 const MAX_RATE: u32 = 2822400;
 const MAX_CHANNELS: u32 = 64;
 
 impl EbuR128 {
     /// Allocate audio data buffer used by the filter and check if we can allocate enough memory
     /// for it.
-    fn allocate_audio_data(channels: u32, rate: u32, window: usize) -> Result<Box<[f64]>, Error> {
+    ///
+    /// Returns `Error::NoMem` if the size of the buffer overflows `usize` or the allocation fails,
+    /// which can easily happen for long windows on 32 bit platforms.
+    fn allocate_audio_data(
+        channels: u32,
+        rate: u32,
+        window: usize,
+        precision: Precision,
+    ) -> Result<AudioData, Error> {
         let samples_in_100ms = (rate as usize + 5) / 10;
 
         let mut audio_data_frames = (rate as usize).checked_mul(window).ok_or(Error::NoMem)? / 1000;
@@ -272,32 +1224,44 @@ impl EbuR128 {
                 .ok_or(Error::NoMem)?
                 - (audio_data_frames % samples_in_100ms);
         }
-
-        let audio_data = vec![
-            0.0;
-            audio_data_frames
-                .checked_mul(channels as usize)
-                .ok_or(Error::NoMem)?
-        ]
-        .into_boxed_slice();
-
-        Ok(audio_data)
+        // For very low sample rates 100ms are rounded up to more frames than the window has, but
+        // the blocks have to fit into the buffer
+        let block_frames = samples_in_100ms
+            .checked_mul(window.div_ceil(100))
+            .ok_or(Error::NoMem)?;
+        let audio_data_frames = usize::max(audio_data_frames, block_frames);
+
+        let len = audio_data_frames
+            .checked_mul(channels as usize)
+            .ok_or(Error::NoMem)?;
+
+        match precision {
+            Precision::F64 => Ok(AudioData::F64(allocate_zeroed(len)?)),
+            Precision::F32 => Ok(AudioData::F32(allocate_zeroed(len)?)),
+            Precision::Fixed => Ok(AudioData::I32(allocate_zeroed(len)?)),
+        }
     }
 
     /// Create a new instance with the given configuration.
+    ///
+    /// Returns `Error::InvalidChannels` if the number of channels is 0 or more than 64, and
+    /// `Error::InvalidRate` if the sample rate is below 16Hz or above 2822400Hz (64 times 44.1kHz).
+    /// libebur128 returns `EBUR128_ERROR_NOMEM` in both cases. Returns `Error::UnsupportedRate` if
+    /// `Mode::TRUE_PEAK` is requested for a sample rate that the true peak measurement does not
+    /// support.
     pub fn new(channels: u32, rate: u32, mode: Mode) -> Result<Self, Error> {
         if channels == 0 || channels > MAX_CHANNELS {
-            return Err(Error::NoMem);
+            return Err(Error::InvalidChannels);
         }
 
-        if !(16..=MAX_RATE).contains(&rate) {
-            return Err(Error::NoMem);
+        if !(MIN_RATE..=MAX_RATE).contains(&rate) {
+            return Err(Error::InvalidRate);
         }
 
         let sample_peak = vec![0.0; channels as usize];
         let true_peak = vec![0.0; channels as usize];
 
-        let history = std::usize::MAX;
+        let history = usize::MAX;
         let samples_in_100ms = (rate as usize + 5) / 10;
 
         let window = if mode.contains(Mode::S) {
@@ -308,23 +1272,27 @@ impl EbuR128 {
             return Err(Error::InvalidMode);
         };
 
-        let audio_data = Self::allocate_audio_data(channels, rate, window)?;
+        let audio_data = Self::allocate_audio_data(channels, rate, window, Precision::F64)?;
         // start at the beginning of the buffer
         let audio_data_index = 0;
 
-        let block_energy_history =
-            crate::history::History::new(mode.contains(Mode::HISTOGRAM), history / 100);
+        let block_energy_history = crate::history::History::new(
+            mode.contains(Mode::HISTOGRAM),
+            history / DEFAULT_BLOCK_HOP as usize,
+        );
 
-        let short_term_block_energy_history =
+        let mut short_term_block_energy_history =
             crate::history::History::new(mode.contains(Mode::HISTOGRAM), history / 3000);
+        short_term_block_energy_history.cache_loudness_range();
         let short_term_frame_counter = 0;
 
         let filter = crate::filter::Filter::new(
             rate,
             channels,
+            Weighting::K,
             mode.contains(Mode::SAMPLE_PEAK),
             mode.contains(Mode::TRUE_PEAK),
-        );
+        )?;
 
         let channel_map = default_channel_map(channels);
 
@@ -339,18 +1307,163 @@ impl EbuR128 {
             audio_data_index,
             needed_frames,
             channel_map: channel_map.into_boxed_slice(),
+            channel_gains: vec![1.0; channels as usize].into_boxed_slice(),
             samples_in_100ms,
+            block_hop: DEFAULT_BLOCK_HOP,
+            samples_per_hop: samples_per_hop(rate, DEFAULT_BLOCK_HOP),
             filter,
             block_energy_history,
             short_term_block_energy_history,
             short_term_frame_counter,
+            momentary_max_energy: 0.0,
+            shortterm_max_energy: 0.0,
+            shortterm_max_enabled: false,
+            timeseries: false,
+            momentary_timeseries: Vec::new(),
+            shortterm_timeseries: Vec::new(),
+            shortterm_timeseries_timed_enabled: false,
+            shortterm_timeseries_timed: Vec::new(),
+            block_energies_enabled: false,
+            block_energies: Vec::new(),
+            block_timestamps: Vec::new(),
             sample_peak: sample_peak.into_boxed_slice(),
             true_peak: true_peak.into_boxed_slice(),
+            true_peak_overs_enabled: false,
+            true_peak_overs_threshold: DEFAULT_TRUE_PEAK_OVERS_THRESHOLD,
+            true_peak_overs: vec![0; channels as usize].into_boxed_slice(),
             window,
             history,
+            histogram_resolution: crate::history::DEFAULT_HISTOGRAM_RESOLUTION,
+            dual_mono_compensation: false,
+            non_finite_handling: NonFiniteHandling::Propagate,
+            non_finite_samples: 0,
+            gating_blocks: 0,
+            short_term_blocks: 0,
+            integrated_blocks: 0,
+            last_block_energy: 0.0,
+            buffered_frames: 0,
+            frames_processed: 0,
+            channel_history: false,
+            channel_block_energies: VecDeque::new(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            true_peak_quality: TruePeakQuality::Standard,
+            reference_loudness: DEFAULT_REFERENCE_LOUDNESS,
+            gating: crate::history::Gating::default(),
+            pending_samples: Vec::new(),
+            scratch: Vec::new(),
         })
     }
 
+    /// Create a new instance from a deserialized state.
+    ///
+    /// The configuration is validated the same way as when creating a new instance and the
+    /// remaining state has to fit that configuration.
+    #[cfg(feature = "serde")]
+    fn from_state(state: State) -> Result<Self, String> {
+        let mode = Mode::from_bits(state.mode).ok_or("Invalid mode")?;
+        let mut ebu = EbuR128::new(state.channels, state.rate, mode).map_err(|e| e.to_string())?;
+        ebu.set_channel_map(&state.channel_map)
+            .map_err(|e| e.to_string())?;
+        ebu.set_channel_gains(&state.channel_gains)
+            .map_err(|e| e.to_string())?;
+        ebu.set_max_window(state.window as u32)
+            .map_err(|e| e.to_string())?;
+        if state.history != usize::MAX {
+            ebu.set_max_history(state.history as u32)
+                .map_err(|e| e.to_string())?;
+        }
+        ebu.set_histogram_resolution(state.histogram_resolution)
+            .map_err(|e| e.to_string())?;
+        ebu.set_weighting(state.weighting)
+            .map_err(|e| e.to_string())?;
+        ebu.set_precision(state.precision)
+            .map_err(|e| e.to_string())?;
+        ebu.set_dual_mono_compensation(state.dual_mono_compensation);
+        ebu.set_channel_history(state.channel_history);
+        ebu.set_non_finite_handling(state.non_finite_handling);
+        ebu.set_chunk_size(state.chunk_size)
+            .map_err(|e| e.to_string())?;
+        ebu.set_true_peak_quality(state.true_peak_quality)
+            .map_err(|e| e.to_string())?;
+        ebu.set_reference_loudness(state.reference_loudness)
+            .map_err(|e| e.to_string())?;
+        ebu.set_true_peak_overs_threshold(state.true_peak_overs_threshold)
+            .map_err(|e| e.to_string())?;
+        if state.true_peak_overs_enabled {
+            ebu.enable_true_peak_overs();
+        }
+        ebu.set_gating(state.absolute_gate, state.relative_gate)
+            .map_err(|e| e.to_string())?;
+        ebu.set_block_hop(state.block_hop)
+            .map_err(|e| e.to_string())?;
+
+        if ebu.window != state.window
+            || ebu.history != state.history
+            || ebu.audio_data.len() != state.audio_data.len()
+            || state.audio_data_index >= ebu.audio_data.len() / ebu.channels as usize
+            || state.needed_frames == 0
+            || state.needed_frames > ebu.samples_in_100ms * 4
+            || state.short_term_frame_counter > ebu.audio_data.len() / ebu.channels as usize
+            || state.buffered_frames > ebu.audio_data.len() / ebu.channels as usize
+            || ebu.sample_peak.len() != state.sample_peak.len()
+            || ebu.true_peak.len() != state.true_peak.len()
+            || ebu.true_peak_overs.len() != state.true_peak_overs.len()
+            || (!state.channel_history && !state.channel_block_energies.is_empty())
+            || !(state.channel_block_energies.len() as u64).is_multiple_of(state.channels as u64)
+            || state.block_energies.len() != state.block_timestamps.len()
+            || (!state.shortterm_timeseries_timed_enabled
+                && !state.shortterm_timeseries_timed.is_empty())
+            || state.pending_samples.len() >= state.channels as usize
+        {
+            return Err(String::from("Inconsistent state"));
+        }
+
+        if ebu.block_energy_history.histogram_resolution()
+            != state.block_energy_history.histogram_resolution()
+            || ebu.short_term_block_energy_history.histogram_resolution()
+                != state.short_term_block_energy_history.histogram_resolution()
+        {
+            return Err(String::from("Inconsistent history"));
+        }
+
+        ebu.filter
+            .set_state(&state.filter)
+            .map_err(|_| String::from("Inconsistent filter state"))?;
+
+        ebu.audio_data.copy_from_f64(&state.audio_data);
+        ebu.audio_data_index = state.audio_data_index;
+        ebu.needed_frames = state.needed_frames;
+        ebu.block_energy_history = state.block_energy_history;
+        ebu.short_term_block_energy_history = state.short_term_block_energy_history;
+        ebu.short_term_block_energy_history.cache_loudness_range();
+        ebu.short_term_frame_counter = state.short_term_frame_counter;
+        ebu.momentary_max_energy = state.momentary_max_energy;
+        ebu.shortterm_max_energy = state.shortterm_max_energy;
+        ebu.shortterm_max_enabled = state.shortterm_max_enabled;
+        ebu.timeseries = state.timeseries;
+        ebu.momentary_timeseries = state.momentary_timeseries;
+        ebu.shortterm_timeseries = state.shortterm_timeseries;
+        ebu.shortterm_timeseries_timed_enabled = state.shortterm_timeseries_timed_enabled;
+        ebu.shortterm_timeseries_timed = state.shortterm_timeseries_timed;
+        ebu.block_energies_enabled = state.block_energies_enabled;
+        ebu.block_energies = state.block_energies;
+        ebu.block_timestamps = state.block_timestamps;
+        ebu.sample_peak.copy_from_slice(&state.sample_peak);
+        ebu.true_peak.copy_from_slice(&state.true_peak);
+        ebu.true_peak_overs.copy_from_slice(&state.true_peak_overs);
+        ebu.non_finite_samples = state.non_finite_samples;
+        ebu.gating_blocks = state.gating_blocks;
+        ebu.short_term_blocks = state.short_term_blocks;
+        ebu.integrated_blocks = state.integrated_blocks;
+        ebu.last_block_energy = state.last_block_energy;
+        ebu.buffered_frames = state.buffered_frames;
+        ebu.frames_processed = state.frames_processed;
+        ebu.channel_block_energies = state.channel_block_energies;
+        ebu.pending_samples = state.pending_samples;
+
+        Ok(ebu)
+    }
+
     /// Get the configured mode.
     pub fn mode(&self) -> Mode {
         self.mode
@@ -368,7 +1481,12 @@ impl EbuR128 {
 
     /// Get the configured channel types.
     pub fn channel_map(&self) -> &[Channel] {
-        &*self.channel_map
+        &self.channel_map
+    }
+
+    /// Get the configured linear gain of each channel.
+    pub fn channel_gains(&self) -> &[f64] {
+        &self.channel_gains
     }
 
     /// Get the configured maximum window duration in ms.
@@ -381,6 +1499,183 @@ impl EbuR128 {
         self.history
     }
 
+    /// Get the configured hop between the start of two gating blocks in ms.
+    pub fn block_hop(&self) -> u32 {
+        self.block_hop
+    }
+
+    /// Get the configured resolution of the histogram in bins per LU.
+    pub fn histogram_resolution(&self) -> u32 {
+        self.histogram_resolution
+    }
+
+    /// Get the configured frequency weighting.
+    pub fn weighting(&self) -> Weighting {
+        self.filter.weighting()
+    }
+
+    /// Get the magnitude response in dB of the configured frequency weighting at `freq_hz`.
+    ///
+    /// This is calculated from the filter coefficients for the configured sample rate, e.g. for
+    /// checking the K-weighting against the curve of ITU-R BS.1770: a high pass with about -1dB
+    /// at 100Hz and a high shelf of about +4dB above 2kHz. Frequencies above half the sample rate
+    /// are mirrored.
+    pub fn frequency_response(&self, freq_hz: f64) -> f64 {
+        self.filter.frequency_response(freq_hz)
+    }
+
+    /// Get the configured floating point precision.
+    pub fn precision(&self) -> Precision {
+        self.audio_data.precision()
+    }
+
+    /// Get whether the dual mono compensation is enabled.
+    pub fn dual_mono_compensation(&self) -> bool {
+        self.dual_mono_compensation
+    }
+
+    /// Get whether the energy of each channel is stored for every gating block.
+    pub fn channel_history(&self) -> bool {
+        self.channel_history
+    }
+
+    /// Get the configured handling of non-finite input samples.
+    pub fn non_finite_handling(&self) -> NonFiniteHandling {
+        self.non_finite_handling
+    }
+
+    /// Get the configured maximum number of frames processed at once.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Get the configured oversampling quality of the true peak measurement.
+    pub fn true_peak_quality(&self) -> TruePeakQuality {
+        self.true_peak_quality
+    }
+
+    /// Get the configured reference loudness in LUFS for the relative loudness.
+    pub fn reference_loudness(&self) -> f64 {
+        self.reference_loudness
+    }
+
+    /// Get the configured linear threshold for counting the overs of the true peak measurement.
+    pub fn true_peak_overs_threshold(&self) -> f64 {
+        self.true_peak_overs_threshold
+    }
+
+    /// Get the configured absolute gate in LUFS and relative gate in LU.
+    pub fn gating(&self) -> (f64, f64) {
+        (self.gating.absolute(), self.gating.relative())
+    }
+
+    /// Get the number of non-finite input samples that were replaced with silence.
+    ///
+    /// Samples are only counted with `NonFiniteHandling::Skip`.
+    pub fn non_finite_samples(&self) -> u64 {
+        self.non_finite_samples
+    }
+
+    /// Get the number of frames added for the measurement so far.
+    ///
+    /// All `add_frames()` functions count the frames they add, e.g. for progress reporting or
+    /// for checking that a whole file was measured. Frames passed to the `seed_frames()`
+    /// functions are not counted. This is set back to 0 by
+    /// [`EbuR128::reset`](struct.EbuR128.html#method.reset).
+    pub fn frames_processed(&self) -> u64 {
+        self.frames_processed
+    }
+
+    /// Get the duration of the frames added for the measurement so far.
+    ///
+    /// This is [`EbuR128::frames_processed`](struct.EbuR128.html#method.frames_processed) at the
+    /// configured sample rate, rounded down to full nanoseconds. If the sample rate was changed with
+    /// [`EbuR128::change_parameters`](struct.EbuR128.html#method.change_parameters) all frames
+    /// are counted at the new sample rate.
+    pub fn duration_processed(&self) -> core::time::Duration {
+        let rate = u64::from(self.rate);
+        let secs = self.frames_processed / rate;
+        let nanos = (self.frames_processed % rate) * 1_000_000_000 / rate;
+
+        core::time::Duration::new(secs, nanos as u32)
+    }
+
+    /// Get the number of gating blocks completed so far.
+    ///
+    /// The first gating block is completed after 400ms and every following one after another
+    /// [hop](struct.EbuR128.html#method.set_block_hop), by default 100ms. At least one gating
+    /// block above the gates is needed for the integrated loudness, so this helps to explain
+    /// `Error::NotEnoughData`, e.g. if it is zero. Gating blocks are counted independent of the
+    /// mode and whether they are included in the history.
+    pub fn gating_block_count(&self) -> u64 {
+        self.gating_blocks
+    }
+
+    /// Get the fraction of the current hop of the next gating block that is already filled,
+    /// between 0.0 and 1.0.
+    ///
+    /// With the default hop of 100ms the first gating block needs four hops, so while filling it
+    /// this starts at 0.0 for each 100ms. Use
+    /// [`EbuR128::gating_block_count`](struct.EbuR128.html#method.gating_block_count) to check if
+    /// the first gating block was completed.
+    pub fn current_block_progress(&self) -> f64 {
+        let filled = (self.samples_per_hop - self.needed_frames % self.samples_per_hop)
+            % self.samples_per_hop;
+
+        filled as f64 / self.samples_per_hop as f64
+    }
+
+    /// Get whether the most recently completed gating block is below the absolute gate, or
+    /// `None` if no gating block was completed yet.
+    ///
+    /// Gating blocks below the absolute gate are not included in the integrated loudness, e.g.
+    /// for showing in a meter why the integrated loudness doesn't change during quiet passages.
+    /// This uses the absolute gate configured with
+    /// [`EbuR128::set_gating`](struct.EbuR128.html#method.set_gating) like
+    /// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global). The relative gate
+    /// depends on all measured gating blocks and is not considered.
+    pub fn is_current_block_gated(&self) -> Option<bool> {
+        if self.gating_blocks == 0 {
+            return None;
+        }
+
+        Some(self.gating.is_below_absolute(self.last_block_energy))
+    }
+
+    /// Get whether [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global)
+    /// returns a value, i.e. `Mode::I` is enabled and at least one gating block was completed.
+    ///
+    /// The value can still be `-f64::INFINITY` if all gating blocks are below the gates.
+    pub fn has_integrated(&self) -> bool {
+        self.mode.contains(Mode::I) && self.integrated_blocks > 0
+    }
+
+    /// Get whether [`EbuR128::loudness_range`](struct.EbuR128.html#method.loudness_range)
+    /// returns a value, i.e. `Mode::LRA` is enabled and at least one short term block was
+    /// completed.
+    pub fn has_range(&self) -> bool {
+        self.mode.contains(Mode::LRA) && self.short_term_blocks > 0
+    }
+
+    /// Get whether 400ms of audio were added for
+    /// [`EbuR128::loudness_momentary`](struct.EbuR128.html#method.loudness_momentary).
+    ///
+    /// Before that the momentary loudness is still returned but includes silence for the missing
+    /// audio.
+    pub fn has_momentary(&self) -> bool {
+        self.buffered_frames >= self.samples_in_100ms * 4
+    }
+
+    /// Get whether the short term loudness is measured and 3s of audio were added for
+    /// [`EbuR128::loudness_shortterm`](struct.EbuR128.html#method.loudness_shortterm).
+    ///
+    /// Before that the short term loudness is still returned but includes silence for the
+    /// missing audio. If the maximum window is shorter than 3s, i.e. without `Mode::S`, the short
+    /// term loudness returns `Error::InvalidMode` and this is always `false`.
+    pub fn has_shortterm(&self) -> bool {
+        self.buffered_frames >= self.samples_in_100ms * 30
+    }
+
     /// Set channel type.
     ///
     /// The default is:
@@ -392,6 +1687,11 @@ impl EbuR128 {
     /// * 4 \-> `LeftSurround`
     /// * 5 \-> `RightSurround`
     /// * _ \-> `Unused`
+    ///
+    /// As specified in ITU-R BS.1770 the energy of the surround channels (`LeftSurround`,
+    /// `RightSurround`, `Mp060`, `Mm060`, `Mp090` and `Mm090`) is weighted by +1.5dB, and
+    /// `Unused` and `Lfe` channels are not included in the measurement at all. `DualMono` is
+    /// counted twice and is only allowed for mono input.
     pub fn set_channel(&mut self, channel_number: u32, value: Channel) -> Result<(), Error> {
         if channel_number >= self.channels {
             return Err(Error::InvalidChannelIndex);
@@ -416,6 +1716,9 @@ impl EbuR128 {
     /// * 4 \-> `LeftSurround`
     /// * 5 \-> `RightSurround`
     /// * _ \-> `Unused`
+    ///
+    /// See [`EbuR128::set_channel`](struct.EbuR128.html#method.set_channel) for how the channel
+    /// types affect the measurement.
     pub fn set_channel_map(&mut self, channel_map: &[Channel]) -> Result<(), Error> {
         if channel_map.len() != self.channels as usize {
             return Err(Error::InvalidChannelIndex);
@@ -431,48 +1734,105 @@ impl EbuR128 {
         Ok(())
     }
 
-    /// Change library parameters.
+    /// Set a linear gain for each channel.
     ///
-    /// Note that the channel map will be reset when setting a different number of channels. The
-    /// current unfinished block will be lost.
-    pub fn change_parameters(&mut self, channels: u32, rate: u32) -> Result<(), Error> {
-        if channels == 0 || channels > MAX_CHANNELS {
-            return Err(Error::NoMem);
+    /// The energy of each channel is multiplied by the square of its gain before the energies of
+    /// all channels are summed up, i.e. the measurement behaves as if the channel's samples were
+    /// multiplied by the gain. E.g. a gain of 0.5 lowers the channel by about 6 dB and a gain of
+    /// 0.0 excludes it like `Channel::Unused`. This is useful for downmix and calibration
+    /// workflows and applies to all loudness values.
+    ///
+    /// The gains are applied in addition to the weighting of the
+    /// [channel types](struct.EbuR128.html#method.set_channel), so a `Channel::LeftSurround` with
+    /// a gain of 2.0 is weighted by 1.41 * 4.0. Channels excluded by their type stay excluded.
+    /// The peaks and the filtered samples are not affected. The default is a gain of 1.0 for all
+    /// channels, and like the channel types the gains are applied to blocks measured after
+    /// changing them.
+    ///
+    /// Returns `Error::InvalidChannelIndex` if the number of gains is not the number of channels
+    /// and `Error::InvalidMode` if any gain is not finite.
+    pub fn set_channel_gains(&mut self, gains: &[f64]) -> Result<(), Error> {
+        if gains.len() != self.channels as usize {
+            return Err(Error::InvalidChannelIndex);
         }
 
-        if !(16..=MAX_RATE).contains(&rate) {
-            return Err(Error::NoMem);
+        if gains.iter().any(|gain| !gain.is_finite()) {
+            return Err(Error::InvalidMode);
+        }
+
+        self.channel_gains.copy_from_slice(gains);
+        Ok(())
+    }
+
+    /// Change library parameters.
+    ///
+    /// This can be used for changing the number of channels and/or the sample rate in the middle
+    /// of a stream. The filter coefficients, the true peak interpolator and the audio buffers are
+    /// recreated for the new configuration.
+    ///
+    /// The following state is preserved:
+    ///
+    /// * the history of all completed gating blocks and short term blocks, i.e. the integrated
+    ///   loudness and the loudness range continue to include everything measured before,
+    /// * the maximum momentary and short term loudness,
+    /// * the maximum sample and true peaks if the number of channels stays the same,
+    /// * the mode, the maximum window and the maximum history.
+    ///
+    /// The following state is reset:
+    ///
+    /// * the current unfinished block is dropped, and momentary and short term loudness only
+    ///   include frames added after the change,
+    /// * the filter state, as if a new stream was started,
+    /// * the peaks from the last call to `add_frames()`,
+    /// * the channel map, the channel gains and all peaks if the number of channels changes.
+    ///
+    /// Returns `Error::InvalidChannels` or `Error::InvalidRate` for the same values as
+    /// [`EbuR128::new`](struct.EbuR128.html#method.new), and `Error::UnsupportedRate` if
+    /// `Mode::TRUE_PEAK` is enabled and the new sample rate is not supported by the true peak
+    /// measurement, or if the new sample rate is not supported by the configured weighting. The
+    /// current state is left unchanged in all these cases.
+    pub fn change_parameters(&mut self, channels: u32, rate: u32) -> Result<(), Error> {
+        if channels == 0 || channels > MAX_CHANNELS {
+            return Err(Error::InvalidChannels);
+        }
+
+        if !(MIN_RATE..=MAX_RATE).contains(&rate) {
+            return Err(Error::InvalidRate);
         }
 
         if self.rate == rate && self.channels == channels {
             return Ok(());
         }
 
-        self.audio_data = Self::allocate_audio_data(channels, rate, self.window)?;
+        let filter = self.create_filter(rate, channels, self.filter.weighting())?;
+
+        self.audio_data =
+            Self::allocate_audio_data(channels, rate, self.window, self.audio_data.precision())?;
 
         if self.channels != channels {
             self.channels = channels;
             self.channel_map = default_channel_map(channels).into_boxed_slice();
+            self.channel_gains = vec![1.0; channels as usize].into_boxed_slice();
             self.sample_peak = vec![0.0; channels as usize].into_boxed_slice();
             self.true_peak = vec![0.0; channels as usize].into_boxed_slice();
+            self.true_peak_overs = vec![0; channels as usize].into_boxed_slice();
+            self.channel_block_energies.clear();
         }
+        self.pending_samples.clear();
 
         if self.rate != rate {
             self.rate = rate;
             self.samples_in_100ms = (rate as usize + 5) / 10;
+            self.samples_per_hop = samples_per_hop(rate, self.block_hop);
         }
 
-        self.filter = crate::filter::Filter::new(
-            rate,
-            channels,
-            self.mode.contains(Mode::SAMPLE_PEAK),
-            self.mode.contains(Mode::TRUE_PEAK),
-        );
+        self.filter = filter;
 
         // the first block needs 400ms of audio data
         self.needed_frames = self.samples_in_100ms * 4;
         // start at the beginning of the buffer
         self.audio_data_index = 0;
+        self.buffered_frames = 0;
         // reset short term frame counter
         self.short_term_frame_counter = 0;
 
@@ -482,13 +1842,22 @@ impl EbuR128 {
     /// Set the maximum window duration.
     ///
     /// Set the maximum duration in ms that will be used for
-    /// [`EbuR128::loudness_window`](struct.EbuR128.html#method.loudness_window). Note that this
-    /// destroys the current content of the audio buffer.
+    /// [`EbuR128::loudness_window`](struct.EbuR128.html#method.loudness_window).
+    ///
+    /// This can be called at any time. The most recent audio data that fits into the new window
+    /// is kept and the measurement continues as if the window was configured from the beginning,
+    /// except that a window longer than the audio data kept so far is filled up with silence.
+    /// Until enough frames were added after enlarging the window,
+    /// [`EbuR128::loudness_window`](struct.EbuR128.html#method.loudness_window) for the longer
+    /// windows therefore includes silence for the remaining duration.
+    ///
+    /// Minimum is 3000ms for `Mode::S` and 400ms for `Mode::M`. Returns `Error::NoMem` if the
+    /// window is longer than the [`EbuR128::max_history`](struct.EbuR128.html#method.max_history).
     pub fn set_max_window(&mut self, window: u32) -> Result<(), Error> {
         let window = if self.mode.contains(Mode::S) {
-            std::cmp::max(window, 3000)
+            core::cmp::max(window, 3000)
         } else if self.mode.contains(Mode::M) {
-            std::cmp::max(window, 400)
+            core::cmp::max(window, 400)
         } else {
             window
         };
@@ -497,15 +1866,37 @@ impl EbuR128 {
             return Ok(());
         }
 
-        self.audio_data = Self::allocate_audio_data(self.channels, self.rate, window as usize)?;
-        self.window = window as usize;
+        if window as usize > self.history {
+            return Err(Error::NoMem);
+        }
 
-        // the first block needs 400ms of audio data
-        self.needed_frames = self.samples_in_100ms * 4;
-        // start at the beginning of the buffer
-        self.audio_data_index = 0;
-        // reset short term frame counter
-        self.short_term_frame_counter = 0;
+        let mut audio_data = Self::allocate_audio_data(
+            self.channels,
+            self.rate,
+            window as usize,
+            self.audio_data.precision(),
+        )?;
+
+        // Copy the most recent frames of each channel. Both buffers are a multiple of 100ms long
+        // so keeping the position modulo the buffer length keeps the blocks aligned to the end of
+        // the buffer.
+        let channels = self.channels as usize;
+        let old_frames = self.audio_data.len() / channels;
+        let new_frames = audio_data.len() / channels;
+        let keep = usize::min(old_frames, new_frames);
+        let audio_data_index = self.audio_data_index % new_frames;
+        self.audio_data.copy_recent(
+            self.audio_data_index,
+            &mut audio_data,
+            audio_data_index,
+            channels,
+            keep,
+        );
+
+        self.audio_data = audio_data;
+        self.audio_data_index = audio_data_index;
+        self.buffered_frames = usize::min(self.buffered_frames, keep);
+        self.window = window as usize;
 
         Ok(())
     }
@@ -517,15 +1908,20 @@ impl EbuR128 {
     ///
     /// Applies to [`EbuR128::loudness_range`](struct.EbuR128.html#method.loudness_range) and
     /// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global) when
-    /// `Mode::HISTOGRAM` is not set.
+    /// `Mode::HISTOGRAM` is not set. The histogram does not know the order of the measured gating
+    /// blocks and always includes all of them.
+    ///
+    /// This can be called at any time. When shrinking the history, the oldest gating blocks that
+    /// don't fit anymore are removed. When growing it, all gating blocks measured so far are kept
+    /// and more are kept from now on.
     ///
     /// Default is `ULONG_MAX` (at least ~50 days). Minimum is 3000ms for `Mode::LRA` and 400ms
     /// for `Mode::M`.
     pub fn set_max_history(&mut self, history: u32) -> Result<(), Error> {
         let history = if self.mode.contains(Mode::S) {
-            std::cmp::max(history, 3000)
+            core::cmp::max(history, 3000)
         } else if self.mode.contains(Mode::M) {
-            std::cmp::max(history, 400)
+            core::cmp::max(history, 400)
         } else {
             history
         };
@@ -536,980 +1932,6544 @@ impl EbuR128 {
 
         self.history = history as usize;
 
-        self.block_energy_history.set_max_size(self.history / 100);
+        self.block_energy_history
+            .set_max_size(self.history / self.block_hop as usize);
         self.short_term_block_energy_history
             .set_max_size(self.history / 3000);
+        self.trim_channel_block_energies();
 
         Ok(())
     }
 
-    /// Resets the current state.
-    pub fn reset(&mut self) {
-        // TODO: Use slice::fill() once stabilized
-        for v in &mut *self.audio_data {
-            *v = 0.0;
+    /// Reserve memory for the history of `duration` more audio.
+    ///
+    /// Without `Mode::HISTOGRAM` the gating blocks are stored in a queue that grows while
+    /// measuring, which reallocates from time to time. For a stream with a known maximum length
+    /// this allocates the queues up front, limited to the maximum history, together with the
+    /// momentary and short term timeseries, the block energies and the per channel history if
+    /// they are enabled. This only changes the capacity, not the results.
+    ///
+    /// Returns `Error::NoMem` if the memory can't be allocated.
+    pub fn reserve_history(&mut self, duration: core::time::Duration) -> Result<(), Error> {
+        fn try_reserve<T>(v: &mut Vec<T>, additional: usize) -> Result<(), Error> {
+            v.try_reserve(additional).map_err(|_| Error::NoMem)
         }
 
-        // the first block needs 400ms of audio data
-        self.needed_frames = self.samples_in_100ms * 4;
-        // start at the beginning of the buffer
-        self.audio_data_index = 0;
-        // reset short term frame counter
-        self.short_term_frame_counter = 0;
-
-        // TODO: Use slice::fill() once stabilized
-        for v in &mut *self.true_peak {
-            *v = 0.0;
+        let frames = (duration.as_nanos() * u128::from(self.rate)).div_ceil(1_000_000_000);
+        // Rounded up as the current block might already be partially filled
+        let blocks_of = |len: usize| {
+            let blocks = frames.div_ceil(len as u128);
+            u128::min(blocks, usize::MAX as u128) as usize
+        };
+        let blocks = blocks_of(self.samples_per_hop);
+        // Short term blocks for the loudness range are completed every second
+        let short_term_blocks = blocks_of(self.samples_in_100ms * 10);
+
+        if self.mode.contains(Mode::M) {
+            if self.mode.contains(Mode::I) {
+                self.block_energy_history.reserve(blocks)?;
+                if self.block_energies_enabled {
+                    try_reserve(&mut self.block_energies, blocks)?;
+                    try_reserve(&mut self.block_timestamps, blocks)?;
+                }
+                if self.channel_history {
+                    let channels = self.channels as usize;
+                    let max_blocks = self.history / self.block_hop as usize;
+                    let stored = self.channel_block_energies.len() / channels;
+                    let additional = usize::min(blocks, max_blocks.saturating_sub(stored));
+                    self.channel_block_energies
+                        .try_reserve(additional.saturating_mul(channels))
+                        .map_err(|_| Error::NoMem)?;
+                }
+            }
+            if self.timeseries {
+                try_reserve(&mut self.momentary_timeseries, blocks)?;
+            }
         }
-        // TODO: Use slice::fill() once stabilized
-        for v in &mut *self.sample_peak {
-            *v = 0.0;
+        if self.mode.contains(Mode::S) && self.timeseries {
+            try_reserve(&mut self.shortterm_timeseries, blocks)?;
+        }
+        if self.mode.contains(Mode::LRA) {
+            self.short_term_block_energy_history
+                .reserve(short_term_blocks)?;
+            if self.shortterm_timeseries_timed_enabled {
+                try_reserve(&mut self.shortterm_timeseries_timed, short_term_blocks)?;
+            }
         }
 
-        self.filter.reset();
-        self.block_energy_history.reset();
-        self.short_term_block_energy_history.reset();
+        Ok(())
     }
 
-    /// Process frames. This is the generic variant of the different public add_frames() functions
-    /// that are defined below.
-    fn add_frames<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
-        &mut self,
-        mut src: S,
-    ) -> Result<(), Error> {
-        if src.frames() == 0 {
-            return Ok(());
+    /// Set the hop between the start of two gating blocks in ms.
+    ///
+    /// ITU-R BS.1770 specifies gating blocks of 400ms that overlap by 75%, i.e. a new gating
+    /// block is completed every 100ms, which is the default. With a different hop the gating
+    /// blocks stay 400ms long but are completed more or less often, which changes the time
+    /// resolution of the momentary loudness values reported per block and of the integrated
+    /// loudness. E.g. 200ms gives an overlap of 50%. The results are not compliant with BS.1770
+    /// and EBU R 128 anymore, so this is only useful for experiments.
+    ///
+    /// The new hop applies after the next completed gating block. The maximum history keeps its
+    /// duration, so the queue stores more or less gating blocks. The loudness range still uses
+    /// short term blocks every second but they are only completed at the end of a gating block.
+    ///
+    /// Returns `Error::InvalidMode` if the hop is 0 or longer than the 400ms of a gating block.
+    pub fn set_block_hop(&mut self, hop: u32) -> Result<(), Error> {
+        if hop == 0 || hop > 400 {
+            return Err(Error::InvalidMode);
         }
 
-        if self.channels == 0 {
-            return Err(Error::NoMem);
+        if self.block_hop == hop {
+            return Ok(());
         }
 
-        self.filter.reset_peaks();
-
-        while src.frames() > 0 {
-            let num_frames = src.frames();
-
-            if num_frames >= self.needed_frames {
-                let (current, next) = src.split_at(self.needed_frames);
-
-                self.filter.process(
-                    current,
-                    &mut *self.audio_data,
-                    self.audio_data_index,
-                    &self.channel_map,
-                );
+        self.block_hop = hop;
+        self.samples_per_hop = samples_per_hop(self.rate, hop);
+        self.block_energy_history
+            .set_max_size(self.history / hop as usize);
+        self.trim_channel_block_energies();
 
-                src = next;
-                self.audio_data_index += self.needed_frames;
+        Ok(())
+    }
 
-                if self.mode.contains(Mode::I) {
-                    let energy = crate::filter::Filter::calc_gating_block(
-                        self.samples_in_100ms * 4,
-                        &self.audio_data,
-                        self.audio_data_index,
-                        &self.channel_map,
-                    );
-                    self.block_energy_history.add(energy);
-                }
+    /// Set the resolution of the histogram in bins per LU.
+    ///
+    /// The histogram covers the range from -70 LUFS to +30 LUFS and is used for the loudness
+    /// history if `Mode::HISTOGRAM` is set. Each block's loudness is quantized to the center of
+    /// its bin, i.e. the maximum error is half a bin: 0.05 LU with the default of 10 bins per LU,
+    /// which is the same as libebur128. Higher resolutions reduce the error at the cost of memory
+    /// (24 bytes per bin for each of the two histograms, i.e. 2.4kB for each bin per LU) and
+    /// slower [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global) and
+    /// [`EbuR128::loudness_range`](struct.EbuR128.html#method.loudness_range) calculations.
+    ///
+    /// Returns `Error::NoMem` if the resolution is 0 or higher than 1000 bins per LU. If the
+    /// histogram already contains measurements these are moved to the new bins closest to the
+    /// centers of their current bins, which is lossy.
+    pub fn set_histogram_resolution(&mut self, bins_per_lu: u32) -> Result<(), Error> {
+        if bins_per_lu == 0 || bins_per_lu > crate::history::MAX_HISTOGRAM_RESOLUTION {
+            return Err(Error::NoMem);
+        }
 
-                if self.mode.contains(Mode::LRA) {
-                    self.short_term_frame_counter += self.needed_frames;
-                    if self.short_term_frame_counter == self.samples_in_100ms * 30 {
-                        let energy = self.energy_shortterm()?;
-                        self.short_term_block_energy_history.add(energy);
-                        self.short_term_frame_counter = self.samples_in_100ms * 20;
-                    }
-                }
+        self.histogram_resolution = bins_per_lu;
+        self.block_energy_history
+            .set_histogram_resolution(bins_per_lu);
+        self.short_term_block_energy_history
+            .set_histogram_resolution(bins_per_lu);
 
-                if self.audio_data_index == self.audio_data.len() / self.channels as usize {
-                    self.audio_data_index = 0;
-                }
+        Ok(())
+    }
 
-                // 100ms are needed for all blocks besides the first one
-                self.needed_frames = self.samples_in_100ms;
-            } else {
-                let (current, next) = src.split_at(num_frames);
+    /// Set the frequency weighting.
+    ///
+    /// The default is the K-weighting from ITU-R BS.1770, which is required for loudness
+    /// measurements according to EBU R128. The A- and C-weighting from IEC 61672-1 can be used
+    /// for SPL-style measurements instead. They are normalized to 0dB at 1kHz, but all values are
+    /// still calculated the same way as for the K-weighting, including the -0.691dB offset of
+    /// BS.1770 and the channel weights.
+    ///
+    /// Returns `Error::UnsupportedRate` for the A- and C-weighting if the sample rate is below
+    /// 8kHz. Changing the weighting resets the filter state, the same way as
+    /// [`EbuR128::change_parameters`](struct.EbuR128.html#method.change_parameters) does.
+    pub fn set_weighting(&mut self, weighting: Weighting) -> Result<(), Error> {
+        if weighting == self.filter.weighting() {
+            return Ok(());
+        }
 
-                self.filter.process(
-                    current,
-                    &mut *self.audio_data,
-                    self.audio_data_index,
-                    &self.channel_map,
-                );
+        self.filter = self.create_filter(self.rate, self.channels, weighting)?;
 
-                self.audio_data_index += num_frames;
-                if self.mode.contains(Mode::LRA) {
-                    self.short_term_frame_counter += num_frames;
-                }
+        Ok(())
+    }
 
-                src = next;
-                self.needed_frames -= num_frames;
-            }
+    /// Create a new filter for the configured modes, precision and true peak quality.
+    fn create_filter(
+        &self,
+        rate: u32,
+        channels: u32,
+        weighting: Weighting,
+    ) -> Result<crate::filter::Filter, Error> {
+        let true_peak = self.mode.contains(Mode::TRUE_PEAK);
+        let standard = self.true_peak_quality == TruePeakQuality::Standard;
+
+        let mut filter = crate::filter::Filter::new(
+            rate,
+            channels,
+            weighting,
+            self.mode.contains(Mode::SAMPLE_PEAK),
+            true_peak && standard,
+        )?;
+        if true_peak && !standard {
+            filter.set_true_peak(Some(crate::true_peak::TruePeak::with_quality(
+                rate,
+                channels,
+                self.true_peak_quality,
+            )?));
         }
+        filter.set_precision(self.filter.precision());
+        filter.set_overs_threshold(self.filter_overs_threshold());
 
-        let prev_sample_peak = self.filter.sample_peak();
-        for (sample_peak, prev_sample_peak) in
-            Iterator::zip(self.sample_peak.iter_mut(), prev_sample_peak.iter())
-        {
-            if *prev_sample_peak > *sample_peak {
-                *sample_peak = *prev_sample_peak;
-            }
-        }
+        Ok(filter)
+    }
 
-        let prev_true_peak = self.filter.true_peak();
-        for (true_peak, prev_true_peak) in
-            Iterator::zip(self.true_peak.iter_mut(), prev_true_peak.iter())
-        {
-            if *prev_true_peak > *true_peak {
-                *true_peak = *prev_true_peak;
-            }
+    /// Set the floating point precision of the frequency weighting and the filtered audio data.
+    ///
+    /// The default is `Precision::F64`, which gives the same results as libebur128. With
+    /// `Precision::F32` the frequency weighting is done in single precision as a cascade of
+    /// biquads, and the filtered audio data and the energy of each channel in a gating block are
+    /// stored and summed up in single precision. This is faster and needs half the memory for the
+    /// audio data of the [`EbuR128::max_window`](struct.EbuR128.html#method.max_window).
+    ///
+    /// The loudness values differ from the double precision ones by less than 0.1 LU, usually by
+    /// less than 0.01 LU. The largest deviations are for very low frequencies at high sample
+    /// rates. All values are still returned as `f64` and the history of the gating blocks is kept
+    /// in double precision. The sample peak is not affected and the true peak is always measured
+    /// in single precision.
+    ///
+    /// With `Precision::Fixed` the frequency weighting is done as a cascade of biquads in 32 bit
+    /// fixed point with 29 fractional bits, i.e. with 12dB of headroom above full scale, and the
+    /// energy of each channel in a gating block is summed up in 64 bit integers. Only integer
+    /// operations are needed per sample for integer input samples, and floating point operations
+    /// only once per gating block. Loudness values above the absolute gate of -70 LUFS differ
+    /// from the double precision ones by less than 0.001 LU. Quieter values can differ by more
+    /// because of the rounding noise of the filter, e.g. by 0.2 LU at -110 LUFS. The sample peak
+    /// and true peak are still measured in floating point and should be disabled on targets
+    /// without a floating point unit.
+    ///
+    /// The filtered audio data measured so far is converted to the new precision, but changing
+    /// the precision resets the filter state the same way as
+    /// [`EbuR128::set_weighting`](struct.EbuR128.html#method.set_weighting) does. Returns
+    /// `Error::NoMem` if the converted audio data can't be allocated.
+    pub fn set_precision(&mut self, precision: Precision) -> Result<(), Error> {
+        if precision == self.audio_data.precision() {
+            return Ok(());
         }
 
-        Ok(())
-    }
+        self.audio_data = self.audio_data.convert(precision)?;
+        self.filter.set_precision(precision);
 
-    fn seed_frames<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(&mut self, src: S) {
-        self.filter.seed(src, &self.channel_map);
+        Ok(())
     }
 
-    /// Add interleaved frames to be processed.
-    pub fn add_frames_i16(&mut self, frames: &[i16]) -> Result<(), Error> {
-        self.add_frames(crate::Interleaved::new(frames, self.channels as usize)?)
+    /// Enable or disable the dual mono compensation.
+    ///
+    /// A mono programme that is duplicated on two channels measures 3.01 LU (twice the energy)
+    /// louder than the mono programme itself. With the compensation enabled the energy of the
+    /// channels is halved, so that such input measures exactly like the mono programme measured
+    /// as a single channel.
+    ///
+    /// This affects the momentary, short term, window and integrated loudness and their maxima,
+    /// the relative threshold and the gating of the blocks, which also makes the loudness range
+    /// the same as for the mono programme. The peaks are not affected. The compensation is
+    /// applied to blocks measured while it is enabled and changing it does not change the history
+    /// measured so far.
+    ///
+    /// This is only meaningful for two measured channels that carry the same signal, e.g. the
+    /// default stereo channel map. A single channel set to `Channel::DualMono` is counted twice
+    /// for the opposite reason, i.e. to measure a mono programme as if it was played back on two
+    /// channels, and both should not be combined.
+    pub fn set_dual_mono_compensation(&mut self, enabled: bool) {
+        self.dual_mono_compensation = enabled;
     }
 
-    /// Add interleaved frames to be processed.
-    pub fn add_frames_i32(&mut self, frames: &[i32]) -> Result<(), Error> {
-        self.add_frames(crate::Interleaved::new(frames, self.channels as usize)?)
+    /// Enable or disable storing the energy of each channel for every gating block.
+    ///
+    /// This is needed for
+    /// [`EbuR128::loudness_global_subset`](struct.EbuR128.html#method.loudness_global_subset) and
+    /// is disabled by default. Only the gating blocks completed while it is enabled are stored,
+    /// up to the [maximum history](struct.EbuR128.html#method.set_max_history), which needs 8
+    /// bytes per channel for each gating block, i.e. 80 bytes per channel for each second of
+    /// audio with the default hop. Disabling it discards the stored energies.
+    pub fn set_channel_history(&mut self, enabled: bool) {
+        self.channel_history = enabled;
+        if !enabled {
+            self.channel_block_energies = VecDeque::new();
+        }
     }
 
-    /// Add interleaved frames to be processed.
-    pub fn add_frames_f32(&mut self, frames: &[f32]) -> Result<(), Error> {
-        self.add_frames(crate::Interleaved::new(frames, self.channels as usize)?)
+    /// Set the handling of NaN and infinite input samples.
+    ///
+    /// By default the input is not checked. Corrupted floating point input can contain such
+    /// samples and a single one makes all following loudness values NaN. With
+    /// `NonFiniteHandling::Reject` the `add_frames()` and `seed_frames()` functions return
+    /// `Error::NonFiniteSample` without changing any state, and with `NonFiniteHandling::Skip`
+    /// the samples are replaced with silence and counted in
+    /// [`EbuR128::non_finite_samples`](struct.EbuR128.html#method.non_finite_samples).
+    ///
+    /// Checking requires an additional pass over the input, and replacing the samples an
+    /// additional copy of it into an internal buffer. That buffer is kept and only grows up to
+    /// the [chunk size](struct.EbuR128.html#method.set_chunk_size), so that following calls with
+    /// up to the same number of frames don't allocate. Integer samples are never checked.
+    pub fn set_non_finite_handling(&mut self, handling: NonFiniteHandling) {
+        self.non_finite_handling = handling;
     }
 
-    /// Add interleaved frames to be processed.
-    pub fn add_frames_f64(&mut self, frames: &[f64]) -> Result<(), Error> {
-        self.add_frames(crate::Interleaved::new(frames, self.channels as usize)?)
-    }
+    /// Set the maximum number of frames processed at once.
+    ///
+    /// Bigger inputs passed to the `add_frames()` functions are split into chunks of this many
+    /// frames that are processed one after another, so that the input of a chunk is still in the
+    /// cache for all processing steps, e.g. the non-finite sample handling. This doesn't change
+    /// the results. The default is 8192 frames.
+    ///
+    /// Returns `Error::InvalidMode` if `frames` is 0.
+    pub fn set_chunk_size(&mut self, frames: usize) -> Result<(), Error> {
+        if frames == 0 {
+            return Err(Error::InvalidMode);
+        }
 
-    /// Add planar frames to be processed.
-    pub fn add_frames_planar_i16(&mut self, frames: &[&[i16]]) -> Result<(), Error> {
-        self.add_frames(crate::Planar::new(frames)?)
-    }
+        self.chunk_size = frames;
 
-    /// Add planar frames to be processed.
-    pub fn add_frames_planar_i32(&mut self, frames: &[&[i32]]) -> Result<(), Error> {
-        self.add_frames(crate::Planar::new(frames)?)
+        Ok(())
     }
 
-    /// Add planar frames to be processed.
-    pub fn add_frames_planar_f32(&mut self, frames: &[&[f32]]) -> Result<(), Error> {
-        self.add_frames(crate::Planar::new(frames)?)
-    }
+    /// Set the oversampling quality of the true peak measurement.
+    ///
+    /// The default is `TruePeakQuality::Standard`, which oversamples as recommended by ITU-R
+    /// BS.1770 and gives the same results as libebur128. Real-time meters that can tolerate a
+    /// less accurate true peak can use `TruePeakQuality::Fast`, see
+    /// [`TruePeakQuality`](enum.TruePeakQuality.html) for the expected errors.
+    ///
+    /// This can be called at any time. The maximum true peak measured so far is kept, but the new
+    /// interpolator starts without the previously added frames, like after
+    /// [`EbuR128::reset`](struct.EbuR128.html#method.reset). The quality is stored even if
+    /// `Mode::TRUE_PEAK` is not enabled and used once it is enabled.
+    pub fn set_true_peak_quality(&mut self, quality: TruePeakQuality) -> Result<(), Error> {
+        if quality == self.true_peak_quality {
+            return Ok(());
+        }
 
-    /// Add planar frames to be processed.
-    pub fn add_frames_planar_f64(&mut self, frames: &[&[f64]]) -> Result<(), Error> {
-        self.add_frames(crate::Planar::new(frames)?)
-    }
+        if self.mode.contains(Mode::TRUE_PEAK) {
+            self.filter
+                .set_true_peak(Some(crate::true_peak::TruePeak::with_quality(
+                    self.rate,
+                    self.channels,
+                    quality,
+                )?));
+        }
+        self.true_peak_quality = quality;
 
-    /// Add interleaved frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_i16(&mut self, frames: &[i16]) -> Result<(), Error> {
-        self.seed_frames(crate::Interleaved::new(frames, self.channels as usize)?);
         Ok(())
     }
 
-    /// Add interleaved frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_i32(&mut self, frames: &[i32]) -> Result<(), Error> {
-        self.seed_frames(crate::Interleaved::new(frames, self.channels as usize)?);
-        Ok(())
+    /// Replace the filter coefficients of the true peak interpolator, e.g. for experimenting with
+    /// other interpolation filters than the standard windowed sinc.
+    ///
+    /// `coeffs` contains the `taps * factor` coefficients of the FIR filter in the oversampled
+    /// domain, where `factor` is the
+    /// [oversampling factor](struct.EbuR128.html#method.true_peak_oversampling_factor): output
+    /// phase `o` of an input frame delayed by `i` frames uses `coeffs[i * factor + o]`. At most
+    /// 48 coefficients are supported for 2x and 4x oversampling and 96 for 8x oversampling, and
+    /// shorter filters are padded with zeros. The previously added frames stay in the
+    /// interpolator and the true peak measured so far is kept.
+    ///
+    /// The coefficients are kept by [`EbuR128::reset`](struct.EbuR128.html#method.reset) and
+    /// replaced with the standard ones whenever the interpolator is created again, i.e. when
+    /// changing the sample rate, the number of channels, the weighting or the true peak quality,
+    /// or when enabling `Mode::TRUE_PEAK` again. The results are not compliant with ITU-R
+    /// BS.1770 anymore.
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::TRUE_PEAK` is not enabled or the sample rate is
+    /// not oversampled, and `Error::NoMem` if `coeffs.len() != taps * factor` or if there are too
+    /// many taps.
+    ///
+    /// ```
+    /// # use ebur128::{EbuR128, Mode};
+    /// let mut ebu = EbuR128::new(1, 48_000, Mode::TRUE_PEAK | Mode::SAMPLE_PEAK).unwrap();
+    /// // 4x oversampling with linear interpolation between two frames
+    /// assert_eq!(ebu.true_peak_oversampling_factor(), Ok(4));
+    /// let linear = [0.0, 0.25, 0.5, 0.75, 1.0, 0.75, 0.5, 0.25];
+    /// ebu.set_true_peak_coefficients(2, &linear).unwrap();
+    ///
+    /// // Linear interpolation never overshoots the samples
+    /// ebu.add_frames_f32(&[0.0, 1.0, -1.0, 0.5]).unwrap();
+    /// assert_eq!(ebu.true_peak(0).unwrap(), ebu.sample_peak(0).unwrap());
+    /// ```
+    pub fn set_true_peak_coefficients(&mut self, taps: usize, coeffs: &[f64]) -> Result<(), Error> {
+        match self.filter.true_peak_interpolator_mut() {
+            Some(tp) if self.mode.contains(Mode::TRUE_PEAK) => tp.set_coefficients(taps, coeffs),
+            _ => Err(Error::InvalidMode),
+        }
     }
 
-    /// Add interleaved frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_f32(&mut self, frames: &[f32]) -> Result<(), Error> {
-        self.seed_frames(crate::Interleaved::new(frames, self.channels as usize)?);
-        Ok(())
-    }
+    /// Set the reference loudness in LUFS for the relative loudness in LU.
+    ///
+    /// This is the loudness that is displayed as 0 LU by
+    /// [`EbuR128::loudness_global_lu`](struct.EbuR128.html#method.loudness_global_lu),
+    /// [`EbuR128::loudness_momentary_lu`](struct.EbuR128.html#method.loudness_momentary_lu) and
+    /// [`EbuR128::loudness_shortterm_lu`](struct.EbuR128.html#method.loudness_shortterm_lu). The
+    /// default is the target level of EBU R128 of -23 LUFS, as used by the EBU +9 and +18 scales.
+    ///
+    /// Returns `Error::InvalidMode` if the reference loudness is not finite.
+    pub fn set_reference_loudness(&mut self, lufs: f64) -> Result<(), Error> {
+        if !lufs.is_finite() {
+            return Err(Error::InvalidMode);
+        }
 
-    /// Add interleaved frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_f64(&mut self, frames: &[f64]) -> Result<(), Error> {
-        self.seed_frames(crate::Interleaved::new(frames, self.channels as usize)?);
-        Ok(())
-    }
+        self.reference_loudness = lufs;
 
-    /// Add planar frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_planar_i16(&mut self, frames: &[&[i16]]) -> Result<(), Error> {
-        self.seed_frames(crate::Planar::new(frames)?);
         Ok(())
     }
 
-    /// Add planar frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_planar_i32(&mut self, frames: &[&[i32]]) -> Result<(), Error> {
-        self.seed_frames(crate::Planar::new(frames)?);
-        Ok(())
-    }
+    /// Set the linear threshold for counting the overs of the true peak measurement.
+    ///
+    /// See [`EbuR128::enable_true_peak_overs`](struct.EbuR128.html#method.enable_true_peak_overs)
+    /// for details. The default of 1.0 counts the interpolated samples above 0 dBTP, the
+    /// threshold for a level in dBTP is `10^(dbtp / 20)`. Changing the threshold keeps the
+    /// overs counted so far.
+    ///
+    /// Returns `Error::InvalidMode` if the threshold is negative or not finite.
+    pub fn set_true_peak_overs_threshold(&mut self, threshold: f64) -> Result<(), Error> {
+        if !threshold.is_finite() || threshold < 0.0 {
+            return Err(Error::InvalidMode);
+        }
+
+        self.true_peak_overs_threshold = threshold;
+        self.filter
+            .set_overs_threshold(self.filter_overs_threshold());
 
-    /// Add planar frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_planar_f32(&mut self, frames: &[&[f32]]) -> Result<(), Error> {
-        self.seed_frames(crate::Planar::new(frames)?);
         Ok(())
     }
 
-    /// Add planar frames to warmup filters, but not be considered for measurements.
-    /// See [`EbuR128::loudness_global_multiple`] for example usage.
-    pub fn seed_frames_planar_f64(&mut self, frames: &[&[f64]]) -> Result<(), Error> {
-        self.seed_frames(crate::Planar::new(frames)?);
-        Ok(())
+    /// Threshold for counting the overs in the filter, `None` if counting is disabled.
+    fn filter_overs_threshold(&self) -> Option<f32> {
+        if self.true_peak_overs_enabled {
+            Some(self.true_peak_overs_threshold as f32)
+        } else {
+            None
+        }
     }
 
-    /// Get global integrated loudness in LUFS.
-    pub fn loudness_global(&self) -> Result<f64, Error> {
-        if !self.mode.contains(Mode::I) {
+    /// Set the absolute gate in LUFS and the relative gate in LU for the integrated loudness.
+    ///
+    /// These are used by [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global)
+    /// and [`EbuR128::relative_threshold`](struct.EbuR128.html#method.relative_threshold): gating
+    /// blocks below the absolute gate are ignored, and the relative threshold is the loudness of
+    /// the remaining gating blocks plus the relative gate. The loudness range always uses the
+    /// gates of EBU Tech 3342.
+    ///
+    /// The defaults are the gates of ITU-R BS.1770, -70 LUFS and -10 LU. Other values give results
+    /// that are not compliant with BS.1770 and EBU R 128, which is only useful for research and
+    /// experiments. This can be changed at any time and applies to all measured gating blocks.
+    ///
+    /// Returns `Error::InvalidMode` if either gate is not finite or the absolute gate is below
+    /// -70 LUFS, as gating blocks below that are not stored.
+    pub fn set_gating(&mut self, absolute_lufs: f64, relative_lu: f64) -> Result<(), Error> {
+        if !absolute_lufs.is_finite()
+            || !relative_lu.is_finite()
+            || absolute_lufs < crate::history::DEFAULT_ABSOLUTE_GATE
+        {
             return Err(Error::InvalidMode);
         }
 
-        Ok(self.block_energy_history.gated_loudness())
+        self.gating = crate::history::Gating::new(absolute_lufs, relative_lu);
+
+        Ok(())
     }
 
-    /// Get global integrated loudness in LUFS across multiple instances.
+    /// Switch between the histogram and the queue for storing the loudness history.
     ///
-    /// This can be used to allow parallel iteration of long signals, assuming some care is taken:
-    ///  1. Divide input-signal up in "chunks" of even 100ms samples. Make chunks overlap by 400ms, for example (0-10s, 9.6-20s, 19.6-30s, ...)
-    ///  2. The first chunk is processed as normal. Then in parallel, for each remaining chunk, create a new instance of `EbuR128`, and in parallel:
-    ///     1. Feed the first 100ms of the chunk (these are samples overlapping with last chunk) through `seed_frames_*` function. This is sufficient to make filter-states in each instance what they would have been if a single analyzer would have reached this point.
-    ///     2. Process the remaining samples of each chunk through the analyzer
-    ///  3. Call [`EbuR128::loudness_global_multiple`] over all the chunks to get the global loudness
-    // FIXME: Should maybe be IntoIterator? Maybe AsRef<Self>?
-    pub fn loudness_global_multiple<'a>(
-        iter: impl Iterator<Item = &'a Self>,
-    ) -> Result<f64, Error> {
-        use smallvec::SmallVec;
+    /// This has the same effect as creating the instance with or without `Mode::HISTOGRAM`, but
+    /// keeps the loudness history that was measured so far.
+    ///
+    /// Converting from the queue to the histogram gives exactly the same results as if the
+    /// histogram was used from the beginning. Converting from the histogram to the queue is
+    /// lossy: the exact loudness of each block is not known anymore and the center of its
+    /// histogram bin is used instead, so the results stay as precise as with the histogram
+    /// until enough new blocks were measured. If the maximum history is smaller than the number
+    /// of blocks in the histogram, blocks from the lowest histogram bins are dropped first.
+    pub fn set_history_mode(&mut self, use_histogram: bool) -> Result<(), Error> {
+        self.block_energy_history.set_use_histogram(
+            use_histogram,
+            self.history / self.block_hop as usize,
+            self.histogram_resolution,
+        );
+        self.short_term_block_energy_history.set_use_histogram(
+            use_histogram,
+            self.history / 3000,
+            self.histogram_resolution,
+        );
+        self.short_term_block_energy_history.cache_loudness_range();
+        self.mode.set(Mode::HISTOGRAM, use_histogram);
 
-        let h = iter
-            .map(|e| {
-                if !e.mode.contains(Mode::I) {
-                    Err(Error::InvalidMode)
-                } else {
-                    Ok(&e.block_energy_history)
-                }
-            })
-            .collect::<Result<SmallVec<[_; 16]>, _>>()?;
+        Ok(())
+    }
 
-        Ok(crate::history::History::gated_loudness_multiple(&*h))
+    /// Enable additional modes after construction.
+    ///
+    /// The buffers needed for the new modes are allocated, e.g. the true peak interpolator and
+    /// the loudness histories. The maximum window and history are enlarged to the minimum of the
+    /// new modes if needed, see
+    /// [`EbuR128::set_max_window`](struct.EbuR128.html#method.set_max_window).
+    ///
+    /// Newly enabled modes only measure the frames added from now on. E.g. the integrated
+    /// loudness after enabling `Mode::I` only includes the gating blocks completed afterwards,
+    /// and the short term loudness after enabling `Mode::S` includes silence until 3s of audio
+    /// were added. Already enabled modes continue their measurement.
+    ///
+    /// Returns `Error::UnsupportedRate` if `Mode::TRUE_PEAK` is enabled for a sample rate that
+    /// the true peak measurement does not support, and `Error::NoMem` if the larger window can't
+    /// be allocated. The state is not changed in that case.
+    pub fn enable_mode(&mut self, mode: Mode) -> Result<(), Error> {
+        self.set_mode(self.mode | mode)
     }
 
-    fn energy_in_interval(&self, interval_frames: usize) -> Result<f64, Error> {
-        if interval_frames > self.audio_data.len() / self.channels as usize {
-            return Err(Error::InvalidMode);
+    /// Disable modes after construction.
+    ///
+    /// The measurements of the disabled modes are discarded and their buffers are freed, so
+    /// enabling them again starts from scratch. Modes that include a disabled mode are disabled
+    /// too, e.g. disabling `Mode::S` also disables `Mode::LRA` and disabling `Mode::SAMPLE_PEAK`
+    /// also disables `Mode::TRUE_PEAK`. The maximum window and history are kept.
+    ///
+    /// Modes that are included in another of the given modes are not disabled themselves, e.g.
+    /// `Mode::TRUE_PEAK` only disables the true peak but keeps the sample peak.
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::M` would be disabled as no loudness would be
+    /// measured anymore.
+    pub fn disable_mode(&mut self, mode: Mode) -> Result<(), Error> {
+        const MODES: [Mode; 7] = [
+            Mode::M,
+            Mode::S,
+            Mode::I,
+            Mode::LRA,
+            Mode::SAMPLE_PEAK,
+            Mode::TRUE_PEAK,
+            Mode::HISTOGRAM,
+        ];
+        // Flags of the modes included in the given mode
+        let included = |m: Mode| {
+            MODES
+                .iter()
+                .filter(|d| **d != m && m.contains(**d))
+                .fold(Mode::empty(), |acc, d| acc | *d)
+        };
+        let given = MODES.iter().filter(|m| mode.contains(**m));
+        let implied = given
+            .clone()
+            .fold(Mode::empty(), |acc, m| acc | included(*m));
+
+        // The flags of each mode include the flags of the modes it depends on. Only clear the
+        // flags specific to each disabled mode, which are not part of a mode it includes.
+        let mut remaining = self.mode;
+        for m in given.filter(|m| !implied.contains(**m)) {
+            remaining &= !(*m & !included(*m));
         }
 
-        Ok(crate::filter::Filter::calc_gating_block(
-            interval_frames,
-            &self.audio_data,
-            self.audio_data_index,
-            &self.channel_map,
-        ))
+        // Only keep the modes whose flags are all still set
+        let mode = MODES
+            .iter()
+            .filter(|m| remaining.contains(**m))
+            .fold(Mode::empty(), |acc, m| acc | *m);
+
+        self.set_mode(mode)
     }
 
-    /// Get momentary loudness (last 400ms) in LUFS.
-    pub fn loudness_momentary(&self) -> Result<f64, Error> {
-        let energy = self.energy_in_interval(self.samples_in_100ms * 4)?;
+    fn set_mode(&mut self, mode: Mode) -> Result<(), Error> {
+        if !mode.contains(Mode::M) {
+            return Err(Error::InvalidMode);
+        }
 
-        if energy <= 0.0 {
-            return Ok(-std::f64::INFINITY);
+        if mode == self.mode {
+            return Ok(());
         }
 
-        Ok(energy_to_loudness(energy))
-    }
+        let tp = if mode.contains(Mode::TRUE_PEAK) && self.filter.true_peak_latency().is_none() {
+            Some(crate::true_peak::TruePeak::with_quality(
+                self.rate,
+                self.channels,
+                self.true_peak_quality,
+            )?)
+        } else {
+            None
+        };
 
-    fn energy_shortterm(&self) -> Result<f64, Error> {
-        self.energy_in_interval(self.samples_in_100ms * 30)
-    }
+        let old_mode = self.mode;
+        self.mode = mode;
 
-    /// Get short-term loudness (last 3s) in LUFS.
-    pub fn loudness_shortterm(&self) -> Result<f64, Error> {
-        let energy = self.energy_shortterm()?;
+        // Grow the window and history to the minimum of the new modes. Growing the history
+        // can't fail and keeps the measured gating blocks.
+        if self.history != usize::MAX {
+            let history = self.history as u32;
+            self.set_max_history(history)?;
+        }
+        let window = self.window as u32;
+        if let Err(err) = self.set_max_window(window) {
+            self.mode = old_mode;
+            return Err(err);
+        }
 
-        if energy <= 0.0 {
-            return Ok(-std::f64::INFINITY);
+        self.filter
+            .set_calculate_sample_peak(mode.contains(Mode::SAMPLE_PEAK));
+        if !mode.contains(Mode::SAMPLE_PEAK) {
+            self.sample_peak.fill(0.0);
+        }
+        if mode.contains(Mode::TRUE_PEAK) {
+            if tp.is_some() {
+                self.filter.set_true_peak(tp);
+            }
+        } else {
+            self.filter.set_true_peak(None);
+            self.true_peak.fill(0.0);
+            self.true_peak_overs.fill(0);
         }
 
-        Ok(energy_to_loudness(energy))
+        let use_histogram = mode.contains(Mode::HISTOGRAM);
+        if old_mode.contains(Mode::I) && !mode.contains(Mode::I) {
+            self.block_energy_history =
+                crate::history::History::new(use_histogram, self.history / self.block_hop as usize);
+            self.block_energy_history
+                .set_histogram_resolution(self.histogram_resolution);
+            self.integrated_blocks = 0;
+            self.channel_block_energies = VecDeque::new();
+        }
+        if !mode.contains(Mode::S) {
+            self.shortterm_max_energy = 0.0;
+        }
+        if old_mode.contains(Mode::LRA) && !mode.contains(Mode::LRA) {
+            self.short_term_block_energy_history =
+                crate::history::History::new(use_histogram, self.history / 3000);
+            self.short_term_block_energy_history
+                .set_histogram_resolution(self.histogram_resolution);
+            self.short_term_block_energy_history.cache_loudness_range();
+            self.short_term_blocks = 0;
+            self.short_term_frame_counter = 0;
+        }
+        if use_histogram != old_mode.contains(Mode::HISTOGRAM) {
+            self.set_history_mode(use_histogram)?;
+        }
+
+        Ok(())
     }
 
-    /// Get loudness of the specified window in LUFS.
+    /// Resets the current state.
     ///
-    /// window must not be larger than the current window. The current window can be changed by
-    /// calling [`EbuR128::set_max_window`](struct.EbuR128.html#method.set_max_window).
-    pub fn loudness_window(&self, window: u32) -> Result<f64, Error> {
-        let interval_frames = (self.rate as usize)
-            .checked_mul(window as usize)
-            .ok_or(Error::InvalidMode)?
-            / 1000;
-        let energy = self.energy_in_interval(interval_frames)?;
+    /// This clears all measured loudness history, peaks and filter state but keeps the
+    /// configuration, including the channel map, maximum window and maximum history. Afterwards
+    /// the instance behaves exactly like a newly created one with the same configuration, which
+    /// allows reusing it for measuring multiple files without reallocating.
+    pub fn reset(&mut self) {
+        self.audio_data.reset();
 
-        if energy <= 0.0 {
-            return Ok(-std::f64::INFINITY);
-        }
+        // the first block needs 400ms of audio data
+        self.needed_frames = self.samples_in_100ms * 4;
+        // start at the beginning of the buffer
+        self.audio_data_index = 0;
+        // reset short term frame counter
+        self.short_term_frame_counter = 0;
 
-        Ok(energy_to_loudness(energy))
+        self.momentary_max_energy = 0.0;
+        self.shortterm_max_energy = 0.0;
+        self.momentary_timeseries.clear();
+        self.shortterm_timeseries.clear();
+        self.shortterm_timeseries_timed.clear();
+        self.block_energies.clear();
+        self.block_timestamps.clear();
+        self.non_finite_samples = 0;
+        self.gating_blocks = 0;
+        self.short_term_blocks = 0;
+        self.integrated_blocks = 0;
+        self.last_block_energy = 0.0;
+        self.buffered_frames = 0;
+        self.frames_processed = 0;
+        self.channel_block_energies.clear();
+        self.pending_samples.clear();
+
+        self.reset_peaks();
+
+        self.filter.reset();
+        self.block_energy_history.reset();
+        self.short_term_block_energy_history.reset();
     }
 
-    /// Get loudness range (LRA) of programme in LU.
+    /// Take a snapshot of the current measurement state.
     ///
-    /// Calculates loudness range according to EBU 3342.
-    pub fn loudness_range(&self) -> Result<f64, Error> {
-        if !self.mode.contains(Mode::LRA) {
-            return Err(Error::InvalidMode);
+    /// The snapshot contains everything that changes while adding frames: the buffered audio,
+    /// the filter and true peak states, the histories, the peaks, the recorded values and all
+    /// counters. Restoring it with [`EbuR128::restore`](struct.EbuR128.html#method.restore)
+    /// continues the measurement from this point, e.g. for measuring a region and then rolling
+    /// back to try a different boundary. In contrast to `Clone` the configuration is not copied,
+    /// and in contrast to serialization this doesn't need the `serde` feature.
+    pub fn snapshot(&self) -> EbuR128Snapshot {
+        EbuR128Snapshot {
+            mode: self.mode,
+            rate: self.rate,
+            channels: self.channels,
+            window: self.window,
+            history: self.history,
+            block_hop: self.block_hop,
+            weighting: self.filter.weighting(),
+            audio_data: self.audio_data.clone(),
+            audio_data_index: self.audio_data_index,
+            needed_frames: self.needed_frames,
+            filter: self.filter.state(),
+            block_energy_history: self.block_energy_history.clone(),
+            short_term_block_energy_history: self.short_term_block_energy_history.clone(),
+            short_term_frame_counter: self.short_term_frame_counter,
+            momentary_max_energy: self.momentary_max_energy,
+            shortterm_max_energy: self.shortterm_max_energy,
+            momentary_timeseries: self.momentary_timeseries.clone(),
+            shortterm_timeseries: self.shortterm_timeseries.clone(),
+            shortterm_timeseries_timed: self.shortterm_timeseries_timed.clone(),
+            block_energies: self.block_energies.clone(),
+            block_timestamps: self.block_timestamps.clone(),
+            sample_peak: self.sample_peak.clone(),
+            true_peak: self.true_peak.clone(),
+            true_peak_overs: self.true_peak_overs.clone(),
+            non_finite_samples: self.non_finite_samples,
+            gating_blocks: self.gating_blocks,
+            short_term_blocks: self.short_term_blocks,
+            integrated_blocks: self.integrated_blocks,
+            last_block_energy: self.last_block_energy,
+            buffered_frames: self.buffered_frames,
+            frames_processed: self.frames_processed,
+            channel_block_energies: self.channel_block_energies.clone(),
+            pending_samples: self.pending_samples.clone(),
         }
-
-        Ok(self.short_term_block_energy_history.loudness_range())
     }
 
-    /// Get loudness range (LRA) of programme in LU across multiple instances.
+    /// Restore the measurement state of a snapshot, overwriting the current state.
     ///
-    /// Calculates loudness range according to EBU 3342.
-    // FIXME: Should maybe be IntoIterator? Maybe AsRef<Self>?
-    pub fn loudness_range_multiple<'a>(
-        iter: impl IntoIterator<Item = &'a Self>,
-    ) -> Result<f64, Error> {
-        use smallvec::SmallVec;
-
-        let h = iter
-            .into_iter()
-            .map(|e| {
-                if !e.mode.contains(Mode::LRA) {
-                    Err(Error::InvalidMode)
-                } else {
-                    Ok(&e.short_term_block_energy_history)
-                }
-            })
-            .collect::<Result<SmallVec<[_; 16]>, _>>()?;
-
-        crate::history::History::loudness_range_multiple(&*h)
-    }
-
-    /// Get maximum sample peak from all frames that have been processed.
+    /// The snapshot can be restored any number of times, also into another instance with the
+    /// same configuration. Settings that don't affect the stored state, like the gates or the
+    /// channel map, are kept as currently configured. Recorded values whose recording was
+    /// disabled since the snapshot was taken are dropped.
     ///
-    /// The equation to convert to dBFS is: 20 * log10(out)
-    pub fn sample_peak(&self, channel_number: u32) -> Result<f64, Error> {
-        if !self.mode.contains(Mode::SAMPLE_PEAK) {
+    /// Returns `Error::InvalidMode` without changing anything if the snapshot was taken with a
+    /// different mode, sample rate, number of channels, maximum window, maximum history, block
+    /// hop, precision, weighting or histogram resolution.
+    pub fn restore(&mut self, snapshot: &EbuR128Snapshot) -> Result<(), Error> {
+        if self.mode != snapshot.mode
+            || self.rate != snapshot.rate
+            || self.channels != snapshot.channels
+            || self.window != snapshot.window
+            || self.history != snapshot.history
+            || self.block_hop != snapshot.block_hop
+            || self.filter.weighting() != snapshot.weighting
+            || core::mem::discriminant(&self.audio_data)
+                != core::mem::discriminant(&snapshot.audio_data)
+            || self.audio_data.len() != snapshot.audio_data.len()
+            || self.block_energy_history.histogram_resolution()
+                != snapshot.block_energy_history.histogram_resolution()
+            || self.short_term_block_energy_history.histogram_resolution()
+                != snapshot
+                    .short_term_block_energy_history
+                    .histogram_resolution()
+        {
             return Err(Error::InvalidMode);
         }
 
-        if channel_number >= self.channels {
-            return Err(Error::InvalidChannelIndex);
+        self.filter
+            .set_state(&snapshot.filter)
+            .map_err(|_| Error::InvalidMode)?;
+
+        self.audio_data.clone_from(&snapshot.audio_data);
+        self.audio_data_index = snapshot.audio_data_index;
+        self.needed_frames = snapshot.needed_frames;
+        self.block_energy_history
+            .clone_from(&snapshot.block_energy_history);
+        self.short_term_block_energy_history
+            .clone_from(&snapshot.short_term_block_energy_history);
+        self.short_term_frame_counter = snapshot.short_term_frame_counter;
+        self.momentary_max_energy = snapshot.momentary_max_energy;
+        self.shortterm_max_energy = snapshot.shortterm_max_energy;
+        self.momentary_timeseries.clear();
+        self.shortterm_timeseries.clear();
+        if self.timeseries {
+            self.momentary_timeseries
+                .extend_from_slice(&snapshot.momentary_timeseries);
+            self.shortterm_timeseries
+                .extend_from_slice(&snapshot.shortterm_timeseries);
+        }
+        self.shortterm_timeseries_timed.clear();
+        if self.shortterm_timeseries_timed_enabled {
+            self.shortterm_timeseries_timed
+                .extend_from_slice(&snapshot.shortterm_timeseries_timed);
         }
+        self.block_energies.clear();
+        self.block_timestamps.clear();
+        if self.block_energies_enabled {
+            self.block_energies
+                .extend_from_slice(&snapshot.block_energies);
+            self.block_timestamps
+                .extend_from_slice(&snapshot.block_timestamps);
+        }
+        self.sample_peak.copy_from_slice(&snapshot.sample_peak);
+        self.true_peak.copy_from_slice(&snapshot.true_peak);
+        self.true_peak_overs
+            .copy_from_slice(&snapshot.true_peak_overs);
+        self.non_finite_samples = snapshot.non_finite_samples;
+        self.gating_blocks = snapshot.gating_blocks;
+        self.short_term_blocks = snapshot.short_term_blocks;
+        self.integrated_blocks = snapshot.integrated_blocks;
+        self.last_block_energy = snapshot.last_block_energy;
+        self.buffered_frames = snapshot.buffered_frames;
+        self.frames_processed = snapshot.frames_processed;
+        self.channel_block_energies.clear();
+        if self.channel_history {
+            self.channel_block_energies
+                .extend(snapshot.channel_block_energies.iter().copied());
+        }
+        self.pending_samples.clear();
+        self.pending_samples
+            .extend_from_slice(&snapshot.pending_samples);
 
-        Ok(self.sample_peak[channel_number as usize])
+        Ok(())
     }
 
-    /// Get maximum sample peak from the last call to
-    /// [`EbuR128::add_frames`](struct.EbuR128.html#method.add_frames_i16).
+    /// Resets the maximum sample and true peaks.
     ///
-    /// The equation to convert to dBFS is: 20 * log10(out)
-    pub fn prev_sample_peak(&self, channel_number: u32) -> Result<f64, Error> {
-        if !self.mode.contains(Mode::SAMPLE_PEAK) {
-            return Err(Error::InvalidMode);
-        }
+    /// Afterwards [`EbuR128::sample_peak`](struct.EbuR128.html#method.sample_peak) and
+    /// [`EbuR128::true_peak`](struct.EbuR128.html#method.true_peak) only include the frames added
+    /// after this call, e.g. for showing the peak of each refresh interval in a meter. The
+    /// loudness history and filter state are not changed. The peaks of the last call to
+    /// [`EbuR128::add_frames`](struct.EbuR128.html#method.add_frames_i16) as returned by
+    /// [`EbuR128::prev_sample_peak`](struct.EbuR128.html#method.prev_sample_peak) and
+    /// [`EbuR128::prev_true_peak`](struct.EbuR128.html#method.prev_true_peak) are also kept, so
+    /// right after resetting they still report that call's peaks until frames are added again.
+    pub fn reset_peaks(&mut self) {
+        self.true_peak.fill(0.0);
+        self.true_peak_overs.fill(0);
+        self.sample_peak.fill(0.0);
+    }
 
-        if channel_number >= self.channels {
+    /// Process frames. This is the generic variant of the different public add_frames() functions
+    /// that are defined below.
+    fn add_samples<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+        on_block: Option<&mut dyn FnMut(f64)>,
+        filtered: Option<&mut [f64]>,
+        oversampled: Option<(&mut [f32], usize)>,
+    ) -> Result<(), Error> {
+        if src.channels() != self.channels as usize {
             return Err(Error::InvalidChannelIndex);
         }
 
-        Ok(self.filter.sample_peak()[channel_number as usize])
-    }
+        if let Some(ref filtered) = filtered {
+            if filtered.len() != src.frames() * src.channels() {
+                return Err(Error::NoMem);
+            }
+        }
 
-    /// Get maximum true peak from all frames that have been processed.
-    ///
-    /// Uses an implementation defined algorithm to calculate the true peak. Do not try to compare
-    /// resulting values across different versions of the library, as the algorithm may change.
-    ///
-    /// The current implementation uses a custom polyphase FIR interpolator to calculate true peak.
-    /// Will oversample 4x for sample rates < 96000 Hz, 2x for sample rates < 192000 Hz and leave
-    /// the signal unchanged for 192000 Hz.
-    ///
-    /// The equation to convert to dBTP is: 20 * log10(out)
-    pub fn true_peak(&self, channel_number: u32) -> Result<f64, Error> {
-        if !self.mode.contains(Mode::TRUE_PEAK) {
-            return Err(Error::InvalidMode);
+        let factor = self.filter.true_peak_oversampling_factor().unwrap_or(1);
+        if let Some((ref oversampled, stride)) = oversampled {
+            let len = src.frames() * factor;
+            if stride < len || oversampled.len() < (src.channels() - 1) * stride + len {
+                return Err(Error::NoMem);
+            }
         }
 
-        if channel_number >= self.channels {
-            return Err(Error::InvalidChannelIndex);
+        if src.frames() == 0 {
+            return Ok(());
         }
 
-        if self.sample_peak[channel_number as usize] > self.true_peak[channel_number as usize] {
-            Ok(self.sample_peak[channel_number as usize])
-        } else {
-            Ok(self.true_peak[channel_number as usize])
+        if self.channels == 0 {
+            return Err(Error::NoMem);
         }
+
+        // Nothing is processed if any sample is rejected
+        if self.non_finite_handling == NonFiniteHandling::Reject {
+            self.check_non_finite(&src)?;
+        }
+
+        self.filter.reset_peaks();
+        self.process_chunks(src, on_block, filtered, oversampled)?;
+        self.update_peaks();
+
+        Ok(())
     }
 
-    /// Get maximum true peak from the last call to
-    /// [`EbuR128::add_frames`](struct.EbuR128.html#method.add_frames_i16).
-    ///
-    /// Uses an implementation defined algorithm to calculate the true peak. Do not try to compare
-    /// resulting values across different versions of the library, as the algorithm may change.
-    ///
-    /// The current implementation uses a custom polyphase FIR interpolator to calculate true peak.
-    /// Will oversample 4x for sample rates < 96000 Hz, 2x for sample rates < 192000 Hz and leave
-    /// the signal unchanged for 192000 Hz.
-    ///
-    /// The equation to convert to dBTP is: 20 * log10(out)
-    pub fn prev_true_peak(&self, channel_number: u32) -> Result<f64, Error> {
-        if !self.mode.contains(Mode::TRUE_PEAK) {
-            return Err(Error::InvalidMode);
+    /// Process frames in chunks of at most the configured chunk size, skipping non-finite samples
+    /// if configured. This doesn't reset or update the peaks.
+    fn process_chunks<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        mut src: S,
+        mut on_block: Option<&mut dyn FnMut(f64)>,
+        mut filtered: Option<&mut [f64]>,
+        mut oversampled: Option<(&mut [f32], usize)>,
+    ) -> Result<(), Error> {
+        // Process big inputs in chunks so that the non-finite sample handling only needs one pass
+        // over the input that is still in the cache
+        let factor = self.filter.true_peak_oversampling_factor().unwrap_or(1);
+        let mut filtered_index = 0;
+        while src.frames() > 0 {
+            let frames = core::cmp::min(self.chunk_size, src.frames());
+            let (chunk, next) = src.split_at(frames);
+            let on_block = on_block.as_deref_mut();
+            let filtered = filtered.as_deref_mut().map(|f| (f, filtered_index));
+            let oversampled = oversampled
+                .as_mut()
+                .map(|(o, stride)| (&mut o[filtered_index * factor..], *stride));
+
+            let replaced = self.non_finite_handling == NonFiniteHandling::Skip
+                && self.check_non_finite(&chunk)?;
+            if replaced {
+                // Take the scratch buffer out temporarily so that it can be borrowed while
+                // processing
+                let scratch = core::mem::take(&mut self.scratch);
+                let res = self.process_frames(
+                    crate::Interleaved::new(
+                        &scratch[..frames * chunk.channels()],
+                        self.channels as usize,
+                    )?,
+                    on_block,
+                    filtered,
+                    oversampled,
+                );
+                self.scratch = scratch;
+                res?;
+            } else {
+                self.process_frames(chunk, on_block, filtered, oversampled)?;
+            }
+
+            src = next;
+            filtered_index += frames;
         }
 
-        if channel_number >= self.channels {
-            return Err(Error::InvalidChannelIndex);
+        Ok(())
+    }
+
+    /// Check the input for non-finite samples according to the configured handling.
+    ///
+    /// Returns `true` if there are non-finite samples that should be skipped. An interleaved copy
+    /// of the input with these samples replaced with silence is stored at the beginning of the
+    /// scratch buffer in that case.
+    fn check_non_finite<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: &S,
+    ) -> Result<bool, Error> {
+        if self.non_finite_handling == NonFiniteHandling::Propagate {
+            return Ok(false);
         }
 
-        let sample_peak = self.filter.sample_peak();
-        let true_peak = self.filter.true_peak();
+        let channels = src.channels();
+        let mut non_finite = 0;
+        for c in 0..channels {
+            src.foreach_sample(c, |s| {
+                if !s.is_finite() {
+                    non_finite += 1;
+                }
+            });
+        }
 
-        if sample_peak[channel_number as usize] > true_peak[channel_number as usize] {
-            Ok(sample_peak[channel_number as usize])
-        } else {
-            Ok(true_peak[channel_number as usize])
+        if non_finite == 0 {
+            return Ok(false);
         }
-    }
 
-    /// Get relative threshold in LUFS.
-    pub fn relative_threshold(&self) -> Result<f64, Error> {
-        if !self.mode.contains(Mode::I) {
-            return Err(Error::InvalidMode);
+        if self.non_finite_handling == NonFiniteHandling::Reject {
+            return Err(Error::NonFiniteSample);
         }
 
-        Ok(self.block_energy_history.relative_threshold())
-    }
-}
+        self.non_finite_samples += non_finite;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    #[cfg(feature = "c-tests")]
-    use crate::tests::Signal;
-    use float_eq::assert_float_eq;
-    #[cfg(feature = "c-tests")]
-    use quickcheck_macros::quickcheck;
+        // The scratch buffer only ever grows so that it doesn't have to be reallocated for
+        // following calls with the same number of frames
+        let len = src.frames() * channels;
+        if self.scratch.len() < len {
+            self.scratch.resize(len, 0.0);
+        }
 
-    fn f64_max(mut values: impl Iterator<Item = f64>) -> Option<f64> {
-        let mut v = values.next()?;
-        for candidate in values {
-            if candidate > v {
-                v = candidate
-            }
+        // Converting to f64 is lossless for all floating point sample types
+        for c in 0..channels {
+            src.foreach_sample_zipped(
+                c,
+                self.scratch[..len].chunks_exact_mut(channels),
+                |s, frame| {
+                    frame[c] = if s.is_finite() {
+                        s.to_sample::<f64>()
+                    } else {
+                        0.0
+                    };
+                },
+            );
         }
-        Some(v)
+
+        Ok(true)
     }
 
-    #[test]
-    fn sine_stereo_i16() {
-        let mut data = vec![0i16; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator) * (std::i16::MAX - 1) as f32;
-            out[0] = val as i16;
-            out[1] = val as i16;
-            accumulator += step;
-        }
+    /// Process frames without resetting the peaks of the previous call or updating the overall
+    /// peaks. This allows processing one call of the public add_frames() functions in multiple
+    /// chunks.
+    ///
+    /// `on_block` is called with the momentary loudness whenever a gating block is completed and
+    /// the filtered samples are copied to `filtered`, one channel after another, starting at the
+    /// given index in each channel. The oversampled signal of the true peak measurement is written
+    /// to the beginning of `oversampled` with the given stride between the channels.
+    fn process_frames<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        mut src: S,
+        mut on_block: Option<&mut (dyn FnMut(f64) + '_)>,
+        filtered: Option<(&mut [f64], usize)>,
+        mut oversampled: Option<(&mut [f32], usize)>,
+    ) -> Result<(), Error> {
+        let (mut filtered, mut filtered_index) = match filtered {
+            Some((filtered, index)) => (Some(filtered), index),
+            None => (None, 0),
+        };
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
-        ebu.add_frames_i16(&data).unwrap();
+        let factor = self.filter.true_peak_oversampling_factor().unwrap_or(1);
+        let audio_data_frames = self.audio_data.len() / self.channels as usize;
+        while src.frames() > 0 {
+            // The audio buffer is a multiple of 100ms, other hops might not fill it exactly
+            let num_frames =
+                core::cmp::min(src.frames(), audio_data_frames - self.audio_data_index);
 
+            if num_frames >= self.needed_frames {
+                let (current, next) = src.split_at(self.needed_frames);
+
+                self.audio_data.process(
+                    &mut self.filter,
+                    current,
+                    self.audio_data_index,
+                    &self.channel_map,
+                    oversampled.as_mut().map(|(o, stride)| (&mut **o, *stride)),
+                )?;
+                oversampled =
+                    oversampled.map(|(o, stride)| (&mut o[self.needed_frames * factor..], stride));
+
+                if let Some(ref mut filtered) = filtered {
+                    self.copy_filtered(filtered, filtered_index, self.needed_frames);
+                    filtered_index += self.needed_frames;
+                }
+
+                src = next;
+                self.audio_data_index += self.needed_frames;
+                self.buffered_frames =
+                    usize::min(self.buffered_frames + self.needed_frames, audio_data_frames);
+                self.frames_processed += self.needed_frames as u64;
+                self.gating_blocks += 1;
+
+                if self.mode.contains(Mode::M) {
+                    let energy = self.calc_energy(self.samples_in_100ms * 4);
+                    self.last_block_energy = energy;
+
+                    if energy > self.momentary_max_energy {
+                        self.momentary_max_energy = energy;
+                    }
+
+                    if self.timeseries {
+                        self.momentary_timeseries.push(block_loudness(energy));
+                    }
+
+                    if self.block_energies_enabled && self.mode.contains(Mode::I) {
+                        self.block_energies.push(energy);
+                        self.block_timestamps.push(self.frames_processed);
+                    }
+
+                    if self.mode.contains(Mode::I) {
+                        self.block_energy_history.add(energy);
+                        self.integrated_blocks += 1;
+
+                        // Blocks below the lower boundary are not stored in the history either,
+                        // so that both contain the same blocks if the history is limited
+                        if self.channel_history && energy >= crate::histogram_bins::BOUNDARIES[0] {
+                            self.add_channel_block_energies();
+                        }
+                    }
+
+                    if let Some(ref mut on_block) = on_block {
+                        on_block(block_loudness(energy));
+                    }
+                }
+
+                // The short term energy is a pass over the whole 3s window, so calculate it at
+                // most once per block and only if something needs it
+                let mut shortterm_energy = None;
+                if self.mode.contains(Mode::S) && (self.shortterm_max_enabled || self.timeseries) {
+                    let energy = self.energy_shortterm()?;
+                    shortterm_energy = Some(energy);
+
+                    if self.shortterm_max_enabled && energy > self.shortterm_max_energy {
+                        self.shortterm_max_energy = energy;
+                    }
+
+                    if self.timeseries {
+                        self.shortterm_timeseries.push(block_loudness(energy));
+                    }
+                }
+
+                if self.mode.contains(Mode::LRA) {
+                    self.short_term_frame_counter += self.needed_frames;
+                    if self.short_term_frame_counter >= self.samples_in_100ms * 30 {
+                        let energy = match shortterm_energy {
+                            Some(energy) => energy,
+                            None => self.energy_shortterm()?,
+                        };
+                        self.short_term_block_energy_history.add(energy);
+                        self.short_term_blocks += 1;
+                        if self.shortterm_timeseries_timed_enabled {
+                            let time = self.duration_processed();
+                            self.shortterm_timeseries_timed
+                                .push((time, block_loudness(energy)));
+                        }
+                        self.short_term_frame_counter -= self.samples_in_100ms * 10;
+                    }
+                }
+
+                if self.audio_data_index == audio_data_frames {
+                    self.audio_data_index = 0;
+                }
+
+                // One hop is needed for all blocks besides the first one
+                self.needed_frames = self.samples_per_hop;
+            } else {
+                let (current, next) = src.split_at(num_frames);
+
+                self.audio_data.process(
+                    &mut self.filter,
+                    current,
+                    self.audio_data_index,
+                    &self.channel_map,
+                    oversampled.as_mut().map(|(o, stride)| (&mut **o, *stride)),
+                )?;
+                oversampled =
+                    oversampled.map(|(o, stride)| (&mut o[num_frames * factor..], stride));
+
+                if let Some(ref mut filtered) = filtered {
+                    self.copy_filtered(filtered, filtered_index, num_frames);
+                    filtered_index += num_frames;
+                }
+
+                self.audio_data_index += num_frames;
+                self.buffered_frames =
+                    usize::min(self.buffered_frames + num_frames, audio_data_frames);
+                self.frames_processed += num_frames as u64;
+                if self.mode.contains(Mode::LRA) {
+                    self.short_term_frame_counter += num_frames;
+                }
+
+                src = next;
+                self.needed_frames -= num_frames;
+
+                if self.audio_data_index == audio_data_frames {
+                    self.audio_data_index = 0;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copy the last `frames` filtered frames from the audio buffer to `filtered`, starting at
+    /// `index` in each channel.
+    fn copy_filtered(&self, filtered: &mut [f64], index: usize, frames: usize) {
+        self.audio_data.copy_channels(
+            self.audio_data_index,
+            &self.channel_map,
+            filtered,
+            index,
+            frames,
+        );
+    }
+
+    /// Update the overall peaks with the peaks from the last call.
+    fn update_peaks(&mut self) {
+        let prev_sample_peak = self.filter.sample_peak();
+        for (sample_peak, prev_sample_peak) in
+            Iterator::zip(self.sample_peak.iter_mut(), prev_sample_peak.iter())
+        {
+            if *prev_sample_peak > *sample_peak {
+                *sample_peak = *prev_sample_peak;
+            }
+        }
+
+        let prev_true_peak = self.filter.true_peak();
+        for (true_peak, prev_true_peak) in
+            Iterator::zip(self.true_peak.iter_mut(), prev_true_peak.iter())
+        {
+            if *prev_true_peak > *true_peak {
+                *true_peak = *prev_true_peak;
+            }
+        }
+
+        for (overs, prev_overs) in Iterator::zip(
+            self.true_peak_overs.iter_mut(),
+            self.filter.true_peak_overs(),
+        ) {
+            *overs += *prev_overs;
+        }
+    }
+
+    fn seed_samples<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+    ) -> Result<(), Error> {
+        if src.channels() != self.channels as usize {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        if self.check_non_finite(&src)? {
+            self.filter.seed(
+                crate::Interleaved::new(
+                    &self.scratch[..src.frames() * src.channels()],
+                    self.channels as usize,
+                )?,
+                &self.channel_map,
+            )?;
+        } else {
+            self.filter.seed(src, &self.channel_map)?;
+        }
+
+        Ok(())
+    }
+
+    /// Add interleaved frames to be processed.
+    ///
+    /// This is generic over all types implementing [`Sample`], which includes all the sample
+    /// types of the type specific variants below.
+    pub fn add_frames<T: Sample>(&mut self, frames: &[T]) -> Result<(), Error> {
+        self.add_samples(
+            crate::Interleaved::new(frames, self.channels as usize)?,
+            None,
+            None,
+            None,
+        )
+    }
+
+    /// Add interleaved frames to be processed and get the momentary loudness of every completed
+    /// gating block.
+    ///
+    /// `on_block` is called with the momentary loudness in LUFS, i.e. the value
+    /// [`EbuR128::loudness_momentary`](struct.EbuR128.html#method.loudness_momentary) would return
+    /// at that point, every time a gating block is completed while processing `frames`. Gating
+    /// blocks are 400ms long and a new one is completed every
+    /// [hop](struct.EbuR128.html#method.set_block_hop) (100ms by default). The first block is
+    /// completed after 400ms of audio.
+    pub fn add_frames_with<T: Sample, F: FnMut(f64)>(
+        &mut self,
+        frames: &[T],
+        mut on_block: F,
+    ) -> Result<(), Error> {
+        self.add_samples(
+            crate::Interleaved::new(frames, self.channels as usize)?,
+            Some(&mut on_block),
+            None,
+            None,
+        )
+    }
+
+    /// Add interleaved frames to be processed and get the filtered samples.
+    ///
+    /// The samples after the frequency weighting, i.e. the K-weighting by default, are written
+    /// to `filtered` before squaring. `filtered` must have space for as many samples as `frames`
+    /// and is filled with one channel after another: the samples of channel `c` are at
+    /// `filtered[c * n_frames..(c + 1) * n_frames]`. Channels set to `Channel::Unused` or `Lfe` are
+    /// filled with zeros. Returns `Error::NoMem` if `filtered` has the wrong size.
+    ///
+    /// The filter state is kept across calls and the filtered samples are the same as the ones
+    /// used for the loudness measurement. Like for any IIR filter, the first samples after
+    /// creating the instance, calling [`EbuR128::reset`](struct.EbuR128.html#method.reset) or
+    /// changing the parameters contain the transient response of the filter. This can be
+    /// avoided by seeding the filter with
+    /// [`EbuR128::seed_frames`](struct.EbuR128.html#method.seed_frames) first.
+    pub fn add_frames_filtered<T: Sample>(
+        &mut self,
+        frames: &[T],
+        filtered: &mut [f64],
+    ) -> Result<(), Error> {
+        self.add_samples(
+            crate::Interleaved::new(frames, self.channels as usize)?,
+            None,
+            Some(filtered),
+            None,
+        )
+    }
+
+    /// Add interleaved frames to be processed and get the oversampled signal of the true peak
+    /// measurement, e.g. for drawing the inter-sample waveform.
+    ///
+    /// The `n_frames * factor` interpolated samples of channel `c` are written to
+    /// `oversampled[c * stride..c * stride + n_frames * factor]`, where `factor` is the
+    /// [oversampling factor](struct.EbuR128.html#method.true_peak_oversampling_factor). The
+    /// samples in between the channels are left untouched, so a `stride` of `n_frames * factor`
+    /// gives a planar layout without gaps. Without oversampling the input samples are written
+    /// unchanged.
+    ///
+    /// This is the signal the true peak is measured on and it is written directly by the
+    /// measurement, so the frames are only interpolated once. The interpolator state is kept
+    /// across calls, so the output of consecutive calls is continuous, and the output is delayed
+    /// by [`EbuR128::true_peak_latency_samples`](struct.EbuR128.html#method.true_peak_latency_samples)
+    /// input frames. The maximum absolute value of the samples of each channel is its
+    /// [`EbuR128::prev_true_peak`](struct.EbuR128.html#method.prev_true_peak) afterwards. With
+    /// `NonFiniteHandling::Skip` the non-finite samples are replaced with silence here too.
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::TRUE_PEAK` is not enabled and `Error::NoMem` if
+    /// `stride` or `oversampled` are too small to hold the output. Nothing is processed in that
+    /// case.
+    ///
+    /// ```
+    /// # use ebur128::{EbuR128, Mode};
+    /// let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+    /// let factor = ebu.true_peak_oversampling_factor().unwrap();
+    ///
+    /// // 10ms of a 12kHz sine with the peaks between the samples
+    /// let frames = (0..480)
+    ///     .flat_map(|i| {
+    ///         let phase = (i % 4) as f32 * std::f32::consts::FRAC_PI_2 + std::f32::consts::FRAC_PI_4;
+    ///         [0.5 * f32::sin(phase), 0.0]
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let mut oversampled = vec![0.0f32; 2 * 480 * factor];
+    /// ebu.add_frames_oversampled(&frames, &mut oversampled, 480 * factor)
+    ///     .unwrap();
+    ///
+    /// // The first channel is at the beginning of the buffer
+    /// let peak = oversampled[..480 * factor]
+    ///     .iter()
+    ///     .fold(0.0f32, |peak, s| peak.max(s.abs()));
+    /// assert_eq!(peak as f64, ebu.prev_true_peak(0).unwrap());
+    /// assert!(peak > 0.45);
+    /// ```
+    pub fn add_frames_oversampled<T: Sample>(
+        &mut self,
+        frames: &[T],
+        oversampled: &mut [f32],
+        stride: usize,
+    ) -> Result<(), Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        self.add_samples(
+            crate::Interleaved::new(frames, self.channels as usize)?,
+            None,
+            None,
+            Some((oversampled, stride)),
+        )
+    }
+
+    /// Add planar frames to be processed.
+    ///
+    /// `frames` must contain one slice per channel and all slices must have the same length.
+    /// Returns `Error::InvalidChannelIndex` if the number of slices does not match the configured
+    /// number of channels and `Error::NoMem` if the slices have different lengths.
+    ///
+    /// This is generic over all types implementing [`Sample`], which includes all the sample
+    /// types of the type specific variants below.
+    pub fn add_frames_planar<T: Sample>(&mut self, frames: &[&[T]]) -> Result<(), Error> {
+        self.add_samples(crate::Planar::new(frames)?, None, None, None)
+    }
+
+    /// Add planar frames to be processed and get the momentary loudness of every completed gating
+    /// block.
+    ///
+    /// See [`EbuR128::add_frames_with`](struct.EbuR128.html#method.add_frames_with) for details.
+    pub fn add_frames_planar_with<T: Sample, F: FnMut(f64)>(
+        &mut self,
+        frames: &[&[T]],
+        mut on_block: F,
+    ) -> Result<(), Error> {
+        self.add_samples(crate::Planar::new(frames)?, Some(&mut on_block), None, None)
+    }
+
+    /// Add planar frames to be processed and get the filtered samples.
+    ///
+    /// See [`EbuR128::add_frames_filtered`](struct.EbuR128.html#method.add_frames_filtered) for
+    /// details. The filtered samples have the same layout as the combined input slices.
+    pub fn add_frames_planar_filtered<T: Sample>(
+        &mut self,
+        frames: &[&[T]],
+        filtered: &mut [f64],
+    ) -> Result<(), Error> {
+        self.add_samples(crate::Planar::new(frames)?, None, Some(filtered), None)
+    }
+
+    /// Add interleaved frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    ///
+    /// This is generic over all types implementing [`Sample`], which includes all the sample
+    /// types of the type specific variants below.
+    pub fn seed_frames<T: Sample>(&mut self, frames: &[T]) -> Result<(), Error> {
+        self.seed_samples(crate::Interleaved::new(frames, self.channels as usize)?)
+    }
+
+    /// Add planar frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    ///
+    /// This is generic over all types implementing [`Sample`], which includes all the sample
+    /// types of the type specific variants below.
+    pub fn seed_frames_planar<T: Sample>(&mut self, frames: &[&[T]]) -> Result<(), Error> {
+        self.seed_samples(crate::Planar::new(frames)?)
+    }
+
+    /// Add interleaved frames of unsigned 8 bit samples to be processed.
+    ///
+    /// Silence is 128 like in 8 bit PCM formats and the samples are scaled to `[-1.0, 1.0)`.
+    pub fn add_frames_u8(&mut self, frames: &[u8]) -> Result<(), Error> {
+        self.add_frames(frames)
+    }
+
+    /// Add interleaved frames of signed 8 bit samples to be processed.
+    pub fn add_frames_i8(&mut self, frames: &[i8]) -> Result<(), Error> {
+        self.add_frames(frames)
+    }
+
+    /// Add interleaved frames to be processed.
+    pub fn add_frames_i16(&mut self, frames: &[i16]) -> Result<(), Error> {
+        self.add_frames(frames)
+    }
+
+    /// Add interleaved frames to be processed.
+    pub fn add_frames_i32(&mut self, frames: &[i32]) -> Result<(), Error> {
+        self.add_frames(frames)
+    }
+
+    /// Add interleaved frames of 24 bit samples to be processed.
+    ///
+    /// The samples are stored in the lower 24 bits of each `i32` and the upper 8 bits are
+    /// ignored. Full scale corresponds to `1 << 23`.
+    pub fn add_frames_i24(&mut self, frames: &[i32]) -> Result<(), Error> {
+        use dasp_sample::I24;
+
+        // Check the number of samples before doing anything else
+        crate::Interleaved::new(frames, self.channels as usize)?;
+
+        if frames.is_empty() {
+            return Ok(());
+        }
+
+        // Convert in chunks on the stack to avoid allocating a temporary buffer for the whole
+        // input
+        let mut tmp = [<I24 as dasp_sample::Sample>::EQUILIBRIUM; 4096];
+        let chunk_size = (tmp.len() / self.channels as usize) * self.channels as usize;
+
+        self.filter.reset_peaks();
+        for chunk in frames.chunks(chunk_size) {
+            let tmp = &mut tmp[..chunk.len()];
+            for (o, i) in Iterator::zip(tmp.iter_mut(), chunk) {
+                // Sign-extend from the lower 24 bits
+                *o = I24::new_unchecked((*i << 8) >> 8);
+            }
+
+            self.process_frames(
+                crate::Interleaved::new(tmp, self.channels as usize)?,
+                None,
+                None,
+                None,
+            )?;
+        }
+        self.update_peaks();
+
+        Ok(())
+    }
+
+    /// Add interleaved frames to be processed.
+    pub fn add_frames_f32(&mut self, frames: &[f32]) -> Result<(), Error> {
+        self.add_frames(frames)
+    }
+
+    /// Add interleaved frames of raw bytes in the given sample format to be processed.
+    ///
+    /// The samples are converted from `format` to the native sample type in chunks on the stack,
+    /// so this doesn't allocate, and are then processed like with the typed `add_frames`
+    /// variants. This is useful for raw PCM data, e.g. from files or sockets, that doesn't have
+    /// the byte order of the platform or isn't aligned.
+    ///
+    /// Returns `Error::NoMem` if the length of `bytes` is not a multiple of the frame size, i.e.
+    /// of `format.sample_size()` times the number of channels.
+    pub fn add_frames_raw(&mut self, bytes: &[u8], format: SampleFormat) -> Result<(), Error> {
+        use dasp_sample::I24;
+
+        fn i24(b: [u8; 3]) -> I24 {
+            // Sign-extend from the lower 24 bits
+            I24::new_unchecked(i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8)
+        }
+
+        match format {
+            SampleFormat::U8 => self.add_frames_converted(bytes, format, |b| b[0]),
+            SampleFormat::S8 => self.add_frames_converted(bytes, format, |b| b[0] as i8),
+            SampleFormat::S16Le => {
+                self.add_frames_converted(bytes, format, |b| i16::from_le_bytes([b[0], b[1]]))
+            }
+            SampleFormat::S16Be => {
+                self.add_frames_converted(bytes, format, |b| i16::from_be_bytes([b[0], b[1]]))
+            }
+            SampleFormat::S24Le => {
+                self.add_frames_converted(bytes, format, |b| i24([b[0], b[1], b[2]]))
+            }
+            SampleFormat::S24Be => {
+                self.add_frames_converted(bytes, format, |b| i24([b[2], b[1], b[0]]))
+            }
+            SampleFormat::S32Le => self.add_frames_converted(bytes, format, |b| {
+                i32::from_le_bytes([b[0], b[1], b[2], b[3]])
+            }),
+            SampleFormat::S32Be => self.add_frames_converted(bytes, format, |b| {
+                i32::from_be_bytes([b[0], b[1], b[2], b[3]])
+            }),
+            SampleFormat::F32Le => self.add_frames_converted(bytes, format, |b| {
+                f32::from_le_bytes([b[0], b[1], b[2], b[3]])
+            }),
+            SampleFormat::F32Be => self.add_frames_converted(bytes, format, |b| {
+                f32::from_be_bytes([b[0], b[1], b[2], b[3]])
+            }),
+            SampleFormat::F64Le => self.add_frames_converted(bytes, format, |b| {
+                f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])
+            }),
+            SampleFormat::F64Be => self.add_frames_converted(bytes, format, |b| {
+                f64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])
+            }),
+        }
+    }
+
+    /// Convert raw samples in `format` with `convert()` in chunks on the stack and process them.
+    ///
+    /// `convert()` is called with the `format.sample_size()` bytes of each sample.
+    fn add_frames_converted<T: Sample + Copy>(
+        &mut self,
+        bytes: &[u8],
+        format: SampleFormat,
+        convert: impl Fn(&[u8]) -> T,
+    ) -> Result<(), Error> {
+        let channels = self.channels as usize;
+        let sample_size = format.sample_size();
+        if !bytes.len().is_multiple_of(sample_size * channels) {
+            return Err(Error::NoMem);
+        }
+
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        let mut tmp = [T::EQUILIBRIUM; 4096];
+        let chunk_size = (tmp.len() / channels) * channels * sample_size;
+        let convert_chunk = |chunk: &[u8], tmp: &mut [T]| {
+            for (o, i) in Iterator::zip(tmp.iter_mut(), chunk.chunks_exact(sample_size)) {
+                *o = convert(i);
+            }
+        };
+
+        // Nothing is processed if any sample is rejected. Only floating point samples can be
+        // non-finite.
+        let float = matches!(
+            format,
+            SampleFormat::F32Le | SampleFormat::F32Be | SampleFormat::F64Le | SampleFormat::F64Be
+        );
+        if float && self.non_finite_handling == NonFiniteHandling::Reject {
+            for chunk in bytes.chunks(chunk_size) {
+                let tmp = &mut tmp[..chunk.len() / sample_size];
+                convert_chunk(chunk, tmp);
+                self.check_non_finite(&crate::Interleaved::new(tmp, channels)?)?;
+            }
+        }
+
+        self.filter.reset_peaks();
+        for chunk in bytes.chunks(chunk_size) {
+            let tmp = &mut tmp[..chunk.len() / sample_size];
+            convert_chunk(chunk, tmp);
+            self.process_chunks(crate::Interleaved::new(tmp, channels)?, None, None, None)?;
+        }
+        self.update_peaks();
+
+        Ok(())
+    }
+
+    /// Add interleaved samples from an iterator to be processed.
+    ///
+    /// The samples are collected in chunks on the stack and processed the same way as by
+    /// [`EbuR128::add_frames_f32`](struct.EbuR128.html#method.add_frames_f32), so lazy sources
+    /// like decoders don't have to be collected into a buffer first. If the number of samples is
+    /// not a multiple of the number of channels, the samples of the incomplete last frame are
+    /// kept and completed by the samples of the next call. Frames added in between with other
+    /// functions are processed before them.
+    ///
+    /// Returns `Error::InvalidChannelIndex` without consuming any samples if `channels_hint` is
+    /// not the configured number of channels. With `NonFiniteHandling::Reject` only the chunk
+    /// with the non-finite sample is rejected: the chunks before it were already processed and
+    /// the remaining samples are not consumed.
+    pub fn add_frames_iter<I: IntoIterator<Item = f32>>(
+        &mut self,
+        iter: I,
+        channels_hint: Option<usize>,
+    ) -> Result<(), Error> {
+        let channels = self.channels as usize;
+        if channels_hint.is_some_and(|c| c != channels) {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        let mut iter = iter.into_iter();
+        let mut tmp = [0.0f32; 4096];
+        let chunk_size = (tmp.len() / channels) * channels;
+
+        // Continue with the incomplete frame of the previous call
+        let mut len = self.pending_samples.len();
+        tmp[..len].copy_from_slice(&self.pending_samples);
+        self.pending_samples.clear();
+
+        self.filter.reset_peaks();
+        let result = loop {
+            for (o, i) in Iterator::zip(tmp[len..chunk_size].iter_mut(), &mut iter) {
+                *o = i;
+                len += 1;
+            }
+
+            let complete = (len / channels) * channels;
+            let result = self.process_iter_chunk(&tmp[..complete]);
+            if len < chunk_size || result.is_err() {
+                if result.is_ok() {
+                    self.pending_samples.extend_from_slice(&tmp[complete..len]);
+                }
+                break result;
+            }
+            len = 0;
+        };
+        // Also includes the peaks of the chunks before an error
+        self.update_peaks();
+
+        result
+    }
+
+    /// Process a chunk of complete frames of
+    /// [`EbuR128::add_frames_iter`](struct.EbuR128.html#method.add_frames_iter). This doesn't
+    /// reset or update the peaks.
+    fn process_iter_chunk(&mut self, chunk: &[f32]) -> Result<(), Error> {
+        let chunk = crate::Interleaved::new(chunk, self.channels as usize)?;
+        if self.non_finite_handling == NonFiniteHandling::Reject {
+            self.check_non_finite(&chunk)?;
+        }
+        self.process_chunks(chunk, None, None, None)
+    }
+
+    /// Add interleaved frames of the given duration to be processed.
+    ///
+    /// The number of frames has to match the declared duration at the configured sample rate,
+    /// rounded up or down to a complete frame. Otherwise `Error::DurationMismatch` is returned
+    /// without processing any of the frames. This catches frame counts that were calculated
+    /// wrongly for a duration, e.g. by forgetting the number of channels.
+    ///
+    /// This is generic over all types implementing [`Sample`], which includes all the sample
+    /// types of the type specific variants below.
+    pub fn add_frames_duration<T: Sample>(
+        &mut self,
+        frames: &[T],
+        declared_duration: core::time::Duration,
+    ) -> Result<(), Error> {
+        // Compare in nanoseconds times the sample rate to not lose precision
+        let actual = (frames.len() / self.channels as usize) as u128 * 1_000_000_000;
+        let expected = declared_duration.as_nanos() * u128::from(self.rate);
+        if actual.max(expected) - actual.min(expected) >= 1_000_000_000 {
+            return Err(Error::DurationMismatch);
+        }
+
+        self.add_frames(frames)
+    }
+
+    /// Add interleaved frames of the given duration to be processed.
+    ///
+    /// See [`EbuR128::add_frames_duration`](struct.EbuR128.html#method.add_frames_duration) for
+    /// details.
+    pub fn add_frames_duration_f32(
+        &mut self,
+        frames: &[f32],
+        declared_duration: core::time::Duration,
+    ) -> Result<(), Error> {
+        self.add_frames_duration(frames, declared_duration)
+    }
+
+    /// Add interleaved frames to be processed.
+    pub fn add_frames_f64(&mut self, frames: &[f64]) -> Result<(), Error> {
+        self.add_frames(frames)
+    }
+
+    /// Add planar frames to be processed.
+    pub fn add_frames_planar_i16(&mut self, frames: &[&[i16]]) -> Result<(), Error> {
+        self.add_frames_planar(frames)
+    }
+
+    /// Add planar frames to be processed.
+    pub fn add_frames_planar_i32(&mut self, frames: &[&[i32]]) -> Result<(), Error> {
+        self.add_frames_planar(frames)
+    }
+
+    /// Add planar frames to be processed.
+    pub fn add_frames_planar_f32(&mut self, frames: &[&[f32]]) -> Result<(), Error> {
+        self.add_frames_planar(frames)
+    }
+
+    /// Add planar frames to be processed.
+    pub fn add_frames_planar_f64(&mut self, frames: &[&[f64]]) -> Result<(), Error> {
+        self.add_frames_planar(frames)
+    }
+
+    /// Add interleaved frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_i16(&mut self, frames: &[i16]) -> Result<(), Error> {
+        self.seed_frames(frames)
+    }
+
+    /// Add interleaved frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_i32(&mut self, frames: &[i32]) -> Result<(), Error> {
+        self.seed_frames(frames)
+    }
+
+    /// Add interleaved frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_f32(&mut self, frames: &[f32]) -> Result<(), Error> {
+        self.seed_frames(frames)
+    }
+
+    /// Add interleaved frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_f64(&mut self, frames: &[f64]) -> Result<(), Error> {
+        self.seed_frames(frames)
+    }
+
+    /// Add planar frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_planar_i16(&mut self, frames: &[&[i16]]) -> Result<(), Error> {
+        self.seed_frames_planar(frames)
+    }
+
+    /// Add planar frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_planar_i32(&mut self, frames: &[&[i32]]) -> Result<(), Error> {
+        self.seed_frames_planar(frames)
+    }
+
+    /// Add planar frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_planar_f32(&mut self, frames: &[&[f32]]) -> Result<(), Error> {
+        self.seed_frames_planar(frames)
+    }
+
+    /// Add planar frames to warmup filters, but not be considered for measurements.
+    /// See [`EbuR128::loudness_global_multiple`] for example usage.
+    pub fn seed_frames_planar_f64(&mut self, frames: &[&[f64]]) -> Result<(), Error> {
+        self.seed_frames_planar(frames)
+    }
+
+    /// Get global integrated loudness in LUFS.
+    ///
+    /// Returns `Error::NotEnoughData` if no gating block was completed yet, i.e. before the first
+    /// 400ms of audio were added, as there is nothing to measure. If gating blocks were measured
+    /// but all of them are below the absolute and relative gates, e.g. for silence, the programme
+    /// is quieter than what can be measured and `-f64::INFINITY` is returned. libebur128 returns
+    /// -HUGE_VAL in both cases.
+    pub fn loudness_global(&self) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::I) {
+            return Err(Error::InvalidMode);
+        }
+
+        if self.integrated_blocks == 0 {
+            return Err(Error::NotEnoughData);
+        }
+
+        Ok(self.block_energy_history.gated_loudness(&self.gating))
+    }
+
+    /// Get the integrated loudness in LUFS of only some of the channels.
+    ///
+    /// This measures the gating blocks as if only `channels` were part of the channel map, e.g.
+    /// for the loudness of the dialog channels or of the front channels of a surround programme.
+    /// The configured channel types, weights and gains of the selected channels are used as
+    /// usual, and channels that are excluded from the measurement, e.g. `Channel::Lfe`, stay
+    /// excluded. Selecting all channels gives the same result as
+    /// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global) if the channel
+    /// history was enabled from the beginning of the measurement.
+    ///
+    /// This requires storing the energy of each channel for every gating block, which has to be
+    /// enabled with
+    /// [`EbuR128::set_channel_history`](struct.EbuR128.html#method.set_channel_history) before
+    /// adding any frames. Only those blocks whose energy over all channels is above -70 LUFS are
+    /// stored and the history of the subset contains the same blocks as the history of all
+    /// channels, so the result is gated like the one of `loudness_global()`. With
+    /// `Mode::HISTOGRAM` it is quantized like that, but the blocks are limited by the maximum
+    /// history also then.
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::I` is not enabled or the channel history is
+    /// disabled, `Error::InvalidChannelIndex` if one of `channels` is not a valid channel index
+    /// and `Error::NotEnoughData` if no gating block was completed yet.
+    pub fn loudness_global_subset(&self, channels: &[u32]) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::I) || !self.channel_history {
+            return Err(Error::InvalidMode);
+        }
+
+        let mut selected =
+            smallvec::SmallVec::<[bool; 16]>::from_elem(false, self.channels as usize);
+        for &c in channels {
+            *selected
+                .get_mut(c as usize)
+                .ok_or(Error::InvalidChannelIndex)? = true;
+        }
+
+        if self.integrated_blocks == 0 {
+            return Err(Error::NotEnoughData);
+        }
+
+        let mut history =
+            crate::history::History::new(self.mode.contains(Mode::HISTOGRAM), usize::MAX);
+        history.set_histogram_resolution(self.histogram_resolution);
+
+        let frames_per_block = (self.samples_in_100ms * 4) as f64;
+        let channels = self.channels as usize;
+        for block in 0..self.channel_block_energies.len() / channels {
+            let mut energy = 0.0;
+            for (c, _) in selected
+                .iter()
+                .enumerate()
+                .filter(|(_, selected)| **selected)
+            {
+                energy += self.channel_block_energies[block * channels + c];
+            }
+            history.add(energy / frames_per_block);
+        }
+
+        Ok(history.gated_loudness(&self.gating))
+    }
+
+    /// Get the integrated loudness in LUFS over the trailing `window` of audio.
+    ///
+    /// This is a moving integrated loudness: only the gating blocks that lie completely within
+    /// the last `window` of the added audio are gated like for
+    /// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global), e.g. the last
+    /// 30 seconds. In contrast to the momentary and short term loudness the blocks are gated, and
+    /// in contrast to the integrated loudness older blocks are not included.
+    ///
+    /// The time of each block is needed for this, so the energies of the gating blocks have to
+    /// be recorded with
+    /// [`EbuR128::enable_block_energies`](struct.EbuR128.html#method.enable_block_energies)
+    /// before adding any frames. The window is limited to the
+    /// [`EbuR128::max_history`](struct.EbuR128.html#method.max_history) like the blocks of
+    /// `loudness_global()`, so longer windows give the same result as the maximum history. With
+    /// `Mode::HISTOGRAM` the blocks are quantized like for `loudness_global()`.
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::I` is not enabled or the block energies are not
+    /// recorded, and `Error::NotEnoughData` if no gating block lies within the window yet.
+    pub fn loudness_global_sliding(&self, window: core::time::Duration) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::I) || !self.block_energies_enabled {
+            return Err(Error::InvalidMode);
+        }
+
+        let window = core::cmp::min(
+            window,
+            core::time::Duration::from_millis(self.history as u64),
+        );
+        let window_frames = window.as_nanos() * u128::from(self.rate) / 1_000_000_000;
+        let window_frames = u128::min(window_frames, u64::MAX as u128) as u64;
+        let start = self.frames_processed.saturating_sub(window_frames);
+        let block_frames = (self.samples_in_100ms * 4) as u64;
+
+        let mut history =
+            crate::history::History::new(self.mode.contains(Mode::HISTOGRAM), usize::MAX);
+        history.set_histogram_resolution(self.histogram_resolution);
+
+        let mut blocks = 0;
+        for (energy, timestamp) in Iterator::zip(
+            self.block_energies.iter().rev(),
+            self.block_timestamps.iter().rev(),
+        ) {
+            if timestamp.saturating_sub(block_frames) < start {
+                break;
+            }
+            history.add(*energy);
+            blocks += 1;
+        }
+
+        if blocks == 0 {
+            return Err(Error::NotEnoughData);
+        }
+
+        Ok(history.gated_loudness(&self.gating))
+    }
+
+    /// Get the integrated loudness in LUFS of only the active gating blocks.
+    ///
+    /// Gating blocks below `active_threshold_lufs` are ignored completely and the remaining
+    /// blocks are gated like for
+    /// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global), e.g. for
+    /// measuring a programme without its silent or noisy head and tail. The standard gating
+    /// already ignores blocks below the absolute gate of -70 LUFS, but quiet blocks above it
+    /// still lower the relative threshold, and room tone or noise above the relative threshold
+    /// also lowers the integrated loudness. In contrast to
+    /// [`EbuR128::set_gating`](struct.EbuR128.html#method.set_gating) this doesn't change the
+    /// configured gates, and a threshold below the absolute gate gives the same result as
+    /// `loudness_global()`. The result is not compliant with ITU-R BS.1770.
+    ///
+    /// Returns `Error::InvalidMode` if `Mode::I` is not enabled or the threshold is not finite,
+    /// and `Error::NotEnoughData` if no gating block was completed yet. If all gating blocks are
+    /// below the threshold `-f64::INFINITY` is returned.
+    pub fn loudness_global_active(&self, active_threshold_lufs: f64) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::I) || !active_threshold_lufs.is_finite() {
+            return Err(Error::InvalidMode);
+        }
+
+        if self.integrated_blocks == 0 {
+            return Err(Error::NotEnoughData);
+        }
+
+        let gating = if active_threshold_lufs > self.gating.absolute() {
+            crate::history::Gating::new(active_threshold_lufs, self.gating.relative())
+        } else {
+            self.gating
+        };
+
+        Ok(self.block_energy_history.gated_loudness(&gating))
+    }
+
+    /// Get the global integrated loudness in LU relative to the reference loudness in LUFS.
+    ///
+    /// Returns the same errors as
+    /// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global), and
+    /// `-f64::INFINITY` if the integrated loudness is `-f64::INFINITY`.
+    pub fn loudness_global_relative(&self, reference_lufs: f64) -> Result<f64, Error> {
+        Ok(self.loudness_global()? - reference_lufs)
+    }
+
+    /// Get the global integrated loudness in LU relative to the configured reference loudness.
+    ///
+    /// See [`EbuR128::set_reference_loudness`](struct.EbuR128.html#method.set_reference_loudness)
+    /// and [`EbuR128::loudness_global_relative`](struct.EbuR128.html#method.loudness_global_relative)
+    /// for details.
+    pub fn loudness_global_lu(&self) -> Result<f64, Error> {
+        self.loudness_global_relative(self.reference_loudness)
+    }
+
+    /// Get the gain in dB that has to be applied to reach the target integrated loudness in LUFS.
+    ///
+    /// Common targets are -23 LUFS for EBU R128 and -14 LUFS or -16 LUFS for streaming
+    /// platforms. Returns the same errors as
+    /// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global), and also
+    /// `Error::NotEnoughData` if the integrated loudness is `-f64::INFINITY`, e.g. for silence, as
+    /// no gain reaches the target then.
+    pub fn normalization_gain(&self, target_lufs: f64) -> Result<f64, Error> {
+        Ok(target_lufs - finite_loudness(self.loudness_global()?)?)
+    }
+
+    /// Get the linear gain factor that has to be applied to the samples to reach the target
+    /// integrated loudness in LUFS.
+    ///
+    /// See [`EbuR128::normalization_gain`](struct.EbuR128.html#method.normalization_gain) for
+    /// details.
+    pub fn normalization_gain_linear(&self, target_lufs: f64) -> Result<f64, Error> {
+        Ok(math::pow(
+            10.0,
+            self.normalization_gain(target_lufs)? / 20.0,
+        ))
+    }
+
+    /// Get the ReplayGain 2.0 track gain in dB.
+    ///
+    /// This is the gain needed to reach the ReplayGain 2.0 reference loudness of -18 LUFS, see
+    /// [`EbuR128::normalization_gain`](struct.EbuR128.html#method.normalization_gain), and can be
+    /// stored in the `REPLAYGAIN_TRACK_GAIN` tag.
+    pub fn replaygain_track_gain(&self) -> Result<f64, Error> {
+        self.normalization_gain(REPLAYGAIN_REFERENCE_LOUDNESS)
+    }
+
+    /// Get the ReplayGain 2.0 track peak.
+    ///
+    /// This is the maximum sample peak over all channels as a linear value, which can be stored in
+    /// the `REPLAYGAIN_TRACK_PEAK` tag. Requires `Mode::SAMPLE_PEAK`. ReplayGain 2.0 also allows
+    /// storing the true peak, which is available from
+    /// [`EbuR128::true_peak_max`](struct.EbuR128.html#method.true_peak_max).
+    pub fn replaygain_track_peak(&self) -> Result<f64, Error> {
+        let mut max = 0.0;
+        for c in 0..self.channels {
+            let peak = self.sample_peak(c)?;
+            if peak > max {
+                max = peak;
+            }
+        }
+
+        Ok(max)
+    }
+
+    /// Get the ReplayGain 2.0 album gain in dB across the instances of all tracks of an album.
+    ///
+    /// This is the gain needed to bring the integrated loudness of the whole album as returned by
+    /// [`EbuR128::loudness_global_multiple`](struct.EbuR128.html#method.loudness_global_multiple)
+    /// to -18 LUFS, and returns the same errors. The tracks can have different numbers of channels
+    /// and sample rates but need to use the same gates. Like
+    /// [`EbuR128::normalization_gain`](struct.EbuR128.html#method.normalization_gain) it also
+    /// returns `Error::NotEnoughData` if the whole album is silent.
+    pub fn replaygain_album_gain<'a>(
+        iter: impl IntoIterator<Item = &'a Self>,
+    ) -> Result<f64, Error> {
+        Ok(REPLAYGAIN_REFERENCE_LOUDNESS
+            - finite_loudness(Self::loudness_global_multiple(iter.into_iter())?)?)
+    }
+
+    /// Get the ReplayGain 2.0 album peak across the instances of all tracks of an album.
+    ///
+    /// This is the maximum of the
+    /// [track peaks](struct.EbuR128.html#method.replaygain_track_peak) and returns
+    /// `Error::InvalidMode` if any instance was not created with `Mode::SAMPLE_PEAK`.
+    pub fn replaygain_album_peak<'a>(
+        iter: impl IntoIterator<Item = &'a Self>,
+    ) -> Result<f64, Error> {
+        let mut max = 0.0;
+        for e in iter {
+            let peak = e.replaygain_track_peak()?;
+            if peak > max {
+                max = peak;
+            }
+        }
+
+        Ok(max)
+    }
+
+    /// Get global integrated loudness in LUFS across multiple instances.
+    ///
+    /// This can be used to allow parallel iteration of long signals, assuming some care is taken:
+    ///  1. Divide input-signal up in "chunks" of even 100ms samples. Make chunks overlap by 400ms, for example (0-10s, 9.6-20s, 19.6-30s, ...)
+    ///  2. The first chunk is processed as normal. Then in parallel, for each remaining chunk, create a new instance of `EbuR128`, and in parallel:
+    ///     1. Feed the first 100ms of the chunk (these are samples overlapping with last chunk) through `seed_frames_*` function. This is sufficient to make filter-states in each instance what they would have been if a single analyzer would have reached this point.
+    ///     2. Process the remaining samples of each chunk through the analyzer
+    ///  3. Call [`EbuR128::loudness_global_multiple`] over all the chunks to get the global loudness
+    ///
+    /// The gating blocks don't depend on the input format, so the instances can have different
+    /// numbers of channels, sample rates and other modes, e.g. for the tracks of an album. Returns
+    /// `Error::InvalidMode` if any of the instances was not created with `Mode::I` or if the
+    /// instances don't all use the same gates and
+    /// [block hop](struct.EbuR128.html#method.set_block_hop), and `Error::NotEnoughData` if no
+    /// instance completed a gating block yet. Like
+    /// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global) this returns
+    /// `-f64::INFINITY` if all gating blocks of all instances are below the gates.
+    // FIXME: Should maybe be IntoIterator? Maybe AsRef<Self>?
+    pub fn loudness_global_multiple<'a>(
+        iter: impl Iterator<Item = &'a Self>,
+    ) -> Result<f64, Error> {
+        use smallvec::SmallVec;
+
+        let mut config = None;
+        let mut integrated_blocks = 0;
+        let h = iter
+            .map(|e| {
+                if !e.mode.contains(Mode::I) {
+                    return Err(Error::InvalidMode);
+                }
+                integrated_blocks += e.integrated_blocks;
+
+                let c = config.get_or_insert((e.gating, e.block_hop));
+                if *c != (e.gating, e.block_hop) {
+                    return Err(Error::InvalidMode);
+                }
+
+                Ok(&e.block_energy_history)
+            })
+            .collect::<Result<SmallVec<[_; 16]>, _>>()?;
+
+        if integrated_blocks == 0 {
+            return Err(Error::NotEnoughData);
+        }
+
+        let gating = config.map(|c| c.0).unwrap_or_default();
+        Ok(crate::history::History::gated_loudness_multiple(
+            &h, &gating,
+        ))
+    }
+
+    fn energy_in_interval(&self, interval_frames: usize) -> Result<f64, Error> {
+        if interval_frames > self.audio_data.len() / self.channels as usize {
+            return Err(Error::InvalidMode);
+        }
+
+        Ok(self.calc_energy(interval_frames))
+    }
+
+    /// Calculate the energy of the last `frames` frames, including the dual mono compensation.
+    fn calc_energy(&self, frames: usize) -> f64 {
+        let energy = self.audio_data.calc_gating_block(
+            frames,
+            self.audio_data_index,
+            &self.channel_map,
+            &self.channel_gains,
+        );
+
+        if self.dual_mono_compensation {
+            0.5 * energy
+        } else {
+            energy
+        }
+    }
+
+    /// Store the energy of each channel for the gating block that was just completed, including
+    /// the dual mono compensation.
+    fn add_channel_block_energies(&mut self) {
+        let channels = self.channels as usize;
+        let mut energies = smallvec::SmallVec::<[f64; 16]>::from_elem(0.0, channels);
+        self.audio_data.calc_gating_block_channels(
+            self.samples_in_100ms * 4,
+            self.audio_data_index,
+            &self.channel_map,
+            &self.channel_gains,
+            &mut energies,
+        );
+        if self.dual_mono_compensation {
+            for energy in &mut energies {
+                *energy *= 0.5;
+            }
+        }
+        self.channel_block_energies.extend(energies);
+        self.trim_channel_block_energies();
+    }
+
+    /// Remove the energies of the oldest gating blocks that exceed the maximum history.
+    fn trim_channel_block_energies(&mut self) {
+        let channels = self.channels as usize;
+        let max_blocks = self.history / self.block_hop as usize;
+        let blocks = self.channel_block_energies.len() / channels;
+        if blocks > max_blocks {
+            self.channel_block_energies
+                .drain(..(blocks - max_blocks) * channels);
+        }
+    }
+
+    /// Get momentary loudness (last 400ms) in LUFS.
+    pub fn loudness_momentary(&self) -> Result<f64, Error> {
+        let energy = self.energy_in_interval(self.samples_in_100ms * 4)?;
+
+        if energy <= 0.0 {
+            return Ok(-f64::INFINITY);
+        }
+
+        Ok(energy_to_loudness(energy))
+    }
+
+    fn energy_shortterm(&self) -> Result<f64, Error> {
+        self.energy_in_interval(self.samples_in_100ms * 30)
+    }
+
+    /// Get short-term loudness (last 3s) in LUFS.
+    pub fn loudness_shortterm(&self) -> Result<f64, Error> {
+        let energy = self.energy_shortterm()?;
+
+        if energy <= 0.0 {
+            return Ok(-f64::INFINITY);
+        }
+
+        Ok(energy_to_loudness(energy))
+    }
+
+    /// Get the momentary loudness (last 400ms) in LU relative to the reference loudness in LUFS.
+    pub fn loudness_momentary_relative(&self, reference_lufs: f64) -> Result<f64, Error> {
+        Ok(self.loudness_momentary()? - reference_lufs)
+    }
+
+    /// Get the momentary loudness (last 400ms) in LU relative to the configured reference
+    /// loudness.
+    ///
+    /// See [`EbuR128::set_reference_loudness`](struct.EbuR128.html#method.set_reference_loudness)
+    /// for details.
+    pub fn loudness_momentary_lu(&self) -> Result<f64, Error> {
+        self.loudness_momentary_relative(self.reference_loudness)
+    }
+
+    /// Get the short-term loudness (last 3s) in LU relative to the reference loudness in LUFS.
+    pub fn loudness_shortterm_relative(&self, reference_lufs: f64) -> Result<f64, Error> {
+        Ok(self.loudness_shortterm()? - reference_lufs)
+    }
+
+    /// Get the short-term loudness (last 3s) in LU relative to the configured reference
+    /// loudness.
+    ///
+    /// See [`EbuR128::set_reference_loudness`](struct.EbuR128.html#method.set_reference_loudness)
+    /// for details.
+    pub fn loudness_shortterm_lu(&self) -> Result<f64, Error> {
+        self.loudness_shortterm_relative(self.reference_loudness)
+    }
+
+    /// Get the maximum momentary loudness (400ms) in LUFS measured so far.
+    ///
+    /// The momentary loudness is measured for every gating block, i.e. every
+    /// [hop](struct.EbuR128.html#method.set_block_hop) (100ms by default). Returns
+    /// `Error::NotEnoughData` if no gating block was completed yet and `-f64::INFINITY` if all of
+    /// them were silent.
+    pub fn loudness_momentary_max(&self) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::M) {
+            return Err(Error::InvalidMode);
+        }
+
+        if self.gating_blocks == 0 {
+            return Err(Error::NotEnoughData);
+        }
+
+        if self.momentary_max_energy <= 0.0 {
+            return Ok(-f64::INFINITY);
+        }
+
+        Ok(energy_to_loudness(self.momentary_max_energy))
+    }
+
+    /// Get the maximum short-term loudness (3s) in LUFS measured so far.
+    ///
+    /// The short-term loudness is measured every [hop](struct.EbuR128.html#method.set_block_hop)
+    /// (100ms by default), together with the gating blocks, once the tracking is enabled with
+    /// [`EbuR128::enable_shortterm_max`](struct.EbuR128.html#method.enable_shortterm_max).
+    /// Returns `Error::InvalidMode` if `Mode::S` or the tracking are not enabled,
+    /// `Error::NotEnoughData` if no gating block was completed yet and `-f64::INFINITY` if all of
+    /// them were silent.
+    pub fn loudness_shortterm_max(&self) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::S) || !self.shortterm_max_enabled {
+            return Err(Error::InvalidMode);
+        }
+
+        if self.gating_blocks == 0 {
+            return Err(Error::NotEnoughData);
+        }
+
+        if self.shortterm_max_energy <= 0.0 {
+            return Ok(-f64::INFINITY);
+        }
+
+        Ok(energy_to_loudness(self.shortterm_max_energy))
+    }
+
+    /// Enable tracking of the maximum short-term loudness.
+    ///
+    /// Afterwards the short-term loudness is measured for every gating block if `Mode::S` is
+    /// enabled and its maximum is available from
+    /// [`EbuR128::loudness_shortterm_max`](struct.EbuR128.html#method.loudness_shortterm_max).
+    /// This needs a pass over the whole 3s window for every block, which makes processing a few
+    /// times slower, so the tracking is disabled by default. Only blocks completed after enabling
+    /// it are included. [`EbuR128::reset`](struct.EbuR128.html#method.reset) keeps the tracking
+    /// enabled.
+    pub fn enable_shortterm_max(&mut self) {
+        self.shortterm_max_enabled = true;
+    }
+
+    /// Enable recording of the momentary and short-term loudness of every block.
+    ///
+    /// Afterwards the momentary loudness in LUFS is appended to
+    /// [`EbuR128::momentary_timeseries`](struct.EbuR128.html#method.momentary_timeseries) for
+    /// every gating block if `Mode::M` is enabled, and the short-term loudness to
+    /// [`EbuR128::shortterm_timeseries`](struct.EbuR128.html#method.shortterm_timeseries) if
+    /// `Mode::S` is enabled, e.g. for drawing a loudness graph. Blocks below the absolute
+    /// threshold of the respective window are recorded as `-f64::INFINITY`.
+    ///
+    /// The first block is completed after 400ms and every following one after another
+    /// [hop](struct.EbuR128.html#method.set_block_hop) of `hop` ms (100ms by default) of audio,
+    /// both rounded to whole frames, so as long as the hop is not changed the value at index `i`
+    /// is the loudness at the end of the first
+    /// `4 * ((rate + 5) / 10) + i * max(1, (rate * hop + 500) / 1000)` frames added after
+    /// enabling the recording. The short-term loudness of the first 3s includes silence before
+    /// the first frame.
+    ///
+    /// The recorded values grow without bound and are only cleared by
+    /// [`EbuR128::reset`](struct.EbuR128.html#method.reset), which keeps the recording enabled.
+    /// Recording is disabled by default.
+    pub fn enable_timeseries(&mut self) {
+        self.timeseries = true;
+    }
+
+    /// Get the recorded momentary loudness in LUFS, one value per gating block.
+    ///
+    /// See [`EbuR128::enable_timeseries`](struct.EbuR128.html#method.enable_timeseries) for the
+    /// time of each value. This is empty if the recording is not enabled.
+    pub fn momentary_timeseries(&self) -> &[f64] {
+        &self.momentary_timeseries
+    }
+
+    /// Get the recorded short-term loudness in LUFS, one value per gating block.
+    ///
+    /// See [`EbuR128::enable_timeseries`](struct.EbuR128.html#method.enable_timeseries) for the
+    /// time of each value. This is empty if the recording is not enabled.
+    pub fn shortterm_timeseries(&self) -> &[f64] {
+        &self.shortterm_timeseries
+    }
+
+    /// Enable recording of the short-term loudness of the blocks of the loudness range.
+    ///
+    /// Afterwards the short-term loudness in LUFS of every 3s block that
+    /// [`EbuR128::loudness_range`](struct.EbuR128.html#method.loudness_range) is based on, i.e.
+    /// once per second after the first 3s, is appended to
+    /// [`EbuR128::shortterm_timeseries_timed`](struct.EbuR128.html#method.shortterm_timeseries_timed)
+    /// together with the time of the end of the block if `Mode::LRA` is enabled. This is the
+    /// distribution of EBU Tech 3342, e.g. for drawing it together with the 10th and 95th
+    /// percentile of the loudness range. Blocks below the absolute threshold are recorded as
+    /// `-f64::INFINITY`, the gating of the loudness range is not applied. The time is
+    /// [`EbuR128::duration_processed`](struct.EbuR128.html#method.duration_processed) when the
+    /// block was completed.
+    ///
+    /// The recorded values grow without bound and are only cleared by
+    /// [`EbuR128::reset`](struct.EbuR128.html#method.reset), which keeps the recording enabled.
+    /// Recording is disabled by default.
+    pub fn enable_shortterm_timeseries_timed(&mut self) {
+        self.shortterm_timeseries_timed_enabled = true;
+    }
+
+    /// Get the recorded short-term loudness in LUFS of the blocks of the loudness range, together
+    /// with the time of the end of each block.
+    ///
+    /// See
+    /// [`EbuR128::enable_shortterm_timeseries_timed`](struct.EbuR128.html#method.enable_shortterm_timeseries_timed)
+    /// for details. This is empty if the recording is not enabled.
+    pub fn shortterm_timeseries_timed(&self) -> &[(core::time::Duration, f64)] {
+        &self.shortterm_timeseries_timed
+    }
+
+    /// Enable recording of the energy of every gating block.
+    ///
+    /// Afterwards the mean square energy of every completed gating block of the integrated
+    /// loudness is appended to
+    /// [`EbuR128::block_energies`](struct.EbuR128.html#method.block_energies) if `Mode::I` is
+    /// enabled, e.g. for experimenting with other gates. These are the blocks that
+    /// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global) is based on
+    /// before gating, including silent blocks and those below the absolute gate. The values are
+    /// energies after weighting and summing all channels, not loudness values: the loudness in
+    /// LUFS of a block is `10 * log10(energy) - 0.691`, see
+    /// [`energy_to_lufs`](fn.energy_to_lufs.html).
+    ///
+    /// The recorded values grow without bound and are only cleared by
+    /// [`EbuR128::reset`](struct.EbuR128.html#method.reset), which keeps the recording enabled.
+    /// Recording is disabled by default.
+    pub fn enable_block_energies(&mut self) {
+        self.block_energies_enabled = true;
+    }
+
+    /// Get the recorded energy of the gating blocks.
+    ///
+    /// See [`EbuR128::enable_block_energies`](struct.EbuR128.html#method.enable_block_energies)
+    /// for details. This is empty if the recording is not enabled.
+    pub fn block_energies(&self) -> &[f64] {
+        &self.block_energies
+    }
+
+    /// Get the end of the recorded gating blocks as the number of frames processed until then.
+    ///
+    /// The value at index `i` belongs to the energy at the same index in
+    /// [`EbuR128::block_energies`](struct.EbuR128.html#method.block_energies). The block starts
+    /// 400ms of audio before that. Frames are counted like in
+    /// [`EbuR128::frames_processed`](struct.EbuR128.html#method.frames_processed).
+    pub fn block_timestamps(&self) -> &[u64] {
+        &self.block_timestamps
+    }
+
+    /// Enable counting the overs of the true peak measurement.
+    ///
+    /// Afterwards every interpolated sample of the true peak measurement whose absolute value is
+    /// above the threshold set with
+    /// [`EbuR128::set_true_peak_overs_threshold`](struct.EbuR128.html#method.set_true_peak_overs_threshold)
+    /// is counted per channel, e.g. for quality control reports. By default these are the
+    /// inter-sample overs above 0 dBTP. The counts are available from
+    /// [`EbuR128::true_peak_overs`](struct.EbuR128.html#method.true_peak_overs) if
+    /// `Mode::TRUE_PEAK` is enabled.
+    ///
+    /// The counts are cleared by [`EbuR128::reset_peaks`](struct.EbuR128.html#method.reset_peaks)
+    /// and [`EbuR128::reset`](struct.EbuR128.html#method.reset), which keep the counting enabled.
+    /// Counting is disabled by default.
+    pub fn enable_true_peak_overs(&mut self) {
+        self.true_peak_overs_enabled = true;
+        self.filter
+            .set_overs_threshold(self.filter_overs_threshold());
+    }
+
+    /// Get loudness of the specified window in LUFS.
+    ///
+    /// This is the loudness of the last `window` ms of audio and generalizes the momentary and
+    /// short-term loudness: a window of 400ms gives the same value as
+    /// [`EbuR128::loudness_momentary`](struct.EbuR128.html#method.loudness_momentary) and a window
+    /// of 3000ms the same as
+    /// [`EbuR128::loudness_shortterm`](struct.EbuR128.html#method.loudness_shortterm).
+    ///
+    /// window must not be larger than the current window, otherwise `Error::InvalidMode` is
+    /// returned. The current window can be changed by calling
+    /// [`EbuR128::set_max_window`](struct.EbuR128.html#method.set_max_window).
+    pub fn loudness_window(&self, window: u32) -> Result<f64, Error> {
+        let interval_frames = (self.rate as usize)
+            .checked_mul(window as usize)
+            .ok_or(Error::InvalidMode)?
+            / 1000;
+        let energy = self.energy_in_interval(interval_frames)?;
+
+        if energy <= 0.0 {
+            return Ok(-f64::INFINITY);
+        }
+
+        Ok(energy_to_loudness(energy))
+    }
+
+    /// Get loudness range (LRA) of programme in LU.
+    ///
+    /// Calculates loudness range according to EBU 3342.
+    ///
+    /// Returns `Error::NotEnoughData` if no short term block was completed yet, i.e. before the
+    /// first 3s of audio were added. If all short term blocks are below the gates, e.g. for
+    /// silence, the loudness range is 0 LU. libebur128 returns 0 in both cases.
+    ///
+    /// This is cheap to call repeatedly, e.g. during a live stream: the histogram only has a
+    /// fixed number of bins, and without `Mode::HISTOGRAM` the loudness range is updated whenever
+    /// a short term block is completed, which is once per second.
+    pub fn loudness_range(&self) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::LRA) {
+            return Err(Error::InvalidMode);
+        }
+
+        if self.short_term_blocks == 0 {
+            return Err(Error::NotEnoughData);
+        }
+
+        Ok(self.short_term_block_energy_history.loudness_range())
+    }
+
+    /// Get loudness range (LRA) of programme in LU across multiple instances.
+    ///
+    /// Calculates loudness range according to EBU 3342.
+    ///
+    /// The signal can be split into chunks similar to
+    /// [`EbuR128::loudness_global_multiple`] but because short term blocks are only measured every
+    /// second the chunks have to start at a multiple of 1s and overlap by 2s, plus the 100ms for
+    /// seeding. For example (0-10s, 7.9-20s, 17.9-30s, ...).
+    ///
+    /// Returns `Error::InvalidMode` if any of the instances was not created with `Mode::LRA` or if
+    /// the instances don't all have the same mode, number of channels and sample rate, and
+    /// `Error::NotEnoughData` if no instance completed a short term block yet.
+    // FIXME: Should maybe be IntoIterator? Maybe AsRef<Self>?
+    pub fn loudness_range_multiple<'a>(
+        iter: impl IntoIterator<Item = &'a Self>,
+    ) -> Result<f64, Error> {
+        use smallvec::SmallVec;
+
+        let mut config = None;
+        let mut short_term_blocks = 0;
+        let h = iter
+            .into_iter()
+            .map(|e| {
+                if !e.mode.contains(Mode::LRA) {
+                    return Err(Error::InvalidMode);
+                }
+                short_term_blocks += e.short_term_blocks;
+
+                let c = config.get_or_insert((e.mode, e.channels, e.rate));
+                if *c != (e.mode, e.channels, e.rate) {
+                    return Err(Error::InvalidMode);
+                }
+
+                Ok(&e.short_term_block_energy_history)
+            })
+            .collect::<Result<SmallVec<[_; 16]>, _>>()?;
+
+        if short_term_blocks == 0 {
+            return Err(Error::NotEnoughData);
+        }
+
+        crate::history::History::loudness_range_multiple(&h)
+    }
+
+    /// Get maximum sample peak from all frames that have been processed.
+    ///
+    /// The equation to convert to dBFS is: 20 * log10(out)
+    pub fn sample_peak(&self, channel_number: u32) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::SAMPLE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        if channel_number >= self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        Ok(self.sample_peak[channel_number as usize])
+    }
+
+    /// Get maximum sample peak from the last call to
+    /// [`EbuR128::add_frames`](struct.EbuR128.html#method.add_frames_i16).
+    ///
+    /// The equation to convert to dBFS is: 20 * log10(out)
+    pub fn prev_sample_peak(&self, channel_number: u32) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::SAMPLE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        if channel_number >= self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        Ok(self.filter.sample_peak()[channel_number as usize])
+    }
+
+    /// Get maximum true peak from all frames that have been processed.
+    ///
+    /// Uses an implementation defined algorithm to calculate the true peak. Do not try to compare
+    /// resulting values across different versions of the library, as the algorithm may change.
+    ///
+    /// The current implementation uses a custom polyphase FIR interpolator to calculate true peak.
+    /// Will oversample 4x for sample rates < 96000 Hz, 2x for sample rates < 192000 Hz and leave
+    /// the signal unchanged for sample rates up to 384000 Hz. Higher sample rates are not
+    /// supported.
+    ///
+    /// The equation to convert to dBTP is: 20 * log10(out)
+    pub fn true_peak(&self, channel_number: u32) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        if channel_number >= self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        if self.sample_peak[channel_number as usize] > self.true_peak[channel_number as usize] {
+            Ok(self.sample_peak[channel_number as usize])
+        } else {
+            Ok(self.true_peak[channel_number as usize])
+        }
+    }
+
+    /// Get the number of interpolated samples above the threshold of the specified channel from
+    /// all frames that have been processed.
+    ///
+    /// This is zero unless the counting is enabled with
+    /// [`EbuR128::enable_true_peak_overs`](struct.EbuR128.html#method.enable_true_peak_overs).
+    /// The oversampling factor is the same as for
+    /// [`EbuR128::true_peak`](struct.EbuR128.html#method.true_peak), so a single clipped sample
+    /// usually results in several overs.
+    pub fn true_peak_overs(&self, channel_number: u32) -> Result<u64, Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        if channel_number >= self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        Ok(self.true_peak_overs[channel_number as usize])
+    }
+
+    /// Get maximum true peak over all channels from all frames that have been processed.
+    ///
+    /// See [`EbuR128::true_peak`](struct.EbuR128.html#method.true_peak) for details and for
+    /// getting the true peak of the individual channels.
+    pub fn true_peak_max(&self) -> Result<f64, Error> {
+        let mut max = 0.0;
+        for c in 0..self.channels {
+            let peak = self.true_peak(c)?;
+            if peak > max {
+                max = peak;
+            }
+        }
+
+        Ok(max)
+    }
+
+    /// Get the latency of the true peak measurement in frames.
+    ///
+    /// The true peak is measured on an oversampled signal, which is delayed by this number of
+    /// frames compared to the input because of the interpolation filter. A true peak between two
+    /// samples therefore only shows up in [`EbuR128::true_peak`](struct.EbuR128.html#method.true_peak)
+    /// this many frames after the samples around it were added. With the default
+    /// `TruePeakQuality::Standard` this is currently 6 frames for sample rates < 96000 Hz, 12
+    /// frames for sample rates < 192000 Hz and 0 for higher sample rates that are not oversampled.
+    ///
+    /// The loudness measurement has no such fixed latency. The K-weighting filter is a causal IIR
+    /// filter that is applied sample by sample, so the momentary and short term loudness always
+    /// cover the last 400ms and 3s of the added frames. Its group delay depends on the frequency:
+    /// at 48kHz it is about 1ms at 100Hz, 0.25ms at 200Hz and less than 0.05ms (2 frames) above
+    /// 500Hz.
+    pub fn true_peak_latency_samples(&self) -> Result<usize, Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        Ok(self.filter.true_peak_latency().unwrap_or(0))
+    }
+
+    /// Get the oversampling factor of the true peak measurement.
+    ///
+    /// This is the number of interpolated samples per input frame the true peak is measured on,
+    /// e.g. 4 for sample rates < 96000 Hz with the default `TruePeakQuality::Standard`, and 1 if
+    /// the sample rate is high enough to not need oversampling. Returns `Error::InvalidMode` if
+    /// `Mode::TRUE_PEAK` is not enabled.
+    pub fn true_peak_oversampling_factor(&self) -> Result<usize, Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        Ok(self.filter.true_peak_oversampling_factor().unwrap_or(1))
+    }
+
+    /// Get the filter coefficients of the true peak interpolator in the oversampled domain,
+    /// including the zero padding.
+    ///
+    /// See [`EbuR128::set_true_peak_coefficients`](struct.EbuR128.html#method.set_true_peak_coefficients)
+    /// for the layout. This is empty if the sample rate is not oversampled and returns
+    /// `Error::InvalidMode` if `Mode::TRUE_PEAK` is not enabled.
+    pub fn true_peak_coefficients(&self) -> Result<Vec<f64>, Error> {
+        match self.filter.true_peak_interpolator() {
+            Some(tp) if self.mode.contains(Mode::TRUE_PEAK) => Ok(tp.coefficients()),
+            _ => Err(Error::InvalidMode),
+        }
+    }
+
+    /// Get maximum true peak from the last call to
+    /// [`EbuR128::add_frames`](struct.EbuR128.html#method.add_frames_i16).
+    ///
+    /// Uses an implementation defined algorithm to calculate the true peak. Do not try to compare
+    /// resulting values across different versions of the library, as the algorithm may change.
+    ///
+    /// The current implementation uses a custom polyphase FIR interpolator to calculate true peak.
+    /// Will oversample 4x for sample rates < 96000 Hz, 2x for sample rates < 192000 Hz and leave
+    /// the signal unchanged for sample rates up to 384000 Hz. Higher sample rates are not
+    /// supported.
+    ///
+    /// The equation to convert to dBTP is: 20 * log10(out)
+    pub fn prev_true_peak(&self, channel_number: u32) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::TRUE_PEAK) {
+            return Err(Error::InvalidMode);
+        }
+
+        if channel_number >= self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        let sample_peak = self.filter.sample_peak();
+        let true_peak = self.filter.true_peak();
+
+        if sample_peak[channel_number as usize] > true_peak[channel_number as usize] {
+            Ok(sample_peak[channel_number as usize])
+        } else {
+            Ok(true_peak[channel_number as usize])
+        }
+    }
+
+    /// Get maximum true peak from all frames that have been processed in dBTP.
+    ///
+    /// See [`EbuR128::true_peak`](struct.EbuR128.html#method.true_peak) for details. Returns
+    /// negative infinity if only silence was processed so far.
+    pub fn true_peak_dbtp(&self, channel_number: u32) -> Result<f64, Error> {
+        self.true_peak(channel_number)
+            .map(|peak| 20.0 * math::log10(peak))
+    }
+
+    /// Get maximum true peak over all channels from all frames that have been processed in dBTP.
+    ///
+    /// See [`EbuR128::true_peak`](struct.EbuR128.html#method.true_peak) for details. Returns
+    /// negative infinity if only silence was processed so far.
+    pub fn true_peak_dbtp_max(&self) -> Result<f64, Error> {
+        self.true_peak_max().map(|peak| 20.0 * math::log10(peak))
+    }
+
+    /// Get the peak-to-loudness ratio (PLR) in dB.
+    ///
+    /// This is the maximum true peak over all channels in dBTP minus the integrated loudness in
+    /// LUFS, a common indicator of the dynamics of a programme. A full scale sine has a PLR of
+    /// 3.01 dB on a single channel and 0 dB on two channels.
+    ///
+    /// Requires `Mode::I` and `Mode::TRUE_PEAK`, otherwise `Error::InvalidMode` is returned, and
+    /// returns `Error::NotEnoughData` like
+    /// [`EbuR128::normalization_gain`](struct.EbuR128.html#method.normalization_gain) if no gating
+    /// block above the gates was measured yet.
+    pub fn peak_to_loudness_ratio(&self) -> Result<f64, Error> {
+        let true_peak = self.true_peak_max()?;
+        let loudness = finite_loudness(self.loudness_global()?)?;
+
+        if true_peak <= 0.0 {
+            return Err(Error::NotEnoughData);
+        }
+
+        Ok(20.0 * math::log10(true_peak) - loudness)
+    }
+
+    /// Get the distribution of the loudness of all gating blocks that were measured so far.
+    ///
+    /// Returns the loudness in LUFS at the center of each histogram bin together with the number
+    /// of gating blocks in that bin, in ascending order of loudness. The bins cover the range from
+    /// -70 LUFS to +30 LUFS with the configured
+    /// [`EbuR128::histogram_resolution`](struct.EbuR128.html#method.histogram_resolution) and
+    /// all bins are included, also empty ones. Gating blocks below the absolute gate of -70 LUFS
+    /// are not stored and thus not included, gating blocks above +30 LUFS are counted in the
+    /// last bin.
+    ///
+    /// With `Mode::HISTOGRAM` this is the histogram used for the integrated loudness, otherwise
+    /// the histogram is calculated from the stored gating blocks when calling this function.
+    pub fn loudness_histogram(&self) -> Result<Vec<(f64, u64)>, Error> {
+        if !self.mode.contains(Mode::I) {
+            return Err(Error::InvalidMode);
+        }
+
+        Ok(self
+            .block_energy_history
+            .histogram(self.histogram_resolution))
+    }
+
+    /// Get relative threshold in LUFS.
+    ///
+    /// This is the relative gate used for the integrated loudness: the loudness of all gating
+    /// blocks above the absolute gate of -70 LUFS minus 10 LU, or the
+    /// [configured gates](struct.EbuR128.html#method.set_gating). Gating blocks below this
+    /// threshold are not included in
+    /// [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global).
+    ///
+    /// Returns the absolute gate, by default -70 LUFS, if no gating block above the absolute gate
+    /// was measured yet. This is the same as `ebur128_relative_threshold()` in libebur128.
+    pub fn relative_threshold(&self) -> Result<f64, Error> {
+        if !self.mode.contains(Mode::I) {
+            return Err(Error::InvalidMode);
+        }
+
+        Ok(self.block_energy_history.relative_threshold(&self.gating))
+    }
+
+    /// Get all measurement results for the enabled modes at once.
+    ///
+    /// See [`Measurement`](struct.Measurement.html) for which values are included. The
+    /// individual getters like [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global)
+    /// return the same values.
+    pub fn measurement(&self) -> Measurement {
+        fn finite(value: Result<f64, Error>) -> Option<f64> {
+            value.ok().filter(|v| *v != -f64::INFINITY)
+        }
+
+        let peaks = |peak: fn(&Self, u32) -> Result<f64, Error>| {
+            (0..self.channels)
+                .map(|c| peak(self, c))
+                .collect::<Result<Vec<_>, _>>()
+                .ok()
+        };
+
+        Measurement {
+            integrated: finite(self.loudness_global()),
+            range: self.loudness_range().ok(),
+            momentary: self
+                .mode
+                .contains(Mode::M)
+                .then(|| self.loudness_momentary())
+                .and_then(finite),
+            shortterm: self
+                .mode
+                .contains(Mode::S)
+                .then(|| self.loudness_shortterm())
+                .and_then(finite),
+            momentary_max: finite(self.loudness_momentary_max()),
+            shortterm_max: finite(self.loudness_shortterm_max()),
+            sample_peak: peaks(Self::sample_peak),
+            true_peak: peaks(Self::true_peak),
+        }
+    }
+
+    /// Measure a whole buffer of interleaved frames at once.
+    ///
+    /// This creates a new instance for `channels`, `rate` and `mode`, adds all frames of
+    /// `interleaved` and returns the [`Measurement`](struct.Measurement.html) afterwards, which
+    /// gives the same results as doing these steps one after another. Everything else uses the
+    /// default configuration, use [`EbuR128Builder`](struct.EbuR128Builder.html) and
+    /// [`EbuR128::add_frames_f32`](struct.EbuR128.html#method.add_frames_f32) for other
+    /// settings or for further measurements that are not part of `Measurement`.
+    ///
+    /// Returns the same errors as [`EbuR128::new`](struct.EbuR128.html#method.new) and
+    /// `add_frames_f32()`, e.g. `Error::NoMem` if the length of `interleaved` is not a multiple of
+    /// `channels`.
+    ///
+    /// ```
+    /// # use ebur128::{EbuR128, Mode};
+    /// let silence = vec![0.0f32; 48_000 * 2];
+    /// let measurement = EbuR128::analyze(2, 48_000, Mode::I | Mode::SAMPLE_PEAK, &silence).unwrap();
+    /// // Silence has no integrated loudness
+    /// assert_eq!(measurement.integrated, None);
+    /// assert_eq!(measurement.sample_peak, Some(vec![0.0, 0.0]));
+    /// ```
+    pub fn analyze(
+        channels: u32,
+        rate: u32,
+        mode: Mode,
+        interleaved: &[f32],
+    ) -> Result<Measurement, Error> {
+        let mut ebu = EbuR128::new(channels, rate, mode)?;
+        ebu.add_frames_f32(interleaved)?;
+
+        Ok(ebu.measurement())
+    }
+}
+
+/// Builder for [`EbuR128`](struct.EbuR128.html).
+///
+/// This allows configuring all parameters at once and validates the whole configuration when
+/// building the instance, instead of calling the different setters one after another.
+///
+/// ```
+/// # use ebur128::{Channel, EbuR128Builder, Mode};
+/// let ebu = EbuR128Builder::new()
+///     .channels(2)
+///     .rate(48_000)
+///     .mode(Mode::I | Mode::TRUE_PEAK)
+///     .channel_map(&[Channel::Left, Channel::Right])
+///     .max_history(60_000)
+///     .build()
+///     .unwrap();
+/// assert_eq!(ebu.max_history(), 60_000);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct EbuR128Builder {
+    channels: u32,
+    rate: u32,
+    mode: Mode,
+    channel_map: Option<Vec<Channel>>,
+    channel_gains: Option<Vec<f64>>,
+    max_window: Option<u32>,
+    max_history: Option<u32>,
+    histogram_resolution: Option<u32>,
+    weighting: Option<Weighting>,
+    precision: Option<Precision>,
+    dual_mono_compensation: Option<bool>,
+    channel_history: Option<bool>,
+    non_finite_handling: Option<NonFiniteHandling>,
+    chunk_size: Option<usize>,
+    true_peak_quality: Option<TruePeakQuality>,
+    reference_loudness: Option<f64>,
+    true_peak_overs_threshold: Option<f64>,
+    gating: Option<(f64, f64)>,
+    block_hop: Option<u32>,
+}
+
+impl Default for EbuR128Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EbuR128Builder {
+    /// Create a new builder.
+    ///
+    /// The number of channels, the sample rate and the mode have to be set before calling
+    /// [`EbuR128Builder::build`](struct.EbuR128Builder.html#method.build).
+    pub fn new() -> Self {
+        Self {
+            channels: 0,
+            rate: 0,
+            mode: Mode::empty(),
+            channel_map: None,
+            channel_gains: None,
+            max_window: None,
+            max_history: None,
+            histogram_resolution: None,
+            weighting: None,
+            precision: None,
+            dual_mono_compensation: None,
+            channel_history: None,
+            non_finite_handling: None,
+            chunk_size: None,
+            true_peak_quality: None,
+            reference_loudness: None,
+            true_peak_overs_threshold: None,
+            gating: None,
+            block_hop: None,
+        }
+    }
+
+    /// Set the number of channels.
+    pub fn channels(mut self, channels: u32) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// Set the sample rate.
+    pub fn rate(mut self, rate: u32) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// Set the processing mode.
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the channel types.
+    ///
+    /// See [`EbuR128::set_channel_map`](struct.EbuR128.html#method.set_channel_map) for details
+    /// and the default if this is not set.
+    pub fn channel_map(mut self, channel_map: &[Channel]) -> Self {
+        self.channel_map = Some(channel_map.to_vec());
+        self
+    }
+
+    /// Set a linear gain for each channel.
+    ///
+    /// See [`EbuR128::set_channel_gains`](struct.EbuR128.html#method.set_channel_gains) for
+    /// details.
+    pub fn channel_gains(mut self, gains: &[f64]) -> Self {
+        self.channel_gains = Some(gains.to_vec());
+        self
+    }
+
+    /// Set the maximum window duration in ms.
+    ///
+    /// See [`EbuR128::set_max_window`](struct.EbuR128.html#method.set_max_window) for details.
+    pub fn max_window(mut self, window: u32) -> Self {
+        self.max_window = Some(window);
+        self
+    }
+
+    /// Set the maximum history in ms.
+    ///
+    /// See [`EbuR128::set_max_history`](struct.EbuR128.html#method.set_max_history) for details.
+    pub fn max_history(mut self, history: u32) -> Self {
+        self.max_history = Some(history);
+        self
+    }
+
+    /// Set the resolution of the histogram in bins per LU.
+    ///
+    /// See [`EbuR128::set_histogram_resolution`](struct.EbuR128.html#method.set_histogram_resolution)
+    /// for details.
+    pub fn histogram_resolution(mut self, bins_per_lu: u32) -> Self {
+        self.histogram_resolution = Some(bins_per_lu);
+        self
+    }
+
+    /// Set the frequency weighting.
+    ///
+    /// See [`EbuR128::set_weighting`](struct.EbuR128.html#method.set_weighting) for details.
+    pub fn weighting(mut self, weighting: Weighting) -> Self {
+        self.weighting = Some(weighting);
+        self
+    }
+
+    /// Set the floating point precision.
+    ///
+    /// See [`EbuR128::set_precision`](struct.EbuR128.html#method.set_precision) for details.
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    /// Enable or disable the dual mono compensation.
+    ///
+    /// See [`EbuR128::set_dual_mono_compensation`](struct.EbuR128.html#method.set_dual_mono_compensation)
+    /// for details.
+    pub fn dual_mono_compensation(mut self, enabled: bool) -> Self {
+        self.dual_mono_compensation = Some(enabled);
+        self
+    }
+
+    /// Enable or disable storing the energy of each channel for every gating block.
+    ///
+    /// See [`EbuR128::set_channel_history`](struct.EbuR128.html#method.set_channel_history)
+    /// for details.
+    pub fn channel_history(mut self, enabled: bool) -> Self {
+        self.channel_history = Some(enabled);
+        self
+    }
+
+    /// Set the handling of NaN and infinite input samples.
+    ///
+    /// See [`EbuR128::set_non_finite_handling`](struct.EbuR128.html#method.set_non_finite_handling)
+    /// for details.
+    pub fn non_finite_handling(mut self, handling: NonFiniteHandling) -> Self {
+        self.non_finite_handling = Some(handling);
+        self
+    }
+
+    /// Set the maximum number of frames processed at once.
+    ///
+    /// See [`EbuR128::set_chunk_size`](struct.EbuR128.html#method.set_chunk_size) for details.
+    pub fn chunk_size(mut self, frames: usize) -> Self {
+        self.chunk_size = Some(frames);
+        self
+    }
+
+    /// Set the oversampling quality of the true peak measurement.
+    ///
+    /// See [`EbuR128::set_true_peak_quality`](struct.EbuR128.html#method.set_true_peak_quality)
+    /// for details.
+    pub fn true_peak_quality(mut self, quality: TruePeakQuality) -> Self {
+        self.true_peak_quality = Some(quality);
+        self
+    }
+
+    /// Set the reference loudness in LUFS for the relative loudness.
+    ///
+    /// See [`EbuR128::set_reference_loudness`](struct.EbuR128.html#method.set_reference_loudness)
+    /// for details.
+    pub fn reference_loudness(mut self, lufs: f64) -> Self {
+        self.reference_loudness = Some(lufs);
+        self
+    }
+
+    /// Set the linear threshold for counting the overs of the true peak measurement.
+    ///
+    /// See [`EbuR128::set_true_peak_overs_threshold`](struct.EbuR128.html#method.set_true_peak_overs_threshold)
+    /// for details.
+    pub fn true_peak_overs_threshold(mut self, threshold: f64) -> Self {
+        self.true_peak_overs_threshold = Some(threshold);
+        self
+    }
+
+    /// Set the absolute gate in LUFS and the relative gate in LU for the integrated loudness.
+    ///
+    /// See [`EbuR128::set_gating`](struct.EbuR128.html#method.set_gating) for details.
+    pub fn gating(mut self, absolute_lufs: f64, relative_lu: f64) -> Self {
+        self.gating = Some((absolute_lufs, relative_lu));
+        self
+    }
+
+    /// Set the hop between the start of two gating blocks in ms.
+    ///
+    /// See [`EbuR128::set_block_hop`](struct.EbuR128.html#method.set_block_hop) for details.
+    pub fn block_hop(mut self, hop: u32) -> Self {
+        self.block_hop = Some(hop);
+        self
+    }
+
+    /// Build the configured instance.
+    ///
+    /// Returns the same errors as [`EbuR128::new`](struct.EbuR128.html#method.new) and the
+    /// setters for the individual parameters. Additionally returns `Error::NoMem` if both the
+    /// maximum window and the maximum history are set and the window is longer than the history.
+    pub fn build(&self) -> Result<EbuR128, Error> {
+        if let (Some(window), Some(history)) = (self.max_window, self.max_history) {
+            if window > history {
+                return Err(Error::NoMem);
+            }
+        }
+
+        let mut ebu = EbuR128::new(self.channels, self.rate, self.mode)?;
+
+        if let Some(ref channel_map) = self.channel_map {
+            ebu.set_channel_map(channel_map)?;
+        }
+
+        if let Some(ref gains) = self.channel_gains {
+            ebu.set_channel_gains(gains)?;
+        }
+
+        if let Some(window) = self.max_window {
+            ebu.set_max_window(window)?;
+        }
+
+        if let Some(history) = self.max_history {
+            ebu.set_max_history(history)?;
+        }
+
+        if let Some(bins_per_lu) = self.histogram_resolution {
+            ebu.set_histogram_resolution(bins_per_lu)?;
+        }
+
+        if let Some(weighting) = self.weighting {
+            ebu.set_weighting(weighting)?;
+        }
+
+        if let Some(precision) = self.precision {
+            ebu.set_precision(precision)?;
+        }
+
+        if let Some(enabled) = self.dual_mono_compensation {
+            ebu.set_dual_mono_compensation(enabled);
+        }
+
+        if let Some(enabled) = self.channel_history {
+            ebu.set_channel_history(enabled);
+        }
+
+        if let Some(handling) = self.non_finite_handling {
+            ebu.set_non_finite_handling(handling);
+        }
+
+        if let Some(frames) = self.chunk_size {
+            ebu.set_chunk_size(frames)?;
+        }
+
+        if let Some(quality) = self.true_peak_quality {
+            ebu.set_true_peak_quality(quality)?;
+        }
+
+        if let Some(lufs) = self.reference_loudness {
+            ebu.set_reference_loudness(lufs)?;
+        }
+
+        if let Some(threshold) = self.true_peak_overs_threshold {
+            ebu.set_true_peak_overs_threshold(threshold)?;
+        }
+
+        if let Some((absolute_lufs, relative_lu)) = self.gating {
+            ebu.set_gating(absolute_lufs, relative_lu)?;
+        }
+
+        if let Some(hop) = self.block_hop {
+            ebu.set_block_hop(hop)?;
+        }
+
+        Ok(ebu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "c-tests")]
+    use crate::tests::Signal;
+    use float_eq::assert_float_eq;
+    #[cfg(feature = "c-tests")]
+    use quickcheck_macros::quickcheck;
+
+    fn f64_max(mut values: impl Iterator<Item = f64>) -> Option<f64> {
+        let mut v = values.next()?;
+        for candidate in values {
+            if candidate > v {
+                v = candidate
+            }
+        }
+        Some(v)
+    }
+
+    #[test]
+    fn sine_stereo_i16() {
+        let mut data = vec![0i16; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator) * (i16::MAX - 1) as f32;
+            out[0] = val as i16;
+            out[1] = val as i16;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu.add_frames_i16(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6500000000000054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6820309226891973,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6834583474398446,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.875007988101488,
+            abs <= 0.000001
+        );
+        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.sample_peak(0).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.sample_peak(1).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_sample_peak(0).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_sample_peak(1).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.650000000000006,
+            abs <= 0.000001
+        );
+
+        ebu.reset();
+
+        assert_eq!(ebu.loudness_global(), Err(Error::NotEnoughData));
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -f64::INFINITY,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -f64::INFINITY,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -f64::INFINITY,
+            abs <= 0.000001
+        );
+        assert_eq!(ebu.loudness_range(), Err(Error::NotEnoughData));
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 0.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 0.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 0.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 0.0, abs <= 0.000001);
+
+        assert_float_eq!(ebu.true_peak(0).unwrap(), 0.0, abs <= 0.000001);
+        assert_float_eq!(ebu.true_peak(1).unwrap(), 0.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_true_peak(0).unwrap(), 0.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_true_peak(1).unwrap(), 0.0, abs <= 0.000001);
+
+        assert_float_eq!(ebu.relative_threshold().unwrap(), -70.0, abs <= 0.000001);
+    }
+
+    #[test]
+    fn sine_stereo_i32() {
+        let mut data = vec![0i32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator) * (i32::MAX - 1) as f32;
+            out[0] = val as i32;
+            out[1] = val as i32;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu.add_frames_i32(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6500000000000054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598274425,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715105212,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620040943,
+            abs <= 0.000001
+        );
+        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.650000000000006,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f32() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = val;
+            out[1] = val;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6500000000000054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598268921,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715100236,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620008693,
+            abs <= 0.000001
+        );
+        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.650000000000006,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f64() {
+        let mut data = vec![0.0f64; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = val as f64;
+            out[1] = val as f64;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu.add_frames_f64(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6500000000000054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598268921,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715100236,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620008693,
+            abs <= 0.000001
+        );
+        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.650000000000006,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_i16_no_histogram() {
+        let mut data = vec![0i16; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator) * (i16::MAX - 1) as f32;
+            out[0] = val as i16;
+            out[1] = val as i16;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_i16(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.683303243667768,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6820309226891973,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6834583474398446,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.875007988101488,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006950793233284625,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.sample_peak(0).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.sample_peak(1).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_sample_peak(0).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_sample_peak(1).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.683303243667767,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_i32_no_histogram() {
+        let mut data = vec![0i32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator) * (i32::MAX - 1) as f32;
+            out[0] = val as i32;
+            out[1] = val as i32;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_i32(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6826039914171368,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598274425,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715105212,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620040943,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006921150165073442,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.682603991417135,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f32_no_histogram() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = val;
+            out[1] = val;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6826039914165554,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598268921,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715100236,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620008693,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006921150169403312,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.682603991416554,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f64_no_histogram() {
+        let mut data = vec![0.0f64; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = val as f64;
+            out[1] = val as f64;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_f64(&data).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6826039914165554,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598268921,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715100236,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620008693,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006921150169403312,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.682603991416554,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_i16_planar_no_histogram() {
+        let mut data = vec![0i16; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        let (fst, snd) = data.split_at_mut(48_000 * 5);
+        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
+            let val = f32::sin(accumulator) * (i16::MAX - 1) as f32;
+            *fst = val as i16;
+            *snd = val as i16;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_planar_i16(&[fst, snd]).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.683303243667768,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6820309226891973,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6834583474398446,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.875007988101488,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006950793233284625,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.sample_peak(0).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.sample_peak(1).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_sample_peak(0).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_sample_peak(1).unwrap(),
+            0.99993896484375,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0007814168930054,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.683303243667767,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_i32_planar_no_histogram() {
+        let mut data = vec![0i32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        let (fst, snd) = data.split_at_mut(48_000 * 5);
+        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
+            let val = f32::sin(accumulator) * (i32::MAX - 1) as f32;
+            *fst = val as i32;
+            *snd = val as i32;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_planar_i32(&[fst, snd]).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6826039914171368,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598274425,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715105212,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620040943,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006921150165073442,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.682603991417135,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f32_planar_no_histogram() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        let (fst, snd) = data.split_at_mut(48_000 * 5);
+        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
+            let val = f32::sin(accumulator);
+            *fst = val;
+            *snd = val;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_planar_f32(&[fst, snd]).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6826039914165554,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598268921,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715100236,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620008693,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006921150169403312,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.682603991416554,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f64_planar_no_histogram() {
+        let mut data = vec![0.0f64; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        let (fst, snd) = data.split_at_mut(48_000 * 5);
+        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
+            let val = f32::sin(accumulator);
+            *fst = val as f64;
+            *snd = val as f64;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu.add_frames_planar_f64(&[fst, snd]).unwrap();
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            -0.6826039914165554,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_momentary().unwrap(),
+            -0.6813325598268921,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            -0.6827591715100236,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_window(1).unwrap(),
+            -0.8742956620008693,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            0.00006921150169403312,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
+        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+
+        assert_float_eq!(
+            ebu.true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(0).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.prev_true_peak(1).unwrap(),
+            1.0008491277694702,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            -10.682603991416554,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f32_multiple() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = val;
+            out[1] = val;
+            accumulator += step;
+        }
+
+        let mut ebu1 = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu1.add_frames_f32(&data).unwrap();
+
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 880.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = 0.5 * val;
+            out[1] = 0.5 * val;
+            accumulator += step;
+        }
+
+        let mut ebu2 = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu2.add_frames_f32(&data).unwrap();
+
+        assert_float_eq!(
+            EbuR128::loudness_global_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
+            -2.603757953612454,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            EbuR128::loudness_range_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
+            5.599999999999995,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn sine_stereo_f32_no_histogram_multiple() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = val;
+            out[1] = val;
+            accumulator += step;
+        }
+
+        let mut ebu1 = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu1.add_frames_f32(&data).unwrap();
+
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 880.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = 0.5 * val;
+            out[1] = 0.5 * val;
+            accumulator += step;
+        }
+
+        let mut ebu2 = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu2.add_frames_f32(&data).unwrap();
+
+        assert_float_eq!(
+            EbuR128::loudness_global_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
+            -2.6302830567858275,
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            EbuR128::loudness_range_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
+            5.571749801957784,
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn chunks_queue_with_true_peak() {
+        let mut data = vec![0.0f32; 48_000 * 3];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(1) {
+            let val = f32::sin(accumulator);
+            out[0] = val;
+            accumulator += step;
+        }
+
+        let mut ebu1 = EbuR128::new(1, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        ebu1.add_frames_f32(&data).unwrap();
+
+        let mut ebu_chunks = Vec::new();
+        for i in 0..3usize {
+            let mut ebu_chunk = EbuR128::new(1, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+            let start_index = std::cmp::max(i as isize * 48_000, 0) as usize;
+            let stop_index = std::cmp::min(start_index + 48_000 + (48_00 * 3), data.len());
+            if start_index > 0 {
+                ebu_chunk
+                    .seed_frames_f32(&data[start_index - 48_00..start_index])
+                    .unwrap();
+            }
+            ebu_chunk
+                .add_frames_f32(&data[start_index..stop_index])
+                .unwrap();
+            ebu_chunks.push(ebu_chunk);
+        }
+
+        assert_float_eq!(
+            ebu1.sample_peak(0).unwrap(),
+            f64_max(ebu_chunks.iter().map(|meter| meter.sample_peak(0).unwrap())).unwrap(),
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu1.true_peak(0).unwrap(),
+            f64_max(ebu_chunks.iter().map(|meter| meter.true_peak(0).unwrap())).unwrap(),
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu1.loudness_global().unwrap(),
+            EbuR128::loudness_global_multiple(ebu_chunks.iter()).unwrap(),
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn chunks_histogram_with_true_peak() {
+        let mut data = vec![0.0f32; 48_000 * 3];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(1) {
+            let val = f32::sin(accumulator);
+            out[0] = val;
+            accumulator += step;
+        }
+
+        let mut ebu1 = EbuR128::new(1, 48_000, Mode::all() | Mode::HISTOGRAM).unwrap();
+        ebu1.add_frames_f32(&data).unwrap();
+
+        let mut ebu_chunks = Vec::new();
+        for i in 0..3usize {
+            let mut ebu_chunk =
+                EbuR128::new(1, 48_000, Mode::all() | Mode::HISTOGRAM & !Mode::HISTOGRAM).unwrap();
+            let start_index = std::cmp::max(i as isize * 48_000, 0) as usize;
+            let stop_index = std::cmp::min(start_index + 48_000 + (48_00 * 3), data.len());
+            if start_index > 0 {
+                ebu_chunk
+                    .seed_frames_f32(&data[start_index - 48_00..start_index])
+                    .unwrap();
+            }
+            ebu_chunk
+                .add_frames_f32(&data[start_index..stop_index])
+                .unwrap();
+            ebu_chunks.push(ebu_chunk);
+        }
+
+        assert_float_eq!(
+            ebu1.sample_peak(0).unwrap(),
+            f64_max(ebu_chunks.iter().map(|meter| meter.sample_peak(0).unwrap())).unwrap(),
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu1.true_peak(0).unwrap(),
+            f64_max(ebu_chunks.iter().map(|meter| meter.true_peak(0).unwrap())).unwrap(),
+            abs <= 0.000001
+        );
+
+        assert_float_eq!(
+            ebu1.loudness_global().unwrap(),
+            EbuR128::loudness_global_multiple(ebu_chunks.iter()).unwrap(),
+            abs <= 0.000001
+        );
+    }
+
+    #[test]
+    fn loudness_global_multiple_chunks() {
+        let mut data = vec![0.0f32; 48_000 * 20 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let val = f32::sin(accumulator);
+            // Change the amplitude every second so that some blocks are below the relative gate
+            let amplitude = [0.5, 0.01, 0.25][(i / 48_000) % 3];
+            out[0] = amplitude * val;
+            out[1] = 0.5 * amplitude * val;
+            accumulator += step;
+        }
+
+        let mode = Mode::I | Mode::TRUE_PEAK;
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+
+        // 4 chunks of 5s, each overlapping with the previous one by 400ms of which the first
+        // 100ms are only used for seeding
+        let mut ebu_chunks = Vec::new();
+        for i in 0..4 {
+            let mut ebu_chunk = EbuR128::new(2, 48_000, mode).unwrap();
+            let start_frame = if i == 0 {
+                0
+            } else {
+                i * 48_000 * 5 - 3 * 4_800
+            };
+            let stop_frame = (i + 1) * 48_000 * 5;
+            if start_frame > 0 {
+                ebu_chunk
+                    .seed_frames_f32(&data[2 * (start_frame - 4_800)..2 * start_frame])
+                    .unwrap();
+            }
+            ebu_chunk
+                .add_frames_f32(&data[2 * start_frame..2 * stop_frame])
+                .unwrap();
+            ebu_chunks.push(ebu_chunk);
+        }
+
+        assert_float_eq!(
+            ebu.loudness_global().unwrap(),
+            EbuR128::loudness_global_multiple(ebu_chunks.iter()).unwrap(),
+            // Seeding doesn't restore the filter states exactly and the energies are summed up in
+            // a different order
+            ulps <= 1_000
+        );
+    }
+
+    #[test]
+    fn loudness_global_multiple_mismatch() {
+        let ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+
+        let mut other_hop = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        other_hop.set_block_hop(200).unwrap();
+        for other in &[EbuR128::new(2, 48_000, Mode::M).unwrap(), other_hop] {
+            assert_eq!(
+                EbuR128::loudness_global_multiple([&ebu, other].iter().copied()).unwrap_err(),
+                Error::InvalidMode
+            );
+        }
+
+        // The input format and the other modes don't matter
+        for other in &[
+            EbuR128::new(2, 48_000, Mode::I).unwrap(),
+            EbuR128::new(2, 48_000, Mode::I | Mode::HISTOGRAM).unwrap(),
+            EbuR128::new(1, 48_000, Mode::I).unwrap(),
+            EbuR128::new(2, 44_100, Mode::I | Mode::LRA).unwrap(),
+        ] {
+            assert_eq!(
+                EbuR128::loudness_global_multiple([&ebu, other].iter().copied()),
+                Err(Error::NotEnoughData)
+            );
+        }
+    }
+
+    #[test]
+    fn loudness_range_multiple_chunks() {
+        let mut data = vec![0.0f32; 48_000 * 30 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let val = f32::sin(accumulator);
+            // Change the amplitude every few seconds to get some loudness range
+            let amplitude = [0.5, 0.05, 0.25, 0.1][(i / (48_000 * 4)) % 4];
+            out[0] = amplitude * val;
+            out[1] = 0.5 * amplitude * val;
+            accumulator += step;
+        }
+
+        let mode = Mode::LRA;
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+
+        // 3 chunks of 10s, each overlapping with the previous one by 2.1s of which the first
+        // 100ms are only used for seeding
+        let mut ebu_chunks = Vec::new();
+        for i in 0..3 {
+            let mut ebu_chunk = EbuR128::new(2, 48_000, mode).unwrap();
+            let start_frame = if i == 0 {
+                0
+            } else {
+                i * 48_000 * 10 - 2 * 48_000
+            };
+            let stop_frame = (i + 1) * 48_000 * 10;
+            if start_frame > 0 {
+                ebu_chunk
+                    .seed_frames_f32(&data[2 * (start_frame - 4_800)..2 * start_frame])
+                    .unwrap();
+            }
+            ebu_chunk
+                .add_frames_f32(&data[2 * start_frame..2 * stop_frame])
+                .unwrap();
+            ebu_chunks.push(ebu_chunk);
+        }
+
+        assert!(ebu.loudness_range().unwrap() > 1.0);
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            EbuR128::loudness_range_multiple(ebu_chunks.iter()).unwrap(),
+            abs <= 0.000001
+        );
+
+        let ebu_other = EbuR128::new(2, 44_100, mode).unwrap();
+        assert_eq!(
+            EbuR128::loudness_range_multiple([&ebu, &ebu_other].iter().copied()).unwrap_err(),
+            Error::InvalidMode
+        );
+        let ebu_other = EbuR128::new(2, 48_000, mode | Mode::HISTOGRAM).unwrap();
+        assert_eq!(
+            EbuR128::loudness_range_multiple([&ebu, &ebu_other].iter().copied()).unwrap_err(),
+            Error::InvalidMode
+        );
+        let ebu_other = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(
+            EbuR128::loudness_range_multiple([&ebu, &ebu_other].iter().copied()).unwrap_err(),
+            Error::InvalidMode
+        );
+    }
+
+    #[test]
+    fn relative_threshold() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.relative_threshold().unwrap(), -70.0);
+
+        // Silence is below the absolute gate
+        ebu.add_frames_f32(&[0.0; 48_000 * 2]).unwrap();
+        assert_eq!(ebu.relative_threshold().unwrap(), -70.0);
+
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = 0.5 * val;
+            out[1] = 0.5 * val;
+            accumulator += step;
+        }
+        ebu.add_frames_f32(&data).unwrap();
+
+        // All gating blocks have the same loudness
+        assert_float_eq!(
+            ebu.relative_threshold().unwrap(),
+            ebu.loudness_global().unwrap() - 10.0,
+            abs <= 0.01
+        );
+
+        let ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.relative_threshold().unwrap_err(), Error::InvalidMode);
+    }
+
+    #[test]
+    fn set_history_mode() {
+        let mut data = vec![0.0f32; 48_000 * 20 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let val = f32::sin(accumulator);
+            // Slowly change the amplitude to get many different block loudnesses
+            let amplitude = 0.05 + 0.45 * (i as f32 / (48_000.0 * 20.0));
+            out[0] = amplitude * val;
+            out[1] = 0.7 * amplitude * val;
+            accumulator += step;
+        }
+
+        let mut ebu_queue = EbuR128::new(2, 48_000, Mode::I | Mode::LRA).unwrap();
+        ebu_queue.add_frames_f32(&data).unwrap();
+        let mut ebu_histogram =
+            EbuR128::new(2, 48_000, Mode::I | Mode::LRA | Mode::HISTOGRAM).unwrap();
+        ebu_histogram.add_frames_f32(&data).unwrap();
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::LRA).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+
+        // Converting to the histogram gives exactly the same as using the histogram directly and
+        // stays within the quantization error of the histogram bins of 0.1 LU
+        ebu.set_history_mode(true).unwrap();
+        assert!(ebu.mode().contains(Mode::HISTOGRAM));
+        assert_eq!(
+            ebu.loudness_global().unwrap(),
+            ebu_histogram.loudness_global().unwrap()
+        );
+        assert_eq!(
+            ebu.loudness_range().unwrap(),
+            ebu_histogram.loudness_range().unwrap()
+        );
         assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -0.6500000000000054,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6820309226891973,
-            abs <= 0.000001
+            ebu.loudness_global().unwrap(),
+            ebu_queue.loudness_global().unwrap(),
+            abs <= 0.05
         );
         assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6834583474398446,
-            abs <= 0.000001
+            ebu.loudness_range().unwrap(),
+            ebu_queue.loudness_range().unwrap(),
+            abs <= 0.1
         );
+
+        // Converting back keeps the quantized loudness
+        ebu.set_history_mode(false).unwrap();
+        assert!(!ebu.mode().contains(Mode::HISTOGRAM));
         assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.875007988101488,
+            ebu.loudness_global().unwrap(),
+            ebu_histogram.loudness_global().unwrap(),
             abs <= 0.000001
         );
-        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            ebu_histogram.loudness_range().unwrap(),
+            abs <= 0.1
+        );
 
+        // And new blocks are added to the converted history
+        ebu.add_frames_f32(&data).unwrap();
+        ebu_queue.add_frames_f32(&data).unwrap();
         assert_float_eq!(
-            ebu.sample_peak(0).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
+            ebu.loudness_global().unwrap(),
+            ebu_queue.loudness_global().unwrap(),
+            abs <= 0.05
+        );
+    }
+
+    #[test]
+    fn histogram_resolution() {
+        let mut data = vec![0.0f32; 48_000 * 20 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let val = f32::sin(accumulator);
+            // Slowly change the amplitude to get many different block loudnesses
+            let amplitude = 0.05 + 0.45 * (i as f32 / (48_000.0 * 20.0));
+            out[0] = amplitude * val;
+            out[1] = 0.7 * amplitude * val;
+            accumulator += step;
+        }
+
+        let mut ebu_queue = EbuR128::new(2, 48_000, Mode::I | Mode::LRA).unwrap();
+        ebu_queue.add_frames_f32(&data).unwrap();
+        let expected_global = ebu_queue.loudness_global().unwrap();
+        let expected_range = ebu_queue.loudness_range().unwrap();
+
+        let mut prev_error = f64::INFINITY;
+        for &bins_per_lu in &[1, 10, 100, 1000] {
+            let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::LRA | Mode::HISTOGRAM).unwrap();
+            ebu.set_histogram_resolution(bins_per_lu).unwrap();
+            assert_eq!(ebu.histogram_resolution(), bins_per_lu);
+            ebu.add_frames_f32(&data).unwrap();
+
+            // At most half a bin
+            let error = (ebu.loudness_global().unwrap() - expected_global).abs();
+            assert!(
+                error <= 0.5 / bins_per_lu as f64,
+                "{} {}",
+                bins_per_lu,
+                error
+            );
+            assert!(
+                error < prev_error,
+                "{} {} {}",
+                bins_per_lu,
+                error,
+                prev_error
+            );
+            prev_error = error;
+
+            // Up to half a bin for each of the two percentiles
+            assert_float_eq!(
+                ebu.loudness_range().unwrap(),
+                expected_range,
+                abs <= 1.0 / bins_per_lu as f64
+            );
+        }
+
+        // The default resolution is the same as before
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::LRA | Mode::HISTOGRAM).unwrap();
+        assert_eq!(ebu.histogram_resolution(), 10);
+        ebu.add_frames_f32(&data).unwrap();
+        let mut ebu_10 = EbuR128::new(2, 48_000, Mode::I | Mode::LRA | Mode::HISTOGRAM).unwrap();
+        ebu_10.set_histogram_resolution(100).unwrap();
+        ebu_10.set_histogram_resolution(10).unwrap();
+        ebu_10.add_frames_f32(&data).unwrap();
+        assert_eq!(
+            ebu.loudness_global().unwrap(),
+            ebu_10.loudness_global().unwrap()
         );
+
+        // Changing the resolution keeps the measurements
+        ebu.set_histogram_resolution(100).unwrap();
         assert_float_eq!(
-            ebu.sample_peak(1).unwrap(),
-            0.99993896484375,
+            ebu.loudness_global().unwrap(),
+            ebu_10.loudness_global().unwrap(),
+            abs <= 0.01
+        );
+
+        assert_eq!(ebu.set_histogram_resolution(0).unwrap_err(), Error::NoMem);
+        assert_eq!(
+            ebu.set_histogram_resolution(1001).unwrap_err(),
+            Error::NoMem
+        );
+        assert_eq!(ebu.histogram_resolution(), 100);
+    }
+
+    #[test]
+    fn loudness_histogram() {
+        let mut data = vec![0.0f32; 48_000 * 10 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let val = f32::sin(accumulator);
+            // 5s at two different amplitudes
+            let amplitude = if i < 48_000 * 5 { 0.5 } else { 0.05 };
+            out[0] = amplitude * val;
+            out[1] = amplitude * val;
+            accumulator += step;
+        }
+
+        for &mode in &[Mode::I, Mode::I | Mode::HISTOGRAM] {
+            let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+            assert!(ebu
+                .loudness_histogram()
+                .unwrap()
+                .iter()
+                .all(|(_, count)| *count == 0));
+
+            ebu.add_frames_f32(&data).unwrap();
+            let histogram = ebu.loudness_histogram().unwrap();
+            assert_eq!(histogram.len(), 1000);
+            assert_float_eq!(histogram[0].0, -69.95, abs <= 0.000001);
+            assert_float_eq!(histogram[999].0, 29.95, abs <= 0.000001);
+
+            // 97 gating blocks in total, of which 3 overlap both amplitudes
+            let total = histogram.iter().map(|(_, count)| *count).sum::<u64>();
+            assert_eq!(total, 97);
+
+            // Both amplitudes are 20 LU apart
+            let mut peaks = histogram
+                .iter()
+                .filter(|(_, count)| *count >= 40)
+                .map(|(loudness, _)| *loudness)
+                .collect::<Vec<_>>();
+            peaks.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(peaks.len(), 2);
+            assert_float_eq!(peaks[1] - peaks[0], 20.0, abs <= 0.15);
+        }
+
+        let ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.loudness_histogram().unwrap_err(), Error::InvalidMode);
+    }
+
+    #[test]
+    fn true_peak_dbtp() {
+        let mut data = vec![0.0f32; 48_000 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = 0.5 * val;
+            out[1] = 0.25 * val;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.true_peak_dbtp(0).unwrap(), -f64::INFINITY);
+        assert_eq!(ebu.true_peak_dbtp_max().unwrap(), -f64::INFINITY);
+
+        ebu.add_frames_f32(&data).unwrap();
+
+        for c in 0..2 {
+            assert_float_eq!(
+                ebu.true_peak_dbtp(c).unwrap(),
+                20.0 * f64::log10(ebu.true_peak(c).unwrap()),
+                abs <= 0.000001
+            );
+        }
+        assert_float_eq!(
+            ebu.true_peak_dbtp_max().unwrap(),
+            ebu.true_peak_dbtp(0).unwrap(),
             abs <= 0.000001
         );
+        assert_float_eq!(ebu.true_peak_dbtp(0).unwrap(), -6.0206, abs <= 0.01);
+        assert_float_eq!(ebu.true_peak_dbtp(1).unwrap(), -12.0412, abs <= 0.01);
+
+        assert_eq!(
+            ebu.true_peak_dbtp(2).unwrap_err(),
+            Error::InvalidChannelIndex
+        );
+
+        let ebu = EbuR128::new(2, 48_000, Mode::SAMPLE_PEAK).unwrap();
+        assert_eq!(ebu.true_peak_dbtp_max().unwrap_err(), Error::InvalidMode);
+    }
+
+    #[test]
+    fn add_frames_i24() {
+        let mut data_i24 = vec![0i32; 48_000 * 5 * 2];
+        let mut data_f64 = vec![0.0f64; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f64::consts::PI * 997.0 / 48_000.0;
+        for (out_i24, out_f64) in
+            Iterator::zip(data_i24.chunks_exact_mut(2), data_f64.chunks_exact_mut(2))
+        {
+            let val = f64::sin(accumulator);
+            let val = [
+                f64::round(val * 8_388_607.0) as i32,
+                f64::round(val * 0.5 * 8_388_607.0) as i32,
+            ];
+            for c in 0..2 {
+                // Put garbage into the high byte, which has to be ignored
+                out_i24[c] = (val[c] & 0x00ff_ffff) | 0x5a00_0000;
+                out_f64[c] = val[c] as f64 / 8_388_608.0;
+            }
+            accumulator += step;
+        }
+
+        let mode = Mode::I | Mode::M | Mode::SAMPLE_PEAK | Mode::TRUE_PEAK;
+        let mut ebu_i24 = EbuR128::new(2, 48_000, mode).unwrap();
+        let mut ebu_f64 = EbuR128::new(2, 48_000, mode).unwrap();
+
+        ebu_i24.add_frames_i24(&data_i24).unwrap();
+        ebu_f64.add_frames_f64(&data_f64).unwrap();
+
         assert_float_eq!(
-            ebu.prev_sample_peak(0).unwrap(),
-            0.99993896484375,
+            ebu_i24.loudness_global().unwrap(),
+            ebu_f64.loudness_global().unwrap(),
             abs <= 0.000001
         );
         assert_float_eq!(
-            ebu.prev_sample_peak(1).unwrap(),
-            0.99993896484375,
+            ebu_i24.loudness_momentary().unwrap(),
+            ebu_f64.loudness_momentary().unwrap(),
             abs <= 0.000001
         );
+        for c in 0..2 {
+            assert_float_eq!(
+                ebu_i24.sample_peak(c).unwrap(),
+                ebu_f64.sample_peak(c).unwrap(),
+                abs <= 0.000001
+            );
+            assert_float_eq!(
+                ebu_i24.prev_sample_peak(c).unwrap(),
+                ebu_f64.prev_sample_peak(c).unwrap(),
+                abs <= 0.000001
+            );
+            assert_float_eq!(
+                ebu_i24.true_peak(c).unwrap(),
+                ebu_f64.true_peak(c).unwrap(),
+                abs <= 0.000001
+            );
+            assert_float_eq!(
+                ebu_i24.prev_true_peak(c).unwrap(),
+                ebu_f64.prev_true_peak(c).unwrap(),
+                abs <= 0.000001
+            );
+        }
+        assert_float_eq!(ebu_i24.sample_peak(0).unwrap(), 1.0, abs <= 0.0001);
+        assert_float_eq!(ebu_i24.sample_peak(1).unwrap(), 0.5, abs <= 0.0001);
+
+        assert_eq!(
+            ebu_i24.add_frames_i24(&data_i24[..3]).unwrap_err(),
+            Error::NoMem
+        );
+    }
+
+    #[test]
+    fn add_frames_8bit() {
+        // Full scale sine, quantized to 8 bit and unquantized
+        let mut data_u8 = vec![0u8; 48_000 * 5 * 2];
+        let mut data_i8 = vec![0i8; 48_000 * 5 * 2];
+        let mut data_f64 = vec![0.0f64; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f64::consts::PI * 997.0 / 48_000.0;
+        for ((out_u8, out_i8), out_f64) in Iterator::zip(
+            Iterator::zip(data_u8.chunks_exact_mut(2), data_i8.chunks_exact_mut(2)),
+            data_f64.chunks_exact_mut(2),
+        ) {
+            let val = f64::sin(accumulator) * 127.0;
+            for (c, val) in [val, 0.5 * val].iter().enumerate() {
+                out_u8[c] = (f64::round(*val) + 128.0) as u8;
+                out_i8[c] = f64::round(*val) as i8;
+                out_f64[c] = val / 128.0;
+            }
+            accumulator += step;
+        }
+
+        let mode = Mode::I | Mode::M | Mode::SAMPLE_PEAK | Mode::TRUE_PEAK;
+        let mut ebu_u8 = EbuR128::new(2, 48_000, mode).unwrap();
+        let mut ebu_i8 = EbuR128::new(2, 48_000, mode).unwrap();
+        let mut ebu_f64 = EbuR128::new(2, 48_000, mode).unwrap();
+
+        ebu_u8.add_frames_u8(&data_u8).unwrap();
+        ebu_i8.add_frames_i8(&data_i8).unwrap();
+        ebu_f64.add_frames_f64(&data_f64).unwrap();
+
+        for ebu in &[&ebu_u8, &ebu_i8] {
+            // The quantization noise is about 50dB below the sine
+            assert_float_eq!(
+                ebu.loudness_global().unwrap(),
+                ebu_f64.loudness_global().unwrap(),
+                abs <= 0.01
+            );
+            assert_float_eq!(
+                ebu.loudness_momentary().unwrap(),
+                ebu_f64.loudness_momentary().unwrap(),
+                abs <= 0.01
+            );
+            for c in 0..2 {
+                assert_float_eq!(
+                    ebu.sample_peak(c).unwrap(),
+                    ebu_f64.sample_peak(c).unwrap(),
+                    abs <= 0.5 / 128.0
+                );
+                assert_float_eq!(
+                    ebu.true_peak(c).unwrap(),
+                    ebu_f64.true_peak(c).unwrap(),
+                    abs <= 0.01
+                );
+            }
+        }
+        assert_float_eq!(ebu_u8.sample_peak(0).unwrap(), 127.0 / 128.0, abs <= 0.0001);
+        assert_eq!(
+            ebu_u8.sample_peak(0).unwrap(),
+            ebu_i8.sample_peak(0).unwrap()
+        );
+        assert_eq!(
+            ebu_u8.loudness_global().unwrap(),
+            ebu_i8.loudness_global().unwrap()
+        );
+
+        // Silence is 128 for unsigned samples
+        let mut ebu = EbuR128::new(1, 48_000, mode).unwrap();
+        ebu.add_frames_u8(&[128; 48_000]).unwrap();
+        assert_eq!(ebu.loudness_global(), Ok(-f64::INFINITY));
+        assert_eq!(ebu.sample_peak(0).unwrap(), 0.0);
+        assert_eq!(ebu.true_peak(0).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn add_frames_planar_invalid() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        let data = [0.5f32; 480];
+
+        assert_eq!(
+            ebu.add_frames_planar_f32(&[&data]).unwrap_err(),
+            Error::InvalidChannelIndex
+        );
+        assert_eq!(
+            ebu.add_frames_planar_f32(&[&data, &data, &data])
+                .unwrap_err(),
+            Error::InvalidChannelIndex
+        );
+        assert_eq!(
+            ebu.add_frames_planar_f32(&[&data, &data[..479]])
+                .unwrap_err(),
+            Error::NoMem
+        );
+        assert_eq!(ebu.add_frames_planar_f32(&[]).unwrap_err(), Error::NoMem);
+        assert_eq!(
+            ebu.seed_frames_planar_f32(&[&data]).unwrap_err(),
+            Error::InvalidChannelIndex
+        );
+
+        // Nothing was processed by any of the above
+        assert_eq!(ebu.true_peak(0).unwrap(), 0.0);
+        assert_eq!(ebu.loudness_momentary().unwrap(), -f64::INFINITY);
+
+        ebu.add_frames_planar_f32(&[&data, &data]).unwrap();
+        assert_float_eq!(ebu.sample_peak(0).unwrap(), 0.5, abs <= 0.000001);
+    }
+
+    #[test]
+    fn change_parameters_rate() {
+        fn signal(rate: u32) -> Vec<f32> {
+            let mut data = vec![0.0f32; rate as usize * 10 * 2];
+            let mut accumulator = 0.0;
+            let step = 2.0 * std::f32::consts::PI * 1_000.0 / rate as f32;
+            for (i, out) in data.chunks_exact_mut(2).enumerate() {
+                let val = f32::sin(accumulator);
+                // Change the amplitude every second to get some loudness range
+                let amplitude = 0.1 + 0.4 * ((i / rate as usize) % 3) as f32;
+                out[0] = amplitude * val;
+                out[1] = 0.5 * amplitude * val;
+                accumulator += step;
+            }
+            data
+        }
 
+        let data_44100 = signal(44_100);
+        // Don't end on a complete block so a partial block has to be dropped
+        let data_44100 = &data_44100[..data_44100.len() - 2 * 1_234];
+        let data_48000 = signal(48_000);
+
+        let mode = Mode::I | Mode::LRA | Mode::SAMPLE_PEAK | Mode::TRUE_PEAK;
+
+        let mut ebu = EbuR128::new(2, 44_100, mode).unwrap();
+        ebu.add_frames_f32(data_44100).unwrap();
+        ebu.change_parameters(2, 48_000).unwrap();
+        assert_eq!(ebu.rate(), 48_000);
+        ebu.add_frames_f32(&data_48000).unwrap();
+
+        let mut ebu_44100 = EbuR128::new(2, 44_100, mode).unwrap();
+        ebu_44100.add_frames_f32(data_44100).unwrap();
+        let mut ebu_48000 = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu_48000.add_frames_f32(&data_48000).unwrap();
+
+        // Instances with different sample rates can't be combined via the public API
         assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0007814168930054,
+            ebu.loudness_global().unwrap(),
+            crate::history::History::gated_loudness_multiple(
+                &[
+                    &ebu_44100.block_energy_history,
+                    &ebu_48000.block_energy_history
+                ],
+                &crate::history::Gating::default()
+            ),
             abs <= 0.000001
         );
         assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0007814168930054,
+            ebu.loudness_momentary().unwrap(),
+            ebu_48000.loudness_momentary().unwrap(),
+            abs <= 0.000001
+        );
+        assert_float_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            ebu_48000.loudness_shortterm().unwrap(),
             abs <= 0.000001
         );
+        // Both parts have the same loudness range
+        assert_float_eq!(
+            ebu.loudness_range().unwrap(),
+            ebu_48000.loudness_range().unwrap(),
+            abs <= 0.1
+        );
+        for c in 0..2 {
+            assert_float_eq!(
+                ebu.sample_peak(c).unwrap(),
+                f64::max(
+                    ebu_44100.sample_peak(c).unwrap(),
+                    ebu_48000.sample_peak(c).unwrap()
+                ),
+                abs <= 0.000001
+            );
+            assert_float_eq!(
+                ebu.prev_sample_peak(c).unwrap(),
+                ebu_48000.prev_sample_peak(c).unwrap(),
+                abs <= 0.000001
+            );
+            assert_float_eq!(
+                ebu.true_peak(c).unwrap(),
+                f64::max(
+                    ebu_44100.true_peak(c).unwrap(),
+                    ebu_48000.true_peak(c).unwrap()
+                ),
+                abs <= 0.000001
+            );
+        }
+    }
+
+    #[test]
+    fn reset_reuse() {
+        fn signal(frequency: f32, amplitude: f32) -> Vec<f32> {
+            let mut data = vec![0.0f32; 44_100 * 5 * 3];
+            let mut accumulator = 0.0;
+            let step = 2.0 * std::f32::consts::PI * frequency / 44_100.0;
+            for out in data.chunks_exact_mut(3) {
+                let val = f32::sin(accumulator);
+                out[0] = amplitude * val;
+                out[1] = 0.5 * amplitude * val;
+                out[2] = 0.25 * amplitude * val;
+                accumulator += step;
+            }
+            data
+        }
+
+        let a = signal(440.0, 1.0);
+        let b = signal(1_000.0, 0.25);
+
+        let mode = Mode::I | Mode::LRA | Mode::SAMPLE_PEAK | Mode::TRUE_PEAK;
+        let channel_map = [Channel::Left, Channel::Center, Channel::Right];
+
+        let mut ebu = EbuR128::new(3, 44_100, mode).unwrap();
+        ebu.set_channel_map(&channel_map).unwrap();
+        ebu.add_frames_f32(&a).unwrap();
+        ebu.reset();
+        assert_eq!(ebu.channel_map(), &channel_map);
+        ebu.add_frames_f32(&b).unwrap();
+
+        let mut ebu_fresh = EbuR128::new(3, 44_100, mode).unwrap();
+        ebu_fresh.set_channel_map(&channel_map).unwrap();
+        ebu_fresh.add_frames_f32(&b).unwrap();
+
+        assert_eq!(
+            ebu.loudness_global().unwrap(),
+            ebu_fresh.loudness_global().unwrap()
+        );
+        assert_eq!(
+            ebu.loudness_momentary().unwrap(),
+            ebu_fresh.loudness_momentary().unwrap()
+        );
+        assert_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            ebu_fresh.loudness_shortterm().unwrap()
+        );
+        assert_eq!(
+            ebu.loudness_range().unwrap(),
+            ebu_fresh.loudness_range().unwrap()
+        );
+        for c in 0..3 {
+            assert_eq!(
+                ebu.sample_peak(c).unwrap(),
+                ebu_fresh.sample_peak(c).unwrap()
+            );
+            assert_eq!(ebu.true_peak(c).unwrap(), ebu_fresh.true_peak(c).unwrap());
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        for &(channels, rate, histogram, weighting, precision) in &[
+            (2, 48_000, false, Weighting::K, Precision::F64),
+            (2, 48_000, true, Weighting::K, Precision::F64),
+            (3, 44_100, false, Weighting::K, Precision::F64),
+            (2, 96_000, false, Weighting::K, Precision::F64),
+            (1, 192_000, true, Weighting::K, Precision::F64),
+            (2, 48_000, false, Weighting::A, Precision::F64),
+            (2, 48_000, true, Weighting::C, Precision::F64),
+            (2, 48_000, false, Weighting::K, Precision::F32),
+            (2, 48_000, true, Weighting::A, Precision::F32),
+            (2, 48_000, false, Weighting::K, Precision::Fixed),
+        ] {
+            let mut data = vec![0.0f32; rate as usize * 10 * channels as usize];
+            let mut accumulator = 0.0;
+            let step = 2.0 * std::f32::consts::PI * 440.0 / rate as f32;
+            for (i, frame) in data.chunks_exact_mut(channels as usize).enumerate() {
+                let val = f32::sin(accumulator);
+                // Change the amplitude every second to get some loudness range
+                let amplitude = 0.1 + 0.8 * ((i / rate as usize) % 3) as f32 / 2.0;
+                for (c, out) in frame.iter_mut().enumerate() {
+                    *out = amplitude * val / (c + 1) as f32;
+                }
+                accumulator += step;
+            }
+            // Split in the middle of a block and put an inter-sample peak right before the split.
+            // It is only detected after the split if the interpolator state is restored.
+            let split = 4_321 * rate as usize / 1_000 * channels as usize;
+            for out in &mut data[split - 2 * channels as usize..split] {
+                *out = 0.9;
+            }
+            let (first, second) = data.split_at(split);
+
+            let mut mode = Mode::I | Mode::LRA | Mode::SAMPLE_PEAK | Mode::TRUE_PEAK;
+            if histogram {
+                mode |= Mode::HISTOGRAM;
+            }
+
+            let mut ebu = EbuR128::new(channels, rate, mode).unwrap();
+            ebu.set_weighting(weighting).unwrap();
+            ebu.set_precision(precision).unwrap();
+            ebu.enable_timeseries();
+            ebu.enable_true_peak_overs();
+            ebu.set_true_peak_overs_threshold(0.5).unwrap();
+            ebu.add_frames_f32(&data).unwrap();
+
+            let mut ebu_first = EbuR128::new(channels, rate, mode).unwrap();
+            ebu_first.set_weighting(weighting).unwrap();
+            ebu_first.set_precision(precision).unwrap();
+            ebu_first.enable_timeseries();
+            ebu_first.enable_true_peak_overs();
+            ebu_first.set_true_peak_overs_threshold(0.5).unwrap();
+            ebu_first.add_frames_f32(first).unwrap();
+            let serialized = bincode::serialize(&ebu_first).unwrap();
+            let mut ebu_second = bincode::deserialize::<EbuR128>(&serialized).unwrap();
+            assert_eq!(ebu_first, ebu_second);
+            ebu_second.add_frames_f32(second).unwrap();
+            ebu_first.add_frames_f32(second).unwrap();
+            assert_eq!(ebu_first, ebu_second);
+
+            assert_eq!(
+                ebu.loudness_global().unwrap(),
+                ebu_second.loudness_global().unwrap()
+            );
+            assert_eq!(
+                ebu.loudness_momentary().unwrap(),
+                ebu_second.loudness_momentary().unwrap()
+            );
+            assert_eq!(
+                ebu.loudness_shortterm().unwrap(),
+                ebu_second.loudness_shortterm().unwrap()
+            );
+            assert_eq!(
+                ebu.loudness_range().unwrap(),
+                ebu_second.loudness_range().unwrap()
+            );
+            assert_eq!(
+                ebu.momentary_timeseries(),
+                ebu_second.momentary_timeseries()
+            );
+            assert_eq!(
+                ebu.shortterm_timeseries(),
+                ebu_second.shortterm_timeseries()
+            );
+            for c in 0..channels {
+                assert_eq!(
+                    ebu.sample_peak(c).unwrap(),
+                    ebu_second.sample_peak(c).unwrap()
+                );
+                assert_eq!(ebu.true_peak(c).unwrap(), ebu_second.true_peak(c).unwrap());
+                assert_eq!(
+                    ebu.true_peak_overs(c).unwrap(),
+                    ebu_second.true_peak_overs(c).unwrap()
+                );
+            }
+            assert!(ebu.true_peak_overs(0).unwrap() > 0);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_invalid_state() {
+        let ebu = EbuR128::new(2, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        let mut serialized = bincode::serialize(&ebu).unwrap();
+
+        // The mode is stored first
+        serialized[0] = 0;
+        assert!(bincode::deserialize::<EbuR128>(&serialized).is_err());
+    }
+
+    #[test]
+    fn builder() {
+        let ebu = EbuR128Builder::new()
+            .channels(3)
+            .rate(44_100)
+            .mode(Mode::LRA | Mode::TRUE_PEAK)
+            .channel_map(&[Channel::Left, Channel::Right, Channel::Unused])
+            .max_window(5_000)
+            .max_history(10_000)
+            .build()
+            .unwrap();
+
+        assert_eq!(ebu.channels(), 3);
+        assert_eq!(ebu.rate(), 44_100);
+        assert_eq!(ebu.mode(), Mode::LRA | Mode::TRUE_PEAK);
+        assert_eq!(
+            ebu.channel_map(),
+            &[Channel::Left, Channel::Right, Channel::Unused]
+        );
+        assert_eq!(ebu.max_window(), 5_000);
+        assert_eq!(ebu.max_history(), 10_000);
+
+        let ebu = EbuR128Builder::new()
+            .channels(2)
+            .rate(48_000)
+            .mode(Mode::M)
+            .build()
+            .unwrap();
+        assert_eq!(ebu.channel_map(), &[Channel::Left, Channel::Right]);
+        assert_eq!(ebu.max_window(), 400);
+        assert_eq!(ebu.max_history(), usize::MAX);
+
+        let builder = EbuR128Builder::new().channels(2).rate(48_000).mode(Mode::I);
+        assert_eq!(
+            EbuR128Builder::new()
+                .rate(48_000)
+                .mode(Mode::I)
+                .build()
+                .unwrap_err(),
+            Error::InvalidChannels
+        );
+        assert_eq!(
+            builder.clone().rate(0).build().unwrap_err(),
+            Error::InvalidRate
+        );
+        assert_eq!(
+            builder.clone().mode(Mode::empty()).build().unwrap_err(),
+            Error::InvalidMode
+        );
+        assert_eq!(
+            builder
+                .clone()
+                .mode(Mode::TRUE_PEAK)
+                .rate(768_000)
+                .build()
+                .unwrap_err(),
+            Error::UnsupportedRate
+        );
+        assert_eq!(
+            builder
+                .clone()
+                .channel_map(&[Channel::Left])
+                .build()
+                .unwrap_err(),
+            Error::InvalidChannelIndex
+        );
+        assert_eq!(
+            builder
+                .clone()
+                .max_window(10_000)
+                .max_history(5_000)
+                .build()
+                .unwrap_err(),
+            Error::NoMem
+        );
+    }
+
+    #[test]
+    fn add_frames_generic() {
+        use dasp_sample::I24;
+
+        let mut data = vec![I24::new_unchecked(0); 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f64::consts::PI * 997.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f64::sin(accumulator);
+            out[0] = I24::new_unchecked(f64::round(val * 8_388_607.0) as i32);
+            out[1] = I24::new_unchecked(f64::round(val * 0.5 * 8_388_607.0) as i32);
+            accumulator += step;
+        }
+        let data_i32 = data.iter().map(|s| s.inner()).collect::<Vec<_>>();
+        let data_planar = [
+            data.iter().step_by(2).copied().collect::<Vec<_>>(),
+            data.iter().skip(1).step_by(2).copied().collect::<Vec<_>>(),
+        ];
+
+        let mode = Mode::I | Mode::SAMPLE_PEAK | Mode::TRUE_PEAK;
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        let mut ebu_planar = EbuR128::new(2, 48_000, mode).unwrap();
+        let mut ebu_i24 = EbuR128::new(2, 48_000, mode).unwrap();
+
+        ebu.add_frames(&data).unwrap();
+        ebu_planar
+            .add_frames_planar(&[&data_planar[0], &data_planar[1]])
+            .unwrap();
+        ebu_i24.add_frames_i24(&data_i32).unwrap();
+
+        for other in [&ebu_planar, &ebu_i24] {
+            assert_float_eq!(
+                ebu.loudness_global().unwrap(),
+                other.loudness_global().unwrap(),
+                abs <= 0.000001
+            );
+            for c in 0..2 {
+                assert_float_eq!(
+                    ebu.sample_peak(c).unwrap(),
+                    other.sample_peak(c).unwrap(),
+                    abs <= 0.000001
+                );
+                assert_float_eq!(
+                    ebu.true_peak(c).unwrap(),
+                    other.true_peak(c).unwrap(),
+                    abs <= 0.000001
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn add_frames_with() {
+        let mut data = vec![0.0f32; 48_000 * 3 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let val = f32::sin(accumulator);
+            let amplitude = if (i / 4_800) % 3 == 0 { 0.1 } else { 0.5 };
+            out[0] = amplitude * val;
+            out[1] = amplitude * val;
+            accumulator += step;
+        }
+
+        // Poll the momentary loudness after every completed gating block
+        let mut ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        let mut expected = vec![];
+        ebu.add_frames_f32(&data[..2 * 19_200]).unwrap();
+        expected.push(ebu.loudness_momentary().unwrap());
+        for chunk in data[2 * 19_200..].chunks(2 * 4_800) {
+            ebu.add_frames_f32(chunk).unwrap();
+            expected.push(ebu.loudness_momentary().unwrap());
+        }
+        assert_eq!(expected.len(), 27);
+
+        for mode in [Mode::M, Mode::I | Mode::LRA | Mode::TRUE_PEAK] {
+            let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+            let mut blocks = vec![];
+            for chunk in data.chunks(2 * 1_234) {
+                ebu.add_frames_with(chunk, |loudness| blocks.push(loudness))
+                    .unwrap();
+            }
+            assert_eq!(blocks.len(), expected.len());
+            for (block, expected) in Iterator::zip(blocks.iter(), expected.iter()) {
+                assert_float_eq!(*block, *expected, abs <= 0.000001);
+            }
+
+            let data_planar = [
+                data.iter().step_by(2).copied().collect::<Vec<_>>(),
+                data.iter().skip(1).step_by(2).copied().collect::<Vec<_>>(),
+            ];
+            let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+            let mut blocks = vec![];
+            ebu.add_frames_planar_with(&[&data_planar[0], &data_planar[1]], |loudness| {
+                blocks.push(loudness)
+            })
+            .unwrap();
+            assert_eq!(blocks.len(), expected.len());
+            for (block, expected) in Iterator::zip(blocks.iter(), expected.iter()) {
+                assert_float_eq!(*block, *expected, abs <= 0.000001);
+            }
+        }
+    }
+
+    #[test]
+    fn loudness_max() {
+        let mut data = vec![0.0f32; 48_000 * 10 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let val = f32::sin(accumulator);
+            // Loud transient of 500ms after 3s
+            let amplitude = if (144_000..168_000).contains(&i) {
+                0.9
+            } else {
+                0.1
+            };
+            out[0] = amplitude * val;
+            out[1] = amplitude * val;
+            accumulator += step;
+        }
+
+        // Poll the loudness after every completed gating block
+        let mut ebu = EbuR128::new(2, 48_000, Mode::S).unwrap();
+        let mut momentary = vec![];
+        let mut shortterm = vec![];
+        ebu.add_frames_f32(&data[..2 * 19_200]).unwrap();
+        momentary.push(ebu.loudness_momentary().unwrap());
+        shortterm.push(ebu.loudness_shortterm().unwrap());
+        for chunk in data[2 * 19_200..].chunks(2 * 4_800) {
+            ebu.add_frames_f32(chunk).unwrap();
+            momentary.push(ebu.loudness_momentary().unwrap());
+            shortterm.push(ebu.loudness_shortterm().unwrap());
+        }
+        let momentary_max = momentary.iter().copied().fold(-f64::INFINITY, f64::max);
+        let shortterm_max = shortterm.iter().copied().fold(-f64::INFINITY, f64::max);
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::S).unwrap();
+        assert_eq!(ebu.loudness_shortterm_max(), Err(Error::InvalidMode));
+        ebu.enable_shortterm_max();
+        assert_eq!(ebu.loudness_momentary_max(), Err(Error::NotEnoughData));
+        assert_eq!(ebu.loudness_shortterm_max(), Err(Error::NotEnoughData));
+        ebu.add_frames_f32(&data).unwrap();
+
         assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0007814168930054,
+            ebu.loudness_momentary_max().unwrap(),
+            momentary_max,
             abs <= 0.000001
         );
         assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0007814168930054,
+            ebu.loudness_shortterm_max().unwrap(),
+            shortterm_max,
             abs <= 0.000001
         );
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.650000000000006,
-            abs <= 0.000001
-        );
+        // The level dropped again after the transient but the maximum still includes it
+        assert!(ebu.loudness_momentary_max().unwrap() > ebu.loudness_momentary().unwrap() + 15.0);
+        assert!(ebu.loudness_shortterm_max().unwrap() > ebu.loudness_shortterm().unwrap() + 5.0);
+        assert!(ebu.loudness_momentary_max().unwrap() > ebu.loudness_shortterm_max().unwrap());
 
         ebu.reset();
+        assert_eq!(ebu.loudness_momentary_max(), Err(Error::NotEnoughData));
+        assert_eq!(ebu.loudness_shortterm_max(), Err(Error::NotEnoughData));
 
-        assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -std::f64::INFINITY,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -std::f64::INFINITY,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -std::f64::INFINITY,
-            abs <= 0.000001
+        let mut ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        ebu.enable_shortterm_max();
+        assert_eq!(ebu.loudness_momentary_max(), Err(Error::NotEnoughData));
+        assert_eq!(ebu.loudness_shortterm_max(), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn normalization_gain() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = 0.1 * val;
+            out[1] = 0.1 * val;
+            accumulator += step;
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.normalization_gain(-23.0), Err(Error::NotEnoughData));
+        assert_eq!(
+            ebu.normalization_gain_linear(-23.0),
+            Err(Error::NotEnoughData)
         );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -std::f64::INFINITY,
-            abs <= 0.000001
+
+        let mut ebu_silence = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu_silence.add_frames_f32(&vec![0.0; 48_000 * 2]).unwrap();
+        assert_eq!(
+            ebu_silence.normalization_gain(-23.0),
+            Err(Error::NotEnoughData)
         );
-        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
 
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 0.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 0.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 0.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 0.0, abs <= 0.000001);
+        ebu.add_frames_f32(&data).unwrap();
+        let loudness = ebu.loudness_global().unwrap();
+        for target in &[-23.0, -16.0, -14.0] {
+            let gain = ebu.normalization_gain(*target).unwrap();
+            assert_float_eq!(gain, target - loudness, abs <= 0.000001);
+
+            // Applying the gain gives the target loudness
+            let gain_linear = ebu.normalization_gain_linear(*target).unwrap();
+            let normalized = data
+                .iter()
+                .map(|s| s * gain_linear as f32)
+                .collect::<Vec<_>>();
+            let mut ebu_normalized = EbuR128::new(2, 48_000, Mode::I).unwrap();
+            ebu_normalized.add_frames_f32(&normalized).unwrap();
+            assert_float_eq!(
+                ebu_normalized.loudness_global().unwrap(),
+                *target,
+                abs <= 0.0001
+            );
+        }
 
-        assert_float_eq!(ebu.true_peak(0).unwrap(), 0.0, abs <= 0.000001);
-        assert_float_eq!(ebu.true_peak(1).unwrap(), 0.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_true_peak(0).unwrap(), 0.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_true_peak(1).unwrap(), 0.0, abs <= 0.000001);
+        let ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.normalization_gain(-23.0), Err(Error::InvalidMode));
+    }
 
-        assert_float_eq!(ebu.relative_threshold().unwrap(), -70.0, abs <= 0.000001);
+    #[test]
+    fn weighting() {
+        // Level of a full scale sine relative to 1kHz with the K-weighting
+        fn level(weighting: Weighting, rate: u32, frequency: f64) -> f64 {
+            let mut data = vec![0.0f64; rate as usize * 4];
+            let mut accumulator = 0.0;
+            let step = 2.0 * std::f64::consts::PI * frequency / rate as f64;
+            for out in &mut data {
+                *out = f64::sin(accumulator);
+                accumulator += step;
+            }
+
+            let mut ebu = EbuR128Builder::new()
+                .channels(1)
+                .rate(rate)
+                .mode(Mode::S)
+                .weighting(weighting)
+                .build()
+                .unwrap();
+            assert_eq!(ebu.weighting(), weighting);
+            ebu.add_frames_f64(&data).unwrap();
+
+            // A full scale sine has an energy of 0.5
+            ebu.loudness_shortterm().unwrap() - (10.0 * f64::log10(0.5) - 0.691)
+        }
+
+        for rate in &[44_100, 48_000, 96_000] {
+            for weighting in &[Weighting::A, Weighting::C] {
+                assert_float_eq!(level(*weighting, *rate, 1_000.0), 0.0, abs <= 0.001);
+            }
+
+            // Values of the analog filters from IEC 61672-1
+            assert_float_eq!(level(Weighting::A, *rate, 31.5), -39.52, abs <= 0.05);
+            assert_float_eq!(level(Weighting::A, *rate, 100.0), -19.14, abs <= 0.05);
+            assert_float_eq!(level(Weighting::A, *rate, 4_000.0), 0.96, abs <= 0.05);
+            assert_float_eq!(level(Weighting::C, *rate, 31.5), -3.03, abs <= 0.05);
+            assert_float_eq!(level(Weighting::C, *rate, 100.0), -0.30, abs <= 0.05);
+            assert_float_eq!(level(Weighting::C, *rate, 4_000.0), -0.83, abs <= 0.05);
+        }
+
+        // K-weighting is the default and has +0.691dB at 1kHz
+        let ebu = EbuR128::new(1, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.weighting(), Weighting::K);
+        assert_float_eq!(level(Weighting::K, 48_000, 1_000.0), 0.691, abs <= 0.01);
+
+        let mut ebu = EbuR128::new(1, 4_000, Mode::M).unwrap();
+        assert_eq!(ebu.set_weighting(Weighting::A), Err(Error::UnsupportedRate));
+        assert_eq!(ebu.weighting(), Weighting::K);
     }
 
     #[test]
-    fn sine_stereo_i32() {
-        let mut data = vec![0i32; 48_000 * 5 * 2];
+    fn add_frames_filtered() {
+        let mut data = vec![0.0f32; 48_000 * 2 * 2];
         let mut accumulator = 0.0;
         let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
         for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator) * (std::i32::MAX - 1) as f32;
-            out[0] = val as i32;
-            out[1] = val as i32;
+            let val = f32::sin(accumulator);
+            out[0] = 0.5 * val;
+            out[1] = 0.25 * val;
             accumulator += step;
         }
+        let frames = data.len() / 2;
+
+        // Filter everything at once directly
+        let mut filter = crate::filter::Filter::new(48_000, 2, Weighting::K, false, false).unwrap();
+        let mut expected = vec![0.0; data.len()];
+        filter
+            .process(
+                crate::Interleaved::new(&data, 2).unwrap(),
+                &mut expected,
+                0,
+                &[Channel::Left, Channel::Right],
+                None,
+            )
+            .unwrap();
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
-        ebu.add_frames_i32(&data).unwrap();
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
 
-        assert_float_eq!(
+        // Use chunks that are not aligned to the blocks or the audio buffer
+        let mut ebu_filtered = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        let mut filtered = vec![0.0; data.len()];
+        let mut offset = 0;
+        for chunk in data.chunks(2 * 12_345) {
+            let chunk_frames = chunk.len() / 2;
+            let mut chunk_filtered = vec![0.0; chunk.len()];
+            ebu_filtered
+                .add_frames_filtered(chunk, &mut chunk_filtered)
+                .unwrap();
+            for c in 0..2 {
+                filtered[(c * frames + offset)..(c * frames + offset + chunk_frames)]
+                    .copy_from_slice(&chunk_filtered[(c * chunk_frames)..((c + 1) * chunk_frames)]);
+            }
+            offset += chunk_frames;
+        }
+        for (filtered, expected) in Iterator::zip(filtered.iter(), expected.iter()) {
+            assert_float_eq!(*filtered, *expected, abs <= 0.000001);
+        }
+        assert_eq!(
             ebu.loudness_global().unwrap(),
-            -0.6500000000000054,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598274425,
-            abs <= 0.000001
+            ebu_filtered.loudness_global().unwrap()
+        );
+
+        let data_planar = [
+            data.iter().step_by(2).copied().collect::<Vec<_>>(),
+            data.iter().skip(1).step_by(2).copied().collect::<Vec<_>>(),
+        ];
+        let mut ebu_planar = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu_planar
+            .set_channel_map(&[Channel::Unused, Channel::Right])
+            .unwrap();
+        let mut filtered = vec![1.0; data.len()];
+        ebu_planar
+            .add_frames_planar_filtered(&[&data_planar[0], &data_planar[1]], &mut filtered)
+            .unwrap();
+        assert!(filtered[..frames].iter().all(|v| *v == 0.0));
+        for (filtered, expected) in
+            Iterator::zip(filtered[frames..].iter(), expected[frames..].iter())
+        {
+            assert_float_eq!(*filtered, *expected, abs <= 0.000001);
+        }
+
+        assert_eq!(
+            ebu.add_frames_filtered(&data, &mut vec![0.0; data.len() - 2]),
+            Err(Error::NoMem)
         );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715105212,
-            abs <= 0.000001
+    }
+
+    #[test]
+    fn non_finite_samples() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = val;
+            out[1] = val;
+            accumulator += step;
+        }
+        let (first, second) = data.split_at(48_000 * 2 * 2);
+        let mut corrupt = second.to_vec();
+        corrupt[1_001] = f32::NAN;
+        corrupt[2_000] = f32::INFINITY;
+        let mut silenced = corrupt.clone();
+        silenced[1_001] = 0.0;
+        silenced[2_000] = 0.0;
+        let mode = Mode::I | Mode::SAMPLE_PEAK;
+
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        assert_eq!(ebu.non_finite_handling(), NonFiniteHandling::Propagate);
+        ebu.add_frames_f32(first).unwrap();
+        ebu.add_frames_f32(&corrupt).unwrap();
+        assert!(ebu.loudness_momentary().unwrap().is_nan());
+
+        // Rejected input does not change any state
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu.set_non_finite_handling(NonFiniteHandling::Reject);
+        ebu.add_frames_f32(first).unwrap();
+        assert_eq!(ebu.add_frames_f32(&corrupt), Err(Error::NonFiniteSample));
+        assert_eq!(ebu.seed_frames_f32(&corrupt), Err(Error::NonFiniteSample));
+        ebu.add_frames_f32(second).unwrap();
+        assert_eq!(ebu.non_finite_samples(), 0);
+
+        let mut ebu_clean = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu_clean.add_frames_f32(&data).unwrap();
+        assert_eq!(
+            ebu.loudness_global().unwrap(),
+            ebu_clean.loudness_global().unwrap()
         );
+        assert_eq!(ebu.prev_sample_peak(0), ebu_clean.prev_sample_peak(0));
+
+        // Skipped samples are replaced with silence, for interleaved and planar input
+        let (left, right): (Vec<f32>, Vec<f32>) =
+            corrupt.chunks_exact(2).map(|f| (f[0], f[1])).unzip();
+
+        let mut ebu_clean = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu_clean.add_frames_f32(first).unwrap();
+        ebu_clean.add_frames_f32(&silenced).unwrap();
+
+        for planar in &[false, true] {
+            let mut ebu = EbuR128Builder::new()
+                .channels(2)
+                .rate(48_000)
+                .mode(mode)
+                .non_finite_handling(NonFiniteHandling::Skip)
+                .build()
+                .unwrap();
+            ebu.add_frames_f32(first).unwrap();
+            if *planar {
+                ebu.add_frames_planar_f32(&[&left, &right]).unwrap();
+            } else {
+                ebu.add_frames_f32(&corrupt).unwrap();
+            }
+            assert_eq!(ebu.non_finite_samples(), 2);
+
+            assert_eq!(
+                ebu.loudness_global().unwrap(),
+                ebu_clean.loudness_global().unwrap()
+            );
+            for c in 0..2 {
+                assert_eq!(
+                    ebu.sample_peak(c).unwrap(),
+                    ebu_clean.sample_peak(c).unwrap()
+                );
+            }
+
+            ebu.reset();
+            assert_eq!(ebu.non_finite_samples(), 0);
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_matches_serial() {
+        const CHANNELS: usize = 24;
+        let mut data = vec![0.0f32; 48_000 * 5 * CHANNELS];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(CHANNELS) {
+            for (c, out) in out.iter_mut().enumerate() {
+                *out = f32::sin(accumulator * (c + 1) as f32) / (c + 1) as f32;
+            }
+            accumulator += step;
+        }
+        let mut channel_map = vec![Channel::Left; CHANNELS];
+        channel_map[3] = Channel::Unused;
+        channel_map[4] = Channel::LeftSurround;
+
+        let measure = |threads: usize, weighting: Weighting, precision: Precision| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+
+            pool.install(|| {
+                let mut ebu = EbuR128Builder::new()
+                    .channels(CHANNELS as u32)
+                    .rate(48_000)
+                    .mode(Mode::I | Mode::S)
+                    .channel_map(&channel_map)
+                    .weighting(weighting)
+                    .precision(precision)
+                    .build()
+                    .unwrap();
+                let mut filtered = vec![0.0; data.len()];
+                ebu.add_frames_filtered(&data, &mut filtered).unwrap();
+
+                (
+                    filtered,
+                    ebu.loudness_global().unwrap(),
+                    ebu.loudness_shortterm().unwrap(),
+                )
+            })
+        };
+
+        for weighting in &[Weighting::K, Weighting::A] {
+            for precision in &[Precision::F64, Precision::F32] {
+                assert_eq!(
+                    measure(1, *weighting, *precision),
+                    measure(4, *weighting, *precision)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn channel_weighting() {
+        // 5.1 with the sine either in the front or in the surround channels and a loud LFE
+        let measure = |front: bool, lfe: f32| {
+            let mut data = vec![0.0f32; 48_000 * 5 * 6];
+            let mut accumulator = 0.0;
+            let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+            for out in data.chunks_exact_mut(6) {
+                let val = 0.5 * f32::sin(accumulator);
+                if front {
+                    out[0] = val;
+                    out[1] = val;
+                } else {
+                    out[4] = val;
+                    out[5] = val;
+                }
+                out[3] = lfe * f32::sin(accumulator / 8.0);
+                accumulator += step;
+            }
+
+            let mut ebu = EbuR128::new(6, 48_000, Mode::I).unwrap();
+            ebu.set_channel(3, Channel::Lfe).unwrap();
+            assert_eq!(
+                ebu.channel_map(),
+                &[
+                    Channel::Left,
+                    Channel::Right,
+                    Channel::Center,
+                    Channel::Lfe,
+                    Channel::LeftSurround,
+                    Channel::RightSurround
+                ]
+            );
+            ebu.add_frames_f32(&data).unwrap();
+            ebu.loudness_global().unwrap()
+        };
+
+        let front = measure(true, 0.0);
+        let surround = measure(false, 0.0);
+        assert_float_eq!(surround - front, 10.0 * f64::log10(1.41), abs <= 0.0001);
+        assert_float_eq!(surround - front, 1.5, abs <= 0.01);
+
+        assert_eq!(measure(true, 1.0), front);
+        assert_eq!(measure(false, 1.0), surround);
+    }
+
+    #[test]
+    fn dual_mono_compensation() {
+        let mut mono = vec![0.0f32; 48_000 * 10];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for (i, out) in mono.iter_mut().enumerate() {
+            // Change the amplitude every second to get some loudness range
+            let amplitude = 0.1 + 0.8 * ((i / 48_000) % 3) as f32 / 2.0;
+            *out = amplitude * f32::sin(accumulator);
+            accumulator += step;
+        }
+        let dual_mono = mono.iter().flat_map(|s| [*s, *s]).collect::<Vec<_>>();
+
+        let mode = Mode::I | Mode::LRA;
+        let mut ebu_mono = EbuR128::new(1, 48_000, mode).unwrap();
+        ebu_mono.enable_shortterm_max();
+        ebu_mono.add_frames_f32(&mono).unwrap();
+
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu.add_frames_f32(&dual_mono).unwrap();
         assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620040943,
+            ebu.loudness_global().unwrap() - ebu_mono.loudness_global().unwrap(),
+            10.0 * f64::log10(2.0),
             abs <= 0.000001
         );
-        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
 
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        let mut ebu = EbuR128Builder::new()
+            .channels(2)
+            .rate(48_000)
+            .mode(mode)
+            .dual_mono_compensation(true)
+            .build()
+            .unwrap();
+        assert!(ebu.dual_mono_compensation());
+        ebu.enable_shortterm_max();
+        ebu.add_frames_f32(&dual_mono).unwrap();
 
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+        assert_eq!(
+            ebu.loudness_global().unwrap(),
+            ebu_mono.loudness_global().unwrap()
         );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+        assert_eq!(
+            ebu.loudness_momentary().unwrap(),
+            ebu_mono.loudness_momentary().unwrap()
         );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+        assert_eq!(
+            ebu.loudness_shortterm().unwrap(),
+            ebu_mono.loudness_shortterm().unwrap()
         );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+        assert_eq!(
+            ebu.loudness_window(1_000).unwrap(),
+            ebu_mono.loudness_window(1_000).unwrap()
         );
-
-        assert_float_eq!(
+        assert_eq!(
+            ebu.loudness_momentary_max().unwrap(),
+            ebu_mono.loudness_momentary_max().unwrap()
+        );
+        assert_eq!(
+            ebu.loudness_shortterm_max().unwrap(),
+            ebu_mono.loudness_shortterm_max().unwrap()
+        );
+        assert_eq!(
             ebu.relative_threshold().unwrap(),
-            -10.650000000000006,
-            abs <= 0.000001
+            ebu_mono.relative_threshold().unwrap()
+        );
+        assert_eq!(
+            ebu.loudness_range().unwrap(),
+            ebu_mono.loudness_range().unwrap()
         );
     }
 
     #[test]
-    fn sine_stereo_f32() {
-        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+    fn measurement() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::M | Mode::SAMPLE_PEAK).unwrap();
+        assert_eq!(
+            ebu.measurement(),
+            Measurement {
+                sample_peak: Some(vec![0.0, 0.0]),
+                ..Measurement::default()
+            }
+        );
+
+        let mut data = vec![0.0f32; 48_000 * 2];
         let mut accumulator = 0.0;
         let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
         for out in data.chunks_exact_mut(2) {
             let val = f32::sin(accumulator);
             out[0] = val;
-            out[1] = val;
+            out[1] = val / 2.0;
             accumulator += step;
         }
-
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
         ebu.add_frames_f32(&data).unwrap();
 
-        assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -0.6500000000000054,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598268921,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715100236,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620008693,
-            abs <= 0.000001
-        );
-        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
+        let measurement = ebu.measurement();
+        assert_eq!(measurement.integrated, Some(ebu.loudness_global().unwrap()));
+        assert_eq!(
+            measurement.momentary,
+            Some(ebu.loudness_momentary().unwrap())
+        );
+        assert_eq!(
+            measurement.momentary_max,
+            Some(ebu.loudness_momentary_max().unwrap())
+        );
+        assert_eq!(
+            measurement.sample_peak,
+            Some(vec![
+                ebu.sample_peak(0).unwrap(),
+                ebu.sample_peak(1).unwrap()
+            ])
+        );
+        assert_eq!(measurement.range, None);
+        assert_eq!(measurement.shortterm, None);
+        assert_eq!(measurement.shortterm_max, None);
+        assert_eq!(measurement.true_peak, None);
+    }
 
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+    #[test]
+    fn add_frames_oversampled() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::S).unwrap();
+        let mut oversampled = vec![0.0f32; 1000];
+        assert_eq!(ebu.true_peak_oversampling_factor(), Err(Error::InvalidMode));
+        assert_eq!(
+            ebu.add_frames_oversampled(&[0.0f32; 20], &mut oversampled, 40),
+            Err(Error::InvalidMode)
+        );
+
+        for &(channels, rate, factor) in &[(2, 48_000, 4), (3, 48_000, 4), (3, 192_000, 1)] {
+            let mut ebu = EbuR128::new(channels, rate, Mode::TRUE_PEAK).unwrap();
+            assert_eq!(ebu.true_peak_oversampling_factor(), Ok(factor));
+            let frames = (0..4_800)
+                .flat_map(|i| {
+                    (0..channels).map(move |c| {
+                        let phase = (i % 4) as f32 * std::f32::consts::FRAC_PI_2
+                            + std::f32::consts::FRAC_PI_4;
+                        (c + 1) as f32 / 4.0 * f32::sin(phase)
+                    })
+                })
+                .collect::<Vec<_>>();
+            let len = 4_800 * factor;
+            let stride = len + 10;
+
+            // Too small stride or buffer
+            let mut oversampled = vec![2.0f32; (channels as usize - 1) * stride + len];
+            assert_eq!(
+                ebu.add_frames_oversampled(&frames, &mut oversampled, len - 1),
+                Err(Error::NoMem)
+            );
+            assert_eq!(
+                ebu.add_frames_oversampled(&frames, &mut oversampled[1..], stride),
+                Err(Error::NoMem)
+            );
+            assert_eq!(ebu.prev_true_peak(0), Ok(0.0));
 
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+            ebu.add_frames_oversampled(&frames, &mut oversampled, stride)
+                .unwrap();
+            for c in 0..channels as usize {
+                let channel = &oversampled[c * stride..c * stride + len];
+                let peak = channel.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+                assert_eq!(peak as f64, ebu.prev_true_peak(c as u32).unwrap());
+                if factor > 1 {
+                    assert!(peak as f64 > (c + 1) as f64 / 4.0 * 0.95);
+                }
+                if c + 1 < channels as usize {
+                    assert!(oversampled[c * stride + len..(c + 1) * stride]
+                        .iter()
+                        .all(|&s| s == 2.0));
+                }
+            }
+
+            // The same output when split into multiple calls, chunks and hops
+            let mut split = EbuR128::new(channels, rate, Mode::TRUE_PEAK).unwrap();
+            split.set_chunk_size(1_000).unwrap();
+            split.set_block_hop(30).unwrap();
+            let mut split_oversampled = vec![2.0f32; oversampled.len()];
+            let half = frames.len() / 2;
+            split
+                .add_frames_oversampled(&frames[..half], &mut split_oversampled, stride)
+                .unwrap();
+            split
+                .add_frames_oversampled(&frames[half..], &mut split_oversampled[len / 2..], stride)
+                .unwrap();
+            assert_eq!(split_oversampled, oversampled);
+            assert_eq!(split.true_peak(0), ebu.true_peak(0));
+        }
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+        ebu.set_non_finite_handling(NonFiniteHandling::Skip);
+        let mut frames = vec![0.5f32; 200];
+        frames[10] = f32::NAN;
+        frames[21] = f32::INFINITY;
+        let mut oversampled = vec![0.0f32; 800];
+        ebu.add_frames_oversampled(&frames, &mut oversampled, 400)
+            .unwrap();
+        assert!(oversampled.iter().all(|s| s.is_finite()));
+    }
+
+    #[test]
+    fn analyze() {
+        // Change the amplitude every two seconds to get some loudness range
+        let mut data = vec![0.0f32; 44_100 * 10 * 3];
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 44_100.0;
+        for (i, out) in data.chunks_exact_mut(3).enumerate() {
+            let amplitude = 0.1 + 0.4 * ((i / 88_200) % 3) as f32;
+            let val = amplitude * f32::sin(i as f32 * step);
+            out[0] = val;
+            out[1] = val / 2.0;
+            out[2] = -val;
+        }
+
+        for &mode in &[Mode::all(), Mode::I | Mode::SAMPLE_PEAK, Mode::M] {
+            let mut ebu = EbuR128::new(3, 44_100, mode).unwrap();
+            ebu.add_frames_f32(&data).unwrap();
+            let measurement = EbuR128::analyze(3, 44_100, mode, &data).unwrap();
+            assert_eq!(measurement, ebu.measurement());
+        }
+
+        let measurement = EbuR128::analyze(3, 44_100, Mode::all(), &data).unwrap();
+        assert!(measurement.integrated.is_some());
+        assert!(measurement.range.unwrap() > 0.0);
+        assert_eq!(measurement.true_peak.unwrap().len(), 3);
+
+        assert_eq!(
+            EbuR128::analyze(3, 44_100, Mode::I, &data[..data.len() - 1]),
+            Err(Error::NoMem)
         );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+        assert_eq!(
+            EbuR128::analyze(0, 44_100, Mode::I, &data),
+            Err(Error::InvalidChannels)
         );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+        assert_eq!(
+            EbuR128::analyze(3, 44_100, Mode::I, &[]).unwrap(),
+            Measurement::default()
         );
+    }
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.650000000000006,
-            abs <= 0.000001
+    #[test]
+    fn add_frames_raw() {
+        // Several chunks of the conversion buffer, with negative samples
+        let mut data = vec![0.0f64; 48_000 * 2];
+        let step = 2.0 * std::f64::consts::PI * 440.0 / 48_000.0;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let val = f64::sin(i as f64 * step);
+            out[0] = 0.9 * val;
+            out[1] = -0.4 * val;
+        }
+        let data_i16 = data
+            .iter()
+            .map(|s| (s * i16::MAX as f64) as i16)
+            .collect::<Vec<_>>();
+        let data_i24 = data
+            .iter()
+            .map(|s| (s * 8_388_607.0) as i32)
+            .collect::<Vec<_>>();
+        let data_f32 = data.iter().map(|s| *s as f32).collect::<Vec<_>>();
+
+        let measure = |add: &dyn Fn(&mut EbuR128) -> Result<(), Error>| {
+            let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+            add(&mut ebu).unwrap();
+            (ebu.measurement(), ebu.prev_sample_peak(1).unwrap())
+        };
+
+        let expected = measure(&|ebu| ebu.add_frames_i16(&data_i16));
+        let le = data_i16
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect::<Vec<_>>();
+        let be = data_i16
+            .iter()
+            .flat_map(|s| s.to_be_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            measure(&|ebu| ebu.add_frames_raw(&le, SampleFormat::S16Le)),
+            expected
+        );
+        assert_eq!(
+            measure(&|ebu| ebu.add_frames_raw(&be, SampleFormat::S16Be)),
+            expected
+        );
+
+        // Packed 24 bit samples without the upper byte
+        let expected = measure(&|ebu| ebu.add_frames_i24(&data_i24));
+        let le = data_i24
+            .iter()
+            .flat_map(|s| s.to_le_bytes()[..3].to_vec())
+            .collect::<Vec<_>>();
+        let be = data_i24
+            .iter()
+            .flat_map(|s| s.to_be_bytes()[1..].to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(le.len(), data_i24.len() * 3);
+        assert_eq!(
+            measure(&|ebu| ebu.add_frames_raw(&le, SampleFormat::S24Le)),
+            expected
+        );
+        assert_eq!(
+            measure(&|ebu| ebu.add_frames_raw(&be, SampleFormat::S24Be)),
+            expected
+        );
+
+        let expected = measure(&|ebu| ebu.add_frames_f32(&data_f32));
+        let be = data_f32
+            .iter()
+            .flat_map(|s| s.to_be_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            measure(&|ebu| ebu.add_frames_raw(&be, SampleFormat::F32Be)),
+            expected
+        );
+
+        let expected = measure(&|ebu| ebu.add_frames_f64(&data));
+        let le = data
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            measure(&|ebu| ebu.add_frames_raw(&le, SampleFormat::F64Le)),
+            expected
         );
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        // Not a multiple of the frame size
+        assert_eq!(
+            ebu.add_frames_raw(&le[..le.len() - 8], SampleFormat::F64Le),
+            Err(Error::NoMem)
+        );
+        assert_eq!(
+            ebu.add_frames_raw(&[0; 9], SampleFormat::S24Le),
+            Err(Error::NoMem)
+        );
+        assert_eq!(ebu.add_frames_raw(&[], SampleFormat::S16Le), Ok(()));
+        assert_eq!(ebu.frames_processed(), 0);
+
+        // Nothing is processed if a sample in a later chunk is rejected
+        let mut be = be;
+        let len = be.len();
+        be[len - 4..].copy_from_slice(&f32::NAN.to_be_bytes());
+        ebu.set_non_finite_handling(NonFiniteHandling::Reject);
+        assert_eq!(
+            ebu.add_frames_raw(&be, SampleFormat::F32Be),
+            Err(Error::NonFiniteSample)
+        );
+        assert_eq!(ebu.frames_processed(), 0);
+
+        ebu.set_non_finite_handling(NonFiniteHandling::Skip);
+        ebu.add_frames_raw(&be, SampleFormat::F32Be).unwrap();
+        assert_eq!(ebu.frames_processed(), 48_000);
+        assert_eq!(ebu.non_finite_samples(), 1);
+
+        assert_eq!(SampleFormat::U8.sample_size(), 1);
+        assert_eq!(SampleFormat::S24Be.sample_size(), 3);
+        assert_eq!(SampleFormat::F64Be.sample_size(), 8);
     }
 
     #[test]
-    fn sine_stereo_f64() {
-        let mut data = vec![0.0f64; 48_000 * 5 * 2];
+    fn partial_eq() {
+        let mut data = vec![0.0f32; 48_000 * 2];
         let mut accumulator = 0.0;
         let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
+        for frame in data.chunks_exact_mut(2) {
             let val = f32::sin(accumulator);
-            out[0] = val as f64;
-            out[1] = val as f64;
+            frame[0] = val;
+            frame[1] = val / 2.0;
             accumulator += step;
         }
 
         let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
-        ebu.add_frames_f64(&data).unwrap();
-
-        assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -0.6500000000000054,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598268921,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715100236,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620008693,
-            abs <= 0.000001
-        );
-        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.000001);
-
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.650000000000006,
-            abs <= 0.000001
-        );
+        ebu.add_frames_f32(&data[..20_000]).unwrap();
+        let mut clone = ebu.clone();
+        assert_eq!(ebu, clone);
+
+        // Diverging input
+        clone.add_frames_f32(&data[20_000..20_002]).unwrap();
+        assert_ne!(ebu, clone);
+        ebu.add_frames_f32(&data[20_000..20_002]).unwrap();
+        assert_eq!(ebu, clone);
+
+        // Same input in differently sized batches
+        let mut split = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        for chunk in data[..20_002].chunks(3_000) {
+            split.add_frames_f32(chunk).unwrap();
+        }
+        assert_eq!(ebu, split);
+
+        // Configuration
+        let mut other = ebu.clone();
+        other.set_channel(1, Channel::Center).unwrap();
+        assert_ne!(ebu, other);
+        let mut other = ebu.clone();
+        other.set_reference_loudness(-18.0).unwrap();
+        assert_ne!(ebu, other);
+
+        let mut other = ebu.clone();
+        other.reset();
+        assert_ne!(ebu, other);
+        assert_eq!(other, EbuR128::new(2, 48_000, Mode::all()).unwrap());
     }
 
     #[test]
-    fn sine_stereo_i16_no_histogram() {
-        let mut data = vec![0i16; 48_000 * 5 * 2];
+    fn add_frames_iter() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
         let mut accumulator = 0.0;
         let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator) * (std::i16::MAX - 1) as f32;
-            out[0] = val as i16;
-            out[1] = val as i16;
+        for (i, frame) in data.chunks_exact_mut(2).enumerate() {
+            let val = f32::sin(accumulator);
+            let amplitude = if (i / 48_000) % 2 == 0 { 0.2 } else { 0.8 };
+            frame[0] = amplitude * val;
+            frame[1] = amplitude * val / 2.0;
             accumulator += step;
         }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_i16(&data).unwrap();
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        for chunk in data.chunks(1_000) {
+            ebu.add_frames_f32(chunk).unwrap();
+        }
 
-        assert_float_eq!(
+        // All at once
+        let mut ebu_iter = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu_iter
+            .add_frames_iter(data.iter().copied(), Some(2))
+            .unwrap();
+        assert_eq!(ebu, ebu_iter);
+        assert_eq!(
             ebu.loudness_global().unwrap(),
-            -0.683303243667768,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6820309226891973,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6834583474398446,
-            abs <= 0.000001
+            ebu_iter.loudness_global().unwrap()
         );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.875007988101488,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
+        assert_eq!(
             ebu.loudness_range().unwrap(),
-            0.00006950793233284625,
-            abs <= 0.000001
-        );
+            ebu_iter.loudness_range().unwrap()
+        );
+        assert_eq!(ebu.true_peak(0).unwrap(), ebu_iter.true_peak(0).unwrap());
+
+        // Split in the middle of frames, without and with the hint
+        let mut ebu_iter = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        let mut start = 0;
+        for (i, len) in [1, 4_096, 4_095, 12_345, 3].iter().cycle().enumerate() {
+            let end = usize::min(start + len, data.len());
+            let hint = if i % 2 == 0 { None } else { Some(2) };
+            ebu_iter
+                .add_frames_iter(data[start..end].iter().copied(), hint)
+                .unwrap();
+            start = end;
+            if start == data.len() {
+                break;
+            }
+        }
+        assert_eq!(ebu, ebu_iter);
+
+        // An incomplete frame is only processed once it is completed
+        let mut ebu_iter = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu_iter
+            .add_frames_iter(data[..9_999].iter().copied(), None)
+            .unwrap();
+        assert_eq!(ebu_iter.frames_processed(), 4_999);
+        #[cfg(feature = "serde")]
+        {
+            let serialized = bincode::serialize(&ebu_iter).unwrap();
+            ebu_iter = bincode::deserialize::<EbuR128>(&serialized).unwrap();
+        }
+        ebu_iter
+            .add_frames_iter(data[9_999..].iter().copied(), None)
+            .unwrap();
+        assert_eq!(ebu, ebu_iter);
+
+        assert_eq!(
+            ebu_iter.add_frames_iter(data.iter().copied(), Some(1)),
+            Err(Error::InvalidChannelIndex)
+        );
+        ebu_iter.reset();
+        assert_eq!(ebu_iter, EbuR128::new(2, 48_000, Mode::all()).unwrap());
+
+        // Only the chunk with the non-finite sample and the rest of the iterator are rejected
+        let mut ebu_iter = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu_iter.set_non_finite_handling(NonFiniteHandling::Reject);
+        let mut invalid = data.clone();
+        invalid[5_000] = f32::NAN;
+        let mut iter = invalid.iter().copied();
+        assert_eq!(
+            ebu_iter.add_frames_iter(&mut iter, None),
+            Err(Error::NonFiniteSample)
+        );
+        assert_eq!(ebu_iter.frames_processed(), 2_048);
+        assert_eq!(iter.len(), invalid.len() - 2 * 4_096);
+    }
 
-        assert_float_eq!(
-            ebu.sample_peak(0).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.sample_peak(1).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_sample_peak(0).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
+    #[test]
+    fn loudness_global_sliding() {
+        use core::time::Duration;
+
+        // 20s of a quiet sine followed by 20s of a loud one
+        let mut data = vec![0.0f32; 48_000 * 40];
+        let step = 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0;
+        for (i, out) in data.iter_mut().enumerate() {
+            let amplitude = if i < 48_000 * 20 { 0.05 } else { 0.5 };
+            // One period every 48 samples
+            *out = amplitude * f32::sin((i % 48) as f32 * step);
+        }
+
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        assert_eq!(
+            ebu.loudness_global_sliding(Duration::from_secs(10)),
+            Err(Error::InvalidMode)
         );
-        assert_float_eq!(
-            ebu.prev_sample_peak(1).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
+        ebu.enable_block_energies();
+        assert_eq!(
+            ebu.loudness_global_sliding(Duration::from_secs(10)),
+            Err(Error::NotEnoughData)
         );
 
+        ebu.add_frames_f32(&data[..48_000 * 20]).unwrap();
+        let quiet = ebu.loudness_global().unwrap();
         assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
+            ebu.loudness_global_sliding(Duration::from_secs(10))
+                .unwrap(),
+            quiet,
+            abs <= 0.01
         );
+
+        ebu.add_frames_f32(&data[48_000 * 20..]).unwrap();
+        let global = ebu.loudness_global().unwrap();
+        let sliding = ebu
+            .loudness_global_sliding(Duration::from_secs(10))
+            .unwrap();
+        // Only the loud part. The quiet part is below the relative gate of the whole programme,
+        // but the blocks across the step still lower the integrated loudness a bit
+        assert_float_eq!(sliding, quiet + 20.0, abs <= 0.01);
+        assert!(global < sliding);
+
+        // The window covers the step
+        let sliding_step = ebu
+            .loudness_global_sliding(Duration::from_millis(20_200))
+            .unwrap();
+        assert!(sliding_step < sliding && sliding_step > sliding - 0.5);
+
+        // The whole programme, only summed up in a different order
         assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0007814168930054,
+            ebu.loudness_global_sliding(Duration::from_secs(3_600))
+                .unwrap(),
+            global,
             abs <= 0.000001
         );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
+
+        // Shorter than a gating block
+        assert_eq!(
+            ebu.loudness_global_sliding(Duration::from_millis(399)),
+            Err(Error::NotEnoughData)
         );
+        assert!(ebu
+            .loudness_global_sliding(Duration::from_millis(400))
+            .is_ok());
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.683303243667767,
-            abs <= 0.000001
+        // Limited by the maximum history
+        ebu.set_max_history(5_000).unwrap();
+        assert_eq!(
+            ebu.loudness_global_sliding(Duration::from_secs(30)),
+            ebu.loudness_global_sliding(Duration::from_secs(5))
         );
     }
 
     #[test]
-    fn sine_stereo_i32_no_histogram() {
-        let mut data = vec![0i32; 48_000 * 5 * 2];
+    fn snapshot_restore() {
+        let mut data = vec![0.0f32; 48_000 * 10 * 2];
         let mut accumulator = 0.0;
         let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator) * (std::i32::MAX - 1) as f32;
-            out[0] = val as i32;
-            out[1] = val as i32;
+        for (i, frame) in data.chunks_exact_mut(2).enumerate() {
+            let val = f32::sin(accumulator);
+            let amplitude = 0.1 + 0.4 * ((i / 48_000) % 3) as f32;
+            frame[0] = amplitude * val;
+            frame[1] = amplitude * val / 2.0;
             accumulator += step;
         }
+        let (first, second) = data.split_at(48_000 * 4 * 2 + 1_235);
+        let other = vec![0.9f32; 48_000 * 2 * 2];
 
         let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_i32(&data).unwrap();
-
-        assert_float_eq!(
+        ebu.enable_timeseries();
+        ebu.enable_block_energies();
+        ebu.enable_true_peak_overs();
+        let mut expected = ebu.clone();
+        expected.add_frames_f32(&data).unwrap();
+
+        ebu.add_frames_iter(first.iter().copied(), None).unwrap();
+        let snapshot = ebu.snapshot();
+
+        // Continue differently, then roll back and continue as expected
+        ebu.add_frames_f32(&other).unwrap();
+        assert_ne!(ebu, expected);
+        ebu.restore(&snapshot).unwrap();
+        assert_eq!(ebu.snapshot(), snapshot);
+        ebu.add_frames_iter(second.iter().copied(), None).unwrap();
+        assert_eq!(ebu, expected);
+        assert_eq!(
             ebu.loudness_global().unwrap(),
-            -0.6826039914171368,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598274425,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715105212,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620040943,
-            abs <= 0.000001
+            expected.loudness_global().unwrap()
         );
-        assert_float_eq!(
+        assert_eq!(
             ebu.loudness_range().unwrap(),
-            0.00006921150165073442,
-            abs <= 0.000001
-        );
+            expected.loudness_range().unwrap()
+        );
+
+        // Into another instance with the same configuration
+        let mut restored = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        restored.enable_timeseries();
+        restored.enable_block_energies();
+        restored.enable_true_peak_overs();
+        restored.restore(&snapshot).unwrap();
+        restored
+            .add_frames_iter(second.iter().copied(), None)
+            .unwrap();
+        assert_eq!(restored, expected);
+
+        // Different configurations
+        let mut other = EbuR128::new(2, 44_100, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        assert_eq!(other.restore(&snapshot), Err(Error::InvalidMode));
+        let mut other = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        assert_eq!(other.restore(&snapshot), Err(Error::InvalidMode));
+        let mut other = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
+        other.set_precision(Precision::F32).unwrap();
+        assert_eq!(other.restore(&snapshot), Err(Error::InvalidMode));
+        other.set_precision(Precision::F64).unwrap();
+        other.set_weighting(Weighting::C).unwrap();
+        assert_eq!(other.restore(&snapshot), Err(Error::InvalidMode));
+        assert_eq!(other.frames_processed(), 0);
+    }
 
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+    #[test]
+    fn invalid_channels_and_rate() {
+        for &(channels, rate, err) in &[
+            (0, 48_000, Error::InvalidChannels),
+            (65, 48_000, Error::InvalidChannels),
+            (u32::MAX, 48_000, Error::InvalidChannels),
+            (0, 0, Error::InvalidChannels),
+            (2, 0, Error::InvalidRate),
+            (2, 15, Error::InvalidRate),
+            (2, 2_822_401, Error::InvalidRate),
+            (2, u32::MAX, Error::InvalidRate),
+        ] {
+            assert_eq!(EbuR128::new(channels, rate, Mode::all()).err(), Some(err));
+            assert_eq!(
+                EbuR128Builder::new()
+                    .channels(channels)
+                    .rate(rate)
+                    .mode(Mode::all())
+                    .build()
+                    .err(),
+                Some(err)
+            );
 
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+            let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+            assert_eq!(ebu.change_parameters(channels, rate), Err(err));
+            assert_eq!(ebu.channels(), 2);
+            assert_eq!(ebu.rate(), 48_000);
+        }
+
+        // Unset parameters of the builder
+        assert_eq!(
+            EbuR128Builder::new().rate(48_000).build().err(),
+            Some(Error::InvalidChannels)
         );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+        assert_eq!(
+            EbuR128Builder::new().channels(2).build().err(),
+            Some(Error::InvalidRate)
         );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+
+        // The limits themselves are valid
+        for &(channels, rate) in &[(1, 16), (64, 48_000), (1, 2_822_400)] {
+            let mode = Mode::I | Mode::S | Mode::LRA | Mode::SAMPLE_PEAK;
+            let mut ebu = EbuR128::new(channels, rate, mode).unwrap();
+            ebu.add_frames_f32(&vec![0.5; channels as usize * 100])
+                .unwrap();
+            ebu.loudness_shortterm().unwrap();
+            assert!(ebu.change_parameters(channels, rate).is_ok());
+        }
+    }
+
+    #[test]
+    fn shortterm_timeseries_timed() {
+        let step = 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0;
+        let data = (0..48_000 * 10)
+            .map(|i| 0.5 * f32::sin((i % 48) as f32 * step))
+            .collect::<Vec<_>>();
+
+        let mut ebu = EbuR128::new(1, 48_000, Mode::S | Mode::LRA).unwrap();
+        let mut ebu_disabled = EbuR128::new(1, 48_000, Mode::S | Mode::LRA).unwrap();
+        ebu.enable_shortterm_timeseries_timed();
+
+        let mut recorded = 0;
+        for chunk in data.chunks(4_800) {
+            ebu.add_frames_f32(chunk).unwrap();
+            ebu_disabled.add_frames_f32(chunk).unwrap();
+
+            let timeseries = ebu.shortterm_timeseries_timed();
+            if timeseries.len() > recorded {
+                assert_eq!(timeseries.len(), recorded + 1);
+                recorded = timeseries.len();
+                let (time, loudness) = timeseries[recorded - 1];
+                assert_eq!(time, ebu.duration_processed());
+                assert_float_eq!(loudness, ebu.loudness_shortterm().unwrap(), abs <= 0.000001);
+            }
+        }
+
+        // One block per second after the first 3s
+        assert_eq!(recorded, 8);
+        assert_eq!(
+            ebu.shortterm_timeseries_timed()[0].0,
+            std::time::Duration::from_secs(3)
         );
+        assert!(ebu_disabled.shortterm_timeseries_timed().is_empty());
         assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
+            ebu.loudness_range().unwrap(),
+            ebu_disabled.loudness_range().unwrap(),
             abs <= 0.000001
         );
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.682603991417135,
-            abs <= 0.000001
-        );
+        ebu.reset();
+        assert!(ebu.shortterm_timeseries_timed().is_empty());
+        ebu.add_frames_f32(&data[..48_000 * 3]).unwrap();
+        assert_eq!(ebu.shortterm_timeseries_timed().len(), 1);
     }
 
     #[test]
-    fn sine_stereo_f32_no_histogram() {
+    fn true_peak_coefficients() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.true_peak_coefficients(), Err(Error::InvalidMode));
+        assert_eq!(ebu.true_peak_oversampling_factor(), Err(Error::InvalidMode));
+        assert_eq!(
+            ebu.set_true_peak_coefficients(2, &[0.0; 8]),
+            Err(Error::InvalidMode)
+        );
+
+        let mut ebu = EbuR128::new(2, 192_000, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.true_peak_oversampling_factor(), Ok(1));
+        assert_eq!(ebu.true_peak_coefficients(), Ok(vec![]));
+        assert_eq!(
+            ebu.set_true_peak_coefficients(1, &[1.0]),
+            Err(Error::InvalidMode)
+        );
+
+        // Specialized and generic interpolators. A sine at a quarter of the sample rate with the
+        // peaks exactly between two samples.
+        let data = (0..4_800 * 3)
+            .map(|i| {
+                0.5 * f32::sin(
+                    (i % 4) as f32 * std::f32::consts::FRAC_PI_2 + std::f32::consts::FRAC_PI_4,
+                )
+            })
+            .collect::<Vec<_>>();
+        for &channels in &[1, 3] {
+            let mode = Mode::TRUE_PEAK | Mode::SAMPLE_PEAK;
+            let mut ebu = EbuR128::new(channels, 48_000, mode).unwrap();
+            let standard = ebu.true_peak_coefficients().unwrap();
+            assert_eq!(standard.len(), 48);
+            assert_eq!(ebu.true_peak_oversampling_factor(), Ok(4));
+
+            // Wrong number of coefficients or too many taps
+            assert_eq!(
+                ebu.set_true_peak_coefficients(2, &[0.0; 7]),
+                Err(Error::NoMem)
+            );
+            assert_eq!(
+                ebu.set_true_peak_coefficients(13, &[0.0; 52]),
+                Err(Error::NoMem)
+            );
+            assert_eq!(ebu.true_peak_coefficients().unwrap(), standard);
+
+            // Linear interpolation never overshoots the samples
+            let linear = [0.0, 0.25, 0.5, 0.75, 1.0, 0.75, 0.5, 0.25];
+            ebu.set_true_peak_coefficients(2, &linear).unwrap();
+            let coeffs = ebu.true_peak_coefficients().unwrap();
+            assert_eq!(coeffs[..8], linear);
+            assert_eq!(coeffs[8..], [0.0; 40]);
+
+            let data = &data[..data.len() / channels as usize * channels as usize];
+            ebu.add_frames_f32(data).unwrap();
+            for c in 0..channels {
+                assert_eq!(ebu.true_peak(c), ebu.sample_peak(c));
+            }
+            let mut ebu_standard = EbuR128::new(channels, 48_000, mode).unwrap();
+            ebu_standard.add_frames_f32(data).unwrap();
+            assert!(ebu_standard.true_peak(0).unwrap() > ebu.true_peak(0).unwrap());
+            assert!(ebu != ebu_standard);
+
+            #[cfg(feature = "serde")]
+            {
+                let serialized = bincode::serialize(&ebu).unwrap();
+                let deserialized = bincode::deserialize::<EbuR128>(&serialized).unwrap();
+                assert_eq!(deserialized.true_peak_coefficients().unwrap(), coeffs);
+                assert!(deserialized == ebu);
+
+                let serialized = bincode::serialize(&ebu_standard).unwrap();
+                let deserialized = bincode::deserialize::<EbuR128>(&serialized).unwrap();
+                assert!(deserialized == ebu_standard);
+            }
+
+            // Kept by resetting, replaced when creating the interpolator again
+            ebu.reset();
+            assert_eq!(ebu.true_peak_coefficients().unwrap(), coeffs);
+            ebu.set_true_peak_quality(TruePeakQuality::Accurate)
+                .unwrap();
+            ebu.set_true_peak_quality(TruePeakQuality::Standard)
+                .unwrap();
+            assert_eq!(ebu.true_peak_coefficients().unwrap(), standard);
+        }
+    }
+
+    #[test]
+    fn true_peak_per_channel() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+
+        // Left channel 4 times louder than the right channel. At a quarter of the sample rate
+        // the samples never hit the peaks of the sine.
+        let mut data = vec![0.0f32; 48_000 * 2];
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let val = f32::sin(2.0 * std::f32::consts::PI * (i as f32 + 0.5) / 4.0);
+            out[0] = 0.8 * val;
+            out[1] = 0.2 * val;
+        }
+        ebu.add_frames_f32(&data).unwrap();
+
+        let left = ebu.true_peak(0).unwrap();
+        let right = ebu.true_peak(1).unwrap();
+        assert_float_eq!(left, 0.8, abs <= 0.01);
+        assert_float_eq!(right, 0.2, abs <= 0.01);
+        assert!(left > ebu.sample_peak(0).unwrap());
+        assert!(right > ebu.sample_peak(1).unwrap());
+        assert_eq!(ebu.true_peak_max().unwrap(), left);
+        assert_eq!(ebu.true_peak_dbtp_max().unwrap(), 20.0 * f64::log10(left));
+        assert_eq!(ebu.measurement().true_peak, Some(vec![left, right]));
+
+        assert_eq!(ebu.true_peak(2), Err(Error::InvalidChannelIndex));
+        assert_eq!(ebu.prev_true_peak(2), Err(Error::InvalidChannelIndex));
+    }
+
+    #[test]
+    fn reset_peaks() {
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+
+        ebu.add_frames_f32(&[0.5f32; 48_000]).unwrap();
+        ebu.add_frames_f32(&[0.25f32; 4_800]).unwrap();
+        let loudness = ebu.loudness_global().unwrap();
+        assert_eq!(ebu.sample_peak(0).unwrap(), 0.5);
+        assert_eq!(ebu.prev_sample_peak(0).unwrap(), 0.25);
+
+        ebu.reset_peaks();
+        assert_eq!(ebu.sample_peak(0).unwrap(), 0.0);
+        assert_eq!(ebu.true_peak(0).unwrap(), 0.0);
+        assert_eq!(ebu.prev_sample_peak(0).unwrap(), 0.25);
+        assert_eq!(ebu.loudness_global().unwrap(), loudness);
+
+        ebu.add_frames_f32(&[0.125f32; 4_800]).unwrap();
+        assert_eq!(ebu.sample_peak(0).unwrap(), 0.125);
+        assert_eq!(ebu.prev_sample_peak(0).unwrap(), 0.125);
+        assert!(ebu.true_peak(0).unwrap() >= 0.125);
+    }
+
+    #[test]
+    fn prev_true_peak_transient() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+
+        // A window of 100ms with a transient at the beginning, which is completely interpolated
+        // within the window
+        let mut transient = vec![0.0f32; 4_800 * 2];
+        transient[100 * 2] = 0.9;
+        transient[101 * 2] = -0.9;
+        ebu.add_frames_f32(&transient).unwrap();
+        let peak = ebu.true_peak(0).unwrap();
+        assert!(peak >= 0.9);
+        assert_eq!(ebu.prev_true_peak(0), Ok(peak));
+        assert_eq!(ebu.prev_true_peak(1), Ok(0.0));
+
+        // The previous window still reports the transient after resetting the peaks
+        ebu.reset_peaks();
+        assert_eq!(ebu.true_peak(0), Ok(0.0));
+        assert_eq!(ebu.prev_true_peak(0), Ok(peak));
+
+        // A silent window
+        ebu.add_frames_f32(&[0.0f32; 4_800 * 2]).unwrap();
+        assert_eq!(ebu.true_peak(0), Ok(0.0));
+        assert_eq!(ebu.prev_true_peak(0), Ok(0.0));
+        assert_eq!(ebu.prev_sample_peak(0), Ok(0.0));
+    }
+
+    #[test]
+    fn set_max_window_preserves_data() {
         let mut data = vec![0.0f32; 48_000 * 5 * 2];
         let mut accumulator = 0.0;
         let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
@@ -1520,665 +8480,2017 @@ mod tests {
             accumulator += step;
         }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_f32(&data).unwrap();
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        let mut ebu_ref = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu_ref.set_max_window(2_000).unwrap();
 
+        // Stop in the middle of a block
+        ebu.add_frames_f32(&data[..(2 * 48_000 + 1_234) * 2])
+            .unwrap();
+        ebu_ref
+            .add_frames_f32(&data[..(2 * 48_000 + 1_234) * 2])
+            .unwrap();
+        let momentary = ebu.loudness_momentary().unwrap();
+        assert_eq!(ebu.loudness_window(1_000), Err(Error::InvalidMode));
+
+        // Enlarging keeps the last 400ms and fills the remaining window with silence
+        ebu.set_max_window(2_000).unwrap();
+        assert_eq!(ebu.max_window(), 2_000);
+        assert_float_eq!(ebu.loudness_momentary().unwrap(), momentary, ulps <= 2);
         assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -0.6826039914165554,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598268921,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715100236,
+            ebu.loudness_window(2_000).unwrap(),
+            momentary + 10.0 * f64::log10(0.2),
             abs <= 0.000001
         );
+
+        // After adding enough data the result is the same as with the window configured from the
+        // beginning
+        ebu.add_frames_f32(&data[(2 * 48_000 + 1_234) * 2..])
+            .unwrap();
+        ebu_ref
+            .add_frames_f32(&data[(2 * 48_000 + 1_234) * 2..])
+            .unwrap();
         assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620008693,
-            abs <= 0.000001
+            ebu.loudness_window(2_000).unwrap(),
+            ebu_ref.loudness_window(2_000).unwrap(),
+            ulps <= 2
         );
         assert_float_eq!(
-            ebu.loudness_range().unwrap(),
-            0.00006921150169403312,
+            ebu.loudness_global().unwrap(),
+            ebu_ref.loudness_global().unwrap(),
             abs <= 0.000001
         );
 
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        // Shrinking keeps the most recent data
+        let window = ebu.loudness_window(1_000).unwrap();
+        ebu.set_max_window(1_000).unwrap();
+        assert_float_eq!(ebu.loudness_window(1_000).unwrap(), window, ulps <= 2);
 
+        ebu.set_max_history(10_000).unwrap();
+        assert_eq!(ebu.set_max_window(20_000), Err(Error::NoMem));
+        assert_eq!(ebu.max_window(), 1_000);
+    }
+
+    #[test]
+    fn set_max_history_truncates() {
+        fn sine(amplitude: f32) -> Vec<f32> {
+            let step = 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0;
+            (0..48_000 * 10)
+                .map(|i| amplitude * f32::sin(i as f32 * step))
+                .collect()
+        }
+
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I | Mode::LRA).unwrap();
+        let mut ebu_ref = EbuR128::new(1, 48_000, Mode::I | Mode::LRA).unwrap();
+        ebu.set_max_history(60_000).unwrap();
+
+        // 10s at -6dBFS followed by 10s at -12dBFS, the reference only measures the latter
+        ebu.add_frames_f32(&sine(0.5)).unwrap();
+        ebu.add_frames_f32(&sine(0.25)).unwrap();
+        ebu_ref.add_frames_f32(&sine(0.25)).unwrap();
+        assert!(ebu.loudness_global().unwrap() > ebu_ref.loudness_global().unwrap() + 1.0);
+        assert!(ebu.loudness_range().unwrap() > 5.0);
+
+        ebu.set_max_history(5_000).unwrap();
         assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+            ebu.loudness_global().unwrap(),
+            ebu_ref.loudness_global().unwrap(),
+            abs <= 0.0001
         );
+        assert_float_eq!(ebu.loudness_range().unwrap(), 0.0, abs <= 0.0001);
 
+        // Growing the history keeps the remaining blocks and doesn't add any
+        ebu.set_max_history(120_000).unwrap();
         assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.682603991416554,
-            abs <= 0.000001
+            ebu.loudness_global().unwrap(),
+            ebu_ref.loudness_global().unwrap(),
+            abs <= 0.0001
         );
+        ebu.add_frames_f32(&sine(0.5)).unwrap();
+        ebu_ref.set_max_history(5_000).unwrap();
+        ebu_ref.add_frames_f32(&sine(0.5)).unwrap();
+        assert!(ebu.loudness_global().unwrap() < ebu_ref.loudness_global().unwrap() - 1.0);
     }
 
     #[test]
-    fn sine_stereo_f64_no_histogram() {
-        let mut data = vec![0.0f64; 48_000 * 5 * 2];
+    fn true_peak_latency() {
+        let ebu = EbuR128::new(1, 192_000, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.true_peak_latency_samples(), Ok(0));
+
+        for &(rate, latency) in &[(44_100, 6), (48_000, 6), (96_000, 12)] {
+            let mut ebu = EbuR128::new(1, rate, Mode::TRUE_PEAK).unwrap();
+            assert_eq!(ebu.true_peak_latency_samples(), Ok(latency));
+
+            // The peak of an impulse in the oversampled signal is only seen after the latency
+            ebu.add_frames_f32(&[1.0]).unwrap();
+            ebu.reset_peaks();
+            let mut delay = 0;
+            while ebu.true_peak(0).unwrap() < 0.999 {
+                assert!(delay < 48);
+                ebu.add_frames_f32(&[0.0]).unwrap();
+                delay += 1;
+            }
+            assert_eq!(delay, latency);
+        }
+
+        let ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.true_peak_latency_samples(), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn allocate_audio_data_overflow() {
+        for &precision in &[Precision::F64, Precision::F32, Precision::Fixed] {
+            // Number of samples overflows
+            assert!(matches!(
+                EbuR128::allocate_audio_data(64, 2_822_400, usize::MAX, precision),
+                Err(Error::NoMem)
+            ));
+            // Number of bytes overflows, like a long window on 32 bit platforms
+            assert!(matches!(
+                EbuR128::allocate_audio_data(64, 1_000, usize::MAX / 1_000, precision),
+                Err(Error::NoMem)
+            ));
+            assert_eq!(
+                EbuR128::allocate_audio_data(2, 1_000, 3_000, precision)
+                    .unwrap()
+                    .len(),
+                6_000
+            );
+        }
+    }
+
+    #[test]
+    fn precision_f32() {
+        // Sines from very low to high frequencies plus white noise, which are measured within
+        // 0.1 LU of the double precision values at all sample rates
+        let mut seed = 1u32;
+        let mut noise = move || {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+
+        for &rate in &[44_100, 48_000, 96_000, 192_000] {
+            for &weighting in &[Weighting::K, Weighting::A] {
+                for &freq in &[20.0, 40.0, 100.0, 1_000.0, 10_000.0, 0.0] {
+                    let data = (0..rate as usize * 5)
+                        .flat_map(|i| {
+                            let t = i as f64 / rate as f64;
+                            let v = if freq == 0.0 {
+                                noise()
+                            } else {
+                                (0.5 * f64::sin(2.0 * core::f64::consts::PI * freq * t)) as f32
+                            };
+                            // Second channel gets quiet for the loudness range
+                            [v, if t < 2.5 { v } else { 0.1 * v }]
+                        })
+                        .collect::<Vec<_>>();
+
+                    let mut ebu = EbuR128Builder::new()
+                        .channels(2)
+                        .rate(rate)
+                        .mode(Mode::I | Mode::LRA | Mode::SAMPLE_PEAK)
+                        .weighting(weighting)
+                        .build()
+                        .unwrap();
+                    let mut ebu_f32 = EbuR128Builder::new()
+                        .channels(2)
+                        .rate(rate)
+                        .mode(Mode::I | Mode::LRA | Mode::SAMPLE_PEAK)
+                        .weighting(weighting)
+                        .precision(Precision::F32)
+                        .build()
+                        .unwrap();
+                    assert_eq!(ebu_f32.precision(), Precision::F32);
+
+                    ebu.add_frames_f32(&data).unwrap();
+                    ebu_f32.add_frames_f32(&data).unwrap();
+
+                    for (v, v_f32) in &[
+                        (ebu.loudness_global(), ebu_f32.loudness_global()),
+                        (ebu.loudness_momentary(), ebu_f32.loudness_momentary()),
+                        (ebu.loudness_shortterm(), ebu_f32.loudness_shortterm()),
+                        (ebu.loudness_range(), ebu_f32.loudness_range()),
+                    ] {
+                        let (v, v_f32) = (v.unwrap(), v_f32.unwrap());
+                        assert_float_eq!(v, v_f32, abs <= 0.1);
+                    }
+                    assert_eq!(ebu.sample_peak(0), ebu_f32.sample_peak(0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn precision_fixed() {
+        // Loud and quiet sines from very low to high frequencies plus white noise, which are
+        // measured within 0.001 LU of the double precision values at all sample rates as long as
+        // they are above the absolute gate
+        let mut seed = 1u32;
+        let mut noise = move || {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+
+        for &rate in &[8_000, 48_000, 384_000] {
+            for &weighting in &[Weighting::K, Weighting::A, Weighting::C] {
+                for &(amplitude, freq) in &[
+                    (0.5, 20.0),
+                    (0.5, 100.0),
+                    (0.5, 1_000.0),
+                    (0.5, 3_000.0),
+                    (0.001, 40.0),
+                    (0.001, 1_000.0),
+                    (0.5, 0.0),
+                ] {
+                    let data = (0..rate as usize * 5)
+                        .flat_map(|i| {
+                            let t = i as f64 / rate as f64;
+                            let v = if freq == 0.0 {
+                                noise()
+                            } else {
+                                (amplitude * f64::sin(2.0 * core::f64::consts::PI * freq * t))
+                                    as f32
+                            };
+                            // Second channel gets quiet for the loudness range
+                            [v, if t < 2.5 { v } else { 0.1 * v }]
+                        })
+                        .collect::<Vec<_>>();
+                    let data_i16 = data
+                        .iter()
+                        .map(|v| (v * 32_768.0) as i16)
+                        .collect::<Vec<_>>();
+
+                    let build = |precision| {
+                        EbuR128Builder::new()
+                            .channels(2)
+                            .rate(rate)
+                            .mode(Mode::I | Mode::LRA)
+                            .weighting(weighting)
+                            .precision(precision)
+                            .build()
+                            .unwrap()
+                    };
+                    let mut ebu = build(Precision::F64);
+                    let mut ebu_fixed = build(Precision::Fixed);
+                    let mut ebu_i16 = build(Precision::F64);
+                    let mut ebu_fixed_i16 = build(Precision::Fixed);
+                    assert_eq!(ebu_fixed.precision(), Precision::Fixed);
+
+                    ebu.add_frames_f32(&data).unwrap();
+                    ebu_fixed.add_frames_f32(&data).unwrap();
+                    ebu_i16.add_frames_i16(&data_i16).unwrap();
+                    ebu_fixed_i16.add_frames_i16(&data_i16).unwrap();
+
+                    for (ebu, ebu_fixed) in &[(&ebu, &ebu_fixed), (&ebu_i16, &ebu_fixed_i16)] {
+                        for (v, v_fixed) in &[
+                            (ebu.loudness_global(), ebu_fixed.loudness_global()),
+                            (ebu.loudness_momentary(), ebu_fixed.loudness_momentary()),
+                            (ebu.loudness_range(), ebu_fixed.loudness_range()),
+                        ] {
+                            let (v, v_fixed) = (v.unwrap(), v_fixed.unwrap());
+                            if v > -70.0 {
+                                assert_float_eq!(v, v_fixed, abs <= 0.001);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Converting the audio data keeps the loudness of the windows measured so far
+        let data = (0..48_000 * 2)
+            .map(|i| f32::sin(i as f32 * 0.05) * 0.25)
+            .collect::<Vec<_>>();
+        let mut ebu = EbuR128::new(2, 48_000, Mode::S).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        let shortterm = ebu.loudness_shortterm().unwrap();
+        ebu.set_precision(Precision::Fixed).unwrap();
+        assert_float_eq!(ebu.loudness_shortterm().unwrap(), shortterm, abs <= 0.001);
+        ebu.set_precision(Precision::F32).unwrap();
+        assert_float_eq!(ebu.loudness_shortterm().unwrap(), shortterm, abs <= 0.001);
+    }
+
+    #[test]
+    fn precision_f32_seed() {
+        // Seeding gives the same filter state as adding the frames, also with the A-weighting that
+        // has an additional biquad
+        let mut data = vec![0.0f32; 48_000 * 2];
         let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        let step = 2.0 * std::f32::consts::PI * 50.0 / 48_000.0;
         for out in data.chunks_exact_mut(2) {
+            out[0] = 0.5 * f32::sin(accumulator);
+            out[1] = 0.25 * f32::sin(3.0 * accumulator);
+            accumulator += step;
+        }
+        let (first, second) = data.split_at(48_000);
+
+        for &(weighting, precision) in &[
+            (Weighting::K, Precision::F32),
+            (Weighting::A, Precision::F32),
+            (Weighting::K, Precision::Fixed),
+            (Weighting::A, Precision::Fixed),
+        ] {
+            let build = || {
+                EbuR128Builder::new()
+                    .channels(2)
+                    .rate(48_000)
+                    .mode(Mode::M)
+                    .weighting(weighting)
+                    .precision(precision)
+                    .build()
+                    .unwrap()
+            };
+
+            let mut ebu = build();
+            let mut filtered = vec![0.0; data.len()];
+            ebu.add_frames_filtered(&data, &mut filtered).unwrap();
+
+            let mut ebu_seeded = build();
+            ebu_seeded.seed_frames_f32(first).unwrap();
+            let mut filtered_seeded = vec![0.0; second.len()];
+            ebu_seeded
+                .add_frames_filtered(second, &mut filtered_seeded)
+                .unwrap();
+
+            for (filtered, filtered_seeded) in Iterator::zip(
+                filtered.chunks_exact(48_000),
+                filtered_seeded.chunks_exact(24_000),
+            ) {
+                assert_eq!(&filtered[24_000..], filtered_seeded);
+            }
+        }
+    }
+
+    #[test]
+    fn loudness_window_fixed_windows() {
+        for &rate in &[44_100, 48_000, 96_000] {
+            let mut data = vec![0.0f32; rate as usize * 10 * 2];
+            let mut accumulator = 0.0;
+            let step = 2.0 * std::f32::consts::PI * 440.0 / rate as f32;
+            for (i, out) in data.chunks_exact_mut(2).enumerate() {
+                // Get louder every second so that the windows are different
+                let val = (1 + i / rate as usize) as f32 / 10.0 * f32::sin(accumulator);
+                out[0] = val;
+                out[1] = val;
+                accumulator += step;
+            }
+
+            let mut ebu = EbuR128::new(2, rate, Mode::S).unwrap();
+            ebu.set_max_window(5_000).unwrap();
+            // End in the middle of a gating block
+            ebu.add_frames_f32(&data[..data.len() - 2 * 1_234]).unwrap();
+
+            assert_eq!(
+                ebu.loudness_window(400).unwrap(),
+                ebu.loudness_momentary().unwrap()
+            );
+            assert_eq!(
+                ebu.loudness_window(3_000).unwrap(),
+                ebu.loudness_shortterm().unwrap()
+            );
+            assert!(ebu.loudness_window(5_000).unwrap() < ebu.loudness_window(3_000).unwrap());
+            assert_eq!(ebu.loudness_window(5_001), Err(Error::InvalidMode));
+        }
+    }
+
+    #[test]
+    fn peak_to_loudness_ratio() {
+        for &(channels, amplitude, plr) in &[(1, 1.0, 3.01), (2, 1.0, 0.0), (1, 0.1, 3.01)] {
+            let mut data = vec![0.0f32; 48_000 * 5 * channels];
+            let mut accumulator = 0.0;
+            let step = 2.0 * std::f32::consts::PI * 997.0 / 48_000.0;
+            for out in data.chunks_exact_mut(channels) {
+                for out in out {
+                    *out = amplitude * f32::sin(accumulator);
+                }
+                accumulator += step;
+            }
+
+            let mut ebu = EbuR128::new(channels as u32, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+            ebu.add_frames_f32(&data).unwrap();
+
+            assert_float_eq!(ebu.peak_to_loudness_ratio().unwrap(), plr, abs <= 0.01);
+            assert_float_eq!(
+                ebu.peak_to_loudness_ratio().unwrap(),
+                ebu.true_peak_dbtp_max().unwrap() - ebu.loudness_global().unwrap(),
+                abs <= 0.000001
+            );
+        }
+
+        // Silence
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I | Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.peak_to_loudness_ratio(), Err(Error::NotEnoughData));
+        ebu.add_frames_f32(&[0.0; 48_000]).unwrap();
+        assert_eq!(ebu.peak_to_loudness_ratio(), Err(Error::NotEnoughData));
+
+        let ebu = EbuR128::new(1, 48_000, Mode::I | Mode::SAMPLE_PEAK).unwrap();
+        assert_eq!(ebu.peak_to_loudness_ratio(), Err(Error::InvalidMode));
+        let ebu = EbuR128::new(1, 48_000, Mode::M | Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.peak_to_loudness_ratio(), Err(Error::InvalidMode));
+    }
+
+    #[test]
+    fn timeseries() {
+        let mut data = vec![0.0f32; 44_100 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 44_100.0;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
             let val = f32::sin(accumulator);
-            out[0] = val as f64;
-            out[1] = val as f64;
+            let amplitude = if (i / 4_410) % 3 == 0 { 0.1 } else { 0.5 };
+            out[0] = amplitude * val;
+            out[1] = amplitude * val;
             accumulator += step;
         }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_f64(&data).unwrap();
+        // Poll the loudness after every completed gating block
+        let mut ebu = EbuR128::new(2, 44_100, Mode::M | Mode::S).unwrap();
+        let mut momentary = vec![];
+        let mut shortterm = vec![];
+        ebu.add_frames_f32(&data[..2 * 17_640]).unwrap();
+        momentary.push(ebu.loudness_momentary().unwrap());
+        shortterm.push(ebu.loudness_shortterm().unwrap());
+        for chunk in data[2 * 17_640..].chunks_exact(2 * 4_410) {
+            ebu.add_frames_f32(chunk).unwrap();
+            momentary.push(ebu.loudness_momentary().unwrap());
+            shortterm.push(ebu.loudness_shortterm().unwrap());
+        }
+        assert_eq!(momentary.len(), 47);
+        assert!(ebu.momentary_timeseries().is_empty());
+        assert!(ebu.shortterm_timeseries().is_empty());
+
+        let mut ebu = EbuR128::new(2, 44_100, Mode::M | Mode::S).unwrap();
+        ebu.enable_timeseries();
+        for _ in 0..2 {
+            for chunk in data.chunks(2 * 1_234) {
+                ebu.add_frames_f32(chunk).unwrap();
+            }
+            assert_eq!(ebu.momentary_timeseries(), &momentary[..]);
+            assert_eq!(ebu.shortterm_timeseries(), &shortterm[..]);
 
-        assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -0.6826039914165554,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598268921,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715100236,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620008693,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_range().unwrap(),
-            0.00006921150169403312,
-            abs <= 0.000001
-        );
+            ebu.reset();
+            assert!(ebu.momentary_timeseries().is_empty());
+            assert!(ebu.shortterm_timeseries().is_empty());
+        }
 
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        // Silence is recorded as -inf, and only the enabled modes are recorded
+        let mut ebu = EbuR128::new(1, 48_000, Mode::M).unwrap();
+        ebu.enable_timeseries();
+        ebu.add_frames_f32(&[0.0; 24_000]).unwrap();
+        assert_eq!(ebu.momentary_timeseries(), &[-f64::INFINITY; 2]);
+        assert!(ebu.shortterm_timeseries().is_empty());
+    }
 
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
+    #[test]
+    fn clone_mid_stream() {
+        let mut data = vec![0.0f32; 48_000 * 10 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let val = f32::sin(accumulator);
+            // Change the amplitude every second to get some loudness range
+            let amplitude = 0.1 + 0.8 * ((i / 48_000) % 3) as f32 / 2.0;
+            out[0] = amplitude * val;
+            out[1] = amplitude * val / 2.0;
+            accumulator += step;
+        }
+        // Split in the middle of a block, with an inter-sample peak right before the split
+        let split = 2 * 4_321 * 48;
+        for out in &mut data[split - 4..split] {
+            *out = 0.9;
+        }
+        let (first, second) = data.split_at(split);
+
+        for &(mode, precision) in &[
+            (Mode::all(), Precision::F64),
+            (Mode::all() & !Mode::HISTOGRAM, Precision::F64),
+            (Mode::all(), Precision::F32),
+            (Mode::all(), Precision::Fixed),
+        ] {
+            let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+            ebu.set_precision(precision).unwrap();
+            ebu.enable_timeseries();
+            ebu.add_frames_f32(first).unwrap();
+
+            let mut clone = ebu.clone();
+            let before = ebu.loudness_global().unwrap();
+
+            // The clone is independent of the original
+            clone.add_frames_f32(second).unwrap();
+            assert_eq!(ebu.loudness_global().unwrap(), before);
+
+            ebu.add_frames_f32(second).unwrap();
+            assert_eq!(
+                ebu.loudness_global().unwrap(),
+                clone.loudness_global().unwrap()
+            );
+            assert_eq!(
+                ebu.loudness_range().unwrap(),
+                clone.loudness_range().unwrap()
+            );
+            assert_eq!(
+                ebu.loudness_shortterm().unwrap(),
+                clone.loudness_shortterm().unwrap()
+            );
+            assert_eq!(ebu.momentary_timeseries(), clone.momentary_timeseries());
+            for c in 0..2 {
+                assert_eq!(ebu.sample_peak(c).unwrap(), clone.sample_peak(c).unwrap());
+                assert_eq!(ebu.true_peak(c).unwrap(), clone.true_peak(c).unwrap());
+                assert_eq!(
+                    ebu.prev_true_peak(c).unwrap(),
+                    clone.prev_true_peak(c).unwrap()
+                );
+            }
+        }
+    }
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.682603991416554,
-            abs <= 0.000001
-        );
+    #[test]
+    fn deterministic() {
+        // Integer input without floating point functions so that it is the same on all platforms
+        let measure = |channels: usize, rate: u32| {
+            let mut data = vec![0i16; rate as usize * 6 * channels];
+            let mut seed = 1u32;
+            for (i, frame) in data[..rate as usize * 5 * channels]
+                .chunks_exact_mut(channels)
+                .enumerate()
+            {
+                let triangle = (i % 200) as i32 - 100;
+                let amplitude = 1 + (i / rate as usize) as i32 % 3;
+                for (c, out) in frame.iter_mut().enumerate() {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    let noise = (seed >> 20) as i32 - 2048;
+                    *out = (amplitude * (triangle * 50 + noise) / (c as i32 + 1)) as i16;
+                }
+            }
+
+            let mut ebu = EbuR128::new(channels as u32, rate, Mode::all()).unwrap();
+            for chunk in data.chunks(1_234 * channels) {
+                ebu.add_frames_i16(chunk).unwrap();
+            }
+
+            let mut results = vec![
+                ebu.loudness_global().unwrap(),
+                ebu.loudness_momentary().unwrap(),
+                ebu.loudness_shortterm().unwrap(),
+                ebu.loudness_window(1_000).unwrap(),
+                ebu.loudness_range().unwrap(),
+            ];
+            for c in 0..channels as u32 {
+                results.push(ebu.sample_peak(c).unwrap());
+                results.push(ebu.true_peak(c).unwrap());
+            }
+            results.into_iter().map(f64::to_bits).collect::<Vec<_>>()
+        };
+
+        let expected: [(usize, u32, &[u64]); 2] = [
+            (
+                2,
+                44_100,
+                &[
+                    0xc02a87fc52b1cf89,
+                    0xfff0000000000000,
+                    0xc030d2426005a5f4,
+                    0xc04b57caa7ee1177,
+                    0x4012000000000002,
+                    0x3fe49e8000000000,
+                    0x3fe9d34520000000,
+                    0x3fd4898000000000,
+                    0x3fdb4a4a40000000,
+                ],
+            ),
+            (
+                9,
+                48_000,
+                &[
+                    0xc0291bf238bfca2b,
+                    0xfff0000000000000,
+                    0xc0301bbc71065421,
+                    0xc047b0dd5aecbe73,
+                    0x4011fffffffffffc,
+                    0x3fe49c4000000000,
+                    0x3fea72ea40000000,
+                    0x3fd4a20000000000,
+                    0x3fdab0f900000000,
+                    0x3fcb7a0000000000,
+                    0x3fd1c3a120000000,
+                    0x3fc4a20000000000,
+                    0x3fcb152e00000000,
+                    0x3fc07e0000000000,
+                    0x3fc4935fa0000000,
+                    0x3fbb820000000000,
+                    0x3fc1a6c940000000,
+                    0x3fb7860000000000,
+                    0x3fbe208c40000000,
+                    0x3fb4980000000000,
+                    0x3fbb6d4a80000000,
+                    0x3fb25a0000000000,
+                    0x3fb7a04d80000000,
+                ],
+            ),
+        ];
+
+        for &(channels, rate, _expected) in &expected {
+            let results = measure(channels, rate);
+            for _ in 0..3 {
+                assert_eq!(measure(channels, rate), results);
+            }
+
+            // Bitwise identical results on all platforms
+            #[cfg(feature = "deterministic")]
+            assert_eq!(results, _expected);
+        }
     }
 
     #[test]
-    fn sine_stereo_i16_planar_no_histogram() {
-        let mut data = vec![0i16; 48_000 * 5 * 2];
+    fn chunk_size() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
         let mut accumulator = 0.0;
         let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        let (fst, snd) = data.split_at_mut(48_000 * 5);
-        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
-            let val = f32::sin(accumulator) * (std::i16::MAX - 1) as f32;
-            *fst = val as i16;
-            *snd = val as i16;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let val = f32::sin(accumulator);
+            let amplitude = if (i / 4_800) % 3 == 0 { 0.1 } else { 0.5 };
+            out[0] = amplitude * val;
+            out[1] = 0.5 * amplitude * val;
             accumulator += step;
         }
+        for frame in data.chunks_exact_mut(2 * 10_000) {
+            frame[1] = f32::NAN;
+        }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_planar_i16(&[fst, snd]).unwrap();
+        let measure = |chunk_size: Option<usize>| {
+            let mut builder = EbuR128Builder::new()
+                .channels(2)
+                .rate(48_000)
+                .mode(Mode::all())
+                .non_finite_handling(NonFiniteHandling::Skip);
+            if let Some(chunk_size) = chunk_size {
+                builder = builder.chunk_size(chunk_size);
+            }
+            let mut ebu = builder.build().unwrap();
+            assert_eq!(ebu.chunk_size(), chunk_size.unwrap_or(8192));
 
-        assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -0.683303243667768,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6820309226891973,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6834583474398446,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.875007988101488,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_range().unwrap(),
-            0.00006950793233284625,
-            abs <= 0.000001
+            let mut filtered = vec![0.0; data.len()];
+            let mut blocks = vec![];
+            let (first, second) = data.split_at(2 * 12_345);
+            ebu.add_frames_filtered(first, &mut filtered[..first.len()])
+                .unwrap();
+            ebu.add_frames_with(second, |loudness| blocks.push(loudness))
+                .unwrap();
+
+            (
+                filtered,
+                blocks,
+                ebu.non_finite_samples(),
+                ebu.loudness_global().unwrap(),
+                ebu.loudness_range().unwrap(),
+                ebu.true_peak(0).unwrap(),
+                ebu.true_peak(1).unwrap(),
+                ebu.prev_sample_peak(0).unwrap(),
+            )
+        };
+
+        let expected = measure(Some(usize::MAX));
+        assert_eq!(expected.2, 24);
+        for &chunk_size in &[None, Some(1), Some(1_000), Some(4_800)] {
+            assert_eq!(measure(chunk_size), expected);
+        }
+
+        // Nothing is processed if a later chunk contains a non-finite sample
+        let mut ebu = EbuR128::new(2, 48_000, Mode::all()).unwrap();
+        ebu.set_non_finite_handling(NonFiniteHandling::Reject);
+        ebu.set_chunk_size(1_000).unwrap();
+        assert_eq!(
+            ebu.add_frames_f32(&data[2..2 * 20_000]),
+            Err(Error::NonFiniteSample)
         );
+        assert_eq!(ebu.loudness_shortterm().unwrap(), -f64::INFINITY);
+        assert_eq!(ebu.sample_peak(0).unwrap(), 0.0);
+
+        assert_eq!(ebu.set_chunk_size(0), Err(Error::InvalidMode));
+        assert_eq!(ebu.chunk_size(), 1_000);
+    }
+
+    #[test]
+    fn replaygain() {
+        // Stereo 997Hz sines at -20dBFS and -26dBFS, i.e. about -20 LUFS and -26 LUFS
+        let sine = |amplitude: f32| {
+            let mut data = vec![0.0f32; 48_000 * 10 * 2];
+            let mut accumulator = 0.0;
+            let step = 2.0 * std::f32::consts::PI * 997.0 / 48_000.0;
+            for out in data.chunks_exact_mut(2) {
+                let val = amplitude * f32::sin(accumulator);
+                out[0] = val;
+                out[1] = val;
+                accumulator += step;
+            }
+
+            let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::SAMPLE_PEAK).unwrap();
+            ebu.add_frames_f32(&data).unwrap();
+            ebu
+        };
+        let loud = sine(0.1);
+        let quiet = sine(0.05);
 
+        assert_float_eq!(loud.replaygain_track_gain().unwrap(), 2.0, abs <= 0.05);
+        assert_float_eq!(quiet.replaygain_track_gain().unwrap(), 8.02, abs <= 0.05);
         assert_float_eq!(
-            ebu.sample_peak(0).unwrap(),
-            0.99993896484375,
+            loud.replaygain_track_gain().unwrap(),
+            -18.0 - loud.loudness_global().unwrap(),
             abs <= 0.000001
         );
+        assert_float_eq!(loud.replaygain_track_peak().unwrap(), 0.1, abs <= 0.0001);
+        assert_float_eq!(quiet.replaygain_track_peak().unwrap(), 0.05, abs <= 0.0001);
+
+        // Both tracks are above the relative gate of the album, which has the mean energy of both
+        let album = [loud, quiet];
         assert_float_eq!(
-            ebu.sample_peak(1).unwrap(),
-            0.99993896484375,
+            EbuR128::replaygain_album_gain(&album).unwrap(),
+            -18.0 - EbuR128::loudness_global_multiple(album.iter()).unwrap(),
             abs <= 0.000001
         );
         assert_float_eq!(
-            ebu.prev_sample_peak(0).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
+            EbuR128::replaygain_album_gain(&album).unwrap(),
+            -18.0 + 20.0 - 10.0 * f64::log10((1.0 + 0.25) / 2.0),
+            abs <= 0.05
         );
         assert_float_eq!(
-            ebu.prev_sample_peak(1).unwrap(),
-            0.99993896484375,
-            abs <= 0.000001
+            EbuR128::replaygain_album_peak(&album).unwrap(),
+            0.1,
+            abs <= 0.0001
         );
 
+        // Tracks of an album can have different sample rates and numbers of channels
+        let mut mono = vec![0.0f32; 44_100 * 10];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 997.0 / 44_100.0;
+        for out in &mut mono {
+            // Mono at 3dB above the stereo channels for the same loudness
+            *out = 0.05 * std::f32::consts::SQRT_2 * f32::sin(accumulator);
+            accumulator += step;
+        }
+        let mut quiet_mono = EbuR128::new(1, 44_100, Mode::I | Mode::HISTOGRAM).unwrap();
+        quiet_mono.add_frames_f32(&mono).unwrap();
         assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
+            quiet_mono.replaygain_track_gain().unwrap(),
+            album[1].replaygain_track_gain().unwrap(),
+            abs <= 0.05
         );
+        let mixed = [&album[0], &quiet_mono];
         assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
+            EbuR128::replaygain_album_gain(mixed.iter().copied()).unwrap(),
+            EbuR128::replaygain_album_gain(&album).unwrap(),
+            abs <= 0.05
         );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
+
+        let silence = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(silence.replaygain_track_gain(), Err(Error::NotEnoughData));
+        assert_eq!(silence.replaygain_track_peak(), Err(Error::InvalidMode));
+        assert_eq!(
+            EbuR128::replaygain_album_gain(&[silence]),
+            Err(Error::NotEnoughData)
         );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0007814168930054,
-            abs <= 0.000001
+        assert_eq!(
+            EbuR128::replaygain_album_gain(&[]),
+            Err(Error::NotEnoughData)
         );
+    }
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.683303243667767,
-            abs <= 0.000001
-        );
+    #[test]
+    fn gating() {
+        // 10s at -20 LUFS followed by 10s at -40 LUFS
+        let mut data = vec![0.0f32; 48_000 * 20 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 997.0 / 48_000.0;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let amplitude = if i < 48_000 * 10 { 0.1 } else { 0.01 };
+            let val = amplitude * f32::sin(accumulator);
+            out[0] = val;
+            out[1] = val;
+            accumulator += step;
+        }
+
+        for &mode in &[Mode::I, Mode::I | Mode::HISTOGRAM] {
+            let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+            assert_eq!(ebu.gating(), (-70.0, -10.0));
+            ebu.add_frames_f32(&data).unwrap();
+
+            let mut ebu_explicit = EbuR128Builder::new()
+                .channels(2)
+                .rate(48_000)
+                .mode(mode)
+                .gating(-70.0, -10.0)
+                .build()
+                .unwrap();
+            ebu_explicit.add_frames_f32(&data).unwrap();
+
+            // Explicitly setting the standard gates gives exactly the same results
+            assert_eq!(
+                ebu.loudness_global().unwrap().to_bits(),
+                ebu_explicit.loudness_global().unwrap().to_bits()
+            );
+            assert_eq!(
+                ebu.relative_threshold().unwrap().to_bits(),
+                ebu_explicit.relative_threshold().unwrap().to_bits()
+            );
+            assert_eq!(
+                EbuR128::loudness_global_multiple([&ebu, &ebu_explicit].iter().copied())
+                    .unwrap()
+                    .to_bits(),
+                EbuR128::loudness_global_multiple([&ebu, &ebu].iter().copied())
+                    .unwrap()
+                    .to_bits()
+            );
+
+            // The quiet part is below the standard relative gate
+            assert_float_eq!(ebu.loudness_global().unwrap(), -20.0, abs <= 0.1);
+
+            // A lower relative gate includes the quiet part
+            ebu.set_gating(-70.0, -30.0).unwrap();
+            assert_eq!(ebu.gating(), (-70.0, -30.0));
+            assert_float_eq!(
+                ebu.loudness_global().unwrap(),
+                -20.0 + 10.0 * f64::log10((1.0 + 0.01) / 2.0),
+                abs <= 0.1
+            );
+            assert_float_eq!(ebu.relative_threshold().unwrap(), -53.0, abs <= 0.1);
+
+            // The absolute gate still applies with a low relative gate
+            ebu.set_gating(-30.0, -100.0).unwrap();
+            assert_float_eq!(ebu.loudness_global().unwrap(), -20.0, abs <= 0.1);
+            assert_float_eq!(ebu.relative_threshold().unwrap(), -120.0, abs <= 0.1);
+
+            ebu.set_gating(0.0, -10.0).unwrap();
+            assert_eq!(ebu.loudness_global(), Ok(-f64::INFINITY));
+            assert_eq!(ebu.relative_threshold().unwrap(), 0.0);
+
+            // Instances with different gates can't be combined
+            assert_eq!(
+                EbuR128::loudness_global_multiple([&ebu, &ebu_explicit].iter().copied()),
+                Err(Error::InvalidMode)
+            );
+
+            assert_eq!(ebu.set_gating(-80.0, -10.0), Err(Error::InvalidMode));
+            assert_eq!(ebu.set_gating(f64::NAN, -10.0), Err(Error::InvalidMode));
+            assert_eq!(
+                ebu.set_gating(-70.0, f64::INFINITY),
+                Err(Error::InvalidMode)
+            );
+            assert_eq!(ebu.gating(), (0.0, -10.0));
+        }
     }
 
     #[test]
-    fn sine_stereo_i32_planar_no_histogram() {
-        let mut data = vec![0i32; 48_000 * 5 * 2];
+    fn gating_block_count() {
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        assert_eq!(ebu.gating_block_count(), 0);
+        assert_eq!(ebu.current_block_progress(), 0.0);
+
+        // The first gating block needs 400ms
+        ebu.add_frames_f32(&[0.1; 4_800 * 2]).unwrap();
+        assert_eq!(ebu.gating_block_count(), 0);
+        assert_eq!(ebu.current_block_progress(), 0.0);
+        ebu.add_frames_f32(&[0.1; 2_400 * 2]).unwrap();
+        assert_eq!(ebu.current_block_progress(), 0.5);
+        ebu.add_frames_f32(&[0.1; 14_400 * 2]).unwrap();
+        assert_eq!(ebu.gating_block_count(), 1);
+        assert_eq!(ebu.current_block_progress(), 0.5);
+        assert!(ebu.loudness_global().is_ok());
+
+        // Every following block needs another 100ms
+        ebu.add_frames_f32(&[0.1; 48_000 * 2]).unwrap();
+        assert_eq!(ebu.gating_block_count(), 11);
+        assert_eq!(ebu.current_block_progress(), 0.5);
+        ebu.add_frames_f32(&[0.1; 1_200 * 2]).unwrap();
+        assert_eq!(ebu.current_block_progress(), 0.75);
+        ebu.add_frames_f32(&[0.1; 1_200 * 2]).unwrap();
+        assert_eq!(ebu.gating_block_count(), 12);
+        assert_eq!(ebu.current_block_progress(), 0.0);
+
+        // Blocks are counted also if they are below the gates
+        ebu.add_frames_f32(&[0.0; 4_800 * 2]).unwrap();
+        assert_eq!(ebu.gating_block_count(), 13);
+
+        ebu.reset();
+        assert_eq!(ebu.gating_block_count(), 0);
+        assert_eq!(ebu.current_block_progress(), 0.0);
+    }
+
+    #[test]
+    fn block_hop() {
+        // 1kHz sine at -20 dBFS that gets quieter after 5s
+        let mut data = vec![0.0f32; 44_100 * 10 * 2];
         let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        let (fst, snd) = data.split_at_mut(48_000 * 5);
-        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
-            let val = f32::sin(accumulator) * (std::i32::MAX - 1) as f32;
-            *fst = val as i32;
-            *snd = val as i32;
+        let step = 2.0 * std::f32::consts::PI * 1_000.0 / 44_100.0;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let amplitude = if i < 44_100 * 5 { 0.1 } else { 0.05 };
+            let val = amplitude * f32::sin(accumulator);
+            out[0] = val;
+            out[1] = val;
             accumulator += step;
         }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_planar_i32(&[fst, snd]).unwrap();
+        let mode = Mode::I | Mode::S | Mode::LRA;
+        let mut ebu = EbuR128::new(2, 44_100, mode).unwrap();
+        assert_eq!(ebu.block_hop(), 100);
+        ebu.enable_timeseries();
+        ebu.add_frames_f32(&data).unwrap();
 
+        // Explicitly setting the default hop gives exactly the same results
+        let mut ebu_default = EbuR128Builder::new()
+            .channels(2)
+            .rate(44_100)
+            .mode(mode)
+            .block_hop(100)
+            .build()
+            .unwrap();
+        ebu_default.enable_timeseries();
+        ebu_default.add_frames_f32(&data).unwrap();
+        assert_eq!(
+            ebu.loudness_global().unwrap().to_bits(),
+            ebu_default.loudness_global().unwrap().to_bits()
+        );
+        assert_eq!(
+            ebu.loudness_range().unwrap().to_bits(),
+            ebu_default.loudness_range().unwrap().to_bits()
+        );
+        assert_eq!(
+            ebu.momentary_timeseries(),
+            ebu_default.momentary_timeseries()
+        );
+        assert_eq!(ebu.gating_block_count(), 97);
+
+        // 50% overlap completes a gating block every 200ms, each still covering 400ms
+        let mut ebu_half = EbuR128::new(2, 44_100, mode).unwrap();
+        ebu_half.set_block_hop(200).unwrap();
+        ebu_half.enable_timeseries();
+        ebu_half.add_frames_f32(&data).unwrap();
+        assert_eq!(ebu_half.gating_block_count(), 49);
+        for (i, loudness) in ebu_half.momentary_timeseries().iter().enumerate() {
+            assert_eq!(
+                loudness.to_bits(),
+                ebu.momentary_timeseries()[2 * i].to_bits()
+            );
+        }
         assert_float_eq!(
+            ebu_half.loudness_global().unwrap(),
             ebu.loudness_global().unwrap(),
-            -0.6826039914171368,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598274425,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715105212,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620040943,
-            abs <= 0.000001
+            abs <= 0.05
         );
         assert_float_eq!(
+            ebu_half.loudness_range().unwrap(),
             ebu.loudness_range().unwrap(),
-            0.00006921150165073442,
-            abs <= 0.000001
+            abs <= 0.1
         );
 
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
+        // A hop that doesn't fit into the audio buffer
+        let mut ebu_odd = EbuR128::new(2, 44_100, mode).unwrap();
+        ebu_odd.set_block_hop(70).unwrap();
+        ebu_odd.add_frames_f32(&data).unwrap();
+        assert_eq!(ebu_odd.gating_block_count(), 1 + 9_600 / 70);
         assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+            ebu_odd.loudness_global().unwrap(),
+            ebu.loudness_global().unwrap(),
+            abs <= 0.05
         );
         assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+            ebu_odd.loudness_shortterm().unwrap(),
+            ebu.loudness_shortterm().unwrap(),
+            abs <= 0.05
         );
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.682603991417135,
-            abs <= 0.000001
-        );
+        assert_eq!(ebu.set_block_hop(0), Err(Error::InvalidMode));
+        assert_eq!(ebu.set_block_hop(401), Err(Error::InvalidMode));
+        assert_eq!(ebu.block_hop(), 100);
     }
 
     #[test]
-    fn sine_stereo_f32_planar_no_histogram() {
-        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+    fn channel_gains() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 3];
         let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        let (fst, snd) = data.split_at_mut(48_000 * 5);
-        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
+        let step = 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0;
+        for (i, out) in data.chunks_exact_mut(3).enumerate() {
             let val = f32::sin(accumulator);
-            *fst = val;
-            *snd = val;
+            out[0] = 0.1 * val;
+            out[1] = 0.1 * val;
+            // Unrelated content on the last channel
+            out[2] = if i % 2 == 0 { 0.5 } else { -0.5 };
             accumulator += step;
         }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_planar_f32(&[fst, snd]).unwrap();
+        let mode = Mode::I | Mode::M;
+        let mut ebu = EbuR128::new(3, 48_000, mode).unwrap();
+        assert_eq!(ebu.channel_gains(), &[1.0, 1.0, 1.0]);
+        ebu.set_channel_gains(&[1.0, 1.0, 0.0]).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
 
-        assert_float_eq!(
+        let mut ebu_unused = EbuR128Builder::new()
+            .channels(3)
+            .rate(48_000)
+            .mode(mode)
+            .channel_map(&[Channel::Left, Channel::Right, Channel::Unused])
+            .build()
+            .unwrap();
+        ebu_unused.add_frames_f32(&data).unwrap();
+
+        // A gain of 0.0 behaves exactly like an unused channel
+        assert_eq!(
+            ebu.loudness_global().unwrap().to_bits(),
+            ebu_unused.loudness_global().unwrap().to_bits()
+        );
+        assert_eq!(
+            ebu.loudness_momentary().unwrap().to_bits(),
+            ebu_unused.loudness_momentary().unwrap().to_bits()
+        );
+
+        // Halving both remaining channels is the same as halving the input
+        let mut ebu_half = EbuR128Builder::new()
+            .channels(3)
+            .rate(48_000)
+            .mode(mode)
+            .channel_gains(&[0.5, 0.5, 0.0])
+            .build()
+            .unwrap();
+        ebu_half.add_frames_f32(&data).unwrap();
+        assert_float_eq!(
+            ebu_half.loudness_global().unwrap(),
+            ebu.loudness_global().unwrap() + 20.0 * f64::log10(0.5),
+            abs <= 0.000001
+        );
+
+        // Gains are applied in addition to the channel weighting
+        let mut ebu_surround = EbuR128Builder::new()
+            .channels(3)
+            .rate(48_000)
+            .mode(mode)
+            .channel_map(&[Channel::Left, Channel::LeftSurround, Channel::Unused])
+            .channel_gains(&[1.0, f64::sqrt(1.0 / 1.41), 2.0])
+            .build()
+            .unwrap();
+        ebu_surround.add_frames_f32(&data).unwrap();
+        assert_float_eq!(
+            ebu_surround.loudness_global().unwrap(),
             ebu.loudness_global().unwrap(),
-            -0.6826039914165554,
             abs <= 0.000001
         );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598268921,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715100236,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620008693,
-            abs <= 0.000001
+
+        assert_eq!(
+            ebu.set_channel_gains(&[1.0, 1.0]),
+            Err(Error::InvalidChannelIndex)
         );
-        assert_float_eq!(
-            ebu.loudness_range().unwrap(),
-            0.00006921150169403312,
-            abs <= 0.000001
+        assert_eq!(
+            ebu.set_channel_gains(&[1.0, f64::NAN, 1.0]),
+            Err(Error::InvalidMode)
         );
+        assert_eq!(ebu.channel_gains(), &[1.0, 1.0, 0.0]);
 
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        ebu.change_parameters(2, 48_000).unwrap();
+        assert_eq!(ebu.channel_gains(), &[1.0, 1.0]);
+    }
 
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
+    #[test]
+    fn not_enough_data_and_silence() {
+        let mode = Mode::I | Mode::S | Mode::LRA;
+
+        // Nothing measured yet
+        let mut ebu = EbuR128::new(2, 48_000, mode).unwrap();
+        ebu.enable_shortterm_max();
+        ebu.add_frames_f32(&[0.0; 4_000 * 2]).unwrap();
+        assert_eq!(ebu.loudness_global(), Err(Error::NotEnoughData));
+        assert_eq!(
+            EbuR128::loudness_global_multiple([&ebu, &ebu].iter().copied()),
+            Err(Error::NotEnoughData)
+        );
+        assert_eq!(ebu.loudness_momentary_max(), Err(Error::NotEnoughData));
+        assert_eq!(ebu.loudness_shortterm_max(), Err(Error::NotEnoughData));
+        assert_eq!(ebu.loudness_range(), Err(Error::NotEnoughData));
+        assert_eq!(
+            EbuR128::loudness_range_multiple([&ebu, &ebu].iter().copied()),
+            Err(Error::NotEnoughData)
+        );
+        assert_eq!(ebu.normalization_gain(-23.0), Err(Error::NotEnoughData));
+
+        // Gating blocks were measured but the first short term block needs 3s
+        ebu.add_frames_f32(&[0.0; 48_000 * 2]).unwrap();
+        assert_eq!(ebu.loudness_global(), Ok(-f64::INFINITY));
+        assert_eq!(ebu.loudness_momentary_max(), Ok(-f64::INFINITY));
+        assert_eq!(ebu.loudness_shortterm_max(), Ok(-f64::INFINITY));
+        assert_eq!(ebu.loudness_range(), Err(Error::NotEnoughData));
+
+        // Silence is a valid measurement that is infinitely quiet
+        ebu.add_frames_f32(&[0.0; 48_000 * 3 * 2]).unwrap();
+        assert_eq!(ebu.loudness_global(), Ok(-f64::INFINITY));
+        assert_eq!(
+            EbuR128::loudness_global_multiple([&ebu, &ebu].iter().copied()),
+            Ok(-f64::INFINITY)
+        );
+        assert_eq!(ebu.loudness_range(), Ok(0.0));
+        assert_eq!(
+            EbuR128::loudness_range_multiple([&ebu, &ebu].iter().copied()),
+            Ok(0.0)
+        );
+        // but no gain reaches a target loudness
+        assert_eq!(ebu.normalization_gain(-23.0), Err(Error::NotEnoughData));
+
+        // Combining with an instance that measured nothing yet only includes the silence
+        let empty = EbuR128::new(2, 48_000, mode).unwrap();
+        assert_eq!(
+            EbuR128::loudness_global_multiple([&ebu, &empty].iter().copied()),
+            Ok(-f64::INFINITY)
+        );
+        assert_eq!(
+            EbuR128::loudness_range_multiple([&ebu, &empty].iter().copied()),
+            Ok(0.0)
         );
 
-        assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.682603991416554,
-            abs <= 0.000001
+        ebu.reset();
+        assert_eq!(ebu.loudness_global(), Err(Error::NotEnoughData));
+        assert_eq!(ebu.loudness_range(), Err(Error::NotEnoughData));
+    }
+
+    #[test]
+    fn enable_mode() {
+        // 1kHz sine at -20 dBFS for 2s followed by 5s at -40 dBFS
+        let mut data = vec![0.0f32; 48_000 * 7 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let amplitude = if i < 48_000 * 2 { 0.1 } else { 0.01 };
+            let val = f32::sin(accumulator) * amplitude;
+            out[0] = val;
+            out[1] = val;
+            accumulator += step;
+        }
+        let (first, second) = data.split_at(48_000 * 2 * 2);
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        let mut reference = EbuR128::new(2, 48_000, Mode::M | Mode::I).unwrap();
+        ebu.add_frames_f32(first).unwrap();
+        reference.add_frames_f32(first).unwrap();
+
+        ebu.enable_mode(Mode::I | Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.mode(), Mode::M | Mode::I | Mode::TRUE_PEAK);
+        assert_eq!(ebu.loudness_global(), Err(Error::NotEnoughData));
+        assert_eq!(ebu.true_peak(0), Ok(0.0));
+
+        // Only the gating blocks completed after enabling are integrated
+        let mut blocks = Vec::new();
+        ebu.add_frames_f32(second).unwrap();
+        reference
+            .add_frames_with(second, |loudness| blocks.push(loudness))
+            .unwrap();
+
+        let energies = blocks
+            .iter()
+            .map(|l| 10.0f64.powf((l + 0.691) / 10.0))
+            .collect::<Vec<_>>();
+        let mean = |threshold: f64| {
+            let gated = energies
+                .iter()
+                .filter(|e| **e >= threshold)
+                .collect::<Vec<_>>();
+            gated.iter().copied().sum::<f64>() / gated.len() as f64
+        };
+        let relative = mean(10.0f64.powf((-70.0 + 0.691) / 10.0)) / 10.0;
+        let expected = 10.0 * f64::log10(mean(relative)) - 0.691;
+
+        assert_float_eq!(ebu.loudness_global().unwrap(), expected, abs <= 0.000001);
+        assert!(reference.loudness_global().unwrap() > expected + 1.0);
+        assert_float_eq!(ebu.true_peak(0).unwrap(), 0.01, abs <= 0.0001);
+
+        // Enabling the short term loudness enlarges the window
+        ebu.set_max_window(400).unwrap();
+        ebu.enable_mode(Mode::LRA).unwrap();
+        assert_eq!(ebu.max_window(), 3000);
+        assert_eq!(ebu.loudness_range(), Err(Error::NotEnoughData));
+        ebu.add_frames_f32(second).unwrap();
+        assert!(ebu.loudness_shortterm().unwrap().is_finite());
+        assert!(ebu.loudness_range().is_ok());
+
+        // Disabling a mode also disables the modes including it and discards its measurement
+        ebu.disable_mode(Mode::S | Mode::SAMPLE_PEAK).unwrap();
+        assert_eq!(ebu.mode(), Mode::M | Mode::I);
+        assert_eq!(ebu.loudness_range(), Err(Error::InvalidMode));
+        assert_eq!(ebu.true_peak(0), Err(Error::InvalidMode));
+        ebu.disable_mode(Mode::I).unwrap();
+        ebu.enable_mode(Mode::I).unwrap();
+        assert_eq!(ebu.loudness_global(), Err(Error::NotEnoughData));
+        assert_eq!(ebu.disable_mode(Mode::M), Err(Error::InvalidMode));
+        assert_eq!(ebu.mode(), Mode::M | Mode::I);
+
+        assert_eq!(
+            EbuR128::new(2, 768_000, Mode::M)
+                .unwrap()
+                .enable_mode(Mode::TRUE_PEAK),
+            Err(Error::UnsupportedRate)
         );
     }
 
     #[test]
-    fn sine_stereo_f64_planar_no_histogram() {
-        let mut data = vec![0.0f64; 48_000 * 5 * 2];
+    fn awkward_chunk_sizes() {
+        // 7.3s of a stereo sine with a changing amplitude, so that every block is different
+        let mut data = vec![0.0f32; 44_100 * 73 / 10 * 2];
         let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        let (fst, snd) = data.split_at_mut(48_000 * 5);
-        for (fst, snd) in Iterator::zip(fst.iter_mut(), snd.iter_mut()) {
-            let val = f32::sin(accumulator);
-            *fst = val as f64;
-            *snd = val as f64;
+        let step = 2.0 * std::f32::consts::PI * 997.0 / 44_100.0;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
+            let amplitude = 0.1 + 0.8 * (i % 30_011) as f32 / 30_011.0;
+            out[0] = f32::sin(accumulator) * amplitude;
+            out[1] = f32::sin(accumulator * 1.5) * amplitude * 0.5;
             accumulator += step;
         }
 
-        let mut ebu = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu.add_frames_planar_f64(&[fst, snd]).unwrap();
+        let mode = Mode::all() & !Mode::HISTOGRAM;
+        let mut ebu = EbuR128::new(2, 44_100, mode).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
 
-        assert_float_eq!(
-            ebu.loudness_global().unwrap(),
-            -0.6826039914165554,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_momentary().unwrap(),
-            -0.6813325598268921,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_shortterm().unwrap(),
-            -0.6827591715100236,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.loudness_window(1).unwrap(),
-            -0.8742956620008693,
-            abs <= 0.000001
+        // Prime number chunk sizes in frames that never align to the 100ms blocks, also with a
+        // chunk size that is smaller than the one used for splitting big inputs
+        for &chunks in &[
+            &[1usize, 7, 13, 97, 1_009, 4_801][..],
+            &[4_409][..],
+            &[9_001][..],
+        ] {
+            for &chunk_size in &[DEFAULT_CHUNK_SIZE, 1_000] {
+                let mut ebu_chunks = EbuR128::new(2, 44_100, mode).unwrap();
+                ebu_chunks.set_chunk_size(chunk_size).unwrap();
+
+                let mut start = 0;
+                for &frames in chunks.iter().cycle() {
+                    if start == data.len() {
+                        break;
+                    }
+                    let end = usize::min(start + frames * 2, data.len());
+                    ebu_chunks.add_frames_f32(&data[start..end]).unwrap();
+                    start = end;
+                }
+
+                assert_eq!(
+                    ebu_chunks.loudness_global().unwrap().to_bits(),
+                    ebu.loudness_global().unwrap().to_bits()
+                );
+                assert_eq!(
+                    ebu_chunks.loudness_shortterm().unwrap().to_bits(),
+                    ebu.loudness_shortterm().unwrap().to_bits()
+                );
+                assert_eq!(
+                    ebu_chunks.loudness_momentary().unwrap().to_bits(),
+                    ebu.loudness_momentary().unwrap().to_bits()
+                );
+                assert_eq!(
+                    ebu_chunks.loudness_range().unwrap().to_bits(),
+                    ebu.loudness_range().unwrap().to_bits()
+                );
+                assert_eq!(ebu_chunks.gating_block_count(), ebu.gating_block_count());
+                for channel in 0..2 {
+                    assert_eq!(
+                        ebu_chunks.sample_peak(channel).unwrap().to_bits(),
+                        ebu.sample_peak(channel).unwrap().to_bits()
+                    );
+                    assert_eq!(
+                        ebu_chunks.true_peak(channel).unwrap().to_bits(),
+                        ebu.true_peak(channel).unwrap().to_bits()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn is_current_block_gated() {
+        let sine = |amplitude: f32, frames: usize| {
+            let step = 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0;
+            (0..frames)
+                .map(|i| f32::sin(i as f32 * step) * amplitude)
+                .collect::<Vec<_>>()
+        };
+
+        let mut ebu = EbuR128::new(1, 48_000, Mode::M).unwrap();
+        assert_eq!(ebu.is_current_block_gated(), None);
+
+        // -20 dBFS, followed by -80 dBFS
+        ebu.add_frames_f32(&sine(0.1, 48_000)).unwrap();
+        assert_eq!(ebu.is_current_block_gated(), Some(false));
+        ebu.add_frames_f32(&sine(0.0001, 48_000)).unwrap();
+        assert_eq!(ebu.is_current_block_gated(), Some(true));
+        // A partial block doesn't change it
+        ebu.add_frames_f32(&sine(0.1, 2_400)).unwrap();
+        assert_eq!(ebu.is_current_block_gated(), Some(true));
+
+        // With a higher absolute gate the louder blocks are gated too
+        ebu.set_gating(-10.0, -10.0).unwrap();
+        ebu.add_frames_f32(&sine(0.1, 48_000)).unwrap();
+        assert_eq!(ebu.is_current_block_gated(), Some(true));
+        ebu.set_gating(-70.0, -10.0).unwrap();
+        assert_eq!(ebu.is_current_block_gated(), Some(false));
+
+        ebu.reset();
+        assert_eq!(ebu.is_current_block_gated(), None);
+    }
+
+    #[test]
+    fn add_frames_duration() {
+        use core::time::Duration;
+
+        let mut ebu = EbuR128::new(2, 44_100, Mode::I).unwrap();
+        let mut expected = EbuR128::new(2, 44_100, Mode::I).unwrap();
+        let data = (0..44_100 * 2)
+            .map(|i| f32::sin((i / 2) as f32 * 0.1) * 0.5)
+            .collect::<Vec<_>>();
+
+        ebu.add_frames_duration_f32(&data, Duration::from_secs(1))
+            .unwrap();
+        expected.add_frames_f32(&data).unwrap();
+        // 10ms are 441 frames, 1.01ms are 44.541 frames and rounded either way
+        ebu.add_frames_duration_f32(&data[..441 * 2], Duration::from_millis(10))
+            .unwrap();
+        ebu.add_frames_duration_f32(&data[..44 * 2], Duration::from_micros(1_010))
+            .unwrap();
+        ebu.add_frames_duration_f32(&data[..45 * 2], Duration::from_micros(1_010))
+            .unwrap();
+        expected.add_frames_f32(&data[..441 * 2]).unwrap();
+        expected.add_frames_f32(&data[..44 * 2]).unwrap();
+        expected.add_frames_f32(&data[..45 * 2]).unwrap();
+        assert_eq!(
+            ebu.loudness_global().unwrap().to_bits(),
+            expected.loudness_global().unwrap().to_bits()
+        );
+
+        // Mono frame count for stereo and off by one frame
+        for (frames, duration) in &[
+            (&data[..44_100], Duration::from_secs(1)),
+            (&data[..442 * 2], Duration::from_millis(10)),
+            (&data[..46 * 2], Duration::from_micros(1_010)),
+        ] {
+            assert_eq!(
+                ebu.add_frames_duration_f32(frames, *duration),
+                Err(Error::DurationMismatch)
+            );
+        }
+        // Partial frames are rejected like by all other add_frames functions
+        assert_eq!(
+            ebu.add_frames_duration_f32(&data[..441 * 2 + 1], Duration::from_millis(10)),
+            Err(Error::NoMem)
         );
-        assert_float_eq!(
-            ebu.loudness_range().unwrap(),
-            0.00006921150169403312,
-            abs <= 0.000001
+        assert_eq!(
+            ebu.loudness_global().unwrap().to_bits(),
+            expected.loudness_global().unwrap().to_bits()
         );
+    }
 
-        assert_float_eq!(ebu.sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(0).unwrap(), 1.0, abs <= 0.000001);
-        assert_float_eq!(ebu.prev_sample_peak(1).unwrap(), 1.0, abs <= 0.000001);
+    #[test]
+    fn immersive_layouts() {
+        // 7.1.4 with the signal in only one of the channels
+        let layout = [
+            Channel::Left,
+            Channel::Right,
+            Channel::Center,
+            Channel::Lfe,
+            Channel::LeftSurround,
+            Channel::RightSurround,
+            Channel::Mp135,
+            Channel::Mm135,
+            Channel::Up045,
+            Channel::Um045,
+            Channel::Up135,
+            Channel::Um135,
+        ];
+        let measure = |channel: usize| {
+            let mut data = vec![0.0f32; 48_000 * 2 * layout.len()];
+            let step = 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0;
+            for (i, frame) in data.chunks_exact_mut(layout.len()).enumerate() {
+                frame[channel] = f32::sin(i as f32 * step) * 0.1;
+            }
 
-        assert_float_eq!(
-            ebu.true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(0).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
-        assert_float_eq!(
-            ebu.prev_true_peak(1).unwrap(),
-            1.0008491277694702,
-            abs <= 0.000001
-        );
+            let mut ebu = EbuR128::new(layout.len() as u32, 48_000, Mode::I).unwrap();
+            ebu.set_channel_map(&layout).unwrap();
+            ebu.add_frames_f32(&data).unwrap();
+            ebu.loudness_global().unwrap()
+        };
+
+        let front = measure(0);
+        for channel in &[1, 2, 6, 7, 8, 9, 10, 11] {
+            assert_eq!(measure(*channel), front, "{:?}", layout[*channel]);
+        }
+        for channel in &[4, 5] {
+            assert_float_eq!(
+                measure(*channel) - front,
+                10.0 * f64::log10(1.41),
+                abs <= 0.0001
+            );
+        }
+        assert_eq!(measure(3), -f64::INFINITY);
+
+        // 22.2 with two LFE channels
+        let layout = [
+            Channel::Mp060,
+            Channel::Mm060,
+            Channel::Center,
+            Channel::Lfe,
+            Channel::Mp135,
+            Channel::Mm135,
+            Channel::Left,
+            Channel::Right,
+            Channel::Mp180,
+            Channel::Lfe,
+            Channel::Mp090,
+            Channel::Mm090,
+            Channel::Up045,
+            Channel::Um045,
+            Channel::Up000,
+            Channel::Tp000,
+            Channel::Up135,
+            Channel::Um135,
+            Channel::Up090,
+            Channel::Um090,
+            Channel::Up180,
+            Channel::Bp000,
+            Channel::Bp045,
+            Channel::Bm045,
+        ];
+        let mut data = vec![0.0f32; 48_000 * 2 * layout.len()];
+        let step = 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0;
+        for (i, frame) in data.chunks_exact_mut(layout.len()).enumerate() {
+            for out in frame {
+                *out = f32::sin(i as f32 * step) * 0.1;
+            }
+        }
+        let mut ebu = EbuR128::new(24, 48_000, Mode::I).unwrap();
+        ebu.set_channel_map(&layout).unwrap();
+        assert_eq!(ebu.channel_map(), &layout[..]);
+        ebu.add_frames_f32(&data).unwrap();
 
+        // 18 channels at 0dB and 4 at +1.5dB
         assert_float_eq!(
-            ebu.relative_threshold().unwrap(),
-            -10.682603991416554,
-            abs <= 0.000001
+            ebu.loudness_global().unwrap() - front,
+            10.0 * f64::log10(18.0 + 4.0 * 1.41),
+            abs <= 0.0001
         );
     }
 
     #[test]
-    fn sine_stereo_f32_multiple() {
-        let mut data = vec![0.0f32; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator);
-            out[0] = val;
-            out[1] = val;
-            accumulator += step;
+    fn loudness_global_subset() {
+        // Quiet start, loud center and right channels and a left channel at a different level
+        let mut data = vec![0.0f32; 48_000 * 3 * 5];
+        for (i, frame) in data.chunks_exact_mut(3).enumerate() {
+            let s = f32::sin(i as f32 * 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0);
+            let gain = if i < 48_000 { 0.0001 } else { 1.0 };
+            frame[0] = s * 0.05 * gain;
+            frame[1] = s * 0.5 * gain;
+            frame[2] = s * 0.3 * gain;
         }
+        let left = data.iter().step_by(3).copied().collect::<Vec<_>>();
+
+        for &mode in &[Mode::I, Mode::I | Mode::HISTOGRAM] {
+            let mut ebu = EbuR128Builder::new()
+                .channels(3)
+                .rate(48_000)
+                .mode(mode)
+                .channel_history(true)
+                .build()
+                .unwrap();
+            assert_eq!(ebu.loudness_global_subset(&[0]), Err(Error::NotEnoughData));
+            ebu.add_frames_f32(&data).unwrap();
 
-        let mut ebu1 = EbuR128::new(2, 48_000, Mode::all()).unwrap();
-        ebu1.add_frames_f32(&data).unwrap();
+            assert_eq!(
+                ebu.loudness_global_subset(&[0, 1, 2]).unwrap().to_bits(),
+                ebu.loudness_global().unwrap().to_bits()
+            );
+            assert_eq!(
+                ebu.loudness_global_subset(&[2, 0, 1, 0]).unwrap().to_bits(),
+                ebu.loudness_global().unwrap().to_bits()
+            );
 
-        let mut data = vec![0.0f32; 48_000 * 5 * 2];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 880.0 / 48_000.0;
-        for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator);
-            out[0] = 0.5 * val;
-            out[1] = 0.5 * val;
-            accumulator += step;
+            let mut ebu_left = EbuR128::new(1, 48_000, mode).unwrap();
+            ebu_left.add_frames_f32(&left).unwrap();
+            assert_float_eq!(
+                ebu.loudness_global_subset(&[0]).unwrap(),
+                ebu_left.loudness_global().unwrap(),
+                abs <= 0.000001
+            );
+            assert_eq!(ebu.loudness_global_subset(&[]), Ok(-f64::INFINITY));
+            assert_eq!(
+                ebu.loudness_global_subset(&[3]),
+                Err(Error::InvalidChannelIndex)
+            );
+
+            // Limiting the queue drops the same blocks for all channels
+            if !mode.contains(Mode::HISTOGRAM) {
+                ebu.set_max_history(2_000).unwrap();
+                assert_eq!(
+                    ebu.loudness_global_subset(&[0, 1, 2]).unwrap().to_bits(),
+                    ebu.loudness_global().unwrap().to_bits()
+                );
+            }
+
+            ebu.set_channel_history(false);
+            assert_eq!(ebu.loudness_global_subset(&[0]), Err(Error::InvalidMode));
         }
 
-        let mut ebu2 = EbuR128::new(2, 48_000, Mode::all()).unwrap();
-        ebu2.add_frames_f32(&data).unwrap();
+        let mut ebu = EbuR128::new(3, 48_000, Mode::M).unwrap();
+        ebu.set_channel_history(true);
+        ebu.add_frames_f32(&data).unwrap();
+        assert_eq!(ebu.loudness_global_subset(&[0]), Err(Error::InvalidMode));
+    }
 
-        assert_float_eq!(
-            EbuR128::loudness_global_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
-            -2.603757953612454,
-            abs <= 0.000001
+    #[test]
+    fn has_enough_data() {
+        let data = vec![0.5f32; 48_000 * 2];
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::LRA).unwrap();
+        assert!(!ebu.has_momentary());
+        assert!(!ebu.has_shortterm());
+        assert!(!ebu.has_integrated());
+        assert_eq!(ebu.loudness_global(), Err(Error::NotEnoughData));
+        assert!(!ebu.has_range());
+        assert_eq!(ebu.loudness_range(), Err(Error::NotEnoughData));
+
+        ebu.add_frames_f32(&data[..19_199 * 2]).unwrap();
+        assert!(!ebu.has_momentary());
+        assert!(!ebu.has_integrated());
+        ebu.add_frames_f32(&data[..2]).unwrap();
+        assert!(ebu.has_momentary());
+        assert!(ebu.has_integrated());
+        assert!(ebu.loudness_global().is_ok());
+        assert!(!ebu.has_shortterm());
+
+        ebu.add_frames_f32(&data).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        assert!(!ebu.has_shortterm());
+        assert!(!ebu.has_range());
+        ebu.add_frames_f32(&data[..(48_000 * 3 / 5) * 2]).unwrap();
+        assert!(ebu.has_shortterm());
+        assert!(ebu.has_range());
+        assert!(ebu.loudness_range().is_ok());
+
+        // Shrinking the window keeps only the most recent audio
+        ebu.disable_mode(Mode::S).unwrap();
+        ebu.set_max_window(400).unwrap();
+        assert!(ebu.has_momentary());
+        assert!(!ebu.has_shortterm());
+        assert!(!ebu.has_range());
+        assert_eq!(ebu.loudness_shortterm(), Err(Error::InvalidMode));
+
+        ebu.reset();
+        assert!(!ebu.has_momentary());
+        assert!(!ebu.has_integrated());
+
+        // Without the modes there's never enough data
+        let mut ebu = EbuR128::new(2, 48_000, Mode::M).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        assert!(ebu.has_momentary());
+        assert!(!ebu.has_integrated());
+        assert!(!ebu.has_shortterm());
+        assert!(!ebu.has_range());
+    }
+
+    #[test]
+    fn frames_processed() {
+        use core::time::Duration;
+
+        let data = vec![0.25f32; 48_000 * 2];
+        let planar = vec![0.25f32; 48_000];
+
+        let mut ebu = EbuR128::new(2, 44_100, Mode::all()).unwrap();
+        assert_eq!(ebu.frames_processed(), 0);
+        assert_eq!(ebu.duration_processed(), Duration::from_secs(0));
+
+        ebu.add_frames_f32(&data[..1_000 * 2]).unwrap();
+        ebu.add_frames_i16(&[0; 2 * 2]).unwrap();
+        ebu.add_frames_planar_f32(&[&planar[..30_000], &planar[..30_000]])
+            .unwrap();
+        ebu.add_frames(&data[..13_096 * 2]).unwrap();
+        ebu.seed_frames_f32(&data).unwrap();
+        ebu.add_frames_filtered(&data[..2], &mut [0.0; 2]).unwrap();
+        ebu.add_frames_with(&data[..2 * 2], |_| ()).unwrap();
+        assert_eq!(ebu.frames_processed(), 44_101);
+        assert_eq!(
+            ebu.duration_processed(),
+            Duration::from_secs(1) + Duration::from_nanos(22_675)
         );
 
-        assert_float_eq!(
-            EbuR128::loudness_range_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
-            5.599999999999995,
-            abs <= 0.000001
+        ebu.reset();
+        assert_eq!(ebu.frames_processed(), 0);
+
+        // Frames that are rejected are not counted
+        ebu.set_non_finite_handling(NonFiniteHandling::Reject);
+        assert_eq!(
+            ebu.add_frames_f32(&[0.0, f32::NAN]),
+            Err(Error::NonFiniteSample)
         );
+        assert_eq!(ebu.frames_processed(), 0);
     }
 
     #[test]
-    fn sine_stereo_f32_no_histogram_multiple() {
-        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+    fn true_peak_quality() {
+        let mut data = vec![0.0f32; 48_000 * 2];
         let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        let step = 2.0 * std::f32::consts::PI * 13_000.0 / 48_000.0;
         for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator);
+            let val = f32::sin(accumulator) * 0.5;
             out[0] = val;
-            out[1] = val;
+            out[1] = -val;
             accumulator += step;
         }
 
-        let mut ebu1 = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu1.add_frames_f32(&data).unwrap();
+        let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        let standard = ebu.true_peak(0).unwrap();
+
+        for &quality in &[
+            TruePeakQuality::Standard,
+            TruePeakQuality::Fast,
+            TruePeakQuality::Accurate,
+        ] {
+            let mut ebu = EbuR128Builder::new()
+                .channels(2)
+                .rate(48_000)
+                .mode(Mode::TRUE_PEAK)
+                .true_peak_quality(quality)
+                .build()
+                .unwrap();
+            assert_eq!(ebu.true_peak_quality(), quality);
+            ebu.add_frames_f32(&data).unwrap();
+
+            let tp = ebu.true_peak(0).unwrap();
+            if quality == TruePeakQuality::Standard {
+                assert_eq!(tp.to_bits(), standard.to_bits());
+            } else {
+                assert_float_eq!(tp, 0.5, abs <= 0.05);
+            }
+        }
+
+        // Changing the quality keeps the peaks measured so far and is remembered while the true
+        // peak is not measured
+        ebu.set_true_peak_quality(TruePeakQuality::Fast).unwrap();
+        assert_eq!(ebu.true_peak(0).unwrap().to_bits(), standard.to_bits());
+        ebu.set_mode(Mode::I).unwrap();
+        ebu.set_true_peak_quality(TruePeakQuality::Accurate)
+            .unwrap();
+        ebu.set_mode(Mode::I | Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.true_peak_quality(), TruePeakQuality::Accurate);
+        ebu.add_frames_f32(&data).unwrap();
+        assert_float_eq!(ebu.true_peak(1).unwrap(), 0.5, abs <= 0.05);
+    }
 
+    #[test]
+    fn relative_loudness() {
         let mut data = vec![0.0f32; 48_000 * 5 * 2];
         let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 880.0 / 48_000.0;
+        let step = 2.0 * std::f32::consts::PI * 1_000.0 / 48_000.0;
         for out in data.chunks_exact_mut(2) {
-            let val = f32::sin(accumulator);
-            out[0] = 0.5 * val;
-            out[1] = 0.5 * val;
+            let val = f32::sin(accumulator) * 0.1;
+            out[0] = val;
+            out[1] = val;
             accumulator += step;
         }
 
-        let mut ebu2 = EbuR128::new(2, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu2.add_frames_f32(&data).unwrap();
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::S).unwrap();
+        assert_eq!(ebu.reference_loudness(), -23.0);
+        assert_eq!(ebu.loudness_global_lu(), Err(Error::NotEnoughData));
+        ebu.add_frames_f32(&data).unwrap();
 
-        assert_float_eq!(
-            EbuR128::loudness_global_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
-            -2.6302830567858275,
-            abs <= 0.000001
+        let global = ebu.loudness_global().unwrap();
+        let momentary = ebu.loudness_momentary().unwrap();
+        let shortterm = ebu.loudness_shortterm().unwrap();
+        assert_eq!(ebu.loudness_global_relative(global).unwrap(), 0.0);
+        assert_eq!(ebu.loudness_momentary_relative(momentary).unwrap(), 0.0);
+        assert_eq!(ebu.loudness_shortterm_relative(shortterm).unwrap(), 0.0);
+        assert_eq!(ebu.loudness_global_lu().unwrap(), global + 23.0);
+        assert_eq!(ebu.loudness_shortterm_lu().unwrap(), shortterm + 23.0);
+
+        // At the configured reference level the relative loudness is 0 LU
+        ebu.set_reference_loudness(global).unwrap();
+        assert_eq!(ebu.loudness_global_lu().unwrap(), 0.0);
+        assert_eq!(
+            ebu.set_reference_loudness(f64::NAN),
+            Err(Error::InvalidMode)
+        );
+        assert_eq!(ebu.reference_loudness(), global);
+
+        let mut ebu = EbuR128Builder::new()
+            .channels(2)
+            .rate(48_000)
+            .mode(Mode::I | Mode::S)
+            .reference_loudness(momentary)
+            .build()
+            .unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        assert_eq!(ebu.loudness_momentary_lu().unwrap(), 0.0);
+        assert_eq!(
+            ebu.loudness_global_relative(-18.0).unwrap(),
+            ebu.loudness_global().unwrap() + 18.0
         );
 
-        assert_float_eq!(
-            EbuR128::loudness_range_multiple([&ebu1, &ebu2].iter().copied()).unwrap(),
-            5.571749801957784,
-            abs <= 0.000001
-        );
+        // Silence stays infinitely quiet relative to any reference
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I).unwrap();
+        ebu.add_frames_f32(&[0.0; 48_000 * 2]).unwrap();
+        assert_eq!(ebu.loudness_global_lu().unwrap(), -f64::INFINITY);
+        assert_eq!(ebu.loudness_momentary_lu().unwrap(), -f64::INFINITY);
     }
 
     #[test]
-    fn chunks_queue_with_true_peak() {
-        let mut data = vec![0.0f32; 48_000 * 3];
+    fn block_energies() {
+        let mut data = vec![0.0f32; 44_100 * 10 * 2];
         let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(1) {
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 44_100.0;
+        for (i, out) in data.chunks_exact_mut(2).enumerate() {
             let val = f32::sin(accumulator);
-            out[0] = val;
+            let amplitude = match (i / 4_410) % 7 {
+                0..=3 => 0.0,
+                4 => 0.01,
+                _ => 0.5,
+            };
+            out[0] = amplitude * val;
+            out[1] = amplitude * val * 0.5;
             accumulator += step;
         }
 
-        let mut ebu1 = EbuR128::new(1, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-        ebu1.add_frames_f32(&data).unwrap();
+        let mut ebu = EbuR128::new(2, 44_100, Mode::I).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+        assert!(ebu.block_energies().is_empty());
+        assert!(ebu.block_timestamps().is_empty());
 
-        let mut ebu_chunks = Vec::new();
-        for i in 0..3usize {
-            let mut ebu_chunk = EbuR128::new(1, 48_000, Mode::all() & !Mode::HISTOGRAM).unwrap();
-            let start_index = std::cmp::max(i as isize * 48_000, 0) as usize;
-            let stop_index = std::cmp::min(start_index + 48_000 + (48_00 * 3), data.len());
-            if start_index > 0 {
-                ebu_chunk
-                    .seed_frames_f32(&data[start_index - 48_00..start_index])
-                    .unwrap();
-            }
-            ebu_chunk
-                .add_frames_f32(&data[start_index..stop_index])
-                .unwrap();
-            ebu_chunks.push(ebu_chunk);
+        let mut ebu = EbuR128::new(2, 44_100, Mode::I).unwrap();
+        ebu.enable_block_energies();
+        for chunk in data.chunks(2 * 1_234) {
+            ebu.add_frames_f32(chunk).unwrap();
+        }
+        let energies = ebu.block_energies();
+        assert_eq!(energies.len(), 97);
+        assert!(energies.contains(&0.0));
+        for (i, timestamp) in ebu.block_timestamps().iter().enumerate() {
+            assert_eq!(*timestamp, 17_640 + i as u64 * 4_410);
         }
 
-        assert_float_eq!(
-            ebu1.sample_peak(0).unwrap(),
-            f64_max(ebu_chunks.iter().map(|meter| meter.sample_peak(0).unwrap())).unwrap(),
-            abs <= 0.000001
+        // Gating the recorded blocks manually gives the integrated loudness
+        let mean = |energies: &mut dyn Iterator<Item = f64>| {
+            let (sum, count) = energies.fold((0.0, 0), |(sum, count), e| (sum + e, count + 1));
+            sum / count as f64
+        };
+        let absolute = f64::powf(10.0, (-70.0 + 0.691) / 10.0);
+        let relative = mean(&mut energies.iter().copied().filter(|e| *e >= absolute)) / 10.0;
+        let gated = mean(
+            &mut energies
+                .iter()
+                .copied()
+                .filter(|e| *e >= absolute && *e >= relative),
         );
-
         assert_float_eq!(
-            ebu1.true_peak(0).unwrap(),
-            f64_max(ebu_chunks.iter().map(|meter| meter.true_peak(0).unwrap())).unwrap(),
+            energy_to_loudness(gated),
+            ebu.loudness_global().unwrap(),
             abs <= 0.000001
         );
 
-        assert_float_eq!(
-            ebu1.loudness_global().unwrap(),
-            EbuR128::loudness_global_multiple(ebu_chunks.iter()).unwrap(),
-            abs <= 0.000001
-        );
+        ebu.reset();
+        assert!(ebu.block_energies().is_empty());
+        assert!(ebu.block_timestamps().is_empty());
+        ebu.add_frames_f32(&data[..2 * 17_640]).unwrap();
+        assert_eq!(ebu.block_timestamps(), &[17_640]);
     }
 
     #[test]
-    fn chunks_histogram_with_true_peak() {
-        let mut data = vec![0.0f32; 48_000 * 3];
-        let mut accumulator = 0.0;
-        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
-        for out in data.chunks_exact_mut(1) {
-            let val = f32::sin(accumulator);
-            out[0] = val;
-            accumulator += step;
+    fn true_peak_overs() {
+        // Specialized, generic and no interpolation
+        for &(channels, rate) in &[(2, 48_000), (3, 48_000), (2, 96_000), (2, 192_000)] {
+            let mut clean = vec![0.0f32; rate as usize * channels as usize];
+            let mut accumulator = 0.0;
+            let step = 2.0 * std::f32::consts::PI * 997.0 / rate as f32;
+            for frame in clean.chunks_exact_mut(channels as usize) {
+                let val = f32::sin(accumulator);
+                for out in frame {
+                    *out = 0.9 * val;
+                }
+                accumulator += step;
+            }
+            // Hard clipping a louder sine gives inter-sample overs
+            let clipped = clean
+                .iter()
+                .map(|s| (3.0 * s).clamp(-1.0, 1.0))
+                .collect::<Vec<_>>();
+
+            let mut ebu = EbuR128::new(channels, rate, Mode::TRUE_PEAK).unwrap();
+            ebu.add_frames_f32(&clipped).unwrap();
+            assert_eq!(ebu.true_peak_overs(0), Ok(0));
+
+            // Without ringing of the interpolator from the clipped signal
+            ebu.reset();
+            ebu.enable_true_peak_overs();
+            assert_eq!(ebu.true_peak_overs_threshold(), 1.0);
+            ebu.add_frames_f32(&clean).unwrap();
+            for c in 0..channels {
+                assert_eq!(ebu.true_peak_overs(c), Ok(0));
+            }
+
+            ebu.add_frames_f32(&clipped).unwrap();
+            let overs = ebu.true_peak_overs(0).unwrap();
+            for c in 0..channels {
+                assert_eq!(ebu.true_peak_overs(c), Ok(overs));
+            }
+            if rate < 192_000 {
+                // The clipped samples themselves are not above 0 dBTP
+                assert!(overs > 0, "{} channels at {}Hz", channels, rate);
+            } else {
+                assert_eq!(overs, 0);
+            }
+
+            // The same overs are counted while getting the oversampled signal
+            let mut oversampled_ebu = EbuR128::new(channels, rate, Mode::TRUE_PEAK).unwrap();
+            oversampled_ebu.enable_true_peak_overs();
+            let stride = rate as usize * oversampled_ebu.true_peak_oversampling_factor().unwrap();
+            let mut oversampled = vec![0.0f32; channels as usize * stride];
+            oversampled_ebu
+                .add_frames_oversampled(&clean, &mut oversampled, stride)
+                .unwrap();
+            oversampled_ebu
+                .add_frames_oversampled(&clipped, &mut oversampled, stride)
+                .unwrap();
+            assert_eq!(oversampled_ebu.true_peak_overs(0), Ok(overs));
+
+            // A lower threshold also counts the samples of the clean signal
+            ebu.reset_peaks();
+            assert_eq!(ebu.true_peak_overs(0), Ok(0));
+            ebu.set_true_peak_overs_threshold(0.5).unwrap();
+            ebu.add_frames_f32(&clean).unwrap();
+            assert!(ebu.true_peak_overs(0).unwrap() > 0);
+
+            ebu.reset();
+            assert_eq!(ebu.true_peak_overs(0), Ok(0));
+            ebu.add_frames_f32(&clean[..channels as usize * 100])
+                .unwrap();
+            assert!(ebu.true_peak_overs(0).unwrap() > 0);
         }
 
-        let mut ebu1 = EbuR128::new(1, 48_000, Mode::all() | Mode::HISTOGRAM).unwrap();
-        ebu1.add_frames_f32(&data).unwrap();
+        let mut ebu = EbuR128Builder::new()
+            .channels(2)
+            .rate(48_000)
+            .mode(Mode::TRUE_PEAK)
+            .true_peak_overs_threshold(0.5)
+            .build()
+            .unwrap();
+        assert_eq!(ebu.true_peak_overs_threshold(), 0.5);
+        assert_eq!(ebu.true_peak_overs(2), Err(Error::InvalidChannelIndex));
+        assert_eq!(
+            ebu.set_true_peak_overs_threshold(f64::NAN),
+            Err(Error::InvalidMode)
+        );
+        assert_eq!(
+            ebu.set_true_peak_overs_threshold(-1.0),
+            Err(Error::InvalidMode)
+        );
+        assert_eq!(ebu.true_peak_overs_threshold(), 0.5);
+
+        ebu.disable_mode(Mode::TRUE_PEAK).unwrap();
+        assert_eq!(ebu.true_peak_overs(0), Err(Error::InvalidMode));
+    }
 
-        let mut ebu_chunks = Vec::new();
-        for i in 0..3usize {
-            let mut ebu_chunk =
-                EbuR128::new(1, 48_000, Mode::all() | Mode::HISTOGRAM & !Mode::HISTOGRAM).unwrap();
-            let start_index = std::cmp::max(i as isize * 48_000, 0) as usize;
-            let stop_index = std::cmp::min(start_index + 48_000 + (48_00 * 3), data.len());
-            if start_index > 0 {
-                ebu_chunk
-                    .seed_frames_f32(&data[start_index - 48_00..start_index])
-                    .unwrap();
+    #[test]
+    fn loudness_global_active() {
+        let rate = 48_000;
+        let sine = |seconds: usize, amplitude: f32| {
+            let mut data = vec![0.0f32; rate * seconds * 2];
+            let step = 2.0 * std::f32::consts::PI * 1_000.0 / rate as f32;
+            for (i, frame) in data.chunks_exact_mut(2).enumerate() {
+                let val = amplitude * f32::sin(i as f32 * step);
+                frame[0] = val;
+                frame[1] = val;
             }
-            ebu_chunk
-                .add_frames_f32(&data[start_index..stop_index])
-                .unwrap();
-            ebu_chunks.push(ebu_chunk);
+            data
+        };
+        let programme = sine(10, 0.1);
+        // Room tone at about -30 LUFS, above the relative gate of the padded programme
+        let noise = sine(40, 0.03);
+        let silence = vec![0.0f32; rate * 10 * 2];
+
+        let mut ebu = EbuR128::new(2, rate as u32, Mode::I).unwrap();
+        assert_eq!(ebu.loudness_global_active(-30.0), Err(Error::NotEnoughData));
+        ebu.add_frames_f32(&programme).unwrap();
+        let unpadded = ebu.loudness_global().unwrap();
+        assert_float_eq!(unpadded, -20.0, abs <= 0.01);
+
+        // Only the blocks at the borders of the programme are left in both cases
+        for padding in &[&silence, &noise] {
+            let mut ebu = EbuR128::new(2, rate as u32, Mode::I).unwrap();
+            ebu.add_frames_f32(padding).unwrap();
+            ebu.add_frames_f32(&programme).unwrap();
+            ebu.add_frames_f32(padding).unwrap();
+
+            assert_float_eq!(
+                ebu.loudness_global_active(-25.0).unwrap(),
+                unpadded,
+                abs <= 0.2
+            );
+            assert_eq!(
+                ebu.loudness_global_active(-80.0).unwrap(),
+                ebu.loudness_global().unwrap()
+            );
+            assert_eq!(ebu.loudness_global_active(0.0).unwrap(), -f64::INFINITY);
         }
 
-        assert_float_eq!(
-            ebu1.sample_peak(0).unwrap(),
-            f64_max(ebu_chunks.iter().map(|meter| meter.sample_peak(0).unwrap())).unwrap(),
-            abs <= 0.000001
+        let mut ebu_noise = EbuR128::new(2, rate as u32, Mode::I).unwrap();
+        ebu_noise.add_frames_f32(&noise).unwrap();
+        ebu_noise.add_frames_f32(&programme).unwrap();
+        ebu_noise.add_frames_f32(&noise).unwrap();
+        assert!(ebu_noise.loudness_global().unwrap() < unpadded - 5.0);
+        assert_eq!(
+            ebu_noise.loudness_global_active(f64::NAN),
+            Err(Error::InvalidMode)
         );
 
-        assert_float_eq!(
-            ebu1.true_peak(0).unwrap(),
-            f64_max(ebu_chunks.iter().map(|meter| meter.true_peak(0).unwrap())).unwrap(),
-            abs <= 0.000001
-        );
+        let ebu = EbuR128::new(2, rate as u32, Mode::M).unwrap();
+        assert_eq!(ebu.loudness_global_active(-30.0), Err(Error::InvalidMode));
+    }
 
-        assert_float_eq!(
-            ebu1.loudness_global().unwrap(),
-            EbuR128::loudness_global_multiple(ebu_chunks.iter()).unwrap(),
-            abs <= 0.000001
-        );
+    #[test]
+    fn true_peak_high_rates() {
+        for rate in &[192_000, 352_800, 384_000] {
+            let mut data = vec![0.0f32; *rate as usize * 2];
+            let mut accumulator = 0.0;
+            let step = 2.0 * std::f32::consts::PI * 440.0 / *rate as f32;
+            for out in data.chunks_exact_mut(2) {
+                let val = f32::sin(accumulator);
+                out[0] = 0.5 * val;
+                out[1] = val;
+                accumulator += step;
+            }
+
+            let mut ebu = EbuR128::new(2, *rate, Mode::TRUE_PEAK).unwrap();
+            ebu.add_frames_f32(&data).unwrap();
+
+            for c in 0..2 {
+                assert_float_eq!(
+                    ebu.true_peak(c).unwrap(),
+                    ebu.sample_peak(c).unwrap(),
+                    abs <= 0.000001
+                );
+            }
+            assert_float_eq!(ebu.true_peak(0).unwrap(), 0.5, abs <= 0.000001);
+            assert_float_eq!(ebu.true_peak(1).unwrap(), 1.0, abs <= 0.000001);
+        }
+    }
+
+    #[test]
+    fn true_peak_unsupported_rate() {
+        assert_eq!(
+            EbuR128::new(2, 768_000, Mode::TRUE_PEAK).unwrap_err(),
+            Error::UnsupportedRate
+        );
+        assert!(EbuR128::new(2, 768_000, Mode::SAMPLE_PEAK).is_ok());
+        assert!(EbuR128::new(2, 384_000, Mode::TRUE_PEAK).is_ok());
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::TRUE_PEAK).unwrap();
+        assert_eq!(
+            ebu.change_parameters(2, 768_000).unwrap_err(),
+            Error::UnsupportedRate
+        );
+        assert_eq!(ebu.rate(), 48_000);
+        ebu.add_frames_f32(&[0.5, -0.5]).unwrap();
+        assert_float_eq!(ebu.true_peak(0).unwrap(), 0.5, abs <= 0.000001);
     }
 
     #[cfg(feature = "c-tests")]
     fn compare_results(ebu: &EbuR128, ebu_c: &ebur128_c::EbuR128, channels: u32) {
-        assert_float_eq!(
-            ebu.loudness_global().unwrap(),
+        // The energy of the gating blocks is summed up in a different order than in the C
+        // implementation with the `fast-energy-sum` feature
+        #[cfg(all(feature = "fast-energy-sum", not(feature = "deterministic")))]
+        const LOUDNESS_TOLERANCE: f64 = 0.000000001;
+        #[cfg(any(not(feature = "fast-energy-sum"), feature = "deterministic"))]
+        const LOUDNESS_TOLERANCE: f64 = crate::tests::C_ABS_TOLERANCE;
+
+        // The C implementation returns -HUGE_VAL instead of an error
+        assert_float_eq!(
+            match ebu.loudness_global() {
+                Err(Error::NotEnoughData) => -f64::INFINITY,
+                res => res.unwrap(),
+            },
             ebu_c.loudness_global().unwrap(),
-            ulps <= 2
+            ulps <= 2,
+            abs <= LOUDNESS_TOLERANCE
         );
         assert_float_eq!(
             ebu.loudness_momentary().unwrap(),
             ebu_c.loudness_momentary().unwrap(),
-            ulps <= 2
+            ulps <= 2,
+            abs <= LOUDNESS_TOLERANCE
         );
         assert_float_eq!(
             ebu.loudness_shortterm().unwrap(),
             ebu_c.loudness_shortterm().unwrap(),
-            ulps <= 2
+            ulps <= 2,
+            abs <= LOUDNESS_TOLERANCE
         );
         assert_float_eq!(
             ebu.loudness_window(1).unwrap(),
             ebu_c.loudness_window(1).unwrap(),
-            ulps <= 2
+            ulps <= 2,
+            abs <= LOUDNESS_TOLERANCE
         );
+        // The C implementation returns 0 instead of an error
         assert_float_eq!(
-            ebu.loudness_range().unwrap(),
+            match ebu.loudness_range() {
+                Err(Error::NotEnoughData) => 0.0,
+                res => res.unwrap(),
+            },
             ebu_c.loudness_range().unwrap(),
-            ulps <= 2
+            ulps <= 2,
+            abs <= LOUDNESS_TOLERANCE
         );
 
         for c in 0..channels {
@@ -2210,7 +10522,8 @@ mod tests {
         assert_float_eq!(
             ebu.relative_threshold().unwrap(),
             ebu_c.relative_threshold().unwrap(),
-            ulps <= 2
+            ulps <= 2,
+            abs <= LOUDNESS_TOLERANCE
         );
     }
 
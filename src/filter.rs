@@ -19,20 +19,200 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-use std::fmt;
-
-use crate::ebur128::Channel;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::fmt;
+use core::ops;
+
+use crate::ebur128::{Channel, Precision, Weighting};
+use crate::math;
 use crate::utils::Sample;
 
+/// Coefficients of a biquad (numerator, denominator).
+type BiquadCoefficients = ([f64; 3], [f64; 3]);
+
+/// Coefficients of a biquad of the single precision filter (numerator, denominator). The
+/// denominator is stored as the difference to `(1 - z^-1)^2` without the leading 1.
+type Section = ([f32; 3], [f32; 2]);
+
+/// Coefficients of a biquad of the fixed point filter (numerator, denominator, fractional bits
+/// of the denominator). The denominator is stored like for `Section`, and the numerator with
+/// `FIXED_FRACTIONAL_BITS` fractional bits. The denominator is very small for high sample rates
+/// and uses as many fractional bits as fit.
+type FixedSection = ([i32; 3], [i32; 2], u32);
+
+/// Number of fractional bits of the fixed point filter coefficients and filtered samples. The
+/// remaining two integer bits leave headroom for the gain of the frequency weighting above full
+/// scale and for coefficients up to 4.
+const FIXED_FRACTIONAL_BITS: u32 = 29;
+
+/// Number of samples whose squares are summed up in 64 bit integers before converting the partial
+/// sum to `f64` in `sum_of_squares_fixed()`.
+const FIXED_SUM_CHUNK: usize = 1 << 16;
+
+/// Scale of the sums of `sum_of_squares_fixed()`.
+const FIXED_SUM_SCALE: f64 = 1.0 / (1u64 << (2 * FIXED_FRACTIONAL_BITS - 16)) as f64;
+
+/// Numeric type of the frequency weighted audio data.
+pub trait Float: Copy + Default + ops::Mul<Output = Self> + ops::AddAssign {
+    fn to_f64(self) -> f64;
+
+    fn from_f64(value: f64) -> Self;
+
+    /// Sum of the squares of all samples of `first` and `second`.
+    #[inline]
+    fn energy(first: &[Self], second: &[Self]) -> f64 {
+        sum_of_squares(first, second).to_f64()
+    }
+
+    /// Frequency weight the channels of `src` with the filter for this precision and write the
+    /// filtered samples to `dest`.
+    fn filter<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        filter: &mut Filter,
+        src: &S,
+        dest: &mut [Self],
+        dest_index: usize,
+        channel_map: &[Channel],
+        flush: bool,
+    );
+}
+
+impl Float for f64 {
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    #[inline(always)]
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn filter<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        filter: &mut Filter,
+        src: &S,
+        dest: &mut [Self],
+        dest_index: usize,
+        channel_map: &[Channel],
+        flush: bool,
+    ) {
+        filter.filter_direct(src, dest, dest_index, channel_map, flush)
+    }
+}
+
+impl Float for f32 {
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    #[inline(always)]
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    fn filter<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        filter: &mut Filter,
+        src: &S,
+        dest: &mut [Self],
+        dest_index: usize,
+        channel_map: &[Channel],
+        flush: bool,
+    ) {
+        filter.filter_cascade(src, dest, dest_index, channel_map, flush)
+    }
+}
+
+/// Convert `value` to fixed point with `bits` fractional bits, rounded to the nearest value and
+/// saturated at the limits.
+fn to_fixed(value: f64, bits: u32) -> i32 {
+    let value = value * (1u64 << bits) as f64;
+    // `as` saturates
+    if value >= 0.0 {
+        (value + 0.5) as i32
+    } else {
+        (value - 0.5) as i32
+    }
+}
+
+impl Float for i32 {
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self as f64 / (1u32 << FIXED_FRACTIONAL_BITS) as f64
+    }
+
+    #[inline(always)]
+    fn from_f64(value: f64) -> Self {
+        to_fixed(value, FIXED_FRACTIONAL_BITS)
+    }
+
+    #[inline]
+    fn energy(first: &[Self], second: &[Self]) -> f64 {
+        sum_of_squares_fixed(first) + sum_of_squares_fixed(second)
+    }
+
+    fn filter<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        filter: &mut Filter,
+        src: &S,
+        dest: &mut [Self],
+        dest_index: usize,
+        channel_map: &[Channel],
+        _flush: bool,
+    ) {
+        filter.filter_fixed(src, dest, dest_index, channel_map)
+    }
+}
+
+/// Frequency weighting in single precision as a cascade of biquads.
+///
+/// The poles of the combined filter are very close to each other, which makes it numerically
+/// unusable in single precision: the response for low frequencies is off by several dB. Applying
+/// the second order sections one after another is accurate enough.
+#[derive(Debug, Clone, PartialEq)]
+struct Cascade {
+    /// Coefficients of the biquads.
+    sections: Box<[Section]>,
+    /// One state per biquad and channel, all biquads of a channel after each other.
+    state: Box<[[f32; 4]]>,
+}
+
+/// Frequency weighting in fixed point as a cascade of biquads.
+///
+/// Each biquad is normalized to a gain of 1 at 1kHz, which keeps the coefficients and the
+/// intermediate signals in a similar range. The overall gain is applied in the first biquad.
+#[derive(Debug, Clone, PartialEq)]
+struct FixedCascade {
+    /// Coefficients of the biquads.
+    sections: Box<[FixedSection]>,
+    /// One state per biquad and channel, all biquads of a channel after each other. The previous
+    /// two inputs and outputs followed by the rounding error of the previous output.
+    state: Box<[[i32; 5]]>,
+}
+
 /// BS.1770 filter and optional sample/true peak measurement context.
+#[derive(Clone)]
 pub struct Filter {
+    rate: u32,
     channels: u32,
+    /// Selected frequency weighting.
+    weighting: Weighting,
     /// BS.1770 filter coefficients (numerator).
     b: [f64; 5],
     /// BS.1770 filter coefficients (denominator).
     a: [f64; 5],
     /// One filter state per channel.
     filter_state: Box<[[f64; 5]]>,
+    /// Coefficients of the additional biquad after the main filter (numerator, denominator).
+    /// Only needed for the 6th order A-weighting.
+    biquad: Option<BiquadCoefficients>,
+    /// One biquad state per channel if the additional biquad is used.
+    biquad_state: Box<[[f64; 3]]>,
+    /// Single precision filter used instead of the above with `Precision::F32`.
+    cascade: Option<Cascade>,
+    /// Fixed point filter used instead of the above with `Precision::Fixed`.
+    fixed: Option<FixedCascade>,
 
     /// Whether to measure sample peak.
     calculate_sample_peak: bool,
@@ -43,32 +223,82 @@ pub struct Filter {
     tp: Option<crate::true_peak::TruePeak>,
     /// Previously measured true peak.
     true_peak: Box<[f64]>,
+    /// Threshold for counting the overs of the true peak measurement, if enabled.
+    overs_threshold: Option<f32>,
+    /// Previously counted overs.
+    true_peak_overs: Box<[u64]>,
 }
 
 impl fmt::Debug for Filter {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Filter")
+            .field("rate", &self.rate)
             .field("channels", &self.channels)
+            .field("weighting", &self.weighting)
             .field("b", &self.b)
             .field("a", &self.a)
             .field("filter_state", &self.filter_state)
+            .field("biquad", &self.biquad)
+            .field("biquad_state", &self.biquad_state)
+            .field("cascade", &self.cascade)
+            .field("fixed", &self.fixed)
             .field("calculate_sample_peak", &self.calculate_sample_peak)
             .field("sample_peak", &self.sample_peak)
             .field("calculate_true_peak", &self.tp.is_some())
             .field("true_peak", &self.true_peak)
+            .field("overs_threshold", &self.overs_threshold)
+            .field("true_peak_overs", &self.true_peak_overs)
             .finish()
     }
 }
 
+/// The peaks and overs are only those of the previous call and are not compared.
+impl PartialEq for Filter {
+    fn eq(&self, other: &Self) -> bool {
+        self.rate == other.rate
+            && self.channels == other.channels
+            && self.weighting == other.weighting
+            && self.b == other.b
+            && self.a == other.a
+            && self.filter_state == other.filter_state
+            && self.biquad == other.biquad
+            && self.biquad_state == other.biquad_state
+            && self.cascade == other.cascade
+            && self.fixed == other.fixed
+            && self.calculate_sample_peak == other.calculate_sample_peak
+            && self.tp == other.tp
+            && self.overs_threshold == other.overs_threshold
+    }
+}
+
+/// State of a [`Filter`](struct.Filter.html) for serialization and snapshots.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FilterState {
+    filter_state: Vec<[f64; 5]>,
+    biquad_state: Vec<[f64; 3]>,
+    /// States of the single precision filter, empty with `Precision::F64`.
+    cascade_state: Vec<[f32; 4]>,
+    /// States of the fixed point filter, empty unless `Precision::Fixed` is used.
+    fixed_state: Vec<[i32; 5]>,
+    sample_peak: Vec<f64>,
+    true_peak: Vec<f64>,
+    /// Interleaved input samples stored in the true peak interpolator.
+    true_peak_history: Option<Vec<f32>>,
+    /// Custom coefficients of the true peak interpolator, `None` for the standard ones.
+    true_peak_coefficients: Option<Vec<f64>>,
+}
+
+/// The two biquads of the BS.1770 K-weighting: the high shelf and the high pass.
 #[allow(non_snake_case)]
-fn filter_coefficients(rate: f64) -> ([f64; 5], [f64; 5]) {
+fn k_weighting_sections(rate: f64) -> [BiquadCoefficients; 2] {
     let f0 = 1681.974450955533;
     let G = 3.999843853973347;
     let Q = 0.7071752369554196;
 
-    let K = f64::tan(std::f64::consts::PI * f0 / rate);
-    let Vh = f64::powf(10.0, G / 20.0);
-    let Vb = f64::powf(Vh, 0.4996667741545416);
+    let K = math::tan(core::f64::consts::PI * f0 / rate);
+    let Vh = math::pow(10.0, G / 20.0);
+    let Vb = math::pow(Vh, 0.4996667741545416);
 
     let mut pb = [0.0, 0.0, 0.0];
     let mut pa = [1.0, 0.0, 0.0];
@@ -84,11 +314,18 @@ fn filter_coefficients(rate: f64) -> ([f64; 5], [f64; 5]) {
 
     let f0 = 38.13547087602444;
     let Q = 0.5003270373238773;
-    let K = f64::tan(std::f64::consts::PI * f0 / rate);
+    let K = math::tan(core::f64::consts::PI * f0 / rate);
 
     ra[1] = 2.0 * (K * K - 1.0) / (1.0 + K / Q + K * K);
     ra[2] = (1.0 - K / Q + K * K) / (1.0 + K / Q + K * K);
 
+    [(pb, pa), (rb, ra)]
+}
+
+/// Coefficients of the BS.1770 K-weighting as a single 4th order filter.
+fn filter_coefficients(rate: f64) -> ([f64; 5], [f64; 5]) {
+    let [(pb, pa), (rb, ra)] = k_weighting_sections(rate);
+
     (
         // Numerator
         [
@@ -109,36 +346,480 @@ fn filter_coefficients(rate: f64) -> ([f64; 5], [f64; 5]) {
     )
 }
 
+/// Analog pole frequencies of the A- and C-weighting from IEC 61672-1.
+const WEIGHTING_F1: f64 = 20.598997;
+const WEIGHTING_F2: f64 = 107.65265;
+const WEIGHTING_F3: f64 = 737.86223;
+const WEIGHTING_F4: f64 = 12194.217;
+
+/// Multiply the polynomials `a` and `b` in z^-1.
+fn poly_mul(a: &[f64], b: &[f64], out: &mut [f64]) {
+    assert!(out.len() == a.len() + b.len() - 1);
+
+    for v in &mut *out {
+        *v = 0.0;
+    }
+
+    for (i, a) in a.iter().enumerate() {
+        for (j, b) in b.iter().enumerate() {
+            out[i + j] += a * b;
+        }
+    }
+}
+
+/// Bilinear transform of the analog pole `(s + 2 * pi * f)` without the `(1 + z^-1)` factor,
+/// which is added to the numerator instead.
+fn bilinear_pole(f: f64, rate: f64) -> [f64; 2] {
+    let w = 2.0 * core::f64::consts::PI * f;
+    [2.0 * rate + w, w - 2.0 * rate]
+}
+
+/// Magnitude response of the filter with the given coefficients at frequency `f`.
+fn magnitude(b: &[f64], a: &[f64], f: f64, rate: f64) -> f64 {
+    let w = 2.0 * core::f64::consts::PI * f / rate;
+
+    let eval = |p: &[f64]| {
+        p.iter().enumerate().fold((0.0, 0.0), |(re, im), (k, c)| {
+            (
+                re + c * math::cos(w * k as f64),
+                im - c * math::sin(w * k as f64),
+            )
+        })
+    };
+
+    let (b_re, b_im) = eval(b);
+    let (a_re, a_im) = eval(a);
+
+    math::sqrt((b_re * b_re + b_im * b_im) / (a_re * a_re + a_im * a_im))
+}
+
+/// Coefficients of the A- or C-weighting, normalized to 0dB at 1kHz.
+///
+/// The analog filters are transformed with the bilinear transform. Both share the 4th order
+/// part `s^2 / ((s + w1)^2 * (s + w4)^2)` and the A-weighting additionally needs the biquad
+/// `s^2 / ((s + w2) * (s + w3))`.
+#[allow(clippy::type_complexity)]
+fn weighting_coefficients(
+    weighting: Weighting,
+    rate: f64,
+) -> (([f64; 5], [f64; 5]), Option<BiquadCoefficients>) {
+    // s^2 becomes (1 - z^-1)^2 and the 4 poles add (1 + z^-1)^4, which gives (1 - z^-2)^2
+    let mut b = [1.0, 0.0, -2.0, 0.0, 1.0];
+    let mut a = [0.0; 5];
+
+    let p1 = bilinear_pole(WEIGHTING_F1, rate);
+    let p4 = bilinear_pole(WEIGHTING_F4, rate);
+    let mut p11 = [0.0; 3];
+    let mut p44 = [0.0; 3];
+    poly_mul(&p1, &p1, &mut p11);
+    poly_mul(&p4, &p4, &mut p44);
+    poly_mul(&p11, &p44, &mut a);
+
+    let a0 = a[0];
+    for v in b.iter_mut().chain(a.iter_mut()) {
+        *v /= a0;
+    }
+
+    let biquad = if weighting == Weighting::A {
+        Some(a_weighting_biquad(rate))
+    } else {
+        None
+    };
+
+    let mut gain = magnitude(&b, &a, 1000.0, rate);
+    if let Some((ref bb, ref ba)) = biquad {
+        gain *= magnitude(bb, ba, 1000.0, rate);
+    }
+    for v in &mut b {
+        *v /= gain;
+    }
+
+    ((b, a), biquad)
+}
+
+/// The additional biquad `s^2 / ((s + w2) * (s + w3))` of the A-weighting.
+fn a_weighting_biquad(rate: f64) -> BiquadCoefficients {
+    // s^2 becomes (1 - z^-1)^2 and the 2 poles add (1 + z^-1)^2, which cancels
+    let mut bb = [1.0, -2.0, 1.0];
+    let mut ba = [0.0; 3];
+    poly_mul(
+        &bilinear_pole(WEIGHTING_F2, rate),
+        &bilinear_pole(WEIGHTING_F3, rate),
+        &mut ba,
+    );
+
+    let a0 = ba[0];
+    for v in bb.iter_mut().chain(ba.iter_mut()) {
+        *v /= a0;
+    }
+
+    (bb, ba)
+}
+
+/// The frequency weighting as cascade of biquads for the single precision filter.
+///
+/// The 4th order part of the A- and C-weighting is split into two times `s / ((s + w1) * (s + w4))`
+/// as double poles are numerically very sensitive, and the cascade is normalized to 0dB at 1kHz in
+/// the first biquad.
+fn filter_sections(weighting: Weighting, rate: f64) -> Vec<BiquadCoefficients> {
+    match weighting {
+        Weighting::K => k_weighting_sections(rate).to_vec(),
+        Weighting::A | Weighting::C => {
+            // s becomes (1 - z^-1) and the 2 poles add (1 + z^-1)^2, which gives (1 - z^-2)
+            let mut a = [0.0; 3];
+            poly_mul(
+                &bilinear_pole(WEIGHTING_F1, rate),
+                &bilinear_pole(WEIGHTING_F4, rate),
+                &mut a,
+            );
+            let a0 = a[0];
+            let section = ([1.0, 0.0, -1.0], [1.0, a[1] / a0, a[2] / a0]);
+
+            let mut sections = vec![section, section];
+
+            if weighting == Weighting::A {
+                sections.push(a_weighting_biquad(rate));
+            }
+
+            let gain = sections
+                .iter()
+                .map(|(b, a)| magnitude(b, a, 1000.0, rate))
+                .product::<f64>();
+            for v in &mut sections[0].0 {
+                *v /= gain;
+            }
+
+            sections
+        }
+    }
+}
+
+/// Process one sample with a biquad.
+#[inline(always)]
+fn process_biquad(state: &mut [f64; 3], b: &[f64; 3], a: &[f64; 3], sample: f64) -> f64 {
+    state[0] = sample - a[1] * state[1] - a[2] * state[2];
+    let out = b[0] * state[0] + b[1] * state[1] + b[2] * state[2];
+    state[2] = state[1];
+    state[1] = state[0];
+
+    out
+}
+
+/// Minimum number of channels for processing them in parallel.
+#[cfg(all(feature = "rayon", not(feature = "deterministic")))]
+const PARALLEL_MIN_CHANNELS: usize = 8;
+/// Minimum number of frames for processing the channels in parallel. Below that the overhead of
+/// distributing the work is bigger than the gain.
+#[cfg(all(feature = "rayon", not(feature = "deterministic")))]
+const PARALLEL_MIN_FRAMES: usize = 1024;
+
+/// Filter one channel of `src` and write the filtered samples to `dest`.
+///
+/// If `flush` is set, filter states below `f64::EPSILON` are flushed to zero afterwards.
+#[allow(clippy::too_many_arguments)]
+#[inline(always)]
+fn process_channel<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+    src: &S,
+    c: usize,
+    dest: &mut [f64],
+    filter_state: &mut [f64; 5],
+    biquad: Option<(&BiquadCoefficients, &mut [f64; 3])>,
+    b: &[f64; 5],
+    a: &[f64; 5],
+    flush: bool,
+) {
+    assert!(c < src.channels());
+    assert!(dest.len() >= src.frames());
+
+    src.foreach_sample_zipped(c, dest.iter_mut(), |src, dest| {
+        filter_state[0] = (*src).to_sample::<f64>()
+            - a[1] * filter_state[1]
+            - a[2] * filter_state[2]
+            - a[3] * filter_state[3]
+            - a[4] * filter_state[4];
+        *dest = b[0] * filter_state[0]
+            + b[1] * filter_state[1]
+            + b[2] * filter_state[2]
+            + b[3] * filter_state[3]
+            + b[4] * filter_state[4];
+
+        filter_state[4] = filter_state[3];
+        filter_state[3] = filter_state[2];
+        filter_state[2] = filter_state[1];
+        filter_state[1] = filter_state[0];
+    });
+
+    if flush {
+        for v in filter_state {
+            if math::fabs(*v) < f64::EPSILON {
+                *v = 0.0;
+            }
+        }
+    }
+
+    if let Some(((bb, ba), biquad_state)) = biquad {
+        for dest in &mut dest[..src.frames()] {
+            *dest = process_biquad(biquad_state, bb, ba, *dest);
+        }
+
+        if flush {
+            for v in biquad_state {
+                if math::fabs(*v) < f64::EPSILON {
+                    *v = 0.0;
+                }
+            }
+        }
+    }
+}
+
+/// Process one sample with a biquad of the single precision filter.
+#[inline(always)]
+fn process_section((b, d): &Section, state: &mut [f32; 4], x: f32) -> f32 {
+    // Most of the poles are close to z = 1, and the filter is much more accurate if the
+    // denominator is (1 - z^-1)^2 plus small corrections that don't lose precision. The direct
+    // form I is used because the internal state of the direct form II gets very big compared to
+    // the output for low frequencies.
+    //
+    // The previous output is added last as it is the only value that depends on the previous
+    // sample, which shortens the dependency chain between the samples.
+    let y =
+        b[0] * x + b[1] * state[0] + b[2] * state[1] - state[3] - d[1] * state[3] - d[0] * state[2]
+            + 2.0 * state[2];
+    *state = [x, state[0], y, state[2]];
+
+    y
+}
+
+/// Process one sample with a biquad of the fixed point filter.
+#[inline(always)]
+fn process_fixed_section((b, d, d_bits): &FixedSection, state: &mut [i32; 5], x: i32) -> i32 {
+    let [x1, x2, y1, y2, error] = *state;
+
+    // Same direct form I as `process_section()`. The products fit into 64 bits and only the
+    // final sum has to, so intermediate overflows are wrapped. The rounding error of the previous
+    // output is added back, which moves the rounding noise away from the low frequencies where
+    // the poles amplify it most.
+    let feedback =
+        (d[0] as i64 * y1 as i64 + d[1] as i64 * y2 as i64) >> (d_bits - FIXED_FRACTIONAL_BITS);
+    let acc = (b[0] as i64 * x as i64)
+        .wrapping_add(b[1] as i64 * x1 as i64)
+        .wrapping_add(b[2] as i64 * x2 as i64)
+        .wrapping_sub(feedback)
+        .wrapping_add((2 * y1 as i64 - y2 as i64) << FIXED_FRACTIONAL_BITS)
+        .wrapping_add(error as i64);
+
+    let y = acc >> FIXED_FRACTIONAL_BITS;
+    let (y, error) = if y > i32::MAX as i64 {
+        (i32::MAX, 0)
+    } else if y < i32::MIN as i64 {
+        (i32::MIN, 0)
+    } else {
+        (y as i32, (acc - (y << FIXED_FRACTIONAL_BITS)) as i32)
+    };
+    *state = [x, x1, y, y1, error];
+
+    y
+}
+
+/// Convert a sample to the fixed point format of the filter.
+#[inline(always)]
+fn fixed_sample<T: Sample>(sample: T) -> i32 {
+    sample.to_i32() >> (31 - FIXED_FRACTIONAL_BITS)
+}
+
+/// Filter one channel of `src` with the fixed point filter and write the filtered samples to
+/// `dest`.
+#[inline(always)]
+fn process_channel_fixed<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+    src: &S,
+    c: usize,
+    dest: &mut [i32],
+    sections: &[FixedSection],
+    state: &mut [[i32; 5]],
+) {
+    assert!(c < src.channels());
+    assert!(dest.len() >= src.frames());
+    assert!(state.len() == sections.len());
+
+    src.foreach_sample_zipped(c, dest.iter_mut(), |src, dest| {
+        *dest = Iterator::zip(sections.iter(), state.iter_mut())
+            .fold(fixed_sample(*src), |x, (section, state)| {
+                process_fixed_section(section, state, x)
+            });
+    });
+}
+
+/// Filter one channel of `src` with the single precision filter and write the filtered samples
+/// to `dest`.
+///
+/// If `flush` is set, filter states below `f32::EPSILON` are flushed to zero afterwards.
+#[inline(always)]
+fn process_channel_cascade<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+    src: &S,
+    c: usize,
+    dest: &mut [f32],
+    sections: &[Section],
+    state: &mut [[f32; 4]],
+    flush: bool,
+) {
+    assert!(c < src.channels());
+    assert!(dest.len() >= src.frames());
+    assert!(state.len() == sections.len() && !sections.is_empty());
+
+    // The K- and C-weighting have 2 and the A-weighting has 3 biquads
+    match sections.len() {
+        2 => process_channel_sections::<_, _, 2>(
+            src,
+            c,
+            dest,
+            sections.try_into().unwrap(),
+            state.try_into().unwrap(),
+        ),
+        3 => process_channel_sections::<_, _, 3>(
+            src,
+            c,
+            dest,
+            sections.try_into().unwrap(),
+            state.try_into().unwrap(),
+        ),
+        _ => unreachable!("unsupported number of biquads"),
+    }
+
+    if flush {
+        for v in state.iter_mut().flatten() {
+            if math::fabsf(*v) < f32::EPSILON {
+                *v = 0.0;
+            }
+        }
+    }
+}
+
+/// Same as `process_channel_cascade()` for a fixed number of biquads, which keeps the states in
+/// registers and interleaves the biquads of consecutive samples.
+#[inline(always)]
+fn process_channel_sections<'a, T: Sample + 'a, S: crate::Samples<'a, T>, const N: usize>(
+    src: &S,
+    c: usize,
+    dest: &mut [f32],
+    sections: &[Section; N],
+    state: &mut [[f32; 4]; N],
+) {
+    let mut sections_state = *state;
+
+    src.foreach_sample_zipped(c, dest.iter_mut(), |src, dest| {
+        *dest = Iterator::zip(sections.iter(), sections_state.iter_mut())
+            .fold((*src).to_sample::<f32>(), |x, (section, state)| {
+                process_section(section, state, x)
+            });
+    });
+
+    *state = sections_state;
+}
+
 impl Filter {
+    /// Create a new filter.
+    ///
+    /// The A- and C-weighting are normalized at 1kHz and need a sample rate of at least 8kHz,
+    /// otherwise `Error::UnsupportedRate` is returned.
     pub fn new(
         rate: u32,
         channels: u32,
+        weighting: Weighting,
         calculate_sample_peak: bool,
         calculate_true_peak: bool,
-    ) -> Self {
+    ) -> Result<Self, crate::Error> {
         assert!(rate > 0);
         assert!(channels > 0);
 
-        let (b, a) = filter_coefficients(rate as f64);
+        let ((b, a), biquad) = match weighting {
+            Weighting::K => (filter_coefficients(rate as f64), None),
+            Weighting::A | Weighting::C => {
+                if rate < 8_000 {
+                    return Err(crate::Error::UnsupportedRate);
+                }
+                weighting_coefficients(weighting, rate as f64)
+            }
+        };
+        let biquad_channels = if biquad.is_some() {
+            channels as usize
+        } else {
+            0
+        };
 
         let tp = if calculate_true_peak {
-            crate::true_peak::TruePeak::new(rate, channels)
+            Some(crate::true_peak::TruePeak::new(rate, channels)?)
         } else {
             None
         };
 
-        Filter {
+        Ok(Filter {
+            rate,
             channels,
+            weighting,
             b,
             a,
             filter_state: vec![[0.0; 5]; channels as usize].into_boxed_slice(),
+            biquad,
+            biquad_state: vec![[0.0; 3]; biquad_channels].into_boxed_slice(),
+            cascade: None,
+            fixed: None,
             calculate_sample_peak,
             sample_peak: vec![0.0; channels as usize].into_boxed_slice(),
             tp,
             true_peak: vec![0.0; channels as usize].into_boxed_slice(),
+            overs_threshold: None,
+            true_peak_overs: vec![0; channels as usize].into_boxed_slice(),
+        })
+    }
+
+    /// Magnitude response of the frequency weighting in dB at `freq_hz`.
+    ///
+    /// This is calculated from the double precision coefficients. With `Precision::F32` the
+    /// cascade of second order sections has the same response apart from rounding errors.
+    pub fn frequency_response(&self, freq_hz: f64) -> f64 {
+        let rate = self.rate as f64;
+        let mut gain = magnitude(&self.b, &self.a, freq_hz, rate);
+        if let Some((ref b, ref a)) = self.biquad {
+            gain *= magnitude(b, a, freq_hz, rate);
+        }
+
+        20.0 * math::log10(gain)
+    }
+
+    /// Enables or disables the sample peak measurement.
+    pub fn set_calculate_sample_peak(&mut self, calculate_sample_peak: bool) {
+        self.calculate_sample_peak = calculate_sample_peak;
+        if !calculate_sample_peak {
+            for v in &mut *self.sample_peak {
+                *v = 0.0;
+            }
         }
     }
 
+    /// Enables the true peak measurement with the given interpolator or disables it with `None`.
+    pub fn set_true_peak(&mut self, tp: Option<crate::true_peak::TruePeak>) {
+        if tp.is_none() {
+            for v in &mut *self.true_peak {
+                *v = 0.0;
+            }
+            for v in &mut *self.true_peak_overs {
+                *v = 0;
+            }
+        }
+        self.tp = tp;
+    }
+
+    /// Enables counting the interpolated samples above `threshold` with the true peak
+    /// measurement or disables it with `None`.
+    pub fn set_overs_threshold(&mut self, threshold: Option<f32>) {
+        if threshold.is_none() {
+            for v in &mut *self.true_peak_overs {
+                *v = 0;
+            }
+        }
+        self.overs_threshold = threshold;
+    }
+
     pub fn reset_peaks(&mut self) {
         for v in &mut *self.sample_peak {
             *v = 0.0;
@@ -147,15 +828,32 @@ impl Filter {
         for v in &mut *self.true_peak {
             *v = 0.0;
         }
+
+        for v in &mut *self.true_peak_overs {
+            *v = 0;
+        }
     }
 
     pub fn reset(&mut self) {
         self.reset_peaks();
 
         for f in &mut *self.filter_state {
-            // TODO: Use slice::fill() once stabilized
-            for v in &mut *f {
-                *v = 0.0;
+            f.fill(0.0);
+        }
+
+        for f in &mut *self.biquad_state {
+            f.fill(0.0);
+        }
+
+        if let Some(ref mut cascade) = self.cascade {
+            for f in &mut *cascade.state {
+                f.fill(0.0);
+            }
+        }
+
+        if let Some(ref mut fixed) = self.fixed {
+            for f in &mut *fixed.state {
+                f.fill(0);
             }
         }
 
@@ -164,22 +862,221 @@ impl Filter {
         }
     }
 
+    /// Switch between the double precision, single precision and fixed point filter.
+    ///
+    /// The filter states of the previous precision are reset.
+    pub fn set_precision(&mut self, precision: Precision) {
+        if precision == self.precision() {
+            return;
+        }
+
+        self.cascade = None;
+        self.fixed = None;
+
+        match precision {
+            Precision::F64 => (),
+            Precision::F32 => {
+                let sections = filter_sections(self.weighting, self.rate as f64)
+                    .into_iter()
+                    .map(|(b, a)| {
+                        (
+                            b.map(|v| v as f32),
+                            [(a[1] + 2.0) as f32, (a[2] - 1.0) as f32],
+                        )
+                    })
+                    .collect::<Box<[_]>>();
+                let state = vec![[0.0; 4]; sections.len() * self.channels as usize];
+
+                self.cascade = Some(Cascade {
+                    sections,
+                    state: state.into_boxed_slice(),
+                });
+            }
+            Precision::Fixed => {
+                let rate = self.rate as f64;
+                let mut sections = filter_sections(self.weighting, rate);
+                let gain = sections
+                    .iter()
+                    .map(|(b, a)| magnitude(b, a, 1000.0, rate))
+                    .collect::<Vec<_>>();
+                let total = gain.iter().product::<f64>();
+                for (i, ((b, _), gain)) in Iterator::zip(sections.iter_mut(), gain).enumerate() {
+                    let gain = if i == 0 { gain / total } else { gain };
+                    for v in b {
+                        *v /= gain;
+                    }
+                }
+
+                let sections = sections
+                    .into_iter()
+                    .map(|(b, a)| {
+                        let d = [a[1] + 2.0, a[2] - 1.0];
+                        let max = f64::max(math::fabs(d[0]), math::fabs(d[1]));
+                        let mut d_bits = FIXED_FRACTIONAL_BITS;
+                        while d_bits < 2 * FIXED_FRACTIONAL_BITS
+                            && max * ((1u64 << (d_bits + 1)) as f64) < i32::MAX as f64
+                        {
+                            d_bits += 1;
+                        }
+
+                        // The high-pass biquads have zeros at DC and have to keep them exactly,
+                        // so the sum of the numerator is rounded instead of the middle coefficient
+                        let b0 = i32::from_f64(b[0]);
+                        let b2 = i32::from_f64(b[2]);
+                        let b1 = i32::from_f64(b[0] + b[1] + b[2]) - b0 - b2;
+
+                        ([b0, b1, b2], d.map(|v| to_fixed(v, d_bits)), d_bits)
+                    })
+                    .collect::<Box<[_]>>();
+                let state = vec![[0; 5]; sections.len() * self.channels as usize];
+
+                self.fixed = Some(FixedCascade {
+                    sections,
+                    state: state.into_boxed_slice(),
+                });
+            }
+        }
+
+        for f in &mut *self.filter_state {
+            f.fill(0.0);
+        }
+
+        for f in &mut *self.biquad_state {
+            f.fill(0.0);
+        }
+    }
+
+    pub fn precision(&self) -> Precision {
+        if self.cascade.is_some() {
+            Precision::F32
+        } else if self.fixed.is_some() {
+            Precision::Fixed
+        } else {
+            Precision::F64
+        }
+    }
+
+    /// Get the current state for serialization or a snapshot.
+    pub fn state(&self) -> FilterState {
+        FilterState {
+            filter_state: self.filter_state.to_vec(),
+            biquad_state: self.biquad_state.to_vec(),
+            cascade_state: self
+                .cascade
+                .as_ref()
+                .map(|cascade| cascade.state.to_vec())
+                .unwrap_or_default(),
+            fixed_state: self
+                .fixed
+                .as_ref()
+                .map(|fixed| fixed.state.to_vec())
+                .unwrap_or_default(),
+            sample_peak: self.sample_peak.to_vec(),
+            true_peak: self.true_peak.to_vec(),
+            true_peak_history: self.tp.as_ref().map(|tp| tp.history()),
+            true_peak_coefficients: self.tp.as_ref().and_then(|tp| tp.custom_coefficients()),
+        }
+    }
+
+    /// Restore the state from a deserialized state or a snapshot.
+    ///
+    /// Fails if the state does not fit the configuration of this filter.
+    pub fn set_state(&mut self, state: &FilterState) -> Result<(), crate::Error> {
+        let channels = self.channels as usize;
+        if state.filter_state.len() != channels
+            || state.biquad_state.len() != self.biquad_state.len()
+            || state.cascade_state.len()
+                != self
+                    .cascade
+                    .as_ref()
+                    .map(|cascade| cascade.state.len())
+                    .unwrap_or(0)
+            || state.fixed_state.len()
+                != self
+                    .fixed
+                    .as_ref()
+                    .map(|fixed| fixed.state.len())
+                    .unwrap_or(0)
+            || state.sample_peak.len() != channels
+            || state.true_peak.len() != channels
+        {
+            return Err(crate::Error::NoMem);
+        }
+
+        match (&mut self.tp, &state.true_peak_history) {
+            (Some(tp), Some(history)) => {
+                tp.set_custom_coefficients(state.true_peak_coefficients.as_deref())?;
+                tp.set_history(self.channels, history)?
+            }
+            (None, None) if state.true_peak_coefficients.is_none() => (),
+            _ => return Err(crate::Error::InvalidMode),
+        }
+
+        self.filter_state.copy_from_slice(&state.filter_state);
+        self.biquad_state.copy_from_slice(&state.biquad_state);
+        if let Some(ref mut cascade) = self.cascade {
+            cascade.state.copy_from_slice(&state.cascade_state);
+        }
+        if let Some(ref mut fixed) = self.fixed {
+            fixed.state.copy_from_slice(&state.fixed_state);
+        }
+        self.sample_peak.copy_from_slice(&state.sample_peak);
+        self.true_peak.copy_from_slice(&state.true_peak);
+
+        Ok(())
+    }
+
+    pub fn weighting(&self) -> Weighting {
+        self.weighting
+    }
+
     pub fn sample_peak(&self) -> &[f64] {
-        &*self.sample_peak
+        &self.sample_peak
     }
 
     pub fn true_peak(&self) -> &[f64] {
-        &*self.true_peak
+        &self.true_peak
+    }
+
+    pub fn true_peak_overs(&self) -> &[u64] {
+        &self.true_peak_overs
+    }
+
+    /// True peak interpolator, or `None` if the true peak is not measured.
+    pub fn true_peak_interpolator(&self) -> Option<&crate::true_peak::TruePeak> {
+        self.tp.as_ref()
     }
 
-    pub fn process<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+    pub fn true_peak_interpolator_mut(&mut self) -> Option<&mut crate::true_peak::TruePeak> {
+        self.tp.as_mut()
+    }
+
+    /// Delay of the true peak interpolator in frames, or `None` if the true peak is not measured.
+    pub fn true_peak_latency(&self) -> Option<usize> {
+        self.tp.as_ref().map(crate::true_peak::TruePeak::latency)
+    }
+
+    /// Oversampling factor of the true peak interpolator, or `None` if the true peak is not
+    /// measured.
+    pub fn true_peak_oversampling_factor(&self) -> Option<usize> {
+        self.tp
+            .as_ref()
+            .map(crate::true_peak::TruePeak::oversampling_factor)
+    }
+
+    /// Filter `src` into `dest` starting at `dest_index` in each channel and update the peaks.
+    ///
+    /// The true peak measurement additionally writes its oversampled signal to `oversampled` if
+    /// given, with the given stride between the channels.
+    pub fn process<'a, T: Sample + 'a, S: crate::Samples<'a, T>, F: Float>(
         &mut self,
         src: S,
-        dest: &mut [f64],
+        dest: &mut [F],
         dest_index: usize,
         channel_map: &[crate::ebur128::Channel],
-    ) {
-        assert!(dest.len() % self.channels as usize == 0);
+        oversampled: Option<(&mut [f32], usize)>,
+    ) -> Result<(), crate::Error> {
+        assert!(dest.len().is_multiple_of(self.channels as usize));
         assert!(channel_map.len() == self.channels as usize);
         assert!(src.channels() == self.channels as usize);
         assert!(self.filter_state.len() == self.channels as usize);
@@ -194,7 +1091,7 @@ impl Filter {
                     assert!(c < src.channels());
 
                     src.foreach_sample(c, |sample| {
-                        let v = sample.as_f64_raw().abs();
+                        let v = math::fabs(sample.as_f64_raw());
                         if v > max {
                             max = v;
                         }
@@ -210,10 +1107,277 @@ impl Filter {
             let dest_stride = dest.len() / self.channels as usize;
             assert!(dest_index + src.frames() <= dest_stride);
 
-            for (c, (channel_map, dest)) in
-                Iterator::zip(channel_map.iter(), dest.chunks_exact_mut(dest_stride)).enumerate()
+            F::filter(self, &src, dest, dest_index, channel_map, ftz.is_none());
+
+            if let Some(ref mut tp) = self.tp {
+                if let Some((oversampled, stride)) = oversampled {
+                    let true_peak_overs = &mut *self.true_peak_overs;
+                    let overs = self
+                        .overs_threshold
+                        .map(|threshold| (threshold, true_peak_overs));
+                    tp.check_true_peak_oversampled(
+                        src,
+                        &mut self.true_peak,
+                        overs,
+                        oversampled,
+                        stride,
+                    )?;
+                } else if let Some(threshold) = self.overs_threshold {
+                    tp.check_true_peak_overs(
+                        src,
+                        &mut self.true_peak,
+                        threshold,
+                        &mut self.true_peak_overs,
+                    )?;
+                } else {
+                    tp.check_true_peak(src, &mut self.true_peak)?;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Filter all channels with the double precision filter.
+    fn filter_direct<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: &S,
+        dest: &mut [f64],
+        dest_index: usize,
+        channel_map: &[crate::ebur128::Channel],
+        flush: bool,
+    ) {
+        assert!(self.cascade.is_none() && self.fixed.is_none());
+
+        #[cfg(all(feature = "rayon", not(feature = "deterministic")))]
+        {
+            if self.channels as usize >= PARALLEL_MIN_CHANNELS
+                && src.frames() >= PARALLEL_MIN_FRAMES
+                && rayon::current_num_threads() > 1
+            {
+                self.process_parallel(src, dest, dest_index, channel_map);
+                return;
+            }
+        }
+
+        let dest_stride = dest.len() / self.channels as usize;
+
+        for (c, (channel_map, dest)) in
+            Iterator::zip(channel_map.iter(), dest.chunks_exact_mut(dest_stride)).enumerate()
+        {
+            if channel_map.is_excluded() {
+                continue;
+            }
+
+            assert!(c < src.channels());
+
+            let Filter {
+                ref mut filter_state,
+                ref a,
+                ref b,
+                ref biquad,
+                ref mut biquad_state,
+                ..
+            } = *self;
+
+            process_channel(
+                src,
+                c,
+                &mut dest[dest_index..],
+                &mut filter_state[c],
+                biquad.as_ref().zip(biquad_state.get_mut(c)),
+                b,
+                a,
+                flush,
+            );
+        }
+    }
+
+    /// Filter all channels with the single precision filter.
+    fn filter_cascade<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: &S,
+        dest: &mut [f32],
+        dest_index: usize,
+        channel_map: &[crate::ebur128::Channel],
+        flush: bool,
+    ) {
+        let Cascade {
+            ref sections,
+            ref mut state,
+        } = *self.cascade.as_mut().expect("no single precision filter");
+        let dest_stride = dest.len() / self.channels as usize;
+
+        #[cfg(all(feature = "rayon", not(feature = "deterministic")))]
+        {
+            use rayon::prelude::*;
+
+            if self.channels as usize >= PARALLEL_MIN_CHANNELS
+                && src.frames() >= PARALLEL_MIN_FRAMES
+                && rayon::current_num_threads() > 1
             {
-                if *channel_map == crate::ebur128::Channel::Unused {
+                dest.par_chunks_exact_mut(dest_stride)
+                    .zip(state.par_chunks_exact_mut(sections.len()))
+                    .enumerate()
+                    .for_each(|(c, (dest, state))| {
+                        if channel_map[c].is_excluded() {
+                            return;
+                        }
+
+                        // The flush-to-zero mode is per thread and needs to be set on each
+                        // worker thread
+                        ftz::with_ftz(|ftz| {
+                            process_channel_cascade(
+                                src,
+                                c,
+                                &mut dest[dest_index..],
+                                sections,
+                                state,
+                                ftz.is_none(),
+                            )
+                        })
+                    });
+                return;
+            }
+        }
+
+        for (c, ((channel_map, dest), state)) in
+            Iterator::zip(channel_map.iter(), dest.chunks_exact_mut(dest_stride))
+                .zip(state.chunks_exact_mut(sections.len()))
+                .enumerate()
+        {
+            if channel_map.is_excluded() {
+                continue;
+            }
+
+            process_channel_cascade(src, c, &mut dest[dest_index..], sections, state, flush);
+        }
+    }
+
+    /// Filter all channels with the fixed point filter.
+    ///
+    /// The channels are always processed serially as there is not much to gain from processing
+    /// them in parallel on the targets the fixed point filter is meant for.
+    fn filter_fixed<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: &S,
+        dest: &mut [i32],
+        dest_index: usize,
+        channel_map: &[crate::ebur128::Channel],
+    ) {
+        let FixedCascade {
+            ref sections,
+            ref mut state,
+        } = *self.fixed.as_mut().expect("no fixed point filter");
+        let dest_stride = dest.len() / self.channels as usize;
+
+        for (c, ((channel_map, dest), state)) in
+            Iterator::zip(channel_map.iter(), dest.chunks_exact_mut(dest_stride))
+                .zip(state.chunks_exact_mut(sections.len()))
+                .enumerate()
+        {
+            if channel_map.is_excluded() {
+                continue;
+            }
+
+            process_channel_fixed(src, c, &mut dest[dest_index..], sections, state);
+        }
+    }
+
+    /// Same as `filter_direct()` but processes the channels in parallel.
+    ///
+    /// The channels are independent of each other and processed exactly the same way, so the
+    /// results are the same as when processing them serially.
+    #[cfg(all(feature = "rayon", not(feature = "deterministic")))]
+    fn process_parallel<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: &S,
+        dest: &mut [f64],
+        dest_index: usize,
+        channel_map: &[crate::ebur128::Channel],
+    ) {
+        use rayon::prelude::*;
+
+        let dest_stride = dest.len() / self.channels as usize;
+
+        let Filter {
+            ref mut filter_state,
+            ref a,
+            ref b,
+            ref biquad,
+            ref mut biquad_state,
+            ..
+        } = *self;
+
+        let channels = dest
+            .par_chunks_exact_mut(dest_stride)
+            .zip(filter_state.par_iter_mut());
+
+        // The flush-to-zero mode is per thread and needs to be set on each worker thread
+        if let Some(ref biquad) = *biquad {
+            channels
+                .zip(biquad_state.par_iter_mut())
+                .enumerate()
+                .for_each(|(c, ((dest, filter_state), biquad_state))| {
+                    if channel_map[c].is_excluded() {
+                        return;
+                    }
+
+                    ftz::with_ftz(|ftz| {
+                        process_channel(
+                            src,
+                            c,
+                            &mut dest[dest_index..],
+                            filter_state,
+                            Some((biquad, biquad_state)),
+                            b,
+                            a,
+                            ftz.is_none(),
+                        )
+                    })
+                });
+        } else {
+            channels.enumerate().for_each(|(c, (dest, filter_state))| {
+                if channel_map[c].is_excluded() {
+                    return;
+                }
+
+                ftz::with_ftz(|ftz| {
+                    process_channel(
+                        src,
+                        c,
+                        &mut dest[dest_index..],
+                        filter_state,
+                        None,
+                        b,
+                        a,
+                        ftz.is_none(),
+                    )
+                })
+            });
+        }
+    }
+
+    pub fn seed<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+        channel_map: &[crate::ebur128::Channel],
+    ) -> Result<(), crate::Error> {
+        assert!(channel_map.len() == self.channels as usize);
+        assert!(src.channels() == self.channels as usize);
+        assert!(self.filter_state.len() == self.channels as usize);
+
+        if self.cascade.is_some() {
+            return self.seed_cascade(src, channel_map);
+        }
+
+        if self.fixed.is_some() {
+            return self.seed_fixed(src, channel_map);
+        }
+
+        ftz::with_ftz(|ftz| {
+            for (c, channel_map) in channel_map.iter().enumerate() {
+                if channel_map.is_excluded() {
                     continue;
                 }
 
@@ -223,21 +1387,31 @@ impl Filter {
                     ref mut filter_state,
                     ref a,
                     ref b,
+                    ref biquad,
+                    ref mut biquad_state,
                     ..
                 } = *self;
                 let filter_state = &mut filter_state[c];
+                let mut biquad_state = biquad_state.get_mut(c);
 
-                src.foreach_sample_zipped(c, dest[dest_index..].iter_mut(), |src, dest| {
+                src.foreach_sample(c, |src| {
                     filter_state[0] = (*src).to_sample::<f64>()
                         - a[1] * filter_state[1]
                         - a[2] * filter_state[2]
                         - a[3] * filter_state[3]
                         - a[4] * filter_state[4];
-                    *dest = b[0] * filter_state[0]
-                        + b[1] * filter_state[1]
-                        + b[2] * filter_state[2]
-                        + b[3] * filter_state[3]
-                        + b[4] * filter_state[4];
+
+                    // The output of the main filter is only needed as input of the biquad
+                    if let (Some((ref bb, ref ba)), Some(biquad_state)) =
+                        (*biquad, biquad_state.as_deref_mut())
+                    {
+                        let out = b[0] * filter_state[0]
+                            + b[1] * filter_state[1]
+                            + b[2] * filter_state[2]
+                            + b[3] * filter_state[3]
+                            + b[4] * filter_state[4];
+                        process_biquad(biquad_state, bb, ba, out);
+                    }
 
                     filter_state[4] = filter_state[3];
                     filter_state[3] = filter_state[2];
@@ -246,8 +1420,11 @@ impl Filter {
                 });
 
                 if ftz.is_none() {
-                    for v in filter_state {
-                        if v.abs() < std::f64::EPSILON {
+                    for v in filter_state
+                        .iter_mut()
+                        .chain(biquad_state.into_iter().flatten())
+                    {
+                        if math::fabs(*v) < f64::EPSILON {
                             *v = 0.0;
                         }
                     }
@@ -255,52 +1432,45 @@ impl Filter {
             }
 
             if let Some(ref mut tp) = self.tp {
-                assert!(self.true_peak.len() == self.channels as usize);
-                tp.check_true_peak(src, &mut *self.true_peak);
+                tp.seed(src)?;
             }
-        });
+
+            Ok(())
+        })
     }
 
-    pub fn seed<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+    /// Same as `seed()` with the single precision filter.
+    fn seed_cascade<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
         &mut self,
         src: S,
         channel_map: &[crate::ebur128::Channel],
-    ) {
-        assert!(channel_map.len() == self.channels as usize);
-        assert!(src.channels() == self.channels as usize);
-        assert!(self.filter_state.len() == self.channels as usize);
-
+    ) -> Result<(), crate::Error> {
         ftz::with_ftz(|ftz| {
-            for (c, channel_map) in channel_map.iter().enumerate() {
-                if *channel_map == crate::ebur128::Channel::Unused {
+            let Cascade {
+                ref sections,
+                ref mut state,
+            } = *self.cascade.as_mut().expect("no single precision filter");
+
+            for (c, (channel_map, state)) in
+                Iterator::zip(channel_map.iter(), state.chunks_exact_mut(sections.len()))
+                    .enumerate()
+            {
+                if channel_map.is_excluded() {
                     continue;
                 }
 
                 assert!(c < src.channels());
 
-                let Filter {
-                    ref mut filter_state,
-                    ref a,
-                    ..
-                } = *self;
-                let filter_state = &mut filter_state[c];
-
                 src.foreach_sample(c, |src| {
-                    filter_state[0] = (*src).to_sample::<f64>()
-                        - a[1] * filter_state[1]
-                        - a[2] * filter_state[2]
-                        - a[3] * filter_state[3]
-                        - a[4] * filter_state[4];
-
-                    filter_state[4] = filter_state[3];
-                    filter_state[3] = filter_state[2];
-                    filter_state[2] = filter_state[1];
-                    filter_state[1] = filter_state[0];
+                    Iterator::zip(sections.iter(), state.iter_mut())
+                        .fold((*src).to_sample::<f32>(), |x, (section, state)| {
+                            process_section(section, state, x)
+                        });
                 });
 
                 if ftz.is_none() {
-                    for v in filter_state {
-                        if v.abs() < std::f64::EPSILON {
+                    for v in state.iter_mut().flatten() {
+                        if math::fabsf(*v) < f32::EPSILON {
                             *v = 0.0;
                         }
                     }
@@ -308,56 +1478,135 @@ impl Filter {
             }
 
             if let Some(ref mut tp) = self.tp {
-                assert!(self.true_peak.len() == self.channels as usize);
-                tp.seed(src);
+                tp.seed(src)?;
             }
-        });
+
+            Ok(())
+        })
+    }
+
+    /// Same as `seed()` with the fixed point filter.
+    fn seed_fixed<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+        channel_map: &[crate::ebur128::Channel],
+    ) -> Result<(), crate::Error> {
+        let FixedCascade {
+            ref sections,
+            ref mut state,
+        } = *self.fixed.as_mut().expect("no fixed point filter");
+
+        for (c, (channel_map, state)) in
+            Iterator::zip(channel_map.iter(), state.chunks_exact_mut(sections.len())).enumerate()
+        {
+            if channel_map.is_excluded() {
+                continue;
+            }
+
+            assert!(c < src.channels());
+
+            src.foreach_sample(c, |src| {
+                Iterator::zip(sections.iter(), state.iter_mut())
+                    .fold(fixed_sample(*src), |x, (section, state)| {
+                        process_fixed_section(section, state, x)
+                    });
+            });
+        }
+
+        if let Some(ref mut tp) = self.tp {
+            tp.seed(src)?;
+        }
+
+        Ok(())
     }
 
-    pub fn calc_gating_block(
+    pub fn calc_gating_block<F: Float>(
         frames_per_block: usize,
-        audio_data: &[f64],
+        audio_data: &[F],
         audio_data_index: usize,
         channel_map: &[Channel],
+        channel_gains: &[f64],
     ) -> f64 {
         let mut sum = 0.0;
 
+        Self::foreach_channel_energy(
+            frames_per_block,
+            audio_data,
+            audio_data_index,
+            channel_map,
+            channel_gains,
+            |_, channel_sum| sum += channel_sum,
+        );
+
+        sum /= frames_per_block as f64;
+
+        sum
+    }
+
+    /// Store the weighted sum of squares of each channel over the gating block in `energies`.
+    ///
+    /// Summing these up for all channels and dividing by `frames_per_block` gives exactly the
+    /// result of `calc_gating_block()`. Excluded channels are stored as 0.0.
+    pub fn calc_gating_block_channels<F: Float>(
+        frames_per_block: usize,
+        audio_data: &[F],
+        audio_data_index: usize,
+        channel_map: &[Channel],
+        channel_gains: &[f64],
+        energies: &mut [f64],
+    ) {
+        assert_eq!(energies.len(), channel_map.len());
+
+        energies.fill(0.0);
+
+        Self::foreach_channel_energy(
+            frames_per_block,
+            audio_data,
+            audio_data_index,
+            channel_map,
+            channel_gains,
+            |c, channel_sum| energies[c] = channel_sum,
+        );
+    }
+
+    fn foreach_channel_energy<F: Float>(
+        frames_per_block: usize,
+        audio_data: &[F],
+        audio_data_index: usize,
+        channel_map: &[Channel],
+        channel_gains: &[f64],
+        mut func: impl FnMut(usize, f64),
+    ) {
         let channels = channel_map.len();
-        assert!(audio_data.len() % channels == 0);
+        assert_eq!(channel_gains.len(), channels);
+        assert!(audio_data.len().is_multiple_of(channels));
         let audio_data_stride = audio_data.len() / channels;
         assert!(audio_data_index <= audio_data_stride);
 
-        for (c, (channel, audio_data)) in Iterator::zip(
-            channel_map.iter(),
+        for (c, ((channel, gain), audio_data)) in Iterator::zip(
+            Iterator::zip(channel_map.iter(), channel_gains.iter()),
             audio_data.chunks_exact(audio_data_stride),
         )
         .enumerate()
         {
-            if *channel == Channel::Unused {
+            if channel.is_excluded() {
                 continue;
             }
 
             assert!(c < channels);
             assert!(audio_data_index <= audio_data.len());
 
-            let mut channel_sum = 0.0;
-
-            // XXX: Don't use channel_sum += sum() here because that gives slightly different
-            // results than the C version because of rounding errors
-            if audio_data_index < frames_per_block {
-                for frame in &audio_data[..audio_data_index] {
-                    channel_sum += *frame * *frame;
-                }
-
-                for frame in &audio_data[(audio_data.len() - frames_per_block + audio_data_index)..]
-                {
-                    channel_sum += *frame * *frame;
-                }
+            let mut channel_sum = if audio_data_index < frames_per_block {
+                F::energy(
+                    &audio_data[..audio_data_index],
+                    &audio_data[(audio_data.len() - frames_per_block + audio_data_index)..],
+                )
             } else {
-                for frame in &audio_data[(audio_data_index - frames_per_block)..audio_data_index] {
-                    channel_sum += *frame * *frame;
-                }
-            }
+                F::energy(
+                    &audio_data[(audio_data_index - frames_per_block)..audio_data_index],
+                    &[],
+                )
+            };
 
             match channel {
                 Channel::LeftSurround
@@ -373,25 +1622,138 @@ impl Filter {
                 }
                 _ => (),
             }
+            channel_sum *= *gain * *gain;
 
-            sum += channel_sum;
+            func(c, channel_sum);
         }
+    }
+}
 
-        sum /= frames_per_block as f64;
+/// Number of partial sums in `sum_of_squares_lanes()`.
+#[cfg(all(feature = "fast-energy-sum", not(feature = "deterministic")))]
+const SUM_LANES: usize = 8;
+
+/// Sum of the squares of all samples of `first` and `second`.
+///
+/// By default the squares are summed up one after another like the C implementation. With the
+/// `fast-energy-sum` feature they are accumulated in `SUM_LANES` partial sums, which allows the
+/// compiler to use SIMD instructions for them. This gives slightly different results because of
+/// rounding errors, but the partial sums are the same on all CPUs. The `deterministic` feature
+/// always sums up the samples one after another.
+#[inline]
+fn sum_of_squares<F: Float>(first: &[F], second: &[F]) -> F {
+    #[cfg(any(not(feature = "fast-energy-sum"), feature = "deterministic"))]
+    {
+        let sum = sum_of_squares_serial(F::default(), first);
+        sum_of_squares_serial(sum, second)
+    }
 
+    #[cfg(all(feature = "fast-energy-sum", not(feature = "deterministic")))]
+    {
+        #[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+        {
+            if is_x86_feature_detected!("avx") {
+                // Safety: AVX support was checked right above
+                unsafe {
+                    let mut sum = sum_of_squares_avx(first);
+                    sum += sum_of_squares_avx(second);
+                    return sum;
+                }
+            }
+        }
+
+        let mut sum = sum_of_squares_lanes(first);
+        sum += sum_of_squares_lanes(second);
         sum
     }
 }
 
+/// Sum of the squares of all fixed point samples.
+///
+/// The squares are shifted right by 16 bits and summed up exactly in 64 bit integers for up to
+/// `FIXED_SUM_CHUNK` samples, so that only one conversion to `f64` is needed per chunk.
+#[inline]
+fn sum_of_squares_fixed(data: &[i32]) -> f64 {
+    let mut sum = 0.0;
+    for chunk in data.chunks(FIXED_SUM_CHUNK) {
+        let mut chunk_sum = 0u64;
+        for sample in chunk {
+            chunk_sum += (*sample as i64 * *sample as i64) as u64 >> 16;
+        }
+        sum += chunk_sum as f64;
+    }
+
+    sum * FIXED_SUM_SCALE
+}
+
+/// Same as `sum_of_squares_lanes()` but compiled with AVX enabled, which allows the compiler to
+/// use wider registers for the partial sums. FMA is deliberately not enabled so that the results
+/// are exactly the same as without AVX.
+#[cfg(all(
+    feature = "std",
+    feature = "fast-energy-sum",
+    not(feature = "deterministic"),
+    any(target_arch = "x86", target_arch = "x86_64")
+))]
+#[target_feature(enable = "avx")]
+unsafe fn sum_of_squares_avx<F: Float>(data: &[F]) -> F {
+    sum_of_squares_lanes(data)
+}
+
+#[cfg(all(feature = "fast-energy-sum", not(feature = "deterministic")))]
+#[inline(always)]
+fn sum_of_squares_lanes<F: Float>(data: &[F]) -> F {
+    let mut sums = [F::default(); SUM_LANES];
+
+    let mut chunks = data.chunks_exact(SUM_LANES);
+    for chunk in &mut chunks {
+        for (sum, sample) in Iterator::zip(sums.iter_mut(), chunk) {
+            *sum += *sample * *sample;
+        }
+    }
+
+    let mut sum = F::default();
+    for partial_sum in &sums {
+        sum += *partial_sum;
+    }
+    for sample in chunks.remainder() {
+        sum += *sample * *sample;
+    }
+
+    sum
+}
+
+/// Add the squares of all samples to `sum` one after another, like the C implementation.
+#[cfg(any(test, not(feature = "fast-energy-sum"), feature = "deterministic"))]
+#[inline(always)]
+fn sum_of_squares_serial<F: Float>(mut sum: F, data: &[F]) -> F {
+    // XXX: Don't use channel_sum += sum() here because that gives slightly different
+    // results than the C version because of rounding errors
+    for sample in data {
+        sum += *sample * *sample;
+    }
+
+    sum
+}
+
+// Denormal handling: the recursive filter states decay towards zero after the input became silent
+// and calculations with denormal floats are very slow on many CPUs. On x86 with SSE2
+// flush-to-zero is enabled while filtering so the states never become denormal. Everywhere else
+// states below f64::EPSILON are flushed to zero after each call. As EbuR128 never filters more
+// than 400ms at once, the states are flushed long before they could decay into the denormal
+// range. With the `deterministic` feature the states are flushed the same way on all platforms.
 #[cfg(all(
     any(target_arch = "x86", target_arch = "x86_64"),
-    target_feature = "sse2"
+    target_feature = "sse2",
+    not(feature = "deterministic")
 ))]
+// FIXME: _mm_getcsr() / _mm_setcsr() are deprecated in favour of inline assembly
+#[allow(deprecated)]
 mod ftz {
     #[cfg(target_arch = "x86")]
-    use std::arch::x86::{_mm_getcsr, _mm_setcsr, _MM_FLUSH_ZERO_ON};
+    use core::arch::x86::{_mm_getcsr, _mm_setcsr, _MM_FLUSH_ZERO_ON};
     #[cfg(target_arch = "x86_64")]
-    use std::arch::x86_64::{_mm_getcsr, _mm_setcsr, _MM_FLUSH_ZERO_ON};
+    use core::arch::x86_64::{_mm_getcsr, _mm_setcsr, _MM_FLUSH_ZERO_ON};
 
     pub struct Ftz(u32);
 
@@ -421,10 +1783,11 @@ mod ftz {
     }
 }
 
-#[cfg(not(any(all(
+#[cfg(not(all(
     any(target_arch = "x86", target_arch = "x86_64"),
-    target_feature = "sse2"
-)),))]
+    target_feature = "sse2",
+    not(feature = "deterministic")
+)))]
 mod ftz {
     pub enum Ftz {}
 
@@ -433,6 +1796,107 @@ mod ftz {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_eq::assert_float_eq;
+
+    #[test]
+    fn sum_of_squares() {
+        for &len in &[0, 1, 7, 8, 9, 4_800, 19_200, 144_000, 144_003] {
+            let data = (0..len)
+                .map(|i| ((i * 7_919) % 2_001) as f64 / 1_000.0 - 1.0)
+                .collect::<Vec<_>>();
+            let data_f32 = data.iter().map(|x| *x as f32).collect::<Vec<_>>();
+            let (first, second) = data.split_at(len / 3);
+
+            let serial = sum_of_squares_serial(0.0, &data);
+            let serial_f32 = sum_of_squares_serial(0.0, &data_f32);
+
+            // Exactly the serial sum over both parts by default, otherwise it only differs because
+            // of rounding errors
+            #[cfg(any(not(feature = "fast-energy-sum"), feature = "deterministic"))]
+            {
+                assert_eq!(
+                    super::sum_of_squares(first, second).to_bits(),
+                    serial.to_bits()
+                );
+                assert_eq!(
+                    super::sum_of_squares(&data_f32, &[]).to_bits(),
+                    serial_f32.to_bits()
+                );
+            }
+            assert_float_eq!(
+                super::sum_of_squares(first, second),
+                serial,
+                rmax <= 0.0000000001
+            );
+            assert_float_eq!(
+                super::sum_of_squares(&data_f32, &[]),
+                serial_f32,
+                rmax <= 0.0001
+            );
+
+            #[cfg(all(feature = "fast-energy-sum", not(feature = "deterministic")))]
+            {
+                let lanes = sum_of_squares_lanes(&data);
+                assert_float_eq!(lanes, serial, rmax <= 0.0000000001);
+
+                // The same partial sums are used with AVX
+                #[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+                {
+                    if is_x86_feature_detected!("avx") {
+                        unsafe {
+                            assert_eq!(sum_of_squares_avx(&data).to_bits(), lanes.to_bits());
+                            assert_eq!(
+                                sum_of_squares_avx(&data_f32).to_bits(),
+                                sum_of_squares_lanes(&data_f32).to_bits()
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn frequency_response() {
+        // K-weighting: high pass below 100Hz and a shelf of about +4dB above 2kHz
+        for &rate in &[44_100, 48_000] {
+            let filter = Filter::new(rate, 1, Weighting::K, false, false).unwrap();
+            for &(freq, expected, tolerance) in &[
+                (20.0, -13.27, 0.05),
+                (50.0, -3.93, 0.05),
+                (100.0, -1.13, 0.05),
+                (500.0, 0.04, 0.05),
+                (1_000.0, 0.69, 0.05),
+                (2_000.0, 3.07, 0.05),
+                (5_000.0, 4.0, 0.1),
+                (10_000.0, 4.0, 0.1),
+                (16_000.0, 4.0, 0.1),
+            ] {
+                assert_float_eq!(
+                    filter.frequency_response(freq),
+                    expected,
+                    abs <= tolerance,
+                    "{}Hz at {}Hz",
+                    freq,
+                    rate
+                );
+            }
+        }
+
+        // A- and C-weighting: normalized at 1kHz, IEC 61672-1 values at 100Hz and 4kHz
+        for &(weighting, low, high) in &[(Weighting::A, -19.1, 1.0), (Weighting::C, -0.3, -0.8)] {
+            let filter = Filter::new(48_000, 1, weighting, false, false).unwrap();
+            assert_float_eq!(filter.frequency_response(1_000.0), 0.0, abs <= 0.000001);
+            assert_float_eq!(filter.frequency_response(100.0), low, abs <= 0.1);
+            // The bilinear transform compresses the response towards the Nyquist frequency
+            assert_float_eq!(filter.frequency_response(4_000.0), high, abs <= 0.2);
+        }
+    }
+}
+
 #[cfg(feature = "c-tests")]
 use std::os::raw::c_void;
 
@@ -489,7 +1953,7 @@ extern "C" {
 
 #[cfg(feature = "c-tests")]
 #[cfg(test)]
-mod tests {
+mod c_tests {
     use super::*;
     use crate::tests::Signal;
     use float_eq::assert_float_eq;
@@ -536,6 +2000,7 @@ mod tests {
                 *r,
                 *c,
                 ulps <= 2,
+                abs <= crate::tests::C_ABS_TOLERANCE,
                 "Rust and C implementation differ at sample {}",
                 i
             );
@@ -548,6 +2013,11 @@ mod tests {
         calculate_sample_peak: bool,
         calculate_true_peak: bool,
     ) {
+        // The C implementation does no true peak measurement at all for these rates
+        if calculate_true_peak && signal.rate >= 192_000 {
+            return;
+        }
+
         // Maximum of 400ms but our input is up to 5000ms, so distribute it evenly
         // by shrinking accordingly.
         let frames = signal.data.len() / signal.channels as usize;
@@ -563,9 +2033,11 @@ mod tests {
             let mut f = Filter::new(
                 signal.rate,
                 signal.channels,
+                Weighting::K,
                 calculate_sample_peak,
                 calculate_true_peak,
-            );
+            )
+            .unwrap();
 
             let mut data_out_tmp = vec![0.0f64; frames * signal.channels as usize];
 
@@ -578,7 +2050,9 @@ mod tests {
                 &mut data_out_tmp,
                 0,
                 &channel_map,
-            );
+                None,
+            )
+            .unwrap();
 
             for (c, src) in data_out_tmp.chunks_exact(frames).enumerate() {
                 for (i, src) in src.iter().enumerate() {
@@ -636,6 +2110,11 @@ mod tests {
         calculate_sample_peak: bool,
         calculate_true_peak: bool,
     ) {
+        // The C implementation does no true peak measurement at all for these rates
+        if calculate_true_peak && signal.rate >= 192_000 {
+            return;
+        }
+
         // Maximum of 400ms but our input is up to 5000ms, so distribute it evenly
         // by shrinking accordingly.
         let frames = signal.data.len() / signal.channels as usize;
@@ -651,9 +2130,11 @@ mod tests {
             let mut f = Filter::new(
                 signal.rate,
                 signal.channels,
+                Weighting::K,
                 calculate_sample_peak,
                 calculate_true_peak,
-            );
+            )
+            .unwrap();
 
             let mut data_out_tmp = vec![0.0f64; frames * signal.channels as usize];
 
@@ -666,7 +2147,9 @@ mod tests {
                 &mut data_out_tmp,
                 0,
                 &channel_map,
-            );
+                None,
+            )
+            .unwrap();
 
             for (c, src) in data_out_tmp.chunks_exact(frames).enumerate() {
                 for (i, src) in src.iter().enumerate() {
@@ -724,6 +2207,11 @@ mod tests {
         calculate_sample_peak: bool,
         calculate_true_peak: bool,
     ) {
+        // The C implementation does no true peak measurement at all for these rates
+        if calculate_true_peak && signal.rate >= 192_000 {
+            return;
+        }
+
         // Maximum of 400ms but our input is up to 5000ms, so distribute it evenly
         // by shrinking accordingly.
         let frames = signal.data.len() / signal.channels as usize;
@@ -739,9 +2227,11 @@ mod tests {
             let mut f = Filter::new(
                 signal.rate,
                 signal.channels,
+                Weighting::K,
                 calculate_sample_peak,
                 calculate_true_peak,
-            );
+            )
+            .unwrap();
 
             let mut data_out_tmp = vec![0.0f64; frames * signal.channels as usize];
 
@@ -754,7 +2244,9 @@ mod tests {
                 &mut data_out_tmp,
                 0,
                 &channel_map,
-            );
+                None,
+            )
+            .unwrap();
 
             for (c, src) in data_out_tmp.chunks_exact(frames).enumerate() {
                 for (i, src) in src.iter().enumerate() {
@@ -812,6 +2304,11 @@ mod tests {
         calculate_sample_peak: bool,
         calculate_true_peak: bool,
     ) {
+        // The C implementation does no true peak measurement at all for these rates
+        if calculate_true_peak && signal.rate >= 192_000 {
+            return;
+        }
+
         // Maximum of 400ms but our input is up to 5000ms, so distribute it evenly
         // by shrinking accordingly.
         let frames = signal.data.len() / signal.channels as usize;
@@ -827,9 +2324,11 @@ mod tests {
             let mut f = Filter::new(
                 signal.rate,
                 signal.channels,
+                Weighting::K,
                 calculate_sample_peak,
                 calculate_true_peak,
-            );
+            )
+            .unwrap();
 
             let mut data_out_tmp = vec![0.0f64; frames * signal.channels as usize];
 
@@ -842,7 +2341,9 @@ mod tests {
                 &mut data_out_tmp,
                 0,
                 &channel_map,
-            );
+                None,
+            )
+            .unwrap();
 
             for (c, src) in data_out_tmp.chunks_exact(frames).enumerate() {
                 for (i, src) in src.iter().enumerate() {
@@ -975,6 +2476,7 @@ mod tests {
                 &audio_data,
                 block.audio_data_index / block.channels as usize,
                 &channel_map,
+                &vec![1.0; block.channels as usize],
             )
         };
 
@@ -989,6 +2491,11 @@ mod tests {
             )
         };
 
+        // The squares are summed up in a different order than in the C implementation with the
+        // `fast-energy-sum` feature
+        #[cfg(all(feature = "fast-energy-sum", not(feature = "deterministic")))]
+        assert_float_eq!(energy, energy_c, rmax <= 0.0000000001);
+        #[cfg(any(not(feature = "fast-energy-sum"), feature = "deterministic"))]
         assert_float_eq!(energy, energy_c, ulps <= 2);
     }
 }
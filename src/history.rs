@@ -19,23 +19,27 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-use crate::{energy_to_loudness, Error};
+use crate::{energy_to_loudness, lufs_to_energy, math, Error};
 
-use std::collections::VecDeque;
-use std::fmt;
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
 
 // TODO: Create this at compile-time once f64::powf is a const function
 use crate::histogram_bins::BOUNDARIES as HISTOGRAM_BOUNDARIES;
 use crate::histogram_bins::ENERGIES as HISTOGRAM_ENERGIES;
 
-fn find_histogram_index(energy: f64) -> usize {
+fn find_histogram_index(boundaries: &[f64], energy: f64) -> usize {
     let mut min = 0;
-    let mut max = 1000;
+    let mut max = boundaries.len() - 1;
 
     // Binary search
     loop {
         let mid = (min + max) / 2;
-        if energy >= HISTOGRAM_BOUNDARIES[mid] {
+        if energy >= boundaries[mid] {
             min = mid;
         } else {
             max = mid;
@@ -49,32 +53,214 @@ fn find_histogram_index(energy: f64) -> usize {
     min
 }
 
-/// Histogram of measured energies. See HISTOGRAM_BOUNDARIES and HISTOGRAM_ENERGIES for
-/// the bins of the histogram.
-pub struct Histogram(Box<[u64; 1000]>);
+/// Default resolution of the histogram in bins per LU.
+pub const DEFAULT_HISTOGRAM_RESOLUTION: u32 = 10;
+
+/// Maximum resolution of the histogram in bins per LU.
+pub const MAX_HISTOGRAM_RESOLUTION: u32 = 1000;
+
+/// Absolute gate of ITU-R BS.1770 in LUFS.
+pub const DEFAULT_ABSOLUTE_GATE: f64 = -70.0;
+
+/// Relative gate of ITU-R BS.1770 in LU.
+pub const DEFAULT_RELATIVE_GATE: f64 = -10.0;
+
+/// Absolute and relative gate used for the integrated loudness and the relative threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gating {
+    /// Absolute gate in LUFS.
+    absolute: f64,
+    /// Energy of the absolute gate.
+    absolute_energy: f64,
+    /// Relative gate in LU.
+    relative: f64,
+}
+
+impl Default for Gating {
+    fn default() -> Self {
+        Gating::new(DEFAULT_ABSOLUTE_GATE, DEFAULT_RELATIVE_GATE)
+    }
+}
+
+impl Gating {
+    /// Create a new gating with the absolute gate in LUFS and the relative gate in LU.
+    ///
+    /// Energies below -70 LUFS are never stored in the history so lower absolute gates behave
+    /// like -70 LUFS.
+    pub fn new(absolute: f64, relative: f64) -> Self {
+        // Use exactly the lower boundary of the history for the standard gate so that no stored
+        // energy is gated because of rounding errors
+        let absolute_energy = if absolute == DEFAULT_ABSOLUTE_GATE {
+            HISTOGRAM_BOUNDARIES[0]
+        } else {
+            lufs_to_energy(absolute)
+        };
+
+        Gating {
+            absolute,
+            absolute_energy,
+            relative,
+        }
+    }
+
+    /// Absolute gate in LUFS.
+    pub fn absolute(&self) -> f64 {
+        self.absolute
+    }
+
+    /// Relative gate in LU.
+    pub fn relative(&self) -> f64 {
+        self.relative
+    }
+
+    /// Whether a gating block with the given energy is below the absolute gate and not included
+    /// in the integrated loudness.
+    pub fn is_below_absolute(&self, energy: f64) -> bool {
+        energy < self.absolute_energy || energy < HISTOGRAM_BOUNDARIES[0]
+    }
+}
+
+/// Histogram of measured energies between -70 LUFS and +30 LUFS with a configurable number of
+/// bins per LU. For the default resolution HISTOGRAM_BOUNDARIES and HISTOGRAM_ENERGIES are used
+/// for the bins of the histogram, otherwise they are calculated the same way.
+#[derive(Clone, PartialEq)]
+pub struct Histogram {
+    bins: Box<[u64]>,
+    /// Number of bins per LU.
+    resolution: u32,
+    /// Energy at the center of each bin.
+    energies: Cow<'static, [f64]>,
+    /// Energies between each bin, with one more element than `energies`.
+    boundaries: Cow<'static, [f64]>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Histogram {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(serde::Serialize)]
+        struct HistogramRef<'a> {
+            resolution: u32,
+            bins: &'a [u64],
+        }
+
+        HistogramRef {
+            resolution: self.resolution,
+            bins: &self.bins,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Histogram {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        #[derive(serde::Deserialize)]
+        struct HistogramState {
+            resolution: u32,
+            bins: Vec<u64>,
+        }
+
+        let state = HistogramState::deserialize(deserializer)?;
+        if state.resolution == 0 || state.resolution > MAX_HISTOGRAM_RESOLUTION {
+            return Err(D::Error::custom("Invalid histogram resolution"));
+        }
+
+        let mut h = Histogram::with_resolution(state.resolution);
+        if h.bins.len() != state.bins.len() {
+            return Err(D::Error::invalid_length(
+                state.bins.len(),
+                &alloc::format!(
+                    "{} histogram bins ({} per LU)",
+                    h.bins.len(),
+                    state.resolution
+                )
+                .as_str(),
+            ));
+        }
+        h.bins.copy_from_slice(&state.bins);
+
+        Ok(h)
+    }
+}
 
 impl Histogram {
     fn new() -> Self {
-        Histogram(Box::new([0; 1000]))
+        Self::with_resolution(DEFAULT_HISTOGRAM_RESOLUTION)
+    }
+
+    fn with_resolution(resolution: u32) -> Self {
+        assert!(resolution > 0 && resolution <= MAX_HISTOGRAM_RESOLUTION);
+
+        let (energies, boundaries) = if resolution == DEFAULT_HISTOGRAM_RESOLUTION {
+            (
+                Cow::Borrowed(&HISTOGRAM_ENERGIES[..]),
+                Cow::Borrowed(&HISTOGRAM_BOUNDARIES[..]),
+            )
+        } else {
+            let num_bins = 100 * resolution as usize;
+            let resolution = resolution as f64;
+
+            let energies = (0..num_bins)
+                .map(|i| lufs_to_energy((i as f64 + 0.5) / resolution - 70.0))
+                .collect::<Vec<_>>();
+            let boundaries = (0..=num_bins)
+                .map(|i| lufs_to_energy(i as f64 / resolution - 70.0))
+                .collect::<Vec<_>>();
+
+            (Cow::Owned(energies), Cow::Owned(boundaries))
+        };
+
+        Histogram {
+            bins: vec![0; energies.len()].into_boxed_slice(),
+            resolution,
+            energies,
+            boundaries,
+        }
+    }
+
+    /// Create a new histogram with the given resolution containing the same energies. This is
+    /// lossy as the energies are moved to the bins closest to the centers of their current bins.
+    fn rebin(&self, resolution: u32) -> Self {
+        let mut h = Histogram::with_resolution(resolution);
+        for (count, energy) in Iterator::zip(self.bins.iter(), self.energies.iter()) {
+            if *count > 0 {
+                let idx = find_histogram_index(&h.boundaries, *energy);
+                h.bins[idx] += *count;
+            }
+        }
+
+        h
     }
 
     fn add(&mut self, energy: f64) {
-        let idx = find_histogram_index(energy);
-        self.0[idx] += 1;
+        let idx = find_histogram_index(&self.boundaries, energy);
+        self.bins[idx] += 1;
     }
 
-    fn reset(&mut self) {
-        // TODO: Use slice::fill() once stabilized
-        for v in self.0.iter_mut() {
-            *v = 0;
+    /// Add the counts of all bins of `other`, which must have the same resolution.
+    fn merge(&mut self, other: &Histogram) {
+        assert_eq!(self.resolution, other.resolution);
+
+        for (count, other) in Iterator::zip(self.bins.iter_mut(), other.bins.iter()) {
+            *count += *other;
         }
     }
 
-    fn calc_relative_threshold(&self) -> (u64, f64) {
+    fn reset(&mut self) {
+        self.bins.fill(0);
+    }
+
+    fn calc_relative_threshold(&self, absolute_energy: f64) -> (u64, f64) {
         let mut above_thresh_counter = 0;
         let mut relative_threshold = 0.0;
 
-        for (count, energy) in Iterator::zip(self.0.iter(), HISTOGRAM_ENERGIES.iter()) {
+        let start_index = self.start_index(absolute_energy);
+        for (count, energy) in Iterator::zip(
+            self.bins[start_index..].iter(),
+            self.energies[start_index..].iter(),
+        ) {
             relative_threshold += *count as f64 * *energy;
             above_thresh_counter += *count;
         }
@@ -82,11 +268,29 @@ impl Histogram {
         (above_thresh_counter, relative_threshold)
     }
 
-    fn loudness_range(h: &[u64; 1000]) -> f64 {
+    /// Index of the first bin with an energy above the given relative threshold.
+    fn start_index(&self, relative_threshold: f64) -> usize {
+        if relative_threshold < self.boundaries[0] {
+            0
+        } else {
+            let start_index = find_histogram_index(&self.boundaries, relative_threshold);
+            if relative_threshold > self.energies[start_index] {
+                start_index + 1
+            } else {
+                start_index
+            }
+        }
+    }
+
+    /// Calculate the loudness range for the given bins, which must have the same resolution as
+    /// this histogram.
+    fn loudness_range(&self, h: &[u64]) -> f64 {
+        assert_eq!(h.len(), self.bins.len());
+
         let mut size = 0;
         let mut power = 0.0;
 
-        for (count, energy) in Iterator::zip(h.iter(), HISTOGRAM_ENERGIES.iter()) {
+        for (count, energy) in Iterator::zip(h.iter(), self.energies.iter()) {
             size += *count;
             power += *count as f64 * *energy;
         }
@@ -96,19 +300,10 @@ impl Histogram {
         }
 
         power /= size as f64;
-        let minus_twenty_decibels = f64::powf(10.0, -20.0 / 10.0);
+        let minus_twenty_decibels = math::pow(10.0, -20.0 / 10.0);
         let integrated = minus_twenty_decibels * power;
 
-        let index = if integrated < HISTOGRAM_BOUNDARIES[0] {
-            0
-        } else {
-            let index = find_histogram_index(integrated);
-            if integrated > HISTOGRAM_ENERGIES[index] {
-                index + 1
-            } else {
-                index
-            }
-        };
+        let index = self.start_index(integrated);
         let size = h[index..].iter().sum::<u64>();
         if size == 0 {
             return 0.0;
@@ -124,55 +319,150 @@ impl Histogram {
             size += h[j];
             j += 1;
         }
-        let l_en = HISTOGRAM_ENERGIES[j - 1];
+        let l_en = self.energies[j - 1];
 
         while size <= percentile_high {
             size += h[j];
             j += 1;
         }
-        let h_en = HISTOGRAM_ENERGIES[j - 1];
+        let h_en = self.energies[j - 1];
 
         energy_to_loudness(h_en) - energy_to_loudness(l_en)
     }
 }
 
 /// History of measured energies with a configurable maximum size.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Queue {
     queue: VecDeque<f64>,
     max: usize,
+    /// The energies in ascending order if the loudness range is cached. This is not serialized
+    /// and has to be enabled again after deserializing.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    sorted: Option<Vec<f64>>,
+    /// Loudness range of `sorted`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    range: f64,
+}
+
+/// The cached loudness range is not compared.
+impl PartialEq for Queue {
+    fn eq(&self, other: &Self) -> bool {
+        self.queue == other.queue && self.max == other.max
+    }
 }
 
 impl Queue {
     fn new(max: usize) -> Self {
         Queue {
-            queue: VecDeque::with_capacity(std::cmp::min(max, 5000)),
+            queue: VecDeque::with_capacity(core::cmp::min(max, 5000)),
             max,
+            sorted: None,
+            range: 0.0,
         }
     }
 
     fn add(&mut self, energy: f64) {
         // Remove last element to keep the size
         if self.max == self.queue.len() {
-            self.queue.pop_front();
+            if let Some(removed) = self.queue.pop_front() {
+                if let Some(ref mut sorted) = self.sorted {
+                    let idx = sorted.partition_point(|v| *v < removed);
+                    if sorted.get(idx).map(|v| v.to_bits()) == Some(removed.to_bits()) {
+                        sorted.remove(idx);
+                    } else if let Some(idx) =
+                        sorted.iter().position(|v| v.to_bits() == removed.to_bits())
+                    {
+                        // Only for NaN, which isn't ordered
+                        sorted.remove(idx);
+                    }
+                }
+            }
         }
         self.queue.push_back(energy);
+
+        if let Some(ref mut sorted) = self.sorted {
+            let idx = sorted.partition_point(|v| *v < energy);
+            sorted.insert(idx, energy);
+            self.range = Queue::loudness_range(sorted);
+        }
     }
 
+    /// Keep a sorted copy of the energies and the loudness range of them up to date when adding
+    /// energies.
+    ///
+    /// Each added energy then needs to be inserted into the sorted copy and the loudness range
+    /// summed up again, but querying the loudness range is constant time and doesn't allocate.
+    fn cache_loudness_range(&mut self) {
+        let mut sorted = self.sorted.take().unwrap_or_default();
+        sorted.clear();
+        sorted.extend(self.queue.iter().copied());
+        // Same order as partial_cmp() for the positive energies, but doesn't panic for NaN
+        sorted.sort_unstable_by(f64::total_cmp);
+        self.range = Queue::loudness_range(&sorted);
+        self.sorted = Some(sorted);
+    }
+
+    /// Reserve capacity for `additional` more energies, but not more than the maximum size.
+    fn reserve(&mut self, additional: usize) -> Result<(), Error> {
+        let additional = usize::min(additional, self.max.saturating_sub(self.queue.len()));
+        self.queue
+            .try_reserve(additional)
+            .map_err(|_| Error::NoMem)?;
+        if let Some(ref mut sorted) = self.sorted {
+            sorted.try_reserve(additional).map_err(|_| Error::NoMem)?;
+        }
+
+        Ok(())
+    }
+
+    /// Change the maximum size, removing the oldest energies if there are more than that.
     fn set_max_size(&mut self, max: usize) {
-        if self.queue.len() < max {
-            // FIXME: Use shrink() once stabilized
-            self.queue.resize(max, 0.0);
+        if self.queue.len() > max {
+            let excess = self.queue.len() - max;
+            self.queue.drain(..excess);
             self.queue.shrink_to_fit();
+            if self.sorted.is_some() {
+                self.cache_loudness_range();
+            }
         }
         self.max = max;
     }
 
+    /// Append the energies of `other` after the ones of this queue, removing the oldest energies
+    /// if there are more than the maximum size.
+    fn merge(&mut self, other: &Queue) {
+        // Sort only once at the end instead of inserting each energy
+        let cached = self.sorted.take().is_some();
+        for energy in &other.queue {
+            self.add(*energy);
+        }
+        if cached {
+            self.cache_loudness_range();
+        }
+    }
+
     fn reset(&mut self) {
         self.queue.clear();
+        if let Some(ref mut sorted) = self.sorted {
+            sorted.clear();
+        }
+        self.range = 0.0;
     }
 
-    fn calc_relative_threshold(&self) -> (u64, f64) {
-        (self.queue.len() as u64, self.queue.iter().sum::<f64>())
+    fn calc_relative_threshold(&self, absolute_energy: f64) -> (u64, f64) {
+        let mut above_thresh_counter = 0;
+        let mut relative_threshold = 0.0;
+
+        for v in self.queue.iter() {
+            if *v >= absolute_energy {
+                above_thresh_counter += 1;
+                relative_threshold += *v;
+            }
+        }
+
+        (above_thresh_counter, relative_threshold)
     }
 
     fn loudness_range(q: &[f64]) -> f64 {
@@ -181,7 +471,7 @@ impl Queue {
         }
 
         let power = q.iter().sum::<f64>() / q.len() as f64;
-        let minus_twenty_decibels = f64::powf(10.0, -20.0 / 10.0);
+        let minus_twenty_decibels = math::pow(10.0, -20.0 / 10.0);
         let integrated = minus_twenty_decibels * power;
 
         // TODO: Use iterators here or otherwise get rid of bounds checks
@@ -204,6 +494,8 @@ impl Queue {
 }
 
 /// History of measured energies, either as histogram or a vector.
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum History {
     Queue(Queue),
     Histogram(Histogram),
@@ -238,6 +530,89 @@ impl History {
         }
     }
 
+    /// Convert the history to a histogram with the given resolution or a queue with the given
+    /// maximum size, keeping the measured energies.
+    ///
+    /// Converting from a histogram to a queue is lossy as the exact energies are not known
+    /// anymore. Each energy is replaced by the energy of its histogram bin instead.
+    pub fn set_use_histogram(&mut self, use_histogram: bool, max: usize, resolution: u32) {
+        match (&*self, use_histogram) {
+            (History::Histogram(_), true) | (History::Queue(_), false) => (),
+            (History::Queue(ref q), true) => {
+                let mut h = Histogram::with_resolution(resolution);
+                for energy in &q.queue {
+                    h.add(*energy);
+                }
+                *self = History::Histogram(h);
+            }
+            (History::Histogram(ref h), false) => {
+                let mut q = Queue::new(max);
+                for (count, energy) in Iterator::zip(h.bins.iter(), h.energies.iter()) {
+                    for _ in 0..*count {
+                        q.add(*energy);
+                    }
+                }
+                *self = History::Queue(q);
+            }
+        }
+    }
+
+    /// Cache the loudness range of a queue so that
+    /// [`History::loudness_range`](#method.loudness_range) doesn't have to sort all energies for
+    /// every call, see `Queue::cache_loudness_range()`. This stays enabled until the queue is
+    /// converted to a histogram.
+    ///
+    /// Does nothing if the history is a histogram, which only needs to go over its bins anyway.
+    pub fn cache_loudness_range(&mut self) {
+        if let History::Queue(ref mut q) = self {
+            q.cache_loudness_range();
+        }
+    }
+
+    /// Change the resolution of the histogram in bins per LU. This is lossy if the histogram
+    /// already contains energies, see `Histogram::rebin()`.
+    ///
+    /// Does nothing if the history is a queue.
+    pub fn set_histogram_resolution(&mut self, resolution: u32) {
+        if let History::Histogram(ref mut h) = self {
+            if h.resolution != resolution {
+                *h = h.rebin(resolution);
+            }
+        }
+    }
+
+    /// Resolution of the histogram in bins per LU, or `None` if the history is a queue.
+    pub fn histogram_resolution(&self) -> Option<u32> {
+        match self {
+            History::Histogram(ref h) => Some(h.resolution),
+            History::Queue(_) => None,
+        }
+    }
+
+    /// Loudness at the center of each histogram bin together with the number of energies in that
+    /// bin. If the history is a queue, a histogram with the given resolution is calculated.
+    pub fn histogram(&self, resolution: u32) -> Vec<(f64, u64)> {
+        fn bins(h: &Histogram) -> Vec<(f64, u64)> {
+            Iterator::zip(h.energies.iter(), h.bins.iter())
+                .map(|(energy, count)| (energy_to_loudness(*energy), *count))
+                .collect()
+        }
+
+        match self {
+            History::Histogram(ref h) => bins(h),
+            History::Queue(ref q) => {
+                let mut h = Histogram::with_resolution(resolution);
+                for energy in &q.queue {
+                    h.add(*energy);
+                }
+                bins(&h)
+            }
+        }
+    }
+
+    /// Change the maximum number of energies of the queue, removing the oldest ones if necessary.
+    ///
+    /// Does nothing if the history is a histogram as it does not know the order of the energies.
     pub fn set_max_size(&mut self, max: usize) {
         match self {
             History::Histogram(_) => (),
@@ -245,6 +620,17 @@ impl History {
         }
     }
 
+    /// Reserve capacity for `additional` more energies so that adding them doesn't allocate.
+    ///
+    /// Does nothing if the history is a histogram, which never allocates when adding energies.
+    /// Returns `Error::NoMem` if the capacity can't be allocated.
+    pub fn reserve(&mut self, additional: usize) -> Result<(), Error> {
+        match self {
+            History::Histogram(_) => Ok(()),
+            History::Queue(ref mut q) => q.reserve(additional),
+        }
+    }
+
     pub fn reset(&mut self) {
         match self {
             History::Histogram(ref mut h) => h.reset(),
@@ -252,20 +638,41 @@ impl History {
         }
     }
 
-    fn calc_relative_threshold(&self) -> (u64, f64) {
+    /// Merge the energies of `other` into this history.
+    ///
+    /// For histograms the counts of all bins are summed up, which requires the same resolution.
+    /// For queues the energies of `other` are appended as if they were measured after the ones of
+    /// this queue, keeping the maximum size of this queue. Returns `Error::InvalidMode` if the
+    /// histories are not both histograms with the same resolution or both queues.
+    pub fn merge(&mut self, other: &History) -> Result<(), Error> {
+        match (self, other) {
+            (History::Histogram(ref mut h), History::Histogram(ref other))
+                if h.resolution == other.resolution =>
+            {
+                h.merge(other)
+            }
+            (History::Queue(ref mut q), History::Queue(ref other)) => q.merge(other),
+            _ => return Err(Error::InvalidMode),
+        }
+
+        Ok(())
+    }
+
+    fn calc_relative_threshold(&self, absolute_energy: f64) -> (u64, f64) {
         match self {
-            History::Histogram(ref h) => h.calc_relative_threshold(),
-            History::Queue(ref q) => q.calc_relative_threshold(),
+            History::Histogram(ref h) => h.calc_relative_threshold(absolute_energy),
+            History::Queue(ref q) => q.calc_relative_threshold(absolute_energy),
         }
     }
 
-    pub fn gated_loudness(&self) -> f64 {
-        Self::gated_loudness_multiple(&[self])
+    pub fn gated_loudness(&self, gating: &Gating) -> f64 {
+        Self::gated_loudness_multiple(&[self], gating)
     }
 
-    pub fn gated_loudness_multiple(s: &[&Self]) -> f64 {
+    pub fn gated_loudness_multiple(s: &[&Self], gating: &Gating) -> f64 {
         let (above_thresh_counter, relative_threshold) = s.iter().fold((0, 0.0), |mut acc, h| {
-            let (above_thresh_counter, relative_threshold) = h.calc_relative_threshold();
+            let (above_thresh_counter, relative_threshold) =
+                h.calc_relative_threshold(gating.absolute_energy);
             acc.0 += above_thresh_counter;
             acc.1 += relative_threshold;
 
@@ -273,34 +680,29 @@ impl History {
         });
 
         if above_thresh_counter == 0 {
-            return -std::f64::INFINITY;
+            return -f64::INFINITY;
         }
 
-        let relative_gate = -10.0;
-        let relative_gate_factor = f64::powf(10.0, relative_gate / 10.0);
+        let relative_gate_factor = math::pow(10.0, gating.relative / 10.0);
         let relative_threshold =
             (relative_threshold / above_thresh_counter as f64) * relative_gate_factor;
+        // Gating blocks have to be above both gates
+        let threshold = if relative_threshold > gating.absolute_energy {
+            relative_threshold
+        } else {
+            gating.absolute_energy
+        };
 
         let mut above_thresh_counter = 0;
         let mut gated_loudness = 0.0;
 
-        let start_index = if relative_threshold < HISTOGRAM_BOUNDARIES[0] {
-            0
-        } else {
-            let start_index = find_histogram_index(relative_threshold);
-            if relative_threshold > HISTOGRAM_ENERGIES[start_index] {
-                start_index + 1
-            } else {
-                start_index
-            }
-        };
-
         for h in s {
             match h {
                 History::Histogram(ref h) => {
+                    let start_index = h.start_index(threshold);
                     for (count, energy) in Iterator::zip(
-                        h.0[start_index..].iter(),
-                        HISTOGRAM_ENERGIES[start_index..].iter(),
+                        h.bins[start_index..].iter(),
+                        h.energies[start_index..].iter(),
                     ) {
                         gated_loudness += *count as f64 * *energy;
                         above_thresh_counter += *count;
@@ -308,7 +710,7 @@ impl History {
                 }
                 History::Queue(ref q) => {
                     for v in q.queue.iter() {
-                        if *v >= relative_threshold {
+                        if *v >= threshold {
                             above_thresh_counter += 1;
                             gated_loudness += *v;
                         }
@@ -318,21 +720,21 @@ impl History {
         }
 
         if above_thresh_counter == 0 {
-            return -std::f64::INFINITY;
+            return -f64::INFINITY;
         }
 
         energy_to_loudness(gated_loudness / above_thresh_counter as f64)
     }
 
-    pub fn relative_threshold(&self) -> f64 {
-        let (above_thresh_counter, relative_threshold) = self.calc_relative_threshold();
+    pub fn relative_threshold(&self, gating: &Gating) -> f64 {
+        let (above_thresh_counter, relative_threshold) =
+            self.calc_relative_threshold(gating.absolute_energy);
 
         if above_thresh_counter == 0 {
-            return -70.0;
+            return gating.absolute;
         }
 
-        let relative_gate = -10.0;
-        let relative_gate_factor = f64::powf(10.0, relative_gate / 10.0);
+        let relative_gate_factor = math::pow(10.0, gating.relative / 10.0);
         let relative_threshold =
             (relative_threshold / above_thresh_counter as f64) * relative_gate_factor;
 
@@ -351,29 +753,22 @@ impl History {
         }
 
         match s[0] {
-            History::Histogram(ref h) => {
-                let mut combined;
-
-                let combined = if s.len() == 1 {
-                    &*h.0
-                } else {
-                    combined = [0; 1000];
-
-                    for h in s {
-                        match h {
-                            History::Histogram(ref h) => {
-                                for (i, o) in Iterator::zip(h.0.iter(), combined.iter_mut()) {
-                                    *o += *i;
-                                }
-                            }
-                            _ => return Err(Error::InvalidMode),
-                        }
-                    }
-
-                    &combined
-                };
+            History::Histogram(ref h) if s.len() == 1 => Ok(h.loudness_range(&h.bins)),
+            History::Queue(Queue {
+                sorted: Some(_),
+                range,
+                ..
+            }) if s.len() == 1 => Ok(*range),
+            History::Histogram(_) => {
+                let mut combined = s[0].clone();
+                for other in &s[1..] {
+                    combined.merge(other)?;
+                }
 
-                Ok(Histogram::loudness_range(combined))
+                match combined {
+                    History::Histogram(ref h) => Ok(h.loudness_range(&h.bins)),
+                    History::Queue(_) => unreachable!(),
+                }
             }
             History::Queue(_) => {
                 let mut len = 0;
@@ -400,12 +795,170 @@ impl History {
 
                 combined.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
 
-                Ok(Queue::loudness_range(&*combined))
+                Ok(Queue::loudness_range(&combined))
             }
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_histogram_length() {
+        #[derive(serde::Serialize)]
+        struct HistogramState {
+            resolution: u32,
+            bins: Vec<u64>,
+        }
+
+        let serialized = bincode::serialize(&HistogramState {
+            resolution: 20,
+            bins: vec![0; 1000],
+        })
+        .unwrap();
+        let err = match bincode::deserialize::<Histogram>(&serialized) {
+            Ok(_) => panic!("Deserialized a histogram with the wrong number of bins"),
+            Err(err) => err,
+        };
+        assert_eq!(
+            err.to_string(),
+            "invalid length 1000, expected 2000 histogram bins (20 per LU)"
+        );
+
+        let serialized = bincode::serialize(&HistogramState {
+            resolution: 20,
+            bins: vec![0; 2000],
+        })
+        .unwrap();
+        let h = match bincode::deserialize::<Histogram>(&serialized) {
+            Ok(h) => h,
+            Err(err) => panic!("{}", err),
+        };
+        assert_eq!(h.resolution, 20);
+    }
+
+    #[test]
+    fn merge() {
+        // Loudness between -80 LUFS and +5 LUFS, some of them below the gates
+        let energies = (0..200)
+            .map(|i| math::pow(10.0, (((i * 37) % 85) as f64 - 80.0 + 0.691) / 10.0))
+            .collect::<Vec<_>>();
+
+        for &(use_histogram, max, resolution) in &[
+            (true, usize::MAX, DEFAULT_HISTOGRAM_RESOLUTION),
+            (true, usize::MAX, 100),
+            (false, usize::MAX, DEFAULT_HISTOGRAM_RESOLUTION),
+            (false, 50, DEFAULT_HISTOGRAM_RESOLUTION),
+        ] {
+            let new = || {
+                let mut h = History::new(use_histogram, max);
+                h.set_histogram_resolution(resolution);
+                h
+            };
+
+            let mut single = new();
+            for energy in &energies {
+                single.add(*energy);
+            }
+
+            for &split in &[0, 1, 77, 199, 200] {
+                let mut first = new();
+                let mut second = new();
+                for energy in &energies[..split] {
+                    first.add(*energy);
+                }
+                for energy in &energies[split..] {
+                    second.add(*energy);
+                }
+
+                first.merge(&second).unwrap();
+                if let (History::Queue(ref q), History::Queue(ref q_single)) = (&first, &single) {
+                    assert_eq!(q.queue, q_single.queue);
+                }
+                assert_eq!(first.histogram(resolution), single.histogram(resolution));
+                assert_eq!(
+                    first.gated_loudness(&Gating::default()),
+                    single.gated_loudness(&Gating::default())
+                );
+                assert_eq!(
+                    first.relative_threshold(&Gating::default()),
+                    single.relative_threshold(&Gating::default())
+                );
+                assert_eq!(first.loudness_range(), single.loudness_range());
+            }
+        }
+
+        let mut queue = History::new(false, usize::MAX);
+        let mut histogram = History::new(true, usize::MAX);
+        let mut histogram_100 = History::new(true, usize::MAX);
+        histogram_100.set_histogram_resolution(100);
+        assert_eq!(queue.merge(&histogram), Err(Error::InvalidMode));
+        assert_eq!(histogram.merge(&queue), Err(Error::InvalidMode));
+        assert_eq!(histogram.merge(&histogram_100), Err(Error::InvalidMode));
+        assert_eq!(
+            History::loudness_range_multiple(&[&histogram, &histogram_100]),
+            Err(Error::InvalidMode)
+        );
+    }
+
+    #[test]
+    fn cached_loudness_range() {
+        // Loudness between -80 LUFS and +5 LUFS with many duplicates
+        let energies = (0..500)
+            .map(|i| math::pow(10.0, (((i * 37) % 85) as f64 - 80.0 + 0.691) / 10.0))
+            .collect::<Vec<_>>();
+
+        for &max in &[usize::MAX, 100, 1] {
+            let mut queue = History::new(false, max);
+            let mut cached = History::new(false, max);
+            cached.cache_loudness_range();
+            assert_eq!(cached.loudness_range(), 0.0);
+
+            for energy in &energies {
+                queue.add(*energy);
+                cached.add(*energy);
+                assert_eq!(
+                    cached.loudness_range().to_bits(),
+                    queue.loudness_range().to_bits()
+                );
+            }
+
+            let mut other = History::new(false, usize::MAX);
+            for energy in &energies[..123] {
+                other.add(*energy);
+            }
+            queue.merge(&other).unwrap();
+            cached.merge(&other).unwrap();
+            assert_eq!(
+                cached.loudness_range().to_bits(),
+                queue.loudness_range().to_bits()
+            );
+
+            queue.set_max_size(50);
+            cached.set_max_size(50);
+            assert_eq!(
+                cached.loudness_range().to_bits(),
+                queue.loudness_range().to_bits()
+            );
+            for energy in &energies[..77] {
+                queue.add(*energy);
+                cached.add(*energy);
+            }
+            assert_eq!(
+                cached.loudness_range().to_bits(),
+                queue.loudness_range().to_bits()
+            );
+
+            queue.reset();
+            cached.reset();
+            assert_eq!(cached.loudness_range(), queue.loudness_range());
+        }
+    }
+}
+
 #[cfg(feature = "c-tests")]
 use std::os::raw::c_void;
 
@@ -430,7 +983,7 @@ extern "C" {
 
 #[cfg(feature = "c-tests")]
 #[cfg(test)]
-mod tests {
+mod c_tests {
     use super::*;
     use float_eq::float_eq;
     use quickcheck_macros::quickcheck;
@@ -455,6 +1008,48 @@ mod tests {
         START.call_once(|| unsafe { history_init_c() });
     }
 
+    #[quickcheck]
+    fn compare_c_impl_set_max_size(energy: Vec<Energy>, max: NonZeroU16, new_max: NonZeroU16) {
+        init();
+
+        let mut hist = History::new(false, max.get() as usize);
+        let hist_c = unsafe { history_create_c(0, max.get() as usize) };
+
+        let (first, second) = energy.split_at(energy.len() / 2);
+        for e in first {
+            hist.add(e.0);
+            unsafe {
+                history_add_c(hist_c, e.0);
+            }
+        }
+
+        hist.set_max_size(new_max.get() as usize);
+        unsafe {
+            history_set_max_size_c(hist_c, new_max.get() as usize);
+        }
+
+        for e in second {
+            hist.add(e.0);
+            unsafe {
+                history_add_c(hist_c, e.0);
+            }
+        }
+
+        let val = hist.gated_loudness(&Gating::default());
+        let val_c = unsafe { history_gated_loudness_c(hist_c) };
+
+        unsafe {
+            history_destroy_c(hist_c);
+        }
+
+        assert!(
+            float_eq!(val, val_c, ulps <= 2, abs <= crate::tests::C_ABS_TOLERANCE),
+            "Rust: {} != C: {}",
+            val,
+            val_c
+        );
+    }
+
     #[quickcheck]
     fn compare_c_impl_gated_loudness(
         energy: Vec<Energy>,
@@ -468,7 +1063,7 @@ mod tests {
             hist.add(e.0);
         }
 
-        let val = hist.gated_loudness();
+        let val = hist.gated_loudness(&Gating::default());
 
         let val_c = unsafe {
             let hist_c = history_create_c(if use_histogram { 1 } else { 0 }, max.get() as usize);
@@ -481,7 +1076,7 @@ mod tests {
             val
         };
 
-        if !float_eq!(val, val_c, ulps <= 2) {
+        if !float_eq!(val, val_c, ulps <= 2, abs <= crate::tests::C_ABS_TOLERANCE) {
             Err(format!("{} != {}", val, val_c))
         } else {
             Ok(())
@@ -501,7 +1096,7 @@ mod tests {
             hist.add(e.0);
         }
 
-        let val = hist.relative_threshold();
+        let val = hist.relative_threshold(&Gating::default());
 
         let val_c = unsafe {
             let hist_c = history_create_c(if use_histogram { 1 } else { 0 }, max.get() as usize);
@@ -514,7 +1109,7 @@ mod tests {
             val
         };
 
-        if !float_eq!(val, val_c, ulps <= 2) {
+        if !float_eq!(val, val_c, ulps <= 2, abs <= crate::tests::C_ABS_TOLERANCE) {
             Err(format!("{} != {}", val, val_c))
         } else {
             Ok(())
@@ -547,7 +1142,7 @@ mod tests {
             val
         };
 
-        if !float_eq!(val, val_c, ulps <= 2) {
+        if !float_eq!(val, val_c, ulps <= 2, abs <= crate::tests::C_ABS_TOLERANCE) {
             Err(format!("{} != {}", val, val_c))
         } else {
             Ok(())
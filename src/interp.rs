@@ -19,14 +19,15 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
+use crate::math;
 use crate::utils::FrameAccumulator;
-use std::f64::consts::PI;
+use core::f64::consts::PI;
 
 const ALMOST_ZERO: f64 = 0.000001;
 const TAPS: usize = 48;
 
 // Workaround for missing const-generics
-trait ArrayBuf<Item>: std::borrow::BorrowMut<[Item]> {
+trait ArrayBuf<Item>: core::borrow::BorrowMut<[Item]> {
     const SIZE: usize;
 }
 
@@ -46,7 +47,7 @@ impl<Item> ArrayBuf<Item> for [Item; 12] {
 struct RollingBuffer<A, T> {
     buf: [T; TAPS],
     position: usize,
-    _phantom: std::marker::PhantomData<A>,
+    _phantom: core::marker::PhantomData<A>,
 }
 
 impl<A: ArrayBuf<T>, T: Default + Copy> RollingBuffer<A, T> {
@@ -76,6 +77,13 @@ impl<A: ArrayBuf<T>, T: Default + Copy> RollingBuffer<A, T> {
     }
 }
 
+/// Only the current view is compared, the position in the buffer is an implementation detail.
+impl<A: PartialEq, T> PartialEq for RollingBuffer<A, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_ref() == other.as_ref()
+    }
+}
+
 impl<A, T> AsRef<A> for RollingBuffer<A, T> {
     #[inline(always)]
     fn as_ref(&self) -> &A {
@@ -84,11 +92,11 @@ impl<A, T> AsRef<A> for RollingBuffer<A, T> {
 }
 
 macro_rules! interp_impl {
-    ( $name:ident, $factor:expr ) => {
-        #[derive(Debug, Clone)]
+    ( $name:ident, $factor:expr, $taps:expr ) => {
+        #[derive(Debug, Clone, PartialEq)]
         pub struct $name<F: FrameAccumulator> {
-            filter: [[f32; $factor]; (TAPS / $factor)],
-            buffer: RollingBuffer<[F; TAPS / $factor], F>,
+            filter: [[f32; $factor]; ($taps / $factor)],
+            buffer: RollingBuffer<[F; $taps / $factor], F>,
         }
 
         impl<F> Default for $name<F>
@@ -105,7 +113,7 @@ macro_rules! interp_impl {
             F: FrameAccumulator + Default,
         {
             pub fn new() -> Self {
-                let mut filter: [[_; $factor]; (TAPS / $factor)] = Default::default();
+                let mut filter: [[_; $factor]; ($taps / $factor)] = Default::default();
                 for (j, coeff) in filter
                     .iter_mut()
                     .map(|x| x.iter_mut())
@@ -114,15 +122,15 @@ macro_rules! interp_impl {
                 {
                     let j = j as f64;
                     // Calculate Hanning window,
-                    let window = TAPS + 1;
-                    // Ignore one tap. (Last tap is zero anyways, and we want to hit an even multiple of 48)
+                    let window = $taps + 1;
+                    // Ignore one tap. (Last tap is zero anyways, and we want to hit an even multiple of the factor)
                     let window = (window - 1) as f64;
-                    let w = 0.5 * (1.0 - f64::cos(2.0 * PI * j / window));
+                    let w = 0.5 * (1.0 - math::cos(2.0 * PI * j / window));
 
                     // Calculate sinc and apply hanning window
                     let m = j - window / 2.0;
-                    *coeff = if m.abs() > ALMOST_ZERO {
-                        w * f64::sin(m * PI / $factor as f64) / (m * PI / $factor as f64)
+                    *coeff = if math::fabs(m) > ALMOST_ZERO {
+                        w * math::sin(m * PI / $factor as f64) / (m * PI / $factor as f64)
                     } else {
                         w
                     } as f32;
@@ -134,6 +142,59 @@ macro_rules! interp_impl {
                 }
             }
 
+            /// Create an interpolator with custom filter coefficients instead of the standard
+            /// windowed sinc, e.g. for experimenting with other interpolation filters.
+            ///
+            /// `coeffs` contains the `taps * factor` coefficients of the FIR filter in the
+            /// oversampled domain, i.e. output phase `o` of an input frame delayed by `i` frames
+            /// uses `coeffs[i * factor + o]`. At most 48 coefficients are supported for the factors
+            /// 2 and 4 and 96 for the factor 8, and shorter filters are padded with zeros.
+            ///
+            /// Returns `None` if `coeffs.len() != taps * factor` or if there are too many taps.
+            pub fn with_coefficients(taps: usize, coeffs: &[f64]) -> Option<Self> {
+                if taps > $taps / $factor || coeffs.len() != taps * $factor {
+                    return None;
+                }
+
+                let mut filter: [[_; $factor]; ($taps / $factor)] = Default::default();
+                for (coeff, c) in filter.iter_mut().flatten().zip(coeffs) {
+                    *coeff = *c as f32;
+                }
+
+                Some(Self {
+                    filter,
+                    buffer: RollingBuffer::new(),
+                })
+            }
+
+            /// Filter coefficients in the oversampled domain in the order accepted by
+            /// `with_coefficients()`, including the zero padding.
+            pub fn coefficients(&self) -> impl Iterator<Item = f64> + '_ {
+                self.filter.iter().flatten().map(|c| f64::from(*c))
+            }
+
+            /// Replace the filter coefficients, keeping the delay line.
+            ///
+            /// See `with_coefficients()` for the layout of `coeffs`. Returns `false` and keeps the
+            /// current coefficients if `coeffs.len() != taps * factor` or if there are too many
+            /// taps.
+            pub fn set_coefficients(&mut self, taps: usize, coeffs: &[f64]) -> bool {
+                match Self::with_coefficients(taps, coeffs) {
+                    Some(interp) => {
+                        self.filter = interp.filter;
+                        true
+                    }
+                    None => false,
+                }
+            }
+
+            /// Replace the filter coefficients with the standard windowed sinc, keeping the delay
+            /// line.
+            pub fn set_standard_coefficients(&mut self) {
+                self.filter = Self::new().filter;
+            }
+
+            #[inline(always)]
             pub fn interpolate(&mut self, frame: F) -> [F; $factor] {
                 // Write in Frames in reverse, to enable forward-scanning with filter
                 self.buffer.push_front(frame);
@@ -151,15 +212,70 @@ macro_rules! interp_impl {
                 output
             }
 
+            /// Interpolate all frames of `src` and write the oversampled signal to `dest` with one
+            /// block of samples per channel.
+            ///
+            /// The `src.len() * factor` output samples of channel `c` are written to
+            /// `dest[c * stride..c * stride + src.len() * factor]`, the samples in between the
+            /// channels are left untouched. A `stride` of `src.len() * factor` gives a planar
+            /// layout without gaps.
+            ///
+            /// Panics if `stride` is smaller than `src.len() * factor` or if `dest` is too short for
+            /// the last channel.
+            pub fn process(&mut self, src: &[F], dest: &mut [F::Sample], stride: usize) {
+                let len = src.len() * $factor;
+                assert!(stride >= len);
+                assert!(dest.len() >= (F::CHANNELS - 1) * stride + len);
+
+                for (i, frame) in src.iter().enumerate() {
+                    for (o, new_frame) in self.interpolate(*frame).iter().enumerate() {
+                        for (c, sample) in new_frame.channels().enumerate() {
+                            dest[c * stride + i * $factor + o] = sample;
+                        }
+                    }
+                }
+            }
+
+            /// Clear the delay line, keeping the filter coefficients.
             pub fn reset(&mut self) {
                 self.buffer = RollingBuffer::new();
             }
+
+            /// Delay of the interpolated signal in input frames.
+            ///
+            /// The filter is a windowed sinc and symmetric around its largest tap, so the group
+            /// delay is the position of that tap in the oversampled signal.
+            pub fn latency(&self) -> usize {
+                let (center, _) = self.filter.iter().flatten().enumerate().fold(
+                    (0, 0.0),
+                    |(center, max), (j, coeff)| {
+                        if *coeff > max {
+                            (j, *coeff)
+                        } else {
+                            (center, max)
+                        }
+                    },
+                );
+
+                center / $factor
+            }
+
+            /// Frames currently stored in the buffer, oldest first.
+            ///
+            /// Passing these to a newly created instance via `interpolate()` restores the
+            /// current state.
+            pub fn history(&self) -> impl Iterator<Item = &F> + '_ {
+                let buf: &[F; $taps / $factor] = self.buffer.as_ref();
+                buf.iter().rev()
+            }
         }
     };
 }
 
-interp_impl!(Interp2F, 2);
-interp_impl!(Interp4F, 4);
+interp_impl!(Interp2F, 2, TAPS);
+interp_impl!(Interp4F, 4, TAPS);
+// Same number of taps per phase as with factor 4
+interp_impl!(Interp8F, 8, 2 * TAPS);
 
 #[cfg(feature = "c-tests")]
 use std::os::raw::c_void;
@@ -233,6 +349,45 @@ mod c_tests {
         }
     }
 
+    #[test]
+    fn compare_c_coefficients() {
+        // The impulse response of the C implementation is its filter
+        for &factor in &[2, 4] {
+            let frames = TAPS / factor + 1;
+            let mut data_in = vec![0.0f32; frames];
+            data_in[0] = 1.0;
+            let mut data_out_c = vec![0.0f32; frames * factor];
+
+            unsafe {
+                let interp = interp_create_c(49, factor as u32, 1);
+                interp_process_c(interp, frames, data_in.as_ptr(), data_out_c.as_mut_ptr());
+                interp_destroy_c(interp);
+            }
+
+            let coeffs = if factor == 2 {
+                Interp2F::<[f32; 1]>::new()
+                    .coefficients()
+                    .collect::<Vec<_>>()
+            } else {
+                Interp4F::<[f32; 1]>::new()
+                    .coefficients()
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(coeffs.len(), TAPS);
+
+            for (i, (r, c)) in coeffs.iter().zip(data_out_c.iter()).enumerate() {
+                assert_float_eq!(
+                    *r,
+                    *c as f64,
+                    abs <= 0.000001,
+                    "Rust and C coefficients differ at {} for factor {}",
+                    i,
+                    factor
+                );
+            }
+        }
+    }
+
     #[quickcheck]
     fn compare_c_impl(signal: Signal<f32>) -> quickcheck::TestResult {
         let frames = signal.data.len() / signal.channels as usize;
@@ -279,3 +434,104 @@ mod c_tests {
         quickcheck::TestResult::passed()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_coefficients() {
+        assert!(Interp4F::<[f32; 1]>::with_coefficients(12, &[0.0; 47]).is_none());
+        assert!(Interp4F::<[f32; 1]>::with_coefficients(13, &[0.0; 52]).is_none());
+
+        let standard = Interp4F::<[f32; 2]>::new();
+        let coeffs = standard.coefficients().collect::<Vec<_>>();
+        let interp = Interp4F::<[f32; 2]>::with_coefficients(12, &coeffs).unwrap();
+        assert_eq!(interp.latency(), standard.latency());
+
+        // A filter that only passes through the input frame with a delay of one frame
+        let mut coeffs = [0.0; 8];
+        coeffs[2..4].copy_from_slice(&[1.0; 2]);
+        let mut interp = Interp2F::<[f32; 2]>::with_coefficients(4, &coeffs[..]).unwrap();
+        assert_eq!(interp.coefficients().count(), TAPS);
+        assert_eq!(interp.interpolate([0.5, -0.5]), [[0.0, 0.0]; 2]);
+        assert_eq!(interp.interpolate([0.25, 0.0]), [[0.5, -0.5]; 2]);
+
+        let standard = Interp8F::<[f32; 1]>::new();
+        let coeffs = standard.coefficients().collect::<Vec<_>>();
+        assert_eq!(coeffs.len(), 2 * TAPS);
+        let interp = Interp8F::<[f32; 1]>::with_coefficients(12, &coeffs).unwrap();
+        assert_eq!(interp.latency(), standard.latency());
+    }
+
+    #[test]
+    fn process_stride() {
+        // The oversampled impulse response is the filter, with the gap between channels untouched
+        let standard = Interp4F::<[f32; 2]>::new();
+        let coeffs = standard.coefficients().collect::<Vec<_>>();
+        let frames = TAPS / 4;
+        let stride = TAPS + 3;
+        let mut src = vec![[0.0f32; 2]; frames];
+        src[0] = [1.0, -0.5];
+
+        let mut interp = standard.clone();
+        let mut dest = vec![2.0f32; stride + TAPS];
+        interp.process(&src, &mut dest, stride);
+        for (i, c) in coeffs.iter().enumerate() {
+            assert_eq!(dest[i], *c as f32);
+            assert_eq!(dest[stride + i], -0.5 * *c as f32);
+        }
+        assert_eq!(dest[TAPS..stride], [2.0; 3]);
+
+        // Same as interpolating frame by frame
+        let mut interp = standard.clone();
+        let mut fresh = standard;
+        let mut dest = vec![0.0f32; 2 * TAPS];
+        interp.process(&src[..1], &mut dest, TAPS);
+        interp.process(&src[1..], &mut dest[4..], TAPS);
+        for (i, frame) in src.iter().enumerate() {
+            for (o, [l, r]) in fresh.interpolate(*frame).iter().enumerate() {
+                assert_eq!(dest[i * 4 + o], *l);
+                assert_eq!(dest[TAPS + i * 4 + o], *r);
+            }
+        }
+
+        // A filter that only passes through the input frame with a delay of one frame
+        let mut coeffs = [0.0; 8];
+        coeffs[2..4].copy_from_slice(&[1.0; 2]);
+        let mut interp = Interp2F::<[f32; 1]>::with_coefficients(4, &coeffs[..]).unwrap();
+        let mut dest = [0.0f32; 6];
+        interp.process(&[[0.5], [-0.5], [0.25]], &mut dest, 6);
+        assert_eq!(dest, [0.0, 0.0, 0.5, 0.5, -0.5, -0.5]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn process_stride_too_small() {
+        let mut interp = Interp2F::<[f32; 2]>::new();
+        let mut dest = [0.0f32; 16];
+        interp.process(&[[0.0; 2]; 4], &mut dest, 7);
+    }
+
+    #[test]
+    fn reset() {
+        let mut interp = Interp4F::<[f32; 2]>::new();
+        interp.interpolate([1.0, -1.0]);
+        interp.interpolate([0.5, 0.0]);
+        interp.reset();
+
+        // No ringing from the impulse before resetting
+        let mut fresh = Interp4F::<[f32; 2]>::new();
+        for i in 0..TAPS {
+            let frame = [i as f32 / TAPS as f32, 0.25];
+            assert_eq!(interp.interpolate(frame), fresh.interpolate(frame));
+        }
+
+        let mut interp = Interp2F::<[f32; 1]>::new();
+        interp.interpolate([1.0]);
+        interp.reset();
+        for _ in 0..TAPS {
+            assert_eq!(interp.interpolate([0.0]), [[0.0]; 2]);
+        }
+    }
+}
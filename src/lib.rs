@@ -33,6 +33,50 @@
 //!   * Implements loudness range measurement ([EBU - TECH 3342](https://tech.ebu.ch/docs/tech/tech3342.pdf))
 //!   * True peak scanning
 //!   * Supports all samplerates by recalculation of the filter coefficients
+//!
+//!  The crate can be used in `no_std` environments with an allocator by disabling the default
+//!  `std` feature. The `libm` crate is used for the floating point functions in that case. This
+//!  currently requires a nightly compiler because of the `dasp_sample` dependency.
+//!
+//!  With the `rayon` feature the frequency weighting of the individual channels is done in
+//!  parallel on the global `rayon` thread pool for 8 or more channels. The results are exactly the
+//!  same as without it.
+//!
+//!  The `deterministic` feature guarantees bitwise identical results on all platforms, e.g. for
+//!  comparing against golden files. It disables the runtime selection of SIMD code paths and the
+//!  parallel processing of the `rayon` feature and of the `fast-energy-sum` feature, always uses
+//!  the `libm` floating point functions and handles denormals the same way on all CPUs. This can
+//!  make the measurement slower.
+//!
+//!  With the `fast-energy-sum` feature the energy of the gating blocks is summed up in multiple
+//!  partial sums with SIMD instructions, which makes the short term loudness and loudness range
+//!  measurement about three times faster. The results differ slightly from the C implementation
+//!  because of rounding errors, by default they are the same.
+//!
+//!  The `wasm` feature provides [`wasm-bindgen`](https://crates.io/crates/wasm-bindgen) bindings
+//!  in the [`wasm`](wasm/index.html) module for using the crate from JavaScript, e.g. for
+//!  analyzing Web Audio `AudioBuffer`s in the browser. See `examples/wasm` for an example.
+//!
+//!  The `futures` feature provides an adapter in the [`stream`](stream/index.html) module for
+//!  analyzing the audio of an asynchronous `Stream` and getting periodic
+//!  [`Measurement`](struct.Measurement.html)s. See `examples/stream.rs` for an example.
+//!
+//!  The [`downmix`](downmix/index.html) module provides a wrapper for measuring the loudness of
+//!  a mono downmix of multichannel input, e.g. for checking the mono compatibility.
+//!
+//!  The [`sync`](sync/index.html) module provides a wrapper for sharing an instance between
+//!  threads, e.g. for feeding the audio on a real-time thread and polling the measurements on a
+//!  UI thread. It requires the default `std` feature.
+//!
+//!  The `hound` feature provides helpers in the [`wav`](wav/index.html) module for analyzing WAV
+//!  files with the [`hound`](https://crates.io/crates/hound) crate. See `examples/analyze_wav.rs`
+//!  for an example.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+mod math;
 
 mod ebur128;
 pub use self::ebur128::*;
@@ -70,11 +114,27 @@ pub use utils::{energy_to_loudness, Interleaved, Planar, Samples};
 #[cfg(not(feature = "internal-tests"))]
 pub(crate) use utils::{energy_to_loudness, Interleaved, Planar, Samples};
 
+pub use utils::{energy_to_lufs, lufs_to_energy, ChannelIter, Sample, ENERGY_TO_LUFS_OFFSET};
+
 #[cfg(test)]
 pub mod tests {
-    pub use super::utils::tests::Signal;
+    pub use super::utils::tests::{Signal, C_ABS_TOLERANCE};
 }
 
 #[cfg(feature = "capi")]
 #[allow(clippy::missing_safety_doc)]
 pub mod capi;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "futures")]
+pub mod stream;
+
+pub mod downmix;
+
+#[cfg(feature = "std")]
+pub mod sync;
+
+#[cfg(feature = "hound")]
+pub mod wav;
@@ -0,0 +1,92 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Floating point functions that are not available in `core`.
+//!
+//! With the `std` feature these forward to the inherent methods of `f32` and `f64`, otherwise
+//! the `libm` implementations are used. With the `deterministic` feature the `libm`
+//! implementations are always used as the platform's implementations can differ in the last bits.
+
+#[cfg(all(feature = "std", not(feature = "deterministic")))]
+mod imp {
+    #[inline(always)]
+    pub fn fabs(x: f64) -> f64 {
+        x.abs()
+    }
+
+    #[inline(always)]
+    pub fn fabsf(x: f32) -> f32 {
+        x.abs()
+    }
+
+    #[inline(always)]
+    pub fn pow(x: f64, y: f64) -> f64 {
+        x.powf(y)
+    }
+
+    #[inline(always)]
+    pub fn log10(x: f64) -> f64 {
+        x.log10()
+    }
+
+    #[cfg(test)]
+    #[inline(always)]
+    pub fn log(x: f64) -> f64 {
+        x.ln()
+    }
+
+    #[inline(always)]
+    pub fn sqrt(x: f64) -> f64 {
+        x.sqrt()
+    }
+
+    #[inline(always)]
+    pub fn sin(x: f64) -> f64 {
+        x.sin()
+    }
+
+    #[inline(always)]
+    pub fn cos(x: f64) -> f64 {
+        x.cos()
+    }
+
+    #[inline(always)]
+    pub fn tan(x: f64) -> f64 {
+        x.tan()
+    }
+
+    #[cfg(feature = "precision-true-peak")]
+    #[inline(always)]
+    pub fn fmaf(x: f32, y: f32, z: f32) -> f32 {
+        x.mul_add(y, z)
+    }
+}
+
+#[cfg(any(not(feature = "std"), feature = "deterministic"))]
+mod imp {
+    #[cfg(feature = "precision-true-peak")]
+    pub use libm::fmaf;
+    #[cfg(test)]
+    pub use libm::log;
+    pub use libm::{cos, fabs, fabsf, log10, pow, sin, sqrt, tan};
+}
+
+pub use imp::*;
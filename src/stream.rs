@@ -0,0 +1,251 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Adapter for analyzing the audio of an asynchronous [`Stream`].
+//!
+//! See `examples/stream.rs` for an example.
+
+use crate::ebur128::{EbuR128, Error, Measurement};
+
+use alloc::vec::Vec;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use futures_core::stream::{FusedStream, Stream};
+
+/// Stream adapter feeding chunks of interleaved `f32` samples to an [`EbuR128`] instance.
+///
+/// Yields a [`Measurement`] every time the configured number of 100ms blocks was added, and a last
+/// one with the remaining frames at the end of the input stream. Chunks don't have to contain
+/// complete frames, partial frames are kept until the next chunk completes them. A partial frame
+/// at the end of the input stream is dropped.
+///
+/// Errors from adding the samples are yielded as `Err` and end the stream.
+#[derive(Debug)]
+pub struct MeasurementStream<St, T> {
+    inner: St,
+    ebur128: EbuR128,
+    chunk: Option<T>,
+    pos: usize,
+    partial: Vec<f32>,
+    interval: usize,
+    frames_left: usize,
+    frames_pending: bool,
+    done: bool,
+}
+
+// The chunks are never pinned.
+impl<St: Unpin, T> Unpin for MeasurementStream<St, T> {}
+
+impl<St, T> MeasurementStream<St, T>
+where
+    St: Stream<Item = T> + Unpin,
+    T: AsRef<[f32]>,
+{
+    /// Create a new adapter that yields a [`Measurement`] every `blocks` 100ms blocks.
+    ///
+    /// Returns `Error::InvalidMode` if `blocks` is 0.
+    pub fn new(stream: St, ebur128: EbuR128, blocks: usize) -> Result<Self, Error> {
+        if blocks == 0 {
+            return Err(Error::InvalidMode);
+        }
+
+        let samples_in_100ms = (ebur128.rate() as usize + 5) / 10;
+        let interval = samples_in_100ms.checked_mul(blocks).ok_or(Error::NoMem)?;
+        let partial = Vec::with_capacity(ebur128.channels() as usize);
+
+        Ok(MeasurementStream {
+            inner: stream,
+            ebur128,
+            chunk: None,
+            pos: 0,
+            partial,
+            interval,
+            frames_left: interval,
+            frames_pending: false,
+            done: false,
+        })
+    }
+
+    /// Get the [`EbuR128`] instance.
+    pub fn ebur128(&self) -> &EbuR128 {
+        &self.ebur128
+    }
+
+    /// Get the [`EbuR128`] instance back, e.g. for getting the final results after the stream
+    /// ended.
+    pub fn into_inner(self) -> EbuR128 {
+        self.ebur128
+    }
+
+    /// Add as many samples of the current chunk as possible without crossing the end of the
+    /// current interval.
+    fn process_chunk(&mut self) -> Result<(), Error> {
+        let channels = self.ebur128.channels() as usize;
+        let chunk = match self.chunk {
+            Some(ref chunk) => chunk.as_ref(),
+            None => return Ok(()),
+        };
+        let data = &chunk[self.pos..];
+
+        if !self.partial.is_empty() {
+            // Complete the partial frame from the previous chunk first
+            let n = usize::min(channels - self.partial.len(), data.len());
+            self.partial.extend_from_slice(&data[..n]);
+            self.pos += n;
+
+            if self.partial.len() == channels {
+                self.ebur128.add_frames_f32(&self.partial)?;
+                self.partial.clear();
+                self.frames_left -= 1;
+                self.frames_pending = true;
+            }
+        } else {
+            let frames = usize::min(data.len() / channels, self.frames_left);
+            if frames > 0 {
+                self.ebur128.add_frames_f32(&data[..frames * channels])?;
+                self.pos += frames * channels;
+                self.frames_left -= frames;
+                self.frames_pending = true;
+            }
+
+            let rest = &chunk[self.pos..];
+            if rest.len() < channels {
+                self.partial.extend_from_slice(rest);
+                self.pos = chunk.len();
+            }
+        }
+
+        if self.pos == chunk.len() {
+            self.chunk = None;
+            self.pos = 0;
+        }
+
+        Ok(())
+    }
+}
+
+impl<St, T> Stream for MeasurementStream<St, T>
+where
+    St: Stream<Item = T> + Unpin,
+    T: AsRef<[f32]>,
+{
+    type Item = Result<Measurement, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+
+            if this.frames_left == 0 {
+                this.frames_left = this.interval;
+                this.frames_pending = false;
+                return Poll::Ready(Some(Ok(this.ebur128.measurement())));
+            }
+
+            if this.chunk.is_some() {
+                if let Err(err) = this.process_chunk() {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(err)));
+                }
+                continue;
+            }
+
+            match Pin::new(&mut this.inner).poll_next(cx) {
+                Poll::Ready(Some(chunk)) => {
+                    this.chunk = Some(chunk);
+                    this.pos = 0;
+                }
+                Poll::Ready(None) => {
+                    this.done = true;
+                    if this.frames_pending {
+                        return Poll::Ready(Some(Ok(this.ebur128.measurement())));
+                    }
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<St, T> FusedStream for MeasurementStream<St, T>
+where
+    St: Stream<Item = T> + Unpin,
+    T: AsRef<[f32]>,
+{
+    fn is_terminated(&self) -> bool {
+        self.done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mode;
+
+    use futures::executor::block_on;
+    use futures::stream::{self, StreamExt};
+
+    #[test]
+    fn partial_frames() {
+        let data = (0..48_000 * 2)
+            .map(|i| f32::sin(i as f32 / 2.0 * 0.05))
+            .collect::<Vec<_>>();
+
+        let mut ebu = EbuR128::new(2, 48_000, Mode::I | Mode::SAMPLE_PEAK).unwrap();
+        ebu.add_frames_f32(&data[..48_000 * 2]).unwrap();
+
+        // Odd chunk sizes so that frames are split across chunks
+        let chunks = data.chunks(1_001).map(|c| c.to_vec()).collect::<Vec<_>>();
+        let stream = MeasurementStream::new(
+            stream::iter(chunks),
+            EbuR128::new(2, 48_000, Mode::I | Mode::SAMPLE_PEAK).unwrap(),
+            3,
+        )
+        .unwrap();
+
+        let measurements = block_on(stream.collect::<Vec<_>>());
+        // 3 complete intervals of 300ms and one with the remaining 100ms
+        assert_eq!(measurements.len(), 4);
+        assert_eq!(
+            measurements.last().unwrap().as_ref().unwrap(),
+            &ebu.measurement()
+        );
+    }
+
+    #[test]
+    fn error() {
+        let chunks = vec![vec![0.0f32; 4_800], vec![f32::NAN; 2]];
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        ebu.set_non_finite_handling(crate::NonFiniteHandling::Reject);
+        let mut stream = MeasurementStream::new(stream::iter(chunks), ebu, 1).unwrap();
+
+        block_on(async {
+            assert!(stream.next().await.unwrap().is_ok());
+            assert_eq!(stream.next().await.unwrap(), Err(Error::NonFiniteSample));
+            assert!(stream.next().await.is_none());
+            assert!(stream.is_terminated());
+        });
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Wrapper for sharing an [`EbuR128`] instance between threads.
+//!
+//! A typical use is live metering, where the audio thread adds the frames and a UI thread polls
+//! the measurements.
+
+use crate::ebur128::{EbuR128, Error, Measurement};
+use crate::utils::Sample;
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Handle to an [`EbuR128`] instance shared between threads.
+///
+/// Cloning the handle gives another handle to the same instance, e.g. one for the audio thread
+/// and one for the UI thread. Every call locks the instance for its duration, so values read on
+/// one thread never observe a partially processed batch of frames added on another thread.
+///
+/// # Real-time safety
+///
+/// The instance is protected by a [`Mutex`], so adding frames is *not* lock-free. The audio
+/// thread only has to wait while another thread is reading a value, which is short as the
+/// getters don't process any audio. Reading a [`Measurement`] allocates for the peaks only while
+/// holding the lock if sample or true peak measurement is enabled. Adding a steady stream of
+/// equally sized batches of frames doesn't allocate after the first batch when using
+/// `Mode::HISTOGRAM`. With the default queue the history grows with every gating block.
+///
+/// If a thread panics while holding the lock, the instance is still used by the other handles
+/// afterwards.
+#[derive(Debug, Clone)]
+pub struct SharedEbuR128 {
+    inner: Arc<Mutex<EbuR128>>,
+}
+
+impl SharedEbuR128 {
+    /// Create a shared handle for `ebur128`.
+    pub fn new(ebur128: EbuR128) -> Self {
+        SharedEbuR128 {
+            inner: Arc::new(Mutex::new(ebur128)),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, EbuR128> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Add interleaved frames to be processed.
+    ///
+    /// See [`EbuR128::add_frames`](struct.EbuR128.html#method.add_frames) for details.
+    pub fn add_frames<T: Sample>(&self, frames: &[T]) -> Result<(), Error> {
+        self.lock().add_frames(frames)
+    }
+
+    /// Add planar frames to be processed.
+    ///
+    /// See [`EbuR128::add_frames_planar`](struct.EbuR128.html#method.add_frames_planar) for
+    /// details.
+    pub fn add_frames_planar<T: Sample>(&self, frames: &[&[T]]) -> Result<(), Error> {
+        self.lock().add_frames_planar(frames)
+    }
+
+    /// Get all measurement results for the enabled modes at once.
+    ///
+    /// See [`EbuR128::measurement`](struct.EbuR128.html#method.measurement) for details.
+    pub fn measurement(&self) -> Measurement {
+        self.lock().measurement()
+    }
+
+    /// Get momentary loudness (last 400ms) in LUFS.
+    ///
+    /// See [`EbuR128::loudness_momentary`](struct.EbuR128.html#method.loudness_momentary) for
+    /// details.
+    pub fn loudness_momentary(&self) -> Result<f64, Error> {
+        self.lock().loudness_momentary()
+    }
+
+    /// Get short-term loudness (last 3s) in LUFS.
+    ///
+    /// See [`EbuR128::loudness_shortterm`](struct.EbuR128.html#method.loudness_shortterm) for
+    /// details.
+    pub fn loudness_shortterm(&self) -> Result<f64, Error> {
+        self.lock().loudness_shortterm()
+    }
+
+    /// Get global integrated loudness in LUFS.
+    ///
+    /// See [`EbuR128::loudness_global`](struct.EbuR128.html#method.loudness_global) for details.
+    pub fn loudness_global(&self) -> Result<f64, Error> {
+        self.lock().loudness_global()
+    }
+
+    /// Call `func` with the locked instance, e.g. for calling any other function of it or for
+    /// reading multiple values consistently.
+    pub fn with<R, F: FnOnce(&mut EbuR128) -> R>(&self, func: F) -> R {
+        func(&mut self.lock())
+    }
+}
+
+impl From<EbuR128> for SharedEbuR128 {
+    fn from(ebur128: EbuR128) -> Self {
+        SharedEbuR128::new(ebur128)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mode;
+
+    #[test]
+    fn producer_consumer() {
+        // 5s of a stereo sine
+        let data = (0..48_000 * 5 * 2)
+            .map(|i| 0.5 * f32::sin((i / 2) as f32 * 0.05))
+            .collect::<Vec<_>>();
+        let mode = Mode::I | Mode::S | Mode::TRUE_PEAK;
+
+        let mut expected = EbuR128::new(2, 48_000, mode).unwrap();
+        expected.add_frames_f32(&data).unwrap();
+
+        let shared = SharedEbuR128::new(EbuR128::new(2, 48_000, mode).unwrap());
+        let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        let producer = {
+            let shared = shared.clone();
+            let done = done.clone();
+            std::thread::spawn(move || {
+                for chunk in data.chunks(480 * 2) {
+                    shared.add_frames(chunk).unwrap();
+                }
+                done.store(true, std::sync::atomic::Ordering::SeqCst);
+            })
+        };
+
+        let consumer = {
+            let shared = shared.clone();
+            std::thread::spawn(move || loop {
+                // Read once more after the producer is done so that the last read sees all frames
+                let finished = done.load(std::sync::atomic::Ordering::SeqCst);
+                let measurement = shared.measurement();
+                if let Some(momentary) = measurement.momentary {
+                    assert!(momentary <= 0.0);
+                }
+                if finished {
+                    break measurement;
+                }
+                std::thread::yield_now();
+            })
+        };
+
+        producer.join().unwrap();
+        assert_eq!(consumer.join().unwrap(), expected.measurement());
+
+        assert_eq!(shared.measurement(), expected.measurement());
+        assert_eq!(shared.with(|ebu| ebu.mode()), mode);
+    }
+}
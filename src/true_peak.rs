@@ -19,14 +19,20 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-use crate::interp::{Interp2F, Interp4F};
+use crate::interp::{Interp2F, Interp4F, Interp8F};
+use crate::math;
 use crate::utils::{FrameAccumulator, Sample};
+use crate::{Error, TruePeakQuality};
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
 use dasp_frame::Frame;
 use smallvec::{smallvec, SmallVec};
 
 use UpsamplingScanner::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 enum UpsamplingScanner {
     Mono2F(Interp2F<[f32; 1]>),
     Stereo2F(Interp2F<[f32; 2]>),
@@ -40,23 +46,37 @@ enum UpsamplingScanner {
     OctoSurround4F(Interp4F<[f32; 8]>),
     Generic2F(Box<[Interp2F<[f32; 1]>]>),
     Generic4F(Box<[Interp4F<[f32; 1]>]>),
+    /// Only used for `TruePeakQuality::Accurate`, so there are no specialized variants.
+    Generic8F(Box<[Interp8F<[f32; 1]>]>),
+    /// No oversampling for high sample rates, inter-sample peaks are already captured well enough.
+    NoInterp,
 }
 
 impl UpsamplingScanner {
-    fn new(rate: u32, channels: u32) -> Option<Self> {
+    fn new(rate: u32, channels: u32, quality: TruePeakQuality) -> Result<Self, Error> {
         enum Factor {
+            Eight,
             Four,
             Two,
+            One,
         }
-        let interp_factor = if rate < 96_000 {
-            Factor::Four
-        } else if rate < 192_000 {
-            Factor::Two
-        } else {
-            return None;
+        let interp_factor = match quality {
+            _ if rate > 384_000 => return Err(Error::UnsupportedRate),
+            TruePeakQuality::Standard if rate < 96_000 => Factor::Four,
+            TruePeakQuality::Standard if rate < 192_000 => Factor::Two,
+            TruePeakQuality::Standard => Factor::One,
+            TruePeakQuality::Fast if rate < 96_000 => Factor::Two,
+            TruePeakQuality::Fast => Factor::One,
+            TruePeakQuality::Accurate if rate < 96_000 => Factor::Eight,
+            TruePeakQuality::Accurate if rate < 192_000 => Factor::Four,
+            TruePeakQuality::Accurate => Factor::Two,
         };
 
-        Some(match (channels as usize, interp_factor) {
+        if channels == 0 {
+            return Err(Error::NoMem);
+        }
+
+        Ok(match (channels as usize, interp_factor) {
             (1, Factor::Two) => Mono2F(Interp2F::new()),
             (2, Factor::Two) => Stereo2F(Interp2F::new()),
             (4, Factor::Two) => Quad2F(Interp2F::new()),
@@ -69,26 +89,108 @@ impl UpsamplingScanner {
             (8, Factor::Four) => OctoSurround4F(Interp4F::new()),
             (c, Factor::Two) => Generic2F(vec![Interp2F::new(); c].into()),
             (c, Factor::Four) => Generic4F(vec![Interp4F::new(); c].into()),
+            (c, Factor::Eight) => Generic8F(vec![Interp8F::new(); c].into()),
+            (_, Factor::One) => NoInterp,
         })
     }
 
+    /// Update the maximum true peak of each channel in `peaks`, and count the overs of each
+    /// channel in `overs` if given.
+    ///
+    /// `src` must have one channel per interpolator, and `peaks` and `overs` exactly one value
+    /// per channel. Otherwise the loops over the channels would silently stop at the shorter of
+    /// them and only update part of the peaks, so this panics instead. The callers check this
+    /// beforehand and return an error.
     pub fn check_true_peak<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
         &mut self,
         src: S,
         peaks: &mut [f64],
+        overs: Option<(f32, &mut [u64])>,
+    ) {
+        assert!(
+            src.channels() == peaks.len()
+                && overs
+                    .as_ref()
+                    .is_none_or(|(_, overs)| overs.len() == peaks.len())
+        );
+
+        #[cfg(all(
+            feature = "std",
+            not(feature = "deterministic"),
+            any(target_arch = "x86", target_arch = "x86_64")
+        ))]
+        {
+            if is_x86_feature_detected!("avx") {
+                // Safety: AVX support was checked right above
+                unsafe {
+                    return self.check_true_peak_avx(src, peaks, overs);
+                }
+            }
+        }
+
+        self.check_true_peak_impl(src, peaks, overs)
+    }
+
+    /// Same as `check_true_peak_impl()` but compiled with AVX enabled, which allows the compiler
+    /// to auto-vectorize the interpolation with wider registers. FMA is deliberately not enabled
+    /// so that the results are exactly the same as without AVX.
+    #[cfg(all(
+        feature = "std",
+        not(feature = "deterministic"),
+        any(target_arch = "x86", target_arch = "x86_64")
+    ))]
+    #[target_feature(enable = "avx")]
+    unsafe fn check_true_peak_avx<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+        peaks: &mut [f64],
+        overs: Option<(f32, &mut [u64])>,
+    ) {
+        self.check_true_peak_impl(src, peaks, overs)
+    }
+
+    /// Counting the overs is a separate loop so that it doesn't slow down the normal case.
+    #[inline(always)]
+    fn check_true_peak_impl<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+        peaks: &mut [f64],
+        mut overs: Option<(f32, &mut [u64])>,
     ) {
         macro_rules! tp_specialized_impl {
             ( $channels:expr, $interpolator:expr ) => {{
                 const CHANNELS: usize = $channels;
-                assert!(src.channels() == CHANNELS && peaks.len() == CHANNELS);
+                debug_assert!(src.channels() == CHANNELS && peaks.len() == CHANNELS);
                 let mut tmp_peaks = <[f32; CHANNELS]>::from_fn(|i| peaks[i] as f32);
 
-                src.foreach_frame(|frame: [T; CHANNELS]| {
-                    let frame_f32: [f32; CHANNELS] = Frame::map(frame, |s| s.to_sample::<f32>());
-                    for new_frame in &$interpolator.interpolate(frame_f32) {
-                        tmp_peaks.retain_max_samples(&Frame::map(*new_frame, |s| s.abs()));
+                if let Some((threshold, overs)) = overs {
+                    debug_assert!(overs.len() == CHANNELS);
+                    let mut tmp_overs = [0u64; CHANNELS];
+                    src.foreach_frame(|frame: [T; CHANNELS]| {
+                        let frame_f32: [f32; CHANNELS] =
+                            Frame::map(frame, |s| s.to_sample::<f32>());
+                        for new_frame in &$interpolator.interpolate(frame_f32) {
+                            let new_frame = Frame::map(*new_frame, math::fabsf);
+                            tmp_peaks.retain_max_samples(&new_frame);
+                            for (count, sample) in Iterator::zip(tmp_overs.iter_mut(), &new_frame) {
+                                if *sample > threshold {
+                                    *count += 1;
+                                }
+                            }
+                        }
+                    });
+                    for (dst, src) in Iterator::zip(overs.iter_mut(), &tmp_overs) {
+                        *dst += *src;
                     }
-                });
+                } else {
+                    src.foreach_frame(|frame: [T; CHANNELS]| {
+                        let frame_f32: [f32; CHANNELS] =
+                            Frame::map(frame, |s| s.to_sample::<f32>());
+                        for new_frame in &$interpolator.interpolate(frame_f32) {
+                            tmp_peaks.retain_max_samples(&Frame::map(*new_frame, math::fabsf));
+                        }
+                    });
+                }
                 for (dst, src) in Iterator::zip(peaks.into_iter(), &tmp_peaks) {
                     *dst = *src as f64;
                 }
@@ -97,18 +199,36 @@ impl UpsamplingScanner {
 
         macro_rules! tp_generic_impl {
             ( $interpolators:expr ) => {{
-                assert!(src.channels() == $interpolators.len() && src.channels() == peaks.len());
+                debug_assert!(
+                    src.channels() == $interpolators.len() && src.channels() == peaks.len()
+                );
                 for (c, (interpolator, channel_peak)) in
                     Iterator::zip($interpolators.iter_mut(), peaks.iter_mut()).enumerate()
                 {
-                    src.foreach_sample(c, move |s| {
-                        for [new_sample] in &interpolator.interpolate([s.to_sample::<f32>()]) {
-                            let new_sample = new_sample.abs() as f64;
-                            if new_sample > *channel_peak {
-                                *channel_peak = new_sample;
+                    if let Some((threshold, ref mut overs)) = overs {
+                        let channel_overs = &mut overs[c];
+                        src.foreach_sample(c, move |s| {
+                            for [new_sample] in &interpolator.interpolate([s.to_sample::<f32>()]) {
+                                let new_sample = math::fabsf(*new_sample);
+                                if new_sample > threshold {
+                                    *channel_overs += 1;
+                                }
+                                let new_sample = new_sample as f64;
+                                if new_sample > *channel_peak {
+                                    *channel_peak = new_sample;
+                                }
                             }
-                        }
-                    });
+                        });
+                    } else {
+                        src.foreach_sample(c, move |s| {
+                            for [new_sample] in &interpolator.interpolate([s.to_sample::<f32>()]) {
+                                let new_sample = math::fabsf(*new_sample) as f64;
+                                if new_sample > *channel_peak {
+                                    *channel_peak = new_sample;
+                                }
+                            }
+                        });
+                    }
                 }
             }};
         }
@@ -126,6 +246,224 @@ impl UpsamplingScanner {
             OctoSurround4F(interpolator) => tp_specialized_impl!(8, interpolator),
             Generic2F(interpolators) => tp_generic_impl!(interpolators),
             Generic4F(interpolators) => tp_generic_impl!(interpolators),
+            Generic8F(interpolators) => tp_generic_impl!(interpolators),
+            NoInterp => {
+                for (c, channel_peak) in peaks.iter_mut().enumerate() {
+                    let mut channel_overs = overs
+                        .as_mut()
+                        .map(|(threshold, overs)| (*threshold, &mut overs[c]));
+                    src.foreach_sample(c, |s| {
+                        let sample = math::fabsf(s.to_sample::<f32>());
+                        if let Some((threshold, ref mut count)) = channel_overs {
+                            if sample > threshold {
+                                **count += 1;
+                            }
+                        }
+                        let sample = sample as f64;
+                        if sample > *channel_peak {
+                            *channel_peak = sample;
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    fn oversample<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+        dest: &mut [f32],
+        stride: usize,
+    ) {
+        let factor = self.factor();
+
+        macro_rules! oversample_specialized_impl {
+            ( $channels:expr, $interpolator:expr ) => {{
+                const CHANNELS: usize = $channels;
+                debug_assert!(src.channels() == CHANNELS);
+                let mut offset = 0;
+
+                src.foreach_frame(|frame: [T; CHANNELS]| {
+                    let frame_f32: [f32; CHANNELS] = Frame::map(frame, |s| s.to_sample::<f32>());
+                    $interpolator.process(&[frame_f32], &mut dest[offset..], stride);
+                    offset += factor;
+                });
+            }};
+        }
+
+        macro_rules! oversample_generic_impl {
+            ( $interpolators:expr ) => {{
+                debug_assert!(src.channels() == $interpolators.len());
+                for (c, interpolator) in $interpolators.iter_mut().enumerate() {
+                    let mut offset = c * stride;
+                    let dest = &mut *dest;
+                    src.foreach_sample(c, move |s| {
+                        interpolator.process(
+                            &[[s.to_sample::<f32>()]],
+                            &mut dest[offset..],
+                            stride,
+                        );
+                        offset += factor;
+                    });
+                }
+            }};
+        }
+
+        match self {
+            Mono2F(interpolator) => oversample_specialized_impl!(1, interpolator),
+            Stereo2F(interpolator) => oversample_specialized_impl!(2, interpolator),
+            Quad2F(interpolator) => oversample_specialized_impl!(4, interpolator),
+            Surround2F(interpolator) => oversample_specialized_impl!(6, interpolator),
+            OctoSurround2F(interpolator) => oversample_specialized_impl!(8, interpolator),
+            Mono4F(interpolator) => oversample_specialized_impl!(1, interpolator),
+            Stereo4F(interpolator) => oversample_specialized_impl!(2, interpolator),
+            Quad4F(interpolator) => oversample_specialized_impl!(4, interpolator),
+            Surround4F(interpolator) => oversample_specialized_impl!(6, interpolator),
+            OctoSurround4F(interpolator) => oversample_specialized_impl!(8, interpolator),
+            Generic2F(interpolators) => oversample_generic_impl!(interpolators),
+            Generic4F(interpolators) => oversample_generic_impl!(interpolators),
+            Generic8F(interpolators) => oversample_generic_impl!(interpolators),
+            NoInterp => {
+                for c in 0..src.channels() {
+                    src.foreach_sample_zipped(c, dest[c * stride..].iter_mut(), |s, dest| {
+                        *dest = s.to_sample::<f32>();
+                    });
+                }
+            }
+        }
+    }
+
+    /// Same as `check_true_peak()` but writes the oversampled signal to `dest` like `oversample()`
+    /// and takes the peaks and overs from there, so that the signal is only interpolated once.
+    fn check_true_peak_oversampled<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+        peaks: &mut [f64],
+        mut overs: Option<(f32, &mut [u64])>,
+        dest: &mut [f32],
+        stride: usize,
+    ) {
+        let len = src.frames() * self.factor();
+        self.oversample(src, dest, stride);
+
+        for (c, channel_peak) in peaks.iter_mut().enumerate() {
+            let mut channel_overs = overs
+                .as_mut()
+                .map(|(threshold, overs)| (*threshold, &mut overs[c]));
+            for sample in &dest[c * stride..c * stride + len] {
+                let sample = math::fabsf(*sample);
+                if let Some((threshold, ref mut count)) = channel_overs {
+                    if sample > threshold {
+                        **count += 1;
+                    }
+                }
+                let sample = sample as f64;
+                if sample > *channel_peak {
+                    *channel_peak = sample;
+                }
+            }
+        }
+    }
+
+    fn factor(&self) -> usize {
+        match self {
+            Mono2F(_) | Stereo2F(_) | Quad2F(_) | Surround2F(_) | OctoSurround2F(_) => 2,
+            Generic2F(_) => 2,
+            Mono4F(_) | Stereo4F(_) | Quad4F(_) | Surround4F(_) | OctoSurround4F(_) => 4,
+            Generic4F(_) => 4,
+            Generic8F(_) => 8,
+            NoInterp => 1,
+        }
+    }
+
+    fn latency(&self) -> usize {
+        match self {
+            Mono2F(interpolator) => interpolator.latency(),
+            Stereo2F(interpolator) => interpolator.latency(),
+            Quad2F(interpolator) => interpolator.latency(),
+            Surround2F(interpolator) => interpolator.latency(),
+            OctoSurround2F(interpolator) => interpolator.latency(),
+            Mono4F(interpolator) => interpolator.latency(),
+            Stereo4F(interpolator) => interpolator.latency(),
+            Quad4F(interpolator) => interpolator.latency(),
+            Surround4F(interpolator) => interpolator.latency(),
+            OctoSurround4F(interpolator) => interpolator.latency(),
+            Generic2F(interpolators) => interpolators[0].latency(),
+            Generic4F(interpolators) => interpolators[0].latency(),
+            Generic8F(interpolators) => interpolators[0].latency(),
+            NoInterp => 0,
+        }
+    }
+
+    fn coefficients(&self) -> Vec<f64> {
+        match self {
+            Mono2F(interpolator) => interpolator.coefficients().collect(),
+            Stereo2F(interpolator) => interpolator.coefficients().collect(),
+            Quad2F(interpolator) => interpolator.coefficients().collect(),
+            Surround2F(interpolator) => interpolator.coefficients().collect(),
+            OctoSurround2F(interpolator) => interpolator.coefficients().collect(),
+            Mono4F(interpolator) => interpolator.coefficients().collect(),
+            Stereo4F(interpolator) => interpolator.coefficients().collect(),
+            Quad4F(interpolator) => interpolator.coefficients().collect(),
+            Surround4F(interpolator) => interpolator.coefficients().collect(),
+            OctoSurround4F(interpolator) => interpolator.coefficients().collect(),
+            Generic2F(interpolators) => interpolators[0].coefficients().collect(),
+            Generic4F(interpolators) => interpolators[0].coefficients().collect(),
+            Generic8F(interpolators) => interpolators[0].coefficients().collect(),
+            NoInterp => Vec::new(),
+        }
+    }
+
+    /// Returns `false` and keeps the current coefficients if they don't fit the interpolator.
+    /// The validation only depends on the type of the interpolator, so for the generic variants
+    /// either all or none are changed.
+    fn set_coefficients(&mut self, taps: usize, coeffs: &[f64]) -> bool {
+        match self {
+            Mono2F(interpolator) => interpolator.set_coefficients(taps, coeffs),
+            Stereo2F(interpolator) => interpolator.set_coefficients(taps, coeffs),
+            Quad2F(interpolator) => interpolator.set_coefficients(taps, coeffs),
+            Surround2F(interpolator) => interpolator.set_coefficients(taps, coeffs),
+            OctoSurround2F(interpolator) => interpolator.set_coefficients(taps, coeffs),
+            Mono4F(interpolator) => interpolator.set_coefficients(taps, coeffs),
+            Stereo4F(interpolator) => interpolator.set_coefficients(taps, coeffs),
+            Quad4F(interpolator) => interpolator.set_coefficients(taps, coeffs),
+            Surround4F(interpolator) => interpolator.set_coefficients(taps, coeffs),
+            OctoSurround4F(interpolator) => interpolator.set_coefficients(taps, coeffs),
+            Generic2F(interpolators) => interpolators
+                .iter_mut()
+                .all(|interpolator| interpolator.set_coefficients(taps, coeffs)),
+            Generic4F(interpolators) => interpolators
+                .iter_mut()
+                .all(|interpolator| interpolator.set_coefficients(taps, coeffs)),
+            Generic8F(interpolators) => interpolators
+                .iter_mut()
+                .all(|interpolator| interpolator.set_coefficients(taps, coeffs)),
+            NoInterp => false,
+        }
+    }
+
+    fn set_standard_coefficients(&mut self) {
+        match self {
+            Mono2F(interpolator) => interpolator.set_standard_coefficients(),
+            Stereo2F(interpolator) => interpolator.set_standard_coefficients(),
+            Quad2F(interpolator) => interpolator.set_standard_coefficients(),
+            Surround2F(interpolator) => interpolator.set_standard_coefficients(),
+            OctoSurround2F(interpolator) => interpolator.set_standard_coefficients(),
+            Mono4F(interpolator) => interpolator.set_standard_coefficients(),
+            Stereo4F(interpolator) => interpolator.set_standard_coefficients(),
+            Quad4F(interpolator) => interpolator.set_standard_coefficients(),
+            Surround4F(interpolator) => interpolator.set_standard_coefficients(),
+            OctoSurround4F(interpolator) => interpolator.set_standard_coefficients(),
+            Generic2F(interpolators) => interpolators
+                .iter_mut()
+                .for_each(Interp2F::set_standard_coefficients),
+            Generic4F(interpolators) => interpolators
+                .iter_mut()
+                .for_each(Interp4F::set_standard_coefficients),
+            Generic8F(interpolators) => interpolators
+                .iter_mut()
+                .for_each(Interp8F::set_standard_coefficients),
+            NoInterp => (),
         }
     }
 
@@ -143,44 +481,799 @@ impl UpsamplingScanner {
             OctoSurround4F(interpolator) => interpolator.reset(),
             Generic2F(interpolators) => interpolators.iter_mut().for_each(Interp2F::reset),
             Generic4F(interpolators) => interpolators.iter_mut().for_each(Interp4F::reset),
+            Generic8F(interpolators) => interpolators.iter_mut().for_each(Interp8F::reset),
+            NoInterp => (),
+        }
+    }
+
+    fn history(&self) -> Vec<f32> {
+        macro_rules! history_specialized {
+            ( $interpolator:expr ) => {{
+                $interpolator.history().flatten().copied().collect()
+            }};
+        }
+
+        macro_rules! history_generic {
+            ( $interpolators:expr ) => {{
+                let channels = $interpolators.len();
+                let mut history = Vec::new();
+                for (c, interpolator) in $interpolators.iter().enumerate() {
+                    if history.is_empty() {
+                        history.resize(interpolator.history().count() * channels, 0.0);
+                    }
+                    for (dest, [sample]) in Iterator::zip(
+                        history[c..].iter_mut().step_by(channels),
+                        interpolator.history(),
+                    ) {
+                        *dest = *sample;
+                    }
+                }
+                history
+            }};
+        }
+
+        match self {
+            Mono2F(interpolator) => history_specialized!(interpolator),
+            Stereo2F(interpolator) => history_specialized!(interpolator),
+            Quad2F(interpolator) => history_specialized!(interpolator),
+            Surround2F(interpolator) => history_specialized!(interpolator),
+            OctoSurround2F(interpolator) => history_specialized!(interpolator),
+            Mono4F(interpolator) => history_specialized!(interpolator),
+            Stereo4F(interpolator) => history_specialized!(interpolator),
+            Quad4F(interpolator) => history_specialized!(interpolator),
+            Surround4F(interpolator) => history_specialized!(interpolator),
+            OctoSurround4F(interpolator) => history_specialized!(interpolator),
+            Generic2F(interpolators) => history_generic!(interpolators),
+            Generic4F(interpolators) => history_generic!(interpolators),
+            Generic8F(interpolators) => history_generic!(interpolators),
+            NoInterp => Vec::new(),
         }
     }
 }
 
 /// True peak measurement.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TruePeak {
     /// Interpolator/resampler.
     interp: UpsamplingScanner,
+    /// Number of channels of the interpolator.
+    channels: usize,
+    /// Whether the coefficients were replaced with
+    /// [`TruePeak::set_coefficients`](struct.TruePeak.html#method.set_coefficients).
+    custom_coefficients: bool,
 }
 
 impl TruePeak {
-    pub fn new(rate: u32, channels: u32) -> Option<Self> {
-        UpsamplingScanner::new(rate, channels).map(|interp| Self { interp })
+    /// Create a new true peak measurement for the given configuration.
+    ///
+    /// Returns `Error::UnsupportedRate` if the sample rate is too high for the interpolator.
+    pub fn new(rate: u32, channels: u32) -> Result<Self, Error> {
+        Self::with_quality(rate, channels, TruePeakQuality::Standard)
     }
 
+    /// Create a new true peak measurement with the given oversampling quality.
+    ///
+    /// Returns `Error::UnsupportedRate` if the sample rate is too high for the interpolator, and
+    /// `Error::InvalidChannels` or `Error::InvalidRate` if the number of channels or the sample
+    /// rate is 0.
+    pub fn with_quality(rate: u32, channels: u32, quality: TruePeakQuality) -> Result<Self, Error> {
+        if channels == 0 {
+            return Err(Error::InvalidChannels);
+        }
+        if rate == 0 {
+            return Err(Error::InvalidRate);
+        }
+
+        UpsamplingScanner::new(rate, channels, quality).map(|interp| Self {
+            interp,
+            channels: channels as usize,
+            custom_coefficients: false,
+        })
+    }
+
+    /// Reset the interpolator state.
+    ///
+    /// This does not reallocate anything and afterwards behaves exactly like a newly created
+    /// instance for the same configuration, except that custom coefficients are kept.
     pub fn reset(&mut self) {
         self.interp.reset();
     }
 
+    /// Delay of the interpolated signal in input frames.
+    pub fn latency(&self) -> usize {
+        self.interp.latency()
+    }
+
+    /// Update the maximum true peak of each channel in `peaks` with the given samples.
+    ///
+    /// Returns `Error::InvalidChannelIndex` if the number of channels of the samples or the
+    /// length of `peaks` doesn't match the configured number of channels. Inputs with incomplete
+    /// frames are already rejected when creating the `Interleaved` or `Planar` wrapper, so the
+    /// interpolator always gets complete frames.
     pub fn check_true_peak<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
         &mut self,
         src: S,
         peaks: &mut [f64],
-    ) {
-        self.interp.check_true_peak(src, peaks)
+    ) -> Result<(), Error> {
+        if src.channels() != self.channels || peaks.len() != self.channels {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        self.interp.check_true_peak(src, peaks, None);
+
+        Ok(())
+    }
+
+    /// Same as [`TruePeak::check_true_peak`](struct.TruePeak.html#method.check_true_peak) but
+    /// additionally adds the number of interpolated samples of each channel whose absolute value
+    /// is above `threshold` to `overs`.
+    ///
+    /// Returns `Error::InvalidChannelIndex` if the number of channels of the samples or the
+    /// length of `peaks` or `overs` doesn't match the configured number of channels.
+    pub fn check_true_peak_overs<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+        peaks: &mut [f64],
+        threshold: f32,
+        overs: &mut [u64],
+    ) -> Result<(), Error> {
+        if src.channels() != self.channels
+            || peaks.len() != self.channels
+            || overs.len() != self.channels
+        {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        self.interp
+            .check_true_peak(src, peaks, Some((threshold, overs)));
+
+        Ok(())
+    }
+
+    /// Number of oversampled output samples per input frame.
+    pub fn oversampling_factor(&self) -> usize {
+        self.interp.factor()
+    }
+
+    /// Same as [`TruePeak::check_true_peak`](struct.TruePeak.html#method.check_true_peak) but
+    /// additionally writes the oversampled signal to `dest`, e.g. for drawing the inter-sample
+    /// waveform. The signal is only interpolated once and the peaks are taken from `dest`.
+    ///
+    /// The `frames * oversampling_factor()` output samples of channel `c` are written to
+    /// `dest[c * stride..]`, the samples in between the channels are left untouched. A `stride`
+    /// of `frames * oversampling_factor()` gives a planar layout without gaps. The output is
+    /// delayed by [`TruePeak::latency`](struct.TruePeak.html#method.latency) input frames.
+    ///
+    /// The overs are counted like with
+    /// [`TruePeak::check_true_peak_overs`](struct.TruePeak.html#method.check_true_peak_overs) if a
+    /// threshold and `overs` are given.
+    ///
+    /// Returns `Error::InvalidChannelIndex` if the number of channels of the samples or the
+    /// length of `peaks` or `overs` doesn't match the configured number of channels and
+    /// `Error::NoMem` if `stride` or `dest` are too small to hold the output.
+    pub fn check_true_peak_oversampled<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+        peaks: &mut [f64],
+        overs: Option<(f32, &mut [u64])>,
+        dest: &mut [f32],
+        stride: usize,
+    ) -> Result<(), Error> {
+        if src.channels() != self.channels
+            || peaks.len() != self.channels
+            || overs
+                .as_ref()
+                .is_some_and(|(_, overs)| overs.len() != self.channels)
+        {
+            return Err(Error::InvalidChannelIndex);
+        }
+
+        let len = src.frames() * self.oversampling_factor();
+        if stride < len || dest.len() < (self.channels - 1) * stride + len {
+            return Err(Error::NoMem);
+        }
+
+        self.interp
+            .check_true_peak_oversampled(src, peaks, overs, dest, stride);
+
+        Ok(())
+    }
+
+    /// Pass the given samples through the interpolator without measuring their true peak.
+    ///
+    /// Returns `Error::InvalidChannelIndex` if the number of channels of the samples doesn't match
+    /// the configured number of channels.
+    pub fn seed<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(
+        &mut self,
+        src: S,
+    ) -> Result<(), Error> {
+        let mut true_peaks: SmallVec<[f64; 16]> = smallvec![0.0; self.channels];
+        self.check_true_peak(src, &mut true_peaks)
+    }
+
+    /// Filter coefficients of the interpolator in the oversampled domain, including the zero
+    /// padding, or an empty `Vec` if the sample rate is not oversampled.
+    ///
+    /// See [`TruePeak::set_coefficients`](struct.TruePeak.html#method.set_coefficients) for the
+    /// layout.
+    pub fn coefficients(&self) -> Vec<f64> {
+        self.interp.coefficients()
+    }
+
+    /// Replace the filter coefficients of the interpolator, keeping the samples that are
+    /// currently stored in it.
+    ///
+    /// `coeffs` contains the `taps * factor` coefficients of the FIR filter in the oversampled
+    /// domain, where `factor` is the oversampling factor: output phase `o` of an input frame
+    /// delayed by `i` frames uses `coeffs[i * factor + o]`. At most 48 coefficients are supported
+    /// for the factors 2 and 4 and 96 for the factor 8, and shorter filters are padded with
+    /// zeros.
+    ///
+    /// Returns `Error::InvalidMode` if the sample rate is not oversampled and `Error::NoMem` if
+    /// `coeffs.len() != taps * factor` or if there are too many taps.
+    pub fn set_coefficients(&mut self, taps: usize, coeffs: &[f64]) -> Result<(), Error> {
+        if self.interp.factor() == 1 {
+            return Err(Error::InvalidMode);
+        }
+
+        if !self.interp.set_coefficients(taps, coeffs) {
+            return Err(Error::NoMem);
+        }
+        self.custom_coefficients = true;
+
+        Ok(())
+    }
+
+    /// Coefficients set with
+    /// [`TruePeak::set_coefficients`](struct.TruePeak.html#method.set_coefficients), or `None`
+    /// if the standard coefficients are used.
+    pub(crate) fn custom_coefficients(&self) -> Option<Vec<f64>> {
+        if self.custom_coefficients {
+            Some(self.coefficients())
+        } else {
+            None
+        }
+    }
+
+    /// Restore the coefficients returned by `custom_coefficients()`, or the standard ones for
+    /// `None`.
+    pub(crate) fn set_custom_coefficients(&mut self, coeffs: Option<&[f64]>) -> Result<(), Error> {
+        match coeffs {
+            Some(coeffs) => {
+                let taps = coeffs.len() / core::cmp::max(1, self.interp.factor());
+                self.set_coefficients(taps, coeffs)
+            }
+            None => {
+                self.interp.set_standard_coefficients();
+                self.custom_coefficients = false;
+                Ok(())
+            }
+        }
+    }
+
+    /// Interleaved input samples that are currently stored in the interpolator, oldest first.
+    pub fn history(&self) -> Vec<f32> {
+        self.interp.history()
+    }
+
+    /// Restore the interpolator state from the samples returned by
+    /// [`TruePeak::history`](struct.TruePeak.html#method.history).
+    pub fn set_history(&mut self, channels: u32, history: &[f32]) -> Result<(), Error> {
+        if history.len() != self.interp.history().len() {
+            return Err(Error::NoMem);
+        }
+
+        self.reset();
+        if !history.is_empty() {
+            self.seed(crate::Interleaved::new(history, channels as usize)?)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(rate: u32, channels: usize, seconds: f32) -> Vec<f32> {
+        let mut data = vec![0.0f32; (rate as f32 * seconds) as usize * channels];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 1_234.0 / rate as f32;
+        for frame in data.chunks_exact_mut(channels) {
+            for (c, out) in frame.iter_mut().enumerate() {
+                *out = f32::sin(accumulator * (c + 1) as f32) / (c + 1) as f32;
+            }
+            accumulator += step;
+        }
+
+        data
+    }
+
+    #[test]
+    fn invalid_configuration() {
+        assert_eq!(TruePeak::new(48_000, 0).err(), Some(Error::InvalidChannels));
+        assert_eq!(TruePeak::new(0, 2).err(), Some(Error::InvalidRate));
+        assert_eq!(
+            TruePeak::new(768_000, 2).err(),
+            Some(Error::UnsupportedRate)
+        );
+    }
+
+    #[test]
+    fn reset() {
+        for channels in &[1, 2, 3] {
+            let data = sine(48_000, *channels, 0.1);
+            let (fst, snd) = data.split_at(data.len() / 2);
+
+            let mut tp = TruePeak::new(48_000, *channels as u32).unwrap();
+            let mut peaks = vec![0.0; *channels];
+            tp.check_true_peak(crate::Interleaved::new(fst, *channels).unwrap(), &mut peaks)
+                .unwrap();
+            tp.reset();
+            let mut peaks = vec![0.0; *channels];
+            tp.check_true_peak(crate::Interleaved::new(snd, *channels).unwrap(), &mut peaks)
+                .unwrap();
+
+            let mut tp_fresh = TruePeak::new(48_000, *channels as u32).unwrap();
+            let mut peaks_fresh = vec![0.0; *channels];
+            tp_fresh
+                .check_true_peak(
+                    crate::Interleaved::new(snd, *channels).unwrap(),
+                    &mut peaks_fresh,
+                )
+                .unwrap();
+
+            assert_eq!(peaks, peaks_fresh);
+        }
+    }
+
+    #[test]
+    fn planar_matches_interleaved() {
+        // Covers the specialized and the generic code paths
+        for channels in &[1, 2, 3, 6, 9] {
+            let data = sine(48_000, *channels, 0.5);
+            let data_planar = (0..*channels)
+                .map(|c| {
+                    crate::ChannelIter::new(&data, *channels, c)
+                        .unwrap()
+                        .copied()
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            let data_planar = data_planar.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+            let mut tp = TruePeak::new(48_000, *channels as u32).unwrap();
+            let mut peaks = vec![0.0; *channels];
+            tp.check_true_peak(
+                crate::Interleaved::new(&data, *channels).unwrap(),
+                &mut peaks,
+            )
+            .unwrap();
+
+            let mut tp_planar = TruePeak::new(48_000, *channels as u32).unwrap();
+            let mut peaks_planar = vec![0.0; *channels];
+            tp_planar
+                .check_true_peak(crate::Planar::new(&data_planar).unwrap(), &mut peaks_planar)
+                .unwrap();
+
+            assert_eq!(peaks, peaks_planar);
+        }
+    }
+
+    #[test]
+    fn incomplete_frames() {
+        let data = sine(48_000, 2, 0.1);
+
+        assert_eq!(
+            crate::Interleaved::new(&data[..data.len() - 1], 2).err(),
+            Some(Error::NoMem)
+        );
+        let (left, right) = data.split_at(data.len() / 2);
+        assert_eq!(
+            crate::Planar::new(&[left, &right[1..]]).err(),
+            Some(Error::NoMem)
+        );
+
+        let mut ebu = crate::EbuR128::new(2, 48_000, crate::Mode::TRUE_PEAK).unwrap();
+        assert_eq!(
+            ebu.add_frames_f32(&data[..data.len() - 1]),
+            Err(Error::NoMem)
+        );
+        assert_eq!(ebu.true_peak(0), Ok(0.0));
+        assert_eq!(ebu.true_peak(1), Ok(0.0));
+    }
+
+    #[test]
+    fn channel_mismatch() {
+        // Specialized, generic and no interpolation
+        for &(rate, channels) in &[(48_000, 2), (48_000, 3), (96_000, 9), (192_000, 2)] {
+            let data = sine(rate, channels, 0.1);
+            let mut tp = TruePeak::new(rate, channels as u32).unwrap();
+
+            let mut peaks = vec![0.0; channels + 1];
+            assert_eq!(
+                tp.check_true_peak(
+                    crate::Interleaved::new(&data, channels).unwrap(),
+                    &mut peaks
+                ),
+                Err(Error::InvalidChannelIndex)
+            );
+
+            let mut peaks = vec![0.0; channels - 1];
+            assert_eq!(
+                tp.check_true_peak(
+                    crate::Interleaved::new(&data, channels).unwrap(),
+                    &mut peaks
+                ),
+                Err(Error::InvalidChannelIndex)
+            );
+
+            let mut peaks = vec![0.0; channels];
+            assert_eq!(
+                tp.check_true_peak(crate::Interleaved::new(&data, 1).unwrap(), &mut peaks),
+                Err(Error::InvalidChannelIndex)
+            );
+            assert_eq!(
+                tp.seed(crate::Interleaved::new(&data, 1).unwrap()),
+                Err(Error::InvalidChannelIndex)
+            );
+            assert_eq!(peaks, vec![0.0; channels]);
+
+            // Still works correctly afterwards
+            let mut tp_fresh = TruePeak::new(rate, channels as u32).unwrap();
+            let mut peaks_fresh = vec![0.0; channels];
+            tp.check_true_peak(
+                crate::Interleaved::new(&data, channels).unwrap(),
+                &mut peaks,
+            )
+            .unwrap();
+            tp_fresh
+                .check_true_peak(
+                    crate::Interleaved::new(&data, channels).unwrap(),
+                    &mut peaks_fresh,
+                )
+                .unwrap();
+            assert_eq!(peaks, peaks_fresh);
+        }
+    }
+
+    #[test]
+    fn scanner_length_mismatch() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        // Specialized, generic and no interpolation, bypassing the checks of `TruePeak`
+        for &(rate, channels) in &[(48_000, 2), (48_000, 3), (192_000, 2)] {
+            let data = sine(rate, channels, 0.1);
+            let mut tp = TruePeak::new(rate, channels as u32).unwrap();
+
+            let mut peaks = vec![0.0; channels - 1];
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                tp.interp.check_true_peak(
+                    crate::Interleaved::new(&data, channels).unwrap(),
+                    &mut peaks,
+                    None,
+                )
+            }));
+            assert!(result.is_err());
+            assert_eq!(peaks, vec![0.0; channels - 1]);
+
+            let mut peaks = vec![0.0; channels];
+            let mut overs = vec![0; channels + 1];
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                tp.interp.check_true_peak(
+                    crate::Interleaved::new(&data, channels).unwrap(),
+                    &mut peaks,
+                    Some((0.25, &mut overs)),
+                )
+            }));
+            assert!(result.is_err());
+            assert_eq!(peaks, vec![0.0; channels]);
+            assert_eq!(overs, vec![0; channels + 1]);
+        }
+    }
+
+    #[test]
+    fn oversample() {
+        // Specialized, generic and no interpolation
+        for &(rate, channels, quality, factor) in &[
+            (48_000, 2, TruePeakQuality::Standard, 4),
+            (48_000, 3, TruePeakQuality::Standard, 4),
+            (96_000, 2, TruePeakQuality::Standard, 2),
+            (96_000, 9, TruePeakQuality::Standard, 2),
+            (48_000, 2, TruePeakQuality::Accurate, 8),
+            (192_000, 2, TruePeakQuality::Standard, 1),
+        ] {
+            let data = sine(rate, channels, 0.1);
+            let frames = data.len() / channels;
+            let stride = frames * factor + 5;
+            let mut tp = TruePeak::with_quality(rate, channels as u32, quality).unwrap();
+            assert_eq!(tp.oversampling_factor(), factor);
+
+            // The peaks of the oversampled signal are the true peaks
+            let mut tp_peaks = tp.clone();
+            let mut dest = vec![2.0f32; channels * stride];
+            let mut peaks = vec![0.0; channels];
+            let (fst, snd) = data.split_at(data.len() / 2);
+            tp.check_true_peak_oversampled(
+                crate::Interleaved::new(fst, channels).unwrap(),
+                &mut peaks,
+                None,
+                &mut dest,
+                stride,
+            )
+            .unwrap();
+            tp.check_true_peak_oversampled(
+                crate::Interleaved::new(snd, channels).unwrap(),
+                &mut peaks,
+                None,
+                &mut dest[fst.len() / channels * factor..],
+                stride,
+            )
+            .unwrap();
+
+            let mut expected_peaks = vec![0.0; channels];
+            tp_peaks
+                .check_true_peak(
+                    crate::Interleaved::new(&data, channels).unwrap(),
+                    &mut expected_peaks,
+                )
+                .unwrap();
+            assert_eq!(peaks, expected_peaks);
+            for (c, channel) in dest.chunks_exact(stride).enumerate() {
+                let (channel, gap) = channel.split_at(frames * factor);
+                let peak = channel.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+                assert_eq!(peak as f64, peaks[c]);
+                assert_eq!(gap, [2.0; 5]);
+            }
+            if factor == 1 {
+                assert_eq!(
+                    dest[..frames],
+                    crate::ChannelIter::new(&data, channels, 0)
+                        .unwrap()
+                        .copied()
+                        .collect::<Vec<_>>()[..]
+                );
+            }
+
+            assert_eq!(
+                tp.check_true_peak_oversampled(
+                    crate::Interleaved::new(&data, channels).unwrap(),
+                    &mut peaks,
+                    None,
+                    &mut dest,
+                    frames * factor - 1
+                ),
+                Err(Error::NoMem)
+            );
+            assert_eq!(
+                tp.check_true_peak_oversampled(
+                    crate::Interleaved::new(&data, channels).unwrap(),
+                    &mut peaks,
+                    None,
+                    &mut dest[..(channels - 1) * stride + frames * factor - 1],
+                    stride
+                ),
+                Err(Error::NoMem)
+            );
+            assert_eq!(
+                tp.check_true_peak_oversampled(
+                    crate::Interleaved::new(&data, 1).unwrap(),
+                    &mut peaks,
+                    None,
+                    &mut dest,
+                    stride
+                ),
+                Err(Error::InvalidChannelIndex)
+            );
+        }
+    }
+
+    #[test]
+    fn overs() {
+        // Specialized, generic and no interpolation
+        for &(rate, channels, quality) in &[
+            (48_000, 2, TruePeakQuality::Standard),
+            (48_000, 3, TruePeakQuality::Standard),
+            (96_000, 2, TruePeakQuality::Standard),
+            (48_000, 2, TruePeakQuality::Accurate),
+            (192_000, 2, TruePeakQuality::Standard),
+        ] {
+            let data = sine(rate, channels, 0.1);
+            let frames = data.len() / channels;
+            let mut tp = TruePeak::with_quality(rate, channels as u32, quality).unwrap();
+            let factor = tp.oversampling_factor();
+
+            // The overs counted while oversampling are the same
+            let mut oversampled = vec![0.0f32; data.len() * factor];
+            let mut oversampled_peaks = vec![0.0; channels];
+            let mut oversampled_overs = vec![1; channels];
+            tp.clone()
+                .check_true_peak_oversampled(
+                    crate::Interleaved::new(&data, channels).unwrap(),
+                    &mut oversampled_peaks,
+                    Some((0.25, &mut oversampled_overs)),
+                    &mut oversampled,
+                    frames * factor,
+                )
+                .unwrap();
+
+            // Counting doesn't change the peaks and adds to the previous counts
+            let mut peaks = vec![0.0; channels];
+            tp.clone()
+                .check_true_peak(
+                    crate::Interleaved::new(&data, channels).unwrap(),
+                    &mut peaks,
+                )
+                .unwrap();
+            let mut peaks_overs = vec![0.0; channels];
+            let mut overs = vec![1; channels];
+            tp.check_true_peak_overs(
+                crate::Interleaved::new(&data, channels).unwrap(),
+                &mut peaks_overs,
+                0.25,
+                &mut overs,
+            )
+            .unwrap();
+            assert_eq!(peaks, peaks_overs);
+
+            for (c, channel) in oversampled.chunks_exact(frames * factor).enumerate() {
+                let expected = channel.iter().filter(|s| s.abs() > 0.25).count() as u64;
+                assert!(expected > 0);
+                assert_eq!(overs[c], expected + 1);
+            }
+            assert_eq!(oversampled_overs, overs);
+            assert_eq!(oversampled_peaks, peaks);
+
+            assert_eq!(
+                tp.check_true_peak_overs(
+                    crate::Interleaved::new(&data, channels).unwrap(),
+                    &mut peaks_overs,
+                    0.25,
+                    &mut overs[1..],
+                ),
+                Err(Error::InvalidChannelIndex)
+            );
+        }
+    }
+
+    #[test]
+    fn quality() {
+        // Minimum and maximum true peak in dB relative to the amplitude of a sine, for periodic
+        // samples with different offsets of the peaks between two samples
+        let error = |rate: u32, freq: f64, quality| {
+            let (mut min, mut max) = (f64::INFINITY, -f64::INFINITY);
+            for offset in 0..20 {
+                let mut data = vec![0.0f32; rate as usize / 20];
+                for (i, s) in data.iter_mut().enumerate() {
+                    let t = (i as f64 + offset as f64 / 20.0) / rate as f64;
+                    *s = (0.5 * f64::sin(2.0 * std::f64::consts::PI * freq * t)) as f32;
+                }
+
+                let mut tp = TruePeak::with_quality(rate, 1, quality).unwrap();
+                let mut peaks = [0.0];
+                tp.check_true_peak(crate::Interleaved::new(&data, 1).unwrap(), &mut peaks)
+                    .unwrap();
+
+                let error = 20.0 * f64::log10(peaks[0] / 0.5);
+                min = f64::min(min, error);
+                max = f64::max(max, error);
+            }
+            (min, max)
+        };
+
+        for &rate in &[44_100, 48_000, 96_000] {
+            // Up to two thirds of the Nyquist frequency, above that the ripple of the filters
+            // dominates
+            for &k in &[48.0, 8.0, 4.0, 3.0] {
+                let freq = rate as f64 / k;
+                let standard = error(rate, freq, TruePeakQuality::Standard);
+                let fast = error(rate, freq, TruePeakQuality::Fast);
+                let accurate = error(rate, freq, TruePeakQuality::Accurate);
+
+                assert!(
+                    accurate.0 > -0.11,
+                    "{}Hz at {}Hz: {:?}",
+                    freq,
+                    rate,
+                    accurate
+                );
+                assert!(accurate.0 >= standard.0, "{}Hz at {}Hz", freq, rate);
+                assert!(standard.0 >= fast.0, "{}Hz at {}Hz", freq, rate);
+                for &(_, max) in &[standard, fast, accurate] {
+                    assert!(max < 0.4, "{}Hz at {}Hz: {}", freq, rate, max);
+                }
+            }
+        }
+
+        // The standard quality is the same as without selecting a quality
+        let data = (0..4_800)
+            .map(|i| f32::sin(i as f32 * 0.9) * 0.5)
+            .collect::<Vec<_>>();
+        let mut peaks = [[0.0; 2]; 2];
+        for (tp, peaks) in [
+            TruePeak::new(48_000, 2).unwrap(),
+            TruePeak::with_quality(48_000, 2, TruePeakQuality::Standard).unwrap(),
+        ]
+        .iter_mut()
+        .zip(peaks.iter_mut())
+        {
+            tp.check_true_peak(crate::Interleaved::new(&data, 2).unwrap(), peaks)
+                .unwrap();
+        }
+        assert_eq!(peaks[0][0].to_bits(), peaks[1][0].to_bits());
+        assert_eq!(peaks[0][1].to_bits(), peaks[1][1].to_bits());
+
+        assert_eq!(
+            TruePeak::with_quality(768_000, 2, TruePeakQuality::Fast).err(),
+            Some(Error::UnsupportedRate)
+        );
     }
 
-    pub fn seed<'a, T: Sample + 'a, S: crate::Samples<'a, T>>(&mut self, src: S) {
-        let mut true_peaks: SmallVec<[f64; 16]> = smallvec![0.0; src.channels()];
-        self.interp.check_true_peak(src, &mut true_peaks)
+    #[cfg(all(
+        feature = "std",
+        not(feature = "deterministic"),
+        any(target_arch = "x86", target_arch = "x86_64")
+    ))]
+    #[test]
+    fn avx_matches_fallback() {
+        if !is_x86_feature_detected!("avx") {
+            return;
+        }
+
+        for (channels, quality) in (1..=9).flat_map(|c| {
+            [
+                TruePeakQuality::Standard,
+                TruePeakQuality::Fast,
+                TruePeakQuality::Accurate,
+            ]
+            .map(|q| (c, q))
+        }) {
+            for rate in &[48_000, 96_000] {
+                let data = sine(*rate, channels, 1.0);
+
+                let mut scanner = UpsamplingScanner::new(*rate, channels as u32, quality).unwrap();
+                let mut peaks = vec![0.0; channels];
+                let mut overs = vec![0; channels];
+                scanner.check_true_peak_impl(
+                    crate::Interleaved::new(&data, channels).unwrap(),
+                    &mut peaks,
+                    None,
+                );
+                scanner.check_true_peak_impl(
+                    crate::Interleaved::new(&data, channels).unwrap(),
+                    &mut peaks,
+                    Some((0.1, &mut overs)),
+                );
+
+                let mut scanner_avx =
+                    UpsamplingScanner::new(*rate, channels as u32, quality).unwrap();
+                let mut peaks_avx = vec![0.0; channels];
+                let mut overs_avx = vec![0; channels];
+                unsafe {
+                    scanner_avx.check_true_peak_avx(
+                        crate::Interleaved::new(&data, channels).unwrap(),
+                        &mut peaks_avx,
+                        None,
+                    );
+                    scanner_avx.check_true_peak_avx(
+                        crate::Interleaved::new(&data, channels).unwrap(),
+                        &mut peaks_avx,
+                        Some((0.1, &mut overs_avx)),
+                    );
+                }
+
+                assert_eq!(peaks, peaks_avx);
+                assert_eq!(overs, overs_avx);
+            }
+        }
     }
 }
 
-#[cfg(feature = "c-tests")]
+#[cfg(all(feature = "c-tests", not(target_arch = "wasm32")))]
 use std::os::raw::c_void;
 
-#[cfg(feature = "c-tests")]
+#[cfg(all(feature = "c-tests", not(target_arch = "wasm32")))]
 extern "C" {
     pub fn true_peak_create_c(rate: u32, channels: u32) -> *mut c_void;
     pub fn true_peak_check_short_c(
@@ -205,9 +1298,9 @@ extern "C" {
     pub fn true_peak_destroy_c(tp: *mut c_void);
 }
 
-#[cfg(feature = "c-tests")]
+#[cfg(all(feature = "c-tests", not(target_arch = "wasm32")))]
 #[cfg(test)]
-mod tests {
+mod c_tests {
     use super::*;
     use crate::tests::Signal;
     use float_eq::assert_float_eq;
@@ -236,7 +1329,8 @@ mod tests {
                 )
                 .unwrap(),
                 &mut peaks,
-            );
+            )
+            .unwrap();
         }
 
         unsafe {
@@ -283,7 +1377,8 @@ mod tests {
                 )
                 .unwrap(),
                 &mut peaks,
-            );
+            )
+            .unwrap();
         }
 
         unsafe {
@@ -330,7 +1425,8 @@ mod tests {
                 )
                 .unwrap(),
                 &mut peaks,
-            );
+            )
+            .unwrap();
         }
 
         unsafe {
@@ -377,7 +1473,8 @@ mod tests {
                 )
                 .unwrap(),
                 &mut peaks,
-            );
+            )
+            .unwrap();
         }
 
         unsafe {
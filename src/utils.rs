@@ -19,8 +19,16 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
+use crate::math;
 use dasp_frame::Frame;
 
+/// Offset in dB between the mean square energy of the K-weighted signal and its loudness in LUFS.
+///
+/// ITU-R BS.1770 defines the loudness as `10 * log10(energy) - 0.691`. The K-weighting has a gain
+/// of about +0.691dB at 1kHz, so that a 1kHz sine has the same loudness in LUFS as its level in
+/// dBFS.
+pub const ENERGY_TO_LUFS_OFFSET: f64 = -0.691;
+
 /// Convert linear energy to logarithmic loudness.
 pub fn energy_to_loudness(energy: f64) -> f64 {
     // The non-test version is faster and more accurate but gives
@@ -28,16 +36,31 @@ pub fn energy_to_loudness(energy: f64) -> f64 {
     // tests because of that.
     #[cfg(test)]
     {
-        10.0 * (f64::ln(energy) / f64::ln(10.0)) - 0.691
+        10.0 * (math::log(energy) / math::log(10.0)) + ENERGY_TO_LUFS_OFFSET
     }
     #[cfg(not(test))]
     {
-        10.0 * f64::log10(energy) - 0.691
+        10.0 * math::log10(energy) + ENERGY_TO_LUFS_OFFSET
     }
 }
 
+/// Convert the mean square energy of a block to its loudness in LUFS.
+///
+/// This is the conversion used for all loudness values, e.g. for the energies of
+/// [`EbuR128::block_energies`](struct.EbuR128.html#method.block_energies). Silence with an
+/// energy of 0 gives -inf.
+pub fn energy_to_lufs(energy: f64) -> f64 {
+    energy_to_loudness(energy)
+}
+
+/// Convert a loudness in LUFS to the mean square energy of a block, the inverse of
+/// [`energy_to_lufs`](fn.energy_to_lufs.html).
+pub fn lufs_to_energy(lufs: f64) -> f64 {
+    math::pow(10.0, (lufs - ENERGY_TO_LUFS_OFFSET) / 10.0)
+}
+
 /// Trait for abstracting over interleaved and planar samples.
-pub trait Samples<'a, S: Sample + 'a>: Sized {
+pub trait Samples<'a, S: Sample + 'a>: Sized + Sync {
     /// Call the given closure for each sample of the given channel.
     // FIXME: Workaround for TrustedLen / TrustedRandomAccess being unstable
     // and because of that we wouldn't get nice optimizations
@@ -80,12 +103,22 @@ impl<'a, S> Interleaved<'a, S> {
             return Err(crate::Error::NoMem);
         }
 
-        if data.len() % channels != 0 {
+        if !data.len().is_multiple_of(channels) {
             return Err(crate::Error::NoMem);
         }
 
         Ok(Interleaved { data, channels })
     }
+
+    /// Iterator over the samples of one channel.
+    ///
+    /// Panics if `channel` is not smaller than the number of channels.
+    #[inline]
+    pub fn channel(&self, channel: usize) -> ChannelIter<'a, S> {
+        assert!(channel < self.channels);
+
+        ChannelIter::new_unchecked(self.data, self.channels, channel)
+    }
 }
 
 impl<'a, S: Sample> Samples<'a, S> for Interleaved<'a, S> {
@@ -93,8 +126,8 @@ impl<'a, S: Sample> Samples<'a, S> for Interleaved<'a, S> {
     fn foreach_sample(&self, channel: usize, mut func: impl FnMut(&'a S)) {
         assert!(channel < self.channels);
 
-        for v in self.data.chunks_exact(self.channels) {
-            func(&v[channel])
+        for v in self.channel(channel) {
+            func(v)
         }
     }
 
@@ -107,8 +140,8 @@ impl<'a, S: Sample> Samples<'a, S> for Interleaved<'a, S> {
     ) {
         assert!(channel < self.channels);
 
-        for (v, u) in Iterator::zip(self.data.chunks_exact(self.channels), iter) {
-            func(&v[channel], u)
+        for (v, u) in Iterator::zip(self.channel(channel), iter) {
+            func(v, u)
         }
     }
 
@@ -148,6 +181,76 @@ impl<'a, S: Sample> Samples<'a, S> for Interleaved<'a, S> {
     }
 }
 
+/// Iterator over the samples of one channel of interleaved samples.
+///
+/// This yields every `channels`-th sample starting at the sample of the channel, i.e. a strided
+/// view into the interleaved data that doesn't copy or allocate anything.
+///
+/// ```
+/// # use ebur128::ChannelIter;
+/// let data = [1.0f32, -1.0, 0.5, 2.0, -2.0, 0.25];
+/// let right = ChannelIter::new(&data, 3, 1).unwrap();
+/// assert_eq!(right.copied().collect::<Vec<_>>(), [-1.0, -2.0]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ChannelIter<'a, S> {
+    inner: core::iter::StepBy<core::slice::Iter<'a, S>>,
+}
+
+impl<'a, S> ChannelIter<'a, S> {
+    /// Create a new iterator over the samples of `channel` of the interleaved `data` with
+    /// `channels` channels.
+    ///
+    /// Returns `Error::NoMem` if `channels` is zero or the length of `data` is not a multiple of
+    /// `channels`, like [`EbuR128::add_frames_f32`](struct.EbuR128.html#method.add_frames_f32),
+    /// and `Error::InvalidChannelIndex` if `channel` is not smaller than `channels`.
+    pub fn new(data: &'a [S], channels: usize, channel: usize) -> Result<Self, crate::Error> {
+        let data = Interleaved::new(data, channels)?;
+        if channel >= channels {
+            return Err(crate::Error::InvalidChannelIndex);
+        }
+
+        Ok(data.channel(channel))
+    }
+
+    #[inline]
+    fn new_unchecked(data: &'a [S], channels: usize, channel: usize) -> Self {
+        ChannelIter {
+            inner: data.get(channel..).unwrap_or(&[]).iter().step_by(channels),
+        }
+    }
+}
+
+impl<'a, S> Iterator for ChannelIter<'a, S> {
+    type Item = &'a S;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+
+    #[inline]
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.inner.nth(n)
+    }
+}
+
+impl<'a, S> DoubleEndedIterator for ChannelIter<'a, S> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a, S> ExactSizeIterator for ChannelIter<'a, S> {}
+
+impl<'a, S> core::iter::FusedIterator for ChannelIter<'a, S> {}
+
 /// Struct representing interleaved samples.
 pub struct Planar<'a, S> {
     data: &'a [&'a [S]],
@@ -236,12 +339,35 @@ impl<'a, S: Sample> Samples<'a, S> for Planar<'a, S> {
     }
 }
 
+/// Sample types that can be processed by [`EbuR128`](crate::EbuR128).
+///
+/// This is implemented for `u8`, `i8`, `i16`, `i32`, `f32`, `f64` and `dasp_sample::I24`. Unsigned
+/// 8 bit samples are centered at 128 like in 8 bit PCM formats. Conversion to
+/// floating point values is done via the `dasp_sample` traits, which also have to be implemented
+/// for custom sample types.
 pub trait Sample:
-    dasp_sample::Sample + dasp_sample::Duplex<f32> + dasp_sample::Duplex<f64>
+    dasp_sample::Sample + dasp_sample::Duplex<f32> + dasp_sample::Duplex<f64> + Sync
 {
+    /// Absolute value of the full scale amplitude in the sample type.
     const MAX_AMPLITUDE: f64;
 
+    /// Convert the sample to `f64` without normalizing it to `[-1.0, 1.0]`. Silence has to be
+    /// converted to `0.0`, i.e. unsigned samples are converted without their offset.
     fn as_f64_raw(self) -> f64;
+
+    /// Check if the sample is neither NaN nor infinite.
+    #[inline(always)]
+    fn is_finite(self) -> bool {
+        true
+    }
+
+    /// Convert the sample to a 32 bit fixed point value with 31 fractional bits for
+    /// `Precision::Fixed`, saturating at full scale. The default implementation converts via
+    /// `f64`, the integer sample types are converted without floating point operations.
+    #[inline(always)]
+    fn to_i32(self) -> i32 {
+        (self.to_sample::<f64>() * 2_147_483_648.0) as i32
+    }
 }
 
 impl Sample for f32 {
@@ -251,14 +377,50 @@ impl Sample for f32 {
     fn as_f64_raw(self) -> f64 {
         self as f64
     }
+
+    #[inline(always)]
+    fn is_finite(self) -> bool {
+        f32::is_finite(self)
+    }
 }
 impl Sample for f64 {
     const MAX_AMPLITUDE: f64 = 1.0;
 
+    #[inline(always)]
+    fn as_f64_raw(self) -> f64 {
+        self
+    }
+
+    #[inline(always)]
+    fn is_finite(self) -> bool {
+        f64::is_finite(self)
+    }
+}
+impl Sample for u8 {
+    const MAX_AMPLITUDE: f64 = 128.0;
+
+    #[inline(always)]
+    fn as_f64_raw(self) -> f64 {
+        self as f64 - 128.0
+    }
+
+    #[inline(always)]
+    fn to_i32(self) -> i32 {
+        ((self as i32) - 128) << 24
+    }
+}
+impl Sample for i8 {
+    const MAX_AMPLITUDE: f64 = -(Self::MIN as f64);
+
     #[inline(always)]
     fn as_f64_raw(self) -> f64 {
         self as f64
     }
+
+    #[inline(always)]
+    fn to_i32(self) -> i32 {
+        (self as i32) << 24
+    }
 }
 impl Sample for i16 {
     const MAX_AMPLITUDE: f64 = -(Self::MIN as f64);
@@ -267,6 +429,24 @@ impl Sample for i16 {
     fn as_f64_raw(self) -> f64 {
         self as f64
     }
+
+    #[inline(always)]
+    fn to_i32(self) -> i32 {
+        (self as i32) << 16
+    }
+}
+impl Sample for dasp_sample::I24 {
+    const MAX_AMPLITUDE: f64 = 8_388_608.0;
+
+    #[inline(always)]
+    fn as_f64_raw(self) -> f64 {
+        self.inner() as f64
+    }
+
+    #[inline(always)]
+    fn to_i32(self) -> i32 {
+        self.inner() << 8
+    }
 }
 impl Sample for i32 {
     const MAX_AMPLITUDE: f64 = -(Self::MIN as f64);
@@ -275,6 +455,11 @@ impl Sample for i32 {
     fn as_f64_raw(self) -> f64 {
         self as f64
     }
+
+    #[inline(always)]
+    fn to_i32(self) -> i32 {
+        self
+    }
 }
 
 /// An extension-trait to accumulate samples into a frame
@@ -285,7 +470,7 @@ pub trait FrameAccumulator: Frame {
 
 impl<F: Frame, S> FrameAccumulator for F
 where
-    S: SampleAccumulator + std::fmt::Debug,
+    S: SampleAccumulator + core::fmt::Debug,
     F: IndexMut<Target = S>,
 {
     #[inline(always)]
@@ -295,6 +480,7 @@ where
         }
     }
 
+    #[inline(always)]
     fn retain_max_samples(&mut self, other: &Self) {
         for i in 0..Self::CHANNELS {
             let this = self.index_mut(i);
@@ -347,7 +533,7 @@ impl SampleAccumulator for f32 {
     fn scale_add(&mut self, other: Self, coeff: f32) {
         #[cfg(feature = "precision-true-peak")]
         {
-            *self = other.mul_add(coeff, *self);
+            *self = math::fmaf(other, coeff, *self);
         }
         #[cfg(not(feature = "precision-true-peak"))]
         {
@@ -360,6 +546,15 @@ impl SampleAccumulator for f32 {
 pub mod tests {
     use dasp_sample::{FromSample, Sample};
 
+    /// Absolute tolerance for comparisons with the C implementation in addition to the ulps.
+    ///
+    /// The `libm` floating point functions used with the `deterministic` feature can differ in the
+    /// last bits from the ones used by the C implementation.
+    #[cfg(feature = "deterministic")]
+    pub const C_ABS_TOLERANCE: f64 = 0.000000001;
+    #[cfg(not(feature = "deterministic"))]
+    pub const C_ABS_TOLERANCE: f64 = 0.0;
+
     #[derive(Clone, Debug)]
     pub struct Signal<S: FromSample<f32>> {
         pub data: Vec<S>,
@@ -494,4 +689,66 @@ pub mod tests {
             }
         }
     }
+
+    #[test]
+    fn channel_iter() {
+        use super::ChannelIter;
+        use crate::Error;
+
+        for &channels in &[1, 3, 5] {
+            let data = (0..channels * 7).collect::<Vec<usize>>();
+            for c in 0..channels {
+                let iter = ChannelIter::new(&data, channels, c).unwrap();
+                assert_eq!(iter.len(), 7);
+                assert_eq!(
+                    iter.clone().copied().collect::<Vec<_>>(),
+                    (0..7).map(|i| i * channels + c).collect::<Vec<_>>()
+                );
+                assert_eq!(
+                    iter.rev().copied().collect::<Vec<_>>(),
+                    (0..7).rev().map(|i| i * channels + c).collect::<Vec<_>>()
+                );
+            }
+
+            assert_eq!(
+                ChannelIter::new(&data, channels, channels).err(),
+                Some(Error::InvalidChannelIndex)
+            );
+
+            // Empty input has no samples for any channel
+            let empty: &[usize] = &[];
+            for c in 0..channels {
+                let mut iter = ChannelIter::new(empty, channels, c).unwrap();
+                assert_eq!(iter.len(), 0);
+                assert_eq!(iter.next(), None);
+            }
+        }
+
+        let data = [0.0f32; 10];
+        assert_eq!(ChannelIter::new(&data, 3, 0).err(), Some(Error::NoMem));
+        assert_eq!(ChannelIter::new(&data, 0, 0).err(), Some(Error::NoMem));
+    }
+
+    #[test]
+    fn energy_lufs_roundtrip() {
+        use super::{energy_to_lufs, lufs_to_energy};
+        use float_eq::assert_float_eq;
+
+        for &energy in &[1e-12, 1.17e-7, 0.001, 0.5, 1.0, 2.0, 1000.0] {
+            assert_float_eq!(
+                lufs_to_energy(energy_to_lufs(energy)),
+                energy,
+                rmax <= 1e-12
+            );
+        }
+        for &lufs in &[-100.0, -70.0, -23.0, -0.691, 0.0, 10.0] {
+            assert_float_eq!(energy_to_lufs(lufs_to_energy(lufs)), lufs, abs <= 1e-12);
+        }
+
+        // Full scale 1kHz sine after the K-weighting
+        assert_float_eq!(energy_to_lufs(1.0), -0.691, abs <= 1e-12);
+        assert_float_eq!(lufs_to_energy(-0.691), 1.0, abs <= 1e-12);
+        assert_eq!(energy_to_lufs(0.0), -f64::INFINITY);
+        assert_eq!(lufs_to_energy(-f64::INFINITY), 0.0);
+    }
 }
@@ -0,0 +1,139 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! WebAssembly bindings for analyzing audio in the browser, e.g. Web Audio `AudioBuffer`s.
+//!
+//! See `examples/wasm` for how to build and use them from JavaScript.
+
+use crate::ebur128;
+
+use wasm_bindgen::prelude::*;
+
+fn to_js_error(err: ebur128::Error) -> JsError {
+    JsError::new(&err.to_string())
+}
+
+/// EBU R128 loudness analyzer, available as `EbuR128` in JavaScript.
+///
+/// All measured state is kept between calls, so audio can be added in as many chunks as needed.
+#[wasm_bindgen(js_name = EbuR128)]
+#[derive(Debug)]
+pub struct WasmEbuR128 {
+    inner: ebur128::EbuR128,
+}
+
+#[wasm_bindgen(js_class = EbuR128)]
+impl WasmEbuR128 {
+    /// Create a new instance.
+    ///
+    /// `mode` has the same bit values as [`Mode`](../struct.Mode.html), e.g. `Mode::I` is 5
+    /// and `Mode::all()` is 127.
+    #[wasm_bindgen(constructor)]
+    pub fn new(channels: u32, rate: u32, mode: u8) -> Result<WasmEbuR128, JsError> {
+        let mode = ebur128::Mode::from_bits(mode)
+            .ok_or_else(|| to_js_error(ebur128::Error::InvalidMode))?;
+        let inner = ebur128::EbuR128::new(channels, rate, mode).map_err(to_js_error)?;
+
+        Ok(WasmEbuR128 { inner })
+    }
+
+    /// Add interleaved frames to be processed.
+    #[wasm_bindgen(js_name = addFramesF32)]
+    pub fn add_frames_f32(&mut self, frames: &[f32]) -> Result<(), JsError> {
+        self.inner.add_frames_f32(frames).map_err(to_js_error)
+    }
+
+    /// Add frames of a single channel to be processed.
+    ///
+    /// This is only possible for mono instances. For multiple channels the samples from
+    /// `AudioBuffer.getChannelData()` have to be interleaved first.
+    #[wasm_bindgen(js_name = addFramesMonoF32)]
+    pub fn add_frames_mono_f32(&mut self, frames: &[f32]) -> Result<(), JsError> {
+        self.inner
+            .add_frames_planar_f32(&[frames])
+            .map_err(to_js_error)
+    }
+
+    /// Get the global integrated loudness in LUFS.
+    #[wasm_bindgen(js_name = loudnessGlobal)]
+    pub fn loudness_global(&self) -> Result<f64, JsError> {
+        self.inner.loudness_global().map_err(to_js_error)
+    }
+
+    /// Get the loudness range (LRA) in LU.
+    #[wasm_bindgen(js_name = loudnessRange)]
+    pub fn loudness_range(&self) -> Result<f64, JsError> {
+        self.inner.loudness_range().map_err(to_js_error)
+    }
+
+    /// Get the maximum true peak of the given channel.
+    #[wasm_bindgen(js_name = truePeak)]
+    pub fn true_peak(&self, channel_number: u32) -> Result<f64, JsError> {
+        self.inner.true_peak(channel_number).map_err(to_js_error)
+    }
+
+    /// Get the maximum sample peak of the given channel.
+    #[wasm_bindgen(js_name = samplePeak)]
+    pub fn sample_peak(&self, channel_number: u32) -> Result<f64, JsError> {
+        self.inner.sample_peak(channel_number).map_err(to_js_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_across_calls() {
+        let mut data = vec![0.0f32; 48_000 * 5 * 2];
+        let mut accumulator = 0.0;
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 48_000.0;
+        for out in data.chunks_exact_mut(2) {
+            let val = f32::sin(accumulator);
+            out[0] = val;
+            out[1] = 0.5 * val;
+            accumulator += step;
+        }
+
+        let mode = ebur128::Mode::all();
+        let mut ebu = ebur128::EbuR128::new(2, 48_000, mode).unwrap();
+        ebu.add_frames_f32(&data).unwrap();
+
+        // Same chunk size as an AudioWorklet uses
+        let mut wasm = WasmEbuR128::new(2, 48_000, mode.bits()).unwrap();
+        for chunk in data.chunks(128 * 2) {
+            wasm.add_frames_f32(chunk).unwrap();
+        }
+
+        assert_eq!(
+            wasm.loudness_global().unwrap(),
+            ebu.loudness_global().unwrap()
+        );
+        assert_eq!(
+            wasm.loudness_range().unwrap(),
+            ebu.loudness_range().unwrap()
+        );
+        for c in 0..2 {
+            assert_eq!(wasm.true_peak(c).unwrap(), ebu.true_peak(c).unwrap());
+            assert_eq!(wasm.sample_peak(c).unwrap(), ebu.sample_peak(c).unwrap());
+        }
+    }
+}
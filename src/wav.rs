@@ -0,0 +1,259 @@
+// Copyright (c) 2011 Jan Kokemüller
+// Copyright (c) 2020 Sebastian Dröge <sebastian@centricular.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+//! Helpers for analyzing WAV files with [`hound`](https://crates.io/crates/hound).
+//!
+//! See `examples/analyze_wav.rs` for an example.
+
+use crate::ebur128::{EbuR128, Error, Mode};
+
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+use hound::{SampleFormat, WavReader};
+
+/// Error while analyzing a WAV file.
+#[derive(Debug)]
+pub enum WavError {
+    /// Reading the WAV file failed, or its sample format is not supported.
+    Read(hound::Error),
+    /// Analyzing the samples failed.
+    Analyze(Error),
+}
+
+impl std::error::Error for WavError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            WavError::Read(ref err) => Some(err),
+            WavError::Analyze(ref err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WavError::Read(ref err) => write!(f, "Failed to read WAV file: {}", err),
+            WavError::Analyze(ref err) => write!(f, "Failed to analyze samples: {}", err),
+        }
+    }
+}
+
+impl From<hound::Error> for WavError {
+    fn from(err: hound::Error) -> Self {
+        WavError::Read(err)
+    }
+}
+
+impl From<Error> for WavError {
+    fn from(err: Error) -> Self {
+        WavError::Analyze(err)
+    }
+}
+
+/// Analyze the WAV file at `path` with the given mode.
+///
+/// See [`analyze`](fn.analyze.html) for details.
+pub fn analyze_file<P: AsRef<Path>>(path: P, mode: Mode) -> Result<EbuR128, WavError> {
+    analyze(WavReader::open(path)?, mode)
+}
+
+/// Analyze all samples of a WAV file with the given mode.
+///
+/// Creates an [`EbuR128`](struct.EbuR128.html) instance with the number of channels and sample
+/// rate of the WAV file and the default channel map, and adds all samples of it. Use
+/// [`add_frames`](fn.add_frames.html) for analyzing the samples with a differently configured
+/// instance.
+pub fn analyze<R: Read>(mut reader: WavReader<R>, mode: Mode) -> Result<EbuR128, WavError> {
+    let spec = reader.spec();
+    let mut ebu = EbuR128::new(u32::from(spec.channels), spec.sample_rate, mode)?;
+    add_frames(&mut ebu, &mut reader)?;
+
+    Ok(ebu)
+}
+
+/// Add all remaining samples of a WAV file to `ebu`.
+///
+/// 8, 16 and 32 bit integer samples are added with the corresponding `add_frames` variant, 24 bit
+/// integer samples with [`EbuR128::add_frames_i24`](struct.EbuR128.html#method.add_frames_i24)
+/// and 32 bit float samples with
+/// [`EbuR128::add_frames_f32`](struct.EbuR128.html#method.add_frames_f32). Other sample formats
+/// return `hound::Error::Unsupported`. The samples are read in chunks of
+/// [`EbuR128::chunk_size`](struct.EbuR128.html#method.chunk_size) frames. A partial frame at the
+/// end of a truncated file is dropped.
+///
+/// Returns `Error::InvalidChannels` if the number of channels, and `Error::InvalidRate` if the
+/// sample rate of the WAV file doesn't match the configuration of `ebu`.
+pub fn add_frames<R: Read>(ebu: &mut EbuR128, reader: &mut WavReader<R>) -> Result<(), WavError> {
+    let spec = reader.spec();
+    if u32::from(spec.channels) != ebu.channels() {
+        return Err(Error::InvalidChannels.into());
+    }
+    if spec.sample_rate != ebu.rate() {
+        return Err(Error::InvalidRate.into());
+    }
+
+    match (spec.sample_format, spec.bits_per_sample) {
+        (SampleFormat::Int, 8) => add_samples::<_, i8>(ebu, reader, EbuR128::add_frames_i8),
+        (SampleFormat::Int, 16) => add_samples::<_, i16>(ebu, reader, EbuR128::add_frames_i16),
+        (SampleFormat::Int, 24) => add_samples::<_, i32>(ebu, reader, EbuR128::add_frames_i24),
+        (SampleFormat::Int, 32) => add_samples::<_, i32>(ebu, reader, EbuR128::add_frames_i32),
+        (SampleFormat::Float, 32) => add_samples::<_, f32>(ebu, reader, EbuR128::add_frames_f32),
+        _ => Err(hound::Error::Unsupported.into()),
+    }
+}
+
+fn add_samples<R: Read, T: hound::Sample>(
+    ebu: &mut EbuR128,
+    reader: &mut WavReader<R>,
+    add: fn(&mut EbuR128, &[T]) -> Result<(), Error>,
+) -> Result<(), WavError> {
+    let channels = ebu.channels() as usize;
+    let mut buffer = Vec::with_capacity(ebu.chunk_size() * channels);
+
+    for sample in reader.samples::<T>() {
+        buffer.push(sample?);
+        if buffer.len() == buffer.capacity() {
+            add(ebu, &buffer)?;
+            buffer.clear();
+        }
+    }
+
+    let complete = buffer.len() - buffer.len() % channels;
+    add(ebu, &buffer[..complete])?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    fn write_wav<T: hound::Sample + Copy>(
+        bits_per_sample: u16,
+        sample_format: SampleFormat,
+        samples: &[T],
+    ) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 48_000,
+            bits_per_sample,
+            sample_format,
+        };
+
+        let mut data = Vec::new();
+        let mut writer = hound::WavWriter::new(Cursor::new(&mut data), spec).unwrap();
+        for s in samples {
+            writer.write_sample(*s).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        data
+    }
+
+    #[test]
+    fn sample_formats() {
+        // 3s of a stereo sine, which spans multiple chunks
+        let samples = (0..48_000 * 3 * 2)
+            .map(|i| 0.5 * f32::sin((i / 2) as f32 * 0.05 + (i % 2) as f32))
+            .collect::<Vec<_>>();
+        let mode = Mode::I | Mode::LRA | Mode::TRUE_PEAK;
+
+        let mut expected = EbuR128::new(2, 48_000, mode).unwrap();
+        expected.add_frames_f32(&samples).unwrap();
+        let expected = expected.measurement();
+
+        let wavs = [
+            write_wav(
+                8,
+                SampleFormat::Int,
+                &samples
+                    .iter()
+                    .map(|s| (s * 128.0) as i8)
+                    .collect::<Vec<_>>(),
+            ),
+            write_wav(
+                16,
+                SampleFormat::Int,
+                &samples
+                    .iter()
+                    .map(|s| (s * 32_768.0) as i16)
+                    .collect::<Vec<_>>(),
+            ),
+            write_wav(
+                24,
+                SampleFormat::Int,
+                &samples
+                    .iter()
+                    .map(|s| (s * 8_388_608.0) as i32)
+                    .collect::<Vec<_>>(),
+            ),
+            write_wav(
+                32,
+                SampleFormat::Int,
+                &samples
+                    .iter()
+                    .map(|s| (*s as f64 * 2_147_483_648.0) as i32)
+                    .collect::<Vec<_>>(),
+            ),
+            write_wav(32, SampleFormat::Float, &samples),
+        ];
+
+        for (wav, &tolerance) in wavs.iter().zip(&[0.1, 0.001, 0.0001, 0.0001, 0.0]) {
+            let ebu = analyze(WavReader::new(Cursor::new(wav)).unwrap(), mode).unwrap();
+            let measurement = ebu.measurement();
+
+            let integrated = measurement.integrated.unwrap();
+            assert!((integrated - expected.integrated.unwrap()).abs() <= tolerance);
+            let range = measurement.range.unwrap();
+            assert!((range - expected.range.unwrap()).abs() <= tolerance);
+            let true_peak = measurement.true_peak.unwrap();
+            for (tp, expected) in true_peak.iter().zip(expected.true_peak.as_ref().unwrap()) {
+                assert!((tp - expected).abs() <= tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn mismatched_configuration() {
+        let wav = write_wav(16, SampleFormat::Int, &[0i16; 48_000 * 2]);
+
+        let mut ebu = EbuR128::new(1, 48_000, Mode::I).unwrap();
+        let mut reader = WavReader::new(Cursor::new(&wav)).unwrap();
+        assert!(matches!(
+            add_frames(&mut ebu, &mut reader),
+            Err(WavError::Analyze(Error::InvalidChannels))
+        ));
+
+        let mut ebu = EbuR128::new(2, 44_100, Mode::I).unwrap();
+        assert!(matches!(
+            add_frames(&mut ebu, &mut reader),
+            Err(WavError::Analyze(Error::InvalidRate))
+        ));
+
+        let wav = write_wav(8, SampleFormat::Int, &[0i8; 48_000 * 2]);
+        let ebu = analyze(WavReader::new(Cursor::new(&wav)).unwrap(), Mode::I).unwrap();
+        assert_eq!(ebu.loudness_global(), Ok(-f64::INFINITY));
+    }
+}
@@ -0,0 +1,190 @@
+// Checks that processing a steady stream of equally sized batches doesn't allocate after the
+// first batch, and how often creating the true peak measurement allocates.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::time::Duration;
+
+use ebur128::{EbuR128, Mode, NonFiniteHandling};
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|a| a.set(a.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|a| a.set(a.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|a| a.get())
+}
+
+fn sine(rate: u32, channels: u32) -> Vec<f32> {
+    let mut data = vec![0.0f32; rate as usize * channels as usize];
+    let mut accumulator = 0.0;
+    let step = 2.0 * std::f32::consts::PI * 440.0 / rate as f32;
+    for frame in data.chunks_exact_mut(channels as usize) {
+        let val = f32::sin(accumulator);
+        for (c, out) in frame.iter_mut().enumerate() {
+            *out = val / (c + 1) as f32;
+        }
+        accumulator += step;
+    }
+
+    data
+}
+
+#[test]
+fn steady_stream() {
+    // Specialized and generic true peak code paths, and a rate without oversampling
+    for &(channels, rate) in &[(2, 48_000), (9, 48_000), (2, 96_000), (2, 192_000)] {
+        let data = sine(rate, channels);
+        let batch = 1_024 * channels as usize;
+
+        let data_i16 = data
+            .iter()
+            .map(|s| (s * i16::MAX as f32) as i16)
+            .collect::<Vec<_>>();
+        let data_planar = (0..channels as usize)
+            .map(|c| {
+                data.iter()
+                    .skip(c)
+                    .step_by(channels as usize)
+                    .copied()
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let mut data_non_finite = data.clone();
+        for chunk in data_non_finite.chunks_mut(batch) {
+            chunk[1] = f32::NAN;
+        }
+
+        let mut ebu = EbuR128::new(channels, rate, Mode::all()).unwrap();
+        let mut ebu_i16 = EbuR128::new(channels, rate, Mode::all()).unwrap();
+        let mut ebu_planar = EbuR128::new(channels, rate, Mode::all()).unwrap();
+        let mut ebu_non_finite = EbuR128::new(channels, rate, Mode::all()).unwrap();
+        ebu_non_finite.set_non_finite_handling(NonFiniteHandling::Skip);
+
+        let mut planar = Vec::with_capacity(channels as usize);
+        for (i, start) in (0..data.len() - batch).step_by(batch).enumerate() {
+            let before = allocations();
+
+            ebu.add_frames_f32(&data[start..start + batch]).unwrap();
+            ebu_i16
+                .add_frames_i16(&data_i16[start..start + batch])
+                .unwrap();
+            ebu_non_finite
+                .add_frames_f32(&data_non_finite[start..start + batch])
+                .unwrap();
+
+            planar.clear();
+            for channel in &data_planar {
+                let start = start / channels as usize;
+                planar.push(&channel[start..start + batch / channels as usize]);
+            }
+            ebu_planar.add_frames_planar_f32(&planar).unwrap();
+
+            if i > 0 {
+                assert_eq!(
+                    allocations(),
+                    before,
+                    "{} channels at {}Hz allocated in batch {}",
+                    channels,
+                    rate,
+                    i
+                );
+            }
+        }
+
+        assert!(ebu.loudness_global().unwrap().is_finite());
+        assert!(ebu_non_finite.non_finite_samples() > 0);
+    }
+}
+
+#[test]
+fn reserve_history() {
+    // With a hop of 10ms the 60s are more gating blocks than the initial capacity of the queue
+    let rate = 8_000;
+    let duration = Duration::from_secs(60);
+    let data = sine(rate, 1);
+    let batch = 1_000;
+
+    let mut results = Vec::new();
+    for &reserve in &[false, true] {
+        let mut ebu = EbuR128::new(1, rate, Mode::M | Mode::S | Mode::I | Mode::LRA).unwrap();
+        ebu.set_block_hop(10).unwrap();
+        ebu.enable_timeseries();
+        ebu.enable_block_energies();
+        if reserve {
+            ebu.reserve_history(duration).unwrap();
+        }
+
+        // Allocations can only come from the history, the filter and the audio data don't grow
+        let before = allocations();
+        for _ in 0..duration.as_secs() {
+            for chunk in data.chunks(batch) {
+                ebu.add_frames_f32(chunk).unwrap();
+            }
+        }
+        let allocated = allocations() - before;
+
+        assert_eq!(ebu.gating_block_count(), 5_961);
+        if reserve {
+            assert_eq!(allocated, 0);
+        } else {
+            assert!(allocated > 0);
+        }
+        results.push(ebu);
+    }
+
+    // Only the capacity differs
+    assert_eq!(results[0], results[1]);
+}
+
+#[cfg(feature = "internal-tests")]
+#[test]
+fn true_peak_construction() {
+    use ebur128::true_peak::TruePeak;
+
+    // The specialized interpolators for common layouts and the rates without oversampling don't
+    // allocate, the generic ones allocate a single slice with one interpolator per channel
+    for &(channels, rate, expected) in &[
+        (1, 48_000, 0),
+        (2, 48_000, 0),
+        (8, 96_000, 0),
+        (2, 192_000, 0),
+        (3, 48_000, 1),
+        (9, 96_000, 1),
+        (24, 48_000, 1),
+    ] {
+        for _ in 0..100 {
+            let before = allocations();
+            let tp = TruePeak::new(rate, channels).unwrap();
+            assert_eq!(
+                allocations() - before,
+                expected,
+                "{} channels at {}Hz",
+                channels,
+                rate
+            );
+            drop(tp);
+        }
+    }
+}
@@ -681,3 +681,39 @@ fn seq_3342_6() {
         15.0
     );
 }
+
+#[cfg(feature = "hound")]
+#[test]
+fn wav_helper() {
+    // 16 and 24 bit files with the integrated loudness and loudness range from TECH 3341/3342
+    for &(file_name, expected_loudness, expected_loudness_range) in &[
+        ("seq-3341-1-16bit.wav", -23.0, None),
+        ("seq-3341-7_seq-3342-5-24bit.wav", -23.0, Some(5.0)),
+    ] {
+        let mut input_path = std::path::PathBuf::new();
+        input_path.push(env!("CARGO_MANIFEST_DIR"));
+        input_path.push("tests");
+        input_path.push("reference_files");
+        input_path.push(file_name);
+
+        let e = ebur128::wav::analyze_file(&input_path, ebur128::Mode::I | ebur128::Mode::LRA)
+            .expect("Failed to analyze reference file");
+
+        float_eq::assert_float_eq!(
+            e.loudness_global().expect("Failed to get global loudness"),
+            expected_loudness,
+            abs <= 0.1,
+            "{}",
+            file_name
+        );
+        if let Some(expected_loudness_range) = expected_loudness_range {
+            float_eq::assert_float_eq!(
+                e.loudness_range().expect("Failed to get loudness range"),
+                expected_loudness_range,
+                abs <= 1.0,
+                "{}",
+                file_name
+            );
+        }
+    }
+}